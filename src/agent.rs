@@ -0,0 +1,116 @@
+use tokio::process::Command;
+
+use crate::error::{Error, Result};
+
+/// make gpg-agent forget the cached passphrase for a key
+pub async fn clear_passphrase(keygrip: &str) -> Result {
+    send(&format!("CLEAR_PASSPHRASE --mode=normal {keygrip}")).await
+}
+
+/// make gpg-agent forget every cached passphrase
+pub async fn reload() -> Result {
+    send("RELOADAGENT").await
+}
+
+/// whether gpg-agent has the passphrase for a key cached, or the key doesn't have one, so
+/// using it won't show pinentry
+pub async fn is_unlocked(keygrip: &str) -> Result<bool> {
+    let output = query(&format!("KEYINFO {keygrip}")).await?;
+    Ok(parse_keyinfo(&output).unwrap_or_default())
+}
+
+/// start gpg-agent if it isn't running, e.g. after it was killed or restarted
+pub async fn launch() -> Result {
+    let output = Command::new("gpgconf")
+        .args(["--launch", "gpg-agent"])
+        .output()
+        .await?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(Error::AgentUnavailable(
+            String::from_utf8_lossy(&output.stderr).trim().to_owned(),
+        ))
+    }
+}
+
+/// whether gpg's error output says it couldn't talk to gpg-agent
+pub fn is_unreachable(stderr: &str) -> bool {
+    const MESSAGES: [&str; 4] = [
+        "can't connect to the agent",
+        "problem with the agent",
+        "No agent running",
+        "gpg-agent is not available",
+    ];
+    MESSAGES.iter().any(|message| stderr.contains(message))
+}
+
+/// whether gpg's error output says it needed a passphrase (or a smartcard PIN) but wasn't
+/// allowed to show pinentry
+pub fn needs_passphrase(stderr: &str) -> bool {
+    stderr.contains("No pinentry")
+}
+
+/// whether a key is unlocked, from the `S KEYINFO` line gpg-agent returns for it:
+/// `S KEYINFO <keygrip> <type> <serialno> <idstr> <cached> <protection> ...`
+fn parse_keyinfo(output: &str) -> Option<bool> {
+    let line = output.lines().find(|line| line.starts_with("S KEYINFO "))?;
+    match line.split(' ').collect::<Vec<_>>()[..] {
+        [_, _, _, _, _, _, cached, protection, ..] => Some(cached == "1" || protection == "C"),
+        _ => None,
+    }
+}
+
+/// send a command to gpg-agent
+async fn send(command: &str) -> Result {
+    query(command).await.map(|_| ())
+}
+
+/// send a command to gpg-agent, returning what it printed
+async fn query(command: &str) -> Result<String> {
+    let output = Command::new("gpg-connect-agent")
+        .arg(command)
+        .arg("/bye")
+        .output()
+        .await?;
+
+    if !output.status.success() {
+        return Err(Error::GpgError(
+            String::from_utf8_lossy(&output.stderr).into_owned(),
+        ));
+    }
+    // gpg-connect-agent exits successfully even if the agent returned an error
+    let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+    match stdout.lines().find(|line| line.starts_with("ERR ")) {
+        Some(error) => Err(Error::GpgError(error.to_owned())),
+        None => Ok(stdout),
+    }
+}
+
+#[test]
+fn test_is_unreachable() {
+    assert!(is_unreachable(
+        "gpg: can't connect to the agent: IPC connect call failed\n\
+        gpg: decryption failed: No secret key\n"
+    ));
+    assert!(is_unreachable("gpg: problem with the agent: No agent running\n"));
+    assert!(!is_unreachable("gpg: decryption failed: No secret key\n"));
+}
+
+#[test]
+fn test_needs_passphrase() {
+    assert!(needs_passphrase(
+        "gpg: public key decryption failed: No pinentry\n\
+        gpg: decryption failed: No secret key\n"
+    ));
+    assert!(!needs_passphrase("gpg: decryption failed: No secret key\n"));
+}
+
+#[test]
+fn test_parse_keyinfo() {
+    let keygrip = "0123456789ABCDEF0123456789ABCDEF01234567";
+    assert_eq!(parse_keyinfo(&format!("S KEYINFO {keygrip} D - - 1 P - - -\nOK\n")), Some(true));
+    assert_eq!(parse_keyinfo(&format!("S KEYINFO {keygrip} D - - - P - - -\nOK\n")), Some(false));
+    assert_eq!(parse_keyinfo(&format!("S KEYINFO {keygrip} D - - - C - - -\nOK\n")), Some(true));
+    assert_eq!(parse_keyinfo("OK\n"), None);
+}
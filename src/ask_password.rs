@@ -0,0 +1,314 @@
+use std::{
+    collections::HashSet,
+    io::{self, ErrorKind},
+    os::unix::{
+        fs::{FileTypeExt, MetadataExt},
+        net::UnixDatagram,
+    },
+    path::{Component, Path, PathBuf},
+    process::Stdio,
+    time::Duration,
+};
+
+use log::{debug, info, warn};
+use tokio::{
+    fs::{read_dir, symlink_metadata, OpenOptions},
+    io::{AsyncReadExt, AsyncWriteExt},
+    process::Command,
+};
+
+use crate::{
+    config::{AskPasswordConfig, AskPasswordRule},
+    error::{Error, Result},
+    secret_store::SecretStore,
+};
+
+/// how often the request directories are checked
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// a password request (an `ask.*` file)
+#[derive(Debug, PartialEq)]
+struct Request {
+    /// where the answer is sent
+    socket: PathBuf,
+    id: Option<String>,
+    /// CLOCK_MONOTONIC microseconds after which it's no longer wanted. 0 for never
+    not_after: u64,
+}
+
+/// parse the `[Ask]` section of a request file
+fn parse_request(contents: &str) -> Option<Request> {
+    let mut in_ask = false;
+    let mut socket = None;
+    let mut id = None;
+    let mut not_after = 0;
+    for line in contents.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_ask = line == "[Ask]";
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=').filter(|_| in_ask) else {
+            continue;
+        };
+        match key {
+            "Socket" => socket = Some(PathBuf::from(value)),
+            "Id" => id = Some(value.to_owned()),
+            "NotAfter" => not_after = value.parse().ok()?,
+            _ => {}
+        }
+    }
+    Some(Request {
+        socket: socket?,
+        id,
+        not_after,
+    })
+}
+
+/// read a request file, if it's a regular file (not a symlink) owned by root or this user
+/// returns None if it isn't, since then anyone could have written it
+async fn read_request(path: &Path) -> io::Result<Option<String>> {
+    let mut file = match OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_NOFOLLOW)
+        .open(path)
+        .await
+    {
+        Ok(file) => file,
+        Err(e) if e.raw_os_error() == Some(libc::ELOOP) => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let metadata = file.metadata().await?;
+    if !metadata.is_file() || !is_trusted_owner(metadata.uid()) {
+        return Ok(None);
+    }
+    let mut contents = String::new();
+    file.read_to_string(&mut contents).await?;
+    Ok(Some(contents))
+}
+
+/// whether a file owned by this uid can be trusted: root's or this user's
+fn is_trusted_owner(uid: u32) -> bool {
+    uid == 0 || uid == unsafe { libc::getuid() }
+}
+
+/// whether a request's socket is directly inside the directory the request is in, so the
+/// password can't be sent anywhere else
+fn socket_in(directory: &Path, socket: &Path) -> bool {
+    socket.parent() == Some(directory) && matches!(socket.components().next_back(), Some(Component::Normal(_)))
+}
+
+/// the current CLOCK_MONOTONIC time in microseconds, which `NotAfter` uses
+fn monotonic_usec() -> u64 {
+    let mut time = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut time) };
+    time.tv_sec as u64 * 1_000_000 + time.tv_nsec as u64 / 1000
+}
+
+/// answer allowlisted password requests until the daemon exits
+pub async fn watch_requests(store: SecretStore<'static>) {
+    let config = &store.config.ask_password;
+    if config.answer.is_empty() {
+        return;
+    }
+    let directories = config.directories();
+
+    // requests we've seen, so each is only answered once
+    let mut seen = HashSet::new();
+    loop {
+        for directory in &directories {
+            match scan(&store, config, directory, &mut seen).await {
+                Ok(()) => {}
+                // systemd hasn't created it yet
+                Err(Error::IoError(e)) if e.kind() == ErrorKind::NotFound => {}
+                Err(e) => warn!("Could not check {} for password requests: {e}", directory.display()),
+            }
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+/// answer the new requests in a directory
+async fn scan(
+    store: &SecretStore<'static>,
+    config: &AskPasswordConfig,
+    directory: &Path,
+    seen: &mut HashSet<PathBuf>,
+) -> Result {
+    let mut current = HashSet::new();
+    let mut entries = read_dir(directory).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        if entry.file_name().to_string_lossy().starts_with("ask.") {
+            current.insert(entry.path());
+        }
+    }
+    // forget the ones which were answered or cancelled
+    seen.retain(|path| current.contains(path));
+
+    for path in current {
+        if !seen.insert(path.clone()) {
+            continue;
+        }
+        // it may have been answered by another agent already
+        let contents = match read_request(&path).await {
+            Ok(Some(contents)) => contents,
+            Ok(None) => {
+                warn!("Ignoring password request {}, which isn't a file owned by root or this user", path.display());
+                continue;
+            }
+            Err(_) => continue,
+        };
+        let Some(request) = parse_request(&contents) else {
+            warn!("Could not parse password request {}", path.display());
+            continue;
+        };
+        let is_socket = symlink_metadata(&request.socket)
+            .await
+            .is_ok_and(|metadata| metadata.file_type().is_socket());
+        if !socket_in(directory, &request.socket) || !is_socket {
+            warn!(
+                "Ignoring password request {}, whose socket {} isn't in {}",
+                path.display(),
+                request.socket.display(),
+                directory.display()
+            );
+            continue;
+        }
+        if request.not_after != 0 && request.not_after < monotonic_usec() {
+            continue;
+        }
+        let Some(rule) = config
+            .answer
+            .iter()
+            .find(|rule| request.id.as_ref() == Some(&rule.id))
+        else {
+            debug!("Ignoring password request {:?}, which isn't allowlisted", request.id);
+            continue;
+        };
+
+        match find_password(store, rule).await {
+            Ok(Some(password)) => match reply(config, &request.socket, password).await {
+                Ok(()) => info!("Answered password request {}", rule.id),
+                Err(e) => warn!("Could not answer password request {}: {e}", rule.id),
+            },
+            Ok(None) => {}
+            Err(e) => warn!("Could not read the secret for password request {}: {e}", rule.id),
+        }
+    }
+    Ok(())
+}
+
+/// read the secret a rule answers with
+/// returns None if there isn't exactly one unlocked item with its attributes
+async fn find_password(store: &SecretStore<'static>, rule: &AskPasswordRule) -> Result<Option<Vec<u8>>> {
+    let matches: Vec<_> = store
+        .search_all_collections(rule.attributes.clone())
+        .await?
+        .into_iter()
+        .flat_map(|(collection_id, secrets)| {
+            secrets
+                .into_iter()
+                .map(move |secret_id| (collection_id.clone(), secret_id))
+        })
+        .collect();
+    let [(collection_id, secret_id)] = &matches[..] else {
+        warn!(
+            "{} items match the attributes for password request {}, so it wasn't answered",
+            matches.len(),
+            rule.id
+        );
+        return Ok(None);
+    };
+
+    if store.is_locked(collection_id) {
+        warn!("Not answering password request {}: collection {collection_id} is locked", rule.id);
+        return Ok(None);
+    }
+    store.confirm_read(collection_id).await?;
+    store.confirm_item_read(collection_id, secret_id).await?;
+    let value = store.read_secret(collection_id, secret_id, true).await?;
+    Ok(Some(store.filter_read(collection_id, secret_id, value).await?))
+}
+
+/// send a password to a request's socket
+async fn reply(config: &AskPasswordConfig, socket: &Path, password: Vec<u8>) -> Result {
+    let mut packet = b"+".to_vec();
+    packet.extend_from_slice(&password);
+
+    let sent = UnixDatagram::unbound().and_then(|unbound| unbound.send_to(&packet, socket));
+    match sent {
+        Ok(_) => Ok(()),
+        // the system directory's sockets are owned by root
+        Err(e) if e.kind() == ErrorKind::PermissionDenied && !config.reply_command.is_empty() => {
+            let (program, args) = config.reply_command.split_first().expect("not empty");
+            let mut process = Command::new(program)
+                .args(args)
+                .arg("1")
+                .arg(socket)
+                .stdin(Stdio::piped())
+                .spawn()?;
+            let mut stdin = process.stdin.take().expect("child has stdin");
+            stdin.write_all(&password).await?;
+            drop(stdin);
+
+            if process.wait().await?.success() {
+                Ok(())
+            } else {
+                Err(io::Error::other(format!("{program} failed")).into())
+            }
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[test]
+fn test_parse_request() {
+    let request = parse_request(
+        "[Ask]\nPID=123\nSocket=/run/systemd/ask-password/sck.1\nAcceptCached=1\nEcho=0\n\
+        NotAfter=5000\nId=cryptsetup:/dev/sda2\nMessage=Please enter passphrase for disk\n",
+    );
+    assert_eq!(
+        request,
+        Some(Request {
+            socket: "/run/systemd/ask-password/sck.1".into(),
+            id: Some("cryptsetup:/dev/sda2".to_owned()),
+            not_after: 5000,
+        })
+    );
+
+    // keys in other sections are ignored
+    assert_eq!(parse_request("[Other]\nSocket=/tmp/x\n"), None);
+    assert_eq!(parse_request("[Ask]\nSocket=/tmp/x\nNotAfter=soon\n"), None);
+}
+
+#[test]
+fn test_socket_in() {
+    let directory = Path::new("/run/systemd/ask-password");
+    assert!(socket_in(directory, Path::new("/run/systemd/ask-password/sck.1")));
+    assert!(socket_in(Path::new("/run/systemd/ask-password/"), Path::new("/run/systemd/ask-password/sck.1")));
+
+    assert!(!socket_in(directory, Path::new("/tmp/sck.1")));
+    assert!(!socket_in(directory, Path::new("/run/systemd/ask-password/sub/sck.1")));
+    assert!(!socket_in(directory, Path::new("/run/systemd/ask-password/sub/..")));
+    assert!(!socket_in(directory, Path::new("sck.1")));
+}
+
+#[tokio::test]
+async fn test_read_request() {
+    let dir = std::env::temp_dir().join(format!("pass-secret-service-ask-password-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let request = dir.join("ask.1");
+    std::fs::write(&request, "[Ask]\nSocket=/tmp/x\n").unwrap();
+    assert_eq!(read_request(&request).await.unwrap().as_deref(), Some("[Ask]\nSocket=/tmp/x\n"));
+
+    // a symlink could point at a file another user wrote
+    let link = dir.join("ask.2");
+    std::os::unix::fs::symlink(&request, &link).unwrap();
+    assert_eq!(read_request(&link).await.unwrap(), None);
+    // and only files are read
+    assert_eq!(read_request(&dir).await.unwrap(), None);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,179 @@
+use std::{
+    fs::{remove_file, rename, DirBuilder, File, OpenOptions},
+    io::{self, ErrorKind, Write},
+    os::unix::fs::{DirBuilderExt, OpenOptionsExt},
+    path::PathBuf,
+    sync::{Mutex, OnceLock, PoisonError},
+    time::SystemTime,
+};
+
+use log::{info, warn};
+use serde::Serialize;
+use zbus::{message::Header, Connection};
+
+use crate::{config::AuditConfig, dbus_server::utils::client_exe};
+
+static LOG: OnceLock<Mutex<AuditLog>> = OnceLock::new();
+
+/// the operations which are audited
+#[derive(Serialize, Clone, Copy, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum Operation {
+    CreateItem,
+    GetSecret,
+    SetSecret,
+    DeleteItem,
+    CreateCollection,
+    DeleteCollection,
+}
+
+/// a line in the audit log
+#[derive(Serialize, Debug)]
+struct Entry<'a> {
+    /// unix seconds
+    time: u64,
+    operation: Operation,
+    /// the client's unique bus name
+    sender: Option<&'a str>,
+    /// the client's executable, if it could be found
+    exe: Option<String>,
+    collection: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    item: Option<&'a str>,
+}
+
+fn open_append(path: &PathBuf) -> io::Result<File> {
+    OpenOptions::new()
+        .append(true)
+        .create(true)
+        .mode(0o600)
+        .open(path)
+}
+
+struct AuditLog {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    /// in bytes. 0 never rotates
+    max_size: u64,
+    keep: u32,
+}
+
+impl AuditLog {
+    fn open(path: PathBuf, config: &AuditConfig) -> io::Result<Self> {
+        if let Some(dir) = path.parent() {
+            DirBuilder::new().recursive(true).mode(0o700).create(dir)?;
+        }
+        let file = open_append(&path)?;
+        let size = file.metadata()?.len();
+        Ok(Self {
+            path,
+            file,
+            size,
+            max_size: config.max_size_kb * 1024,
+            keep: config.keep,
+        })
+    }
+
+    fn rotated_path(&self, n: u32) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{n}"));
+        path.into()
+    }
+
+    /// move the log to `<path>.1`, `<path>.1` to `<path>.2` and so on, dropping the oldest,
+    /// and start a new one
+    fn rotate(&mut self) -> io::Result<()> {
+        if self.keep == 0 {
+            remove_file(&self.path)?;
+        } else {
+            for n in (1..self.keep).rev() {
+                match rename(self.rotated_path(n), self.rotated_path(n + 1)) {
+                    Err(e) if e.kind() != ErrorKind::NotFound => return Err(e),
+                    _ => {}
+                }
+            }
+            rename(&self.path, self.rotated_path(1))?;
+        }
+        self.file = open_append(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+
+    fn write(&mut self, line: &[u8]) -> io::Result<()> {
+        let len = line.len() as u64;
+        if self.max_size > 0 && self.size > 0 && self.size + len > self.max_size {
+            self.rotate()?;
+        }
+        self.file.write_all(line)?;
+        self.size += len;
+        Ok(())
+    }
+}
+
+/// open the audit log, if it's enabled
+pub fn init(config: &AuditConfig) -> io::Result<()> {
+    let Some(path) = config.path() else {
+        return Ok(());
+    };
+    let log = AuditLog::open(path, config)?;
+    info!("Writing the audit log to {}", log.path.display());
+    let _ = LOG.set(Mutex::new(log));
+    Ok(())
+}
+
+/// record an operation a client made. does nothing if the audit log is disabled
+pub async fn record(
+    connection: &Connection,
+    header: &Header<'_>,
+    operation: Operation,
+    collection: &str,
+    item: Option<&str>,
+) {
+    let Some(log) = LOG.get() else {
+        return;
+    };
+
+    let entry = Entry {
+        time: SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default(),
+        operation,
+        sender: header.sender().map(|sender| sender.as_str()),
+        exe: client_exe(connection, header).await,
+        collection,
+        item,
+    };
+    let Ok(mut line) = serde_json::to_vec(&entry) else {
+        return;
+    };
+    line.push(b'\n');
+
+    let mut log = log.lock().unwrap_or_else(PoisonError::into_inner);
+    if let Err(e) = log.write(&line) {
+        warn!("Could not write to the audit log: {e}");
+    }
+}
+
+#[test]
+fn test_rotate() {
+    let dir = std::env::temp_dir().join(format!("pass-secret-service-audit-{}", std::process::id()));
+    let config = AuditConfig {
+        path: Some(dir.join("audit.log")),
+        max_size_kb: 1,
+        keep: 2,
+    };
+    let mut log = AuditLog::open(config.path().unwrap(), &config).unwrap();
+
+    let line = [b'x'; 600];
+    for _ in 0..4 {
+        log.write(&line).unwrap();
+    }
+    // every write after the first starts a new log, and only 2 old ones are kept
+    assert_eq!(std::fs::metadata(&log.path).unwrap().len(), 600);
+    assert!(log.rotated_path(2).exists());
+    assert!(!log.rotated_path(3).exists());
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
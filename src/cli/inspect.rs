@@ -0,0 +1,226 @@
+use std::{cmp::Reverse, collections::HashMap, time::SystemTime};
+
+use clap::Subcommand;
+use fuzzy_matcher::{skim::SkimMatcherV2, FuzzyMatcher};
+use zbus::{
+    zvariant::{OwnedObjectPath, Value},
+    Connection,
+};
+
+use crate::{
+    dbus_server::utils::{collection_path, secret_path},
+    error::{OptionNoneNotFound, Result},
+};
+
+use super::proxies::{CollectionProxy, ItemExtProxy, ItemProxy, ManagerProxy, ServiceProxy};
+
+#[derive(Subcommand, Debug)]
+pub enum InspectCommand {
+    /// Fuzzy search collection and item labels
+    Search {
+        query: String,
+        /// Maximum number of results to show
+        #[arg(short = 'n', long, default_value_t = 10)]
+        limit: usize,
+    },
+    /// List when each item was last read and by which client, most recent first
+    Accesses {
+        /// Only list items in this collection
+        collection: Option<String>,
+    },
+    /// Show an item's label (and previous labels), attributes, and times, and optionally its secret
+    Show {
+        /// ID of the collection
+        collection: String,
+        /// ID of the item
+        item: String,
+        /// Print the secret too
+        #[arg(long)]
+        secret: bool,
+    },
+}
+
+struct Match {
+    score: i64,
+    path: OwnedObjectPath,
+    label: String,
+    /// label of the collection this item is in. None for collections
+    collection_label: Option<String>,
+    attributes: HashMap<String, String>,
+}
+
+/// fuzzy search all collection and item labels
+async fn search(connection: &Connection, query: &str, limit: usize) -> Result {
+    let matcher = SkimMatcherV2::default().ignore_case();
+    let service = ServiceProxy::new(connection).await?;
+
+    let mut matches = vec![];
+
+    for collection_path in service.collections().await? {
+        let collection = CollectionProxy::builder(connection)
+            .path(collection_path.clone())?
+            .build()
+            .await?;
+        let collection_label = collection.label().await?;
+
+        if let Some(score) = matcher.fuzzy_match(&collection_label, query) {
+            matches.push(Match {
+                score,
+                path: collection_path,
+                label: collection_label.clone(),
+                collection_label: None,
+                attributes: HashMap::new(),
+            });
+        }
+
+        for item_path in collection.items().await? {
+            let item = ItemProxy::builder(connection)
+                .path(item_path.clone())?
+                .build()
+                .await?;
+            let label = item.label().await?;
+
+            if let Some(score) = matcher.fuzzy_match(&label, query) {
+                matches.push(Match {
+                    score,
+                    path: item_path,
+                    label,
+                    collection_label: Some(collection_label.clone()),
+                    attributes: item.attributes().await?,
+                });
+            }
+        }
+    }
+
+    // best matches first
+    matches.sort_by_key(|m| Reverse(m.score));
+
+    if matches.is_empty() {
+        println!("No matches for \"{query}\"");
+    }
+
+    for m in matches.into_iter().take(limit) {
+        println!("{}", m.path.as_str());
+        println!("  label: {}", m.label);
+        match m.collection_label {
+            Some(collection_label) => println!("  collection: {collection_label}"),
+            None => println!("  (collection)"),
+        }
+
+        let mut attributes: Vec<_> = m.attributes.into_iter().collect();
+        attributes.sort();
+        for (k, v) in attributes {
+            println!("  {k} = {v}");
+        }
+    }
+
+    Ok(())
+}
+
+/// e.g. "3 hours ago"
+fn ago(now: u64, time: u64) -> String {
+    let secs = now.saturating_sub(time);
+    let (amount, unit) = match secs {
+        0..=59 => (secs, "second"),
+        60..=3599 => (secs / 60, "minute"),
+        3600..=86399 => (secs / 3600, "hour"),
+        _ => (secs / 86400, "day"),
+    };
+    let plural = if amount == 1 { "" } else { "s" };
+    format!("{amount} {unit}{plural} ago")
+}
+
+/// list the items which have been read, most recently read first
+async fn accesses(connection: &Connection, collection: Option<&str>) -> Result {
+    let service = ServiceProxy::new(connection).await?;
+    let collection_paths = match collection {
+        Some(id) => vec![collection_path(id)
+            .map(OwnedObjectPath::from)
+            .into_not_found()?],
+        None => service.collections().await?,
+    };
+
+    let mut accesses = vec![];
+    for collection_path in collection_paths {
+        let collection = CollectionProxy::builder(connection)
+            .path(collection_path)?
+            .build()
+            .await?;
+        for item_path in collection.items().await? {
+            let item = ItemExtProxy::builder(connection)
+                .path(item_path.clone())?
+                .build()
+                .await?;
+            let time = item.last_accessed().await?;
+            if time != 0 {
+                accesses.push((time, item.last_accessed_by().await?, item_path));
+            }
+        }
+    }
+    accesses.sort_by_key(|(time, _, _)| Reverse(*time));
+
+    if accesses.is_empty() {
+        println!("No items have been read yet");
+    }
+
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    for (time, exe, path) in accesses {
+        println!("{}", path.as_str());
+        println!("  {} by {exe}", ago(now, time));
+    }
+
+    Ok(())
+}
+
+/// show everything about an item, read in one call
+async fn show(connection: &Connection, collection: &str, item: &str, print_secret: bool) -> Result {
+    let path = secret_path(collection, item).into_not_found()?;
+    let service = ServiceProxy::new(connection).await?;
+    let manager = ManagerProxy::new(connection).await?;
+
+    // the secret is only sent over this connection, so it doesn't need to be encrypted
+    let (_, session) = service.open_session("plain", &Value::from("")).await?;
+    let (secret, attributes, label, created, modified) = manager.get_item_full(&path, &session).await?;
+
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    println!("{}", path.as_str());
+    println!("  label: {label}");
+    let item = ItemExtProxy::builder(connection).path(path.clone())?.build().await?;
+    for (old_label, changed) in item.label_history().await?.into_iter().rev() {
+        println!("  previously: {old_label} (changed {})", ago(now, changed));
+    }
+    println!("  created: {}", ago(now, created));
+    println!("  modified: {}", ago(now, modified));
+
+    let mut attributes: Vec<_> = attributes.into_iter().collect();
+    attributes.sort();
+    for (k, v) in attributes {
+        println!("  {k} = {v}");
+    }
+
+    if print_secret {
+        println!("  secret: {}", String::from_utf8_lossy(&secret.value));
+    } else {
+        println!("  secret: {} bytes ({})", secret.value.len(), secret.content_type);
+    }
+
+    Ok(())
+}
+
+pub async fn run(connection: &Connection, command: InspectCommand) -> Result {
+    match command {
+        InspectCommand::Search { query, limit } => search(connection, &query, limit).await,
+        InspectCommand::Accesses { collection } => {
+            accesses(connection, collection.as_deref()).await
+        }
+        InspectCommand::Show { collection, item, secret } => {
+            show(connection, &collection, &item, secret).await
+        }
+    }
+}
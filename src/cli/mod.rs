@@ -0,0 +1,453 @@
+use std::{
+    io,
+    path::{self, PathBuf},
+    time::SystemTime,
+};
+
+use clap::{Parser, Subcommand};
+use zbus::Connection;
+
+use crate::{
+    dbus_server::utils::{collection_path, secret_path},
+    error::{OptionNoneNotFound, Result},
+    mdellweg::MDELLWEG_SUBDIR,
+    secret_store::dump_db,
+};
+
+use inspect::InspectCommand;
+use proxies::{ItemExtProxy, ManagerProxy};
+
+mod inspect;
+mod proxies;
+
+#[derive(Parser, Debug)]
+#[command(version, about)]
+pub struct Args {
+    /// Path to the config file. Defaults to `$XDG_CONFIG_HOME/pass-secret-service/config.toml`
+    #[arg(short, long, global = true)]
+    pub config: Option<PathBuf>,
+
+    /// Stop any other instance using this password store, and wait for it to release its lock
+    #[arg(long)]
+    pub takeover: bool,
+
+    /// If another service owns org.freedesktop.secrets, wait for it to release the name instead of exiting
+    #[arg(long, conflicts_with = "replace")]
+    pub queue: bool,
+
+    /// Take org.freedesktop.secrets from the service which owns it. Only works if it allows replacement
+    #[arg(long)]
+    pub replace: bool,
+
+    /// Read every secret back after writing it, and fail if it doesn't match (same as `verify-writes` in the config)
+    #[arg(long)]
+    pub verify_writes: bool,
+
+    /// Make gpg-agent forget every cached passphrase when a collection is locked (same as `forget-password-on-lock` in the config)
+    #[arg(long)]
+    pub forget_password_on_lock: bool,
+
+    /// Never let gpg show pinentry. Secrets whose passphrase gpg-agent doesn't have cached can't be read or unlocked (same as `no-interactive` in the config)
+    #[arg(long)]
+    pub no_interactive: bool,
+
+    /// Serve the password store's other entries in a read-only collection (same as `expose-pass-tree` in the config)
+    #[arg(long)]
+    pub expose_pass_tree: bool,
+
+    /// Also serve gnome-keyring's private D-Bus interfaces for old clients which call them (same as `gnome-keyring-compat` in the config)
+    #[arg(long)]
+    pub gnome_keyring_compat: bool,
+
+    /// Import unencrypted gnome-keyring keyrings from `$XDG_DATA_HOME/keyrings` as collections on start (same as `import-keyrings` in the config)
+    #[arg(long)]
+    pub import_keyrings: bool,
+
+    /// Keep the metadata databases in `$XDG_DATA_HOME/pass-secret-service/metadata` instead of the password store, e.g. if it's on NFS (same as `metadata-in-tmpfs` in the config)
+    #[arg(long)]
+    pub metadata_in_tmpfs: bool,
+
+    /// Write and delete secrets with `pass insert` and `pass rm` instead of changing files directly, so pass extensions and its git integration run
+    #[arg(long)]
+    pub use_pass_cli: bool,
+
+    /// Create secret files with this mode (in octal, e.g. 640) instead of the one from `PASSWORD_STORE_UMASK`
+    #[arg(long, value_parser = parse_mode)]
+    pub file_mode: Option<u32>,
+
+    /// Create directories in the password store with this mode (in octal, e.g. 750) instead of the one from `PASSWORD_STORE_UMASK`
+    #[arg(long, value_parser = parse_mode)]
+    pub dir_mode: Option<u32>,
+
+    /// Check the config, password store, gpg, and session bus, print a report, and exit (with 1 if something is wrong) instead of starting
+    #[arg(long)]
+    pub dry_start: bool,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// parse a file mode in octal, like chmod takes it
+fn parse_mode(mode: &str) -> std::result::Result<u32, String> {
+    match u32::from_str_radix(mode, 8) {
+        Ok(mode) if mode <= 0o777 => Ok(mode),
+        _ => Err(format!("{mode:?} isn't a mode in octal, like 640")),
+    }
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Re-encrypt secrets which aren't encrypted to their collection's current `.gpg-id` recipients
+    ReEncrypt {
+        /// IDs of the collections to re-encrypt. Defaults to collections with stale secrets
+        collections: Vec<String>,
+    },
+    /// Count the secrets encrypted to each gpg key, to check re-encryption finished or find secrets encrypted to revoked keys
+    KeyUsage,
+    /// List secrets which are older than their collection's rotation policy, and will be re-encrypted when they're next read
+    PendingRotation,
+    /// Set the PIN for reading high-security collections. The daemon asks for it with pinentry
+    SetPin,
+    /// Choose a PIN which has to be entered (with pinentry) to read an item, even when gpg-agent has the passphrase cached. An empty PIN removes it
+    SetItemPin {
+        /// ID of the collection
+        collection: String,
+        /// ID of the item
+        item: String,
+    },
+    /// Print a snapshot of the daemon's state as JSON, to attach to bug reports. Secret values, attribute values, and item labels are left out
+    DumpState,
+    /// Write a collection's labels, attributes, and aliases (but no secrets) to a YAML file
+    ExportMetadata {
+        /// ID of the collection
+        collection: String,
+        path: PathBuf,
+    },
+    /// Apply a YAML file written by export-metadata to a collection. Items which aren't in the collection are skipped
+    ImportMetadata {
+        /// ID of the collection
+        collection: String,
+        path: PathBuf,
+    },
+    /// Write a collection's secrets, labels, attributes, and aliases to a tarball encrypted to its `.gpg-id`, to move or back it up
+    Export {
+        /// ID of the collection
+        collection: String,
+        /// Where to write it. It must not exist yet
+        path: PathBuf,
+    },
+    /// Create a new collection from a tarball written by export
+    Import {
+        path: PathBuf,
+    },
+    /// Read the labels and attributes written into a collection's entries (with `embed-metadata`) back into its index, e.g. after pulling changes with git
+    ReindexEntries {
+        /// ID of the collection
+        collection: String,
+    },
+    /// Serve collections and items which were added or removed outside the daemon, e.g. by a git pull
+    Reload,
+    /// Remove a collection's entries for secrets whose files are gone, and rebuild its search index
+    Repair {
+        /// ID of the collection
+        collection: String,
+    },
+    /// Serve a directory in the password store as a new collection, without moving its entries
+    AdoptDirectory {
+        /// Path of the directory, relative to the password store
+        directory: String,
+        /// Label of the collection. Defaults to the directory's name
+        #[arg(long, default_value = "")]
+        label: String,
+    },
+    /// Import the collections of mdellweg's pass_secret_service, with their labels, attributes, and aliases, checking every secret decrypts
+    ImportMdellweg {
+        /// Path of its directory, relative to the password store
+        #[arg(default_value = MDELLWEG_SUBDIR)]
+        directory: String,
+    },
+    /// Move a collection out of the secret service's directory into a plain pass directory, and stop serving it
+    EjectCollection {
+        /// ID of the collection
+        collection: String,
+        /// Where to move its entries, relative to the password store
+        target: String,
+    },
+    /// Run `git pull` in the password store and reload it
+    GitPull,
+    /// Run `git push` in the password store
+    GitPush,
+    /// Print the number of collections, items, and sessions, and the daemon's uptime
+    Stats,
+    /// List the items in a collection's trash, most recently deleted first
+    ListTrash {
+        /// ID of the collection
+        collection: String,
+    },
+    /// Move an item out of its collection's trash
+    RestoreItem {
+        /// ID of the collection
+        collection: String,
+        /// ID of the item
+        item: String,
+    },
+    /// Delete items in a collection's trash for good
+    PurgeTrash {
+        /// ID of the collection
+        collection: String,
+        /// ID of the item. Defaults to every item in the trash
+        item: Option<String>,
+    },
+    /// List the collections and items whose ids couldn't be used in paths when the daemon started, and what they were renamed to or where they're served
+    StartupReport,
+    /// List the items in a collection with exactly the same attributes, oldest first
+    ListDuplicates {
+        /// ID of the collection
+        collection: String,
+    },
+    /// Delete all but the newest of each group of items with exactly the same attributes
+    PurgeDuplicates {
+        /// ID of the collection
+        collection: String,
+    },
+    /// Print every table of a collections.redb or attributes.redb file, to debug metadata issues. Includes labels and attribute values, but not keys or PIN hashes. Doesn't need the daemon, and doesn't change the file
+    DumpDb {
+        path: PathBuf,
+    },
+    /// Inspect the secrets served by the running daemon
+    Inspect {
+        #[command(subcommand)]
+        command: InspectCommand,
+    },
+}
+
+/// run a CLI subcommand against the running daemon
+pub async fn run(command: Command) -> Result {
+    // reads the file itself
+    if let Command::DumpDb { path } = command {
+        return dump_db(&path, &mut io::stdout().lock());
+    }
+
+    let connection = Connection::session().await?;
+    let manager = ManagerProxy::new(&connection).await?;
+
+    match command {
+        Command::ReEncrypt { collections } => {
+            let paths = if collections.is_empty() {
+                let mut paths = vec![];
+                for (path, _, stale, initialized) in manager.check_recipients().await? {
+                    if !initialized {
+                        println!("{path}: no .gpg-id, so it can't be re-encrypted");
+                    } else if stale > 0 {
+                        paths.push(path.into());
+                    }
+                }
+                paths
+            } else {
+                collections
+                    .iter()
+                    .filter_map(collection_path)
+                    .collect::<Vec<_>>()
+            };
+
+            if paths.is_empty() {
+                println!("All sampled secrets are encrypted to their current recipients");
+            }
+
+            for path in paths {
+                let count = manager.re_encrypt(&path).await?;
+                println!("{path}: re-encrypted {count} secret(s)");
+            }
+        }
+        Command::KeyUsage => {
+            let usage = manager.key_usage().await?;
+            if usage.is_empty() {
+                println!("No secrets are encrypted with gpg");
+            }
+
+            for (key_id, items, user_id, status) in usage {
+                println!("{key_id}: {items} secret(s), {status}");
+                if !user_id.is_empty() {
+                    println!("  {user_id}");
+                }
+            }
+        }
+        Command::PendingRotation => {
+            let pending = manager.pending_rotation().await?;
+            if pending.is_empty() {
+                println!("No secrets are due for rotation");
+            }
+
+            let now = SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default();
+            for (path, encrypted) in pending {
+                let days = now.saturating_sub(encrypted) / (24 * 60 * 60);
+                println!("{path}: last encrypted {days} days ago");
+            }
+        }
+        Command::SetPin => {
+            manager.set_pin().await?;
+            println!("The PIN was changed");
+        }
+        Command::SetItemPin { collection, item } => {
+            let path = secret_path(&collection, &item).into_not_found()?;
+            let item = ItemExtProxy::builder(&connection).path(path)?.build().await?;
+            if item.set_pin().await? {
+                println!("The item's PIN was changed");
+            } else {
+                println!("The item no longer needs a PIN");
+            }
+        }
+        Command::DumpState => println!("{}", manager.dump_state().await?),
+        Command::ExportMetadata { collection, path } => {
+            let collection = collection_path(&collection).into_not_found()?;
+            // the daemon has its own working directory
+            let path = path::absolute(path)?;
+            manager
+                .export_metadata(&collection, &path.to_string_lossy())
+                .await?;
+        }
+        Command::ImportMetadata { collection, path } => {
+            let collection = collection_path(&collection).into_not_found()?;
+            let path = path::absolute(path)?;
+            let count = manager
+                .import_metadata(&collection, &path.to_string_lossy())
+                .await?;
+            println!("Updated {count} item(s)");
+        }
+        Command::Export { collection, path } => {
+            let collection = collection_path(&collection).into_not_found()?;
+            let path = path::absolute(path)?;
+            let count = manager
+                .export_collection(&collection, &path.to_string_lossy())
+                .await?;
+            println!("Exported {count} item(s)");
+        }
+        Command::Import { path } => {
+            let path = path::absolute(path)?;
+            let (collection, count) = manager.import_collection(&path.to_string_lossy()).await?;
+            println!("{}: imported {count} item(s)", collection.as_str());
+        }
+        Command::ReindexEntries { collection } => {
+            let collection = collection_path(&collection).into_not_found()?;
+            let count = manager.reindex_entries(&collection).await?;
+            println!("Reindexed {count} item(s)");
+        }
+        Command::Reload => {
+            let (collections_added, collections_removed, items_added, items_removed) =
+                manager.reload_store().await?;
+            println!("Collections: {collections_added} added, {collections_removed} removed");
+            println!("Items: {items_added} added, {items_removed} removed");
+        }
+        Command::Repair { collection } => {
+            let collection = collection_path(&collection).into_not_found()?;
+            let (removed, unindexed) = manager.repair_collection(&collection).await?;
+            println!("Removed {removed} entry(s) without a file");
+            if unindexed > 0 {
+                println!("{unindexed} file(s) have no entry. Add them with import-metadata or reindex-entries");
+            }
+        }
+        Command::AdoptDirectory { directory, label } => {
+            let (collection, skipped) = manager.adopt_directory(&directory, &label).await?;
+            println!("{}", collection.as_str());
+            for name in skipped {
+                eprintln!("Skipped {name}, since its name can only have letters, digits, and underscores");
+            }
+        }
+        Command::ImportMdellweg { directory } => {
+            let imported = manager.import_mdellweg(&directory).await?;
+            if imported.is_empty() {
+                println!("{directory} has no collections");
+            }
+            for (collection, count) in imported {
+                println!("{}: imported {count} item(s)", collection.as_str());
+            }
+        }
+        Command::EjectCollection { collection, target } => {
+            let collection = collection_path(&collection).into_not_found()?;
+            let count = manager.eject_collection(&collection, &target).await?;
+            println!("Moved {count} item(s) to {target}");
+        }
+        Command::GitPull => print!("{}", manager.git_pull().await?),
+        Command::GitPush => print!("{}", manager.git_push().await?),
+        Command::Stats => {
+            let mut stats: Vec<_> = manager.stats().await?.into_iter().collect();
+            stats.sort();
+            for (name, value) in stats {
+                println!("{name}: {value}");
+            }
+        }
+        Command::ListTrash { collection } => {
+            let collection = collection_path(&collection).into_not_found()?;
+            let trash = manager.list_trash(&collection).await?;
+            if trash.is_empty() {
+                println!("The trash is empty");
+            }
+
+            let now = SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default();
+            for (id, label, deleted) in trash {
+                let days = now.saturating_sub(deleted) / (24 * 60 * 60);
+                println!("{id}: {label} (deleted {days} days ago)");
+            }
+        }
+        Command::RestoreItem { collection, item } => {
+            let collection = collection_path(&collection).into_not_found()?;
+            println!("{}", manager.restore_item(&collection, &item).await?);
+        }
+        Command::PurgeTrash { collection, item } => {
+            let collection = collection_path(&collection).into_not_found()?;
+            let count = manager
+                .purge_trash(&collection, item.as_deref().unwrap_or_default())
+                .await?;
+            println!("Purged {count} item(s)");
+        }
+        Command::StartupReport => {
+            let report = manager.startup_report().await?;
+            if report.is_empty() {
+                println!("Every collection and item is served at its own id");
+            }
+
+            for (collection, item, renamed_to) in report {
+                match (item.is_empty(), renamed_to.is_empty()) {
+                    (true, _) => println!(
+                        "{collection:?}: served at {}. Rename its directory to fix it",
+                        collection_path(&collection).into_not_found()?
+                    ),
+                    (false, false) => println!("{collection}/{item:?}: renamed to {renamed_to}"),
+                    (false, true) => println!(
+                        "{collection}/{item:?}: served at {}",
+                        secret_path(&collection, &item).into_not_found()?
+                    ),
+                }
+            }
+        }
+        Command::ListDuplicates { collection } => {
+            let collection = collection_path(&collection).into_not_found()?;
+            let groups = manager.list_duplicates(&collection).await?;
+            if groups.is_empty() {
+                println!("No items have the same attributes");
+            }
+
+            for group in groups {
+                println!();
+                for path in group {
+                    println!("{path}");
+                }
+            }
+        }
+        Command::PurgeDuplicates { collection } => {
+            let collection = collection_path(&collection).into_not_found()?;
+            let count = manager.purge_duplicates(&collection).await?;
+            println!("Deleted {count} item(s)");
+        }
+        Command::DumpDb { .. } => unreachable!("handled above"),
+        Command::Inspect { command } => inspect::run(&connection, command).await?,
+    }
+
+    Ok(())
+}
@@ -0,0 +1,119 @@
+use std::collections::HashMap;
+
+use zbus::{
+    proxy,
+    zvariant::{ObjectPath, OwnedObjectPath, OwnedValue, Value},
+};
+
+use crate::dbus_server::utils::ItemFull;
+
+#[proxy(
+    interface = "me.grimsteel.PassSecretService.Manager",
+    default_service = "org.freedesktop.secrets",
+    default_path = "/org/freedesktop/secrets"
+)]
+pub trait Manager {
+    fn check_recipients(&self) -> zbus::Result<Vec<(OwnedObjectPath, u32, u32, bool)>>;
+
+    fn re_encrypt(&self, collection: &ObjectPath<'_>) -> zbus::Result<u32>;
+
+    fn key_usage(&self) -> zbus::Result<Vec<(String, u32, String, String)>>;
+
+    fn pending_rotation(&self) -> zbus::Result<Vec<(OwnedObjectPath, u64)>>;
+
+    fn set_pin(&self) -> zbus::Result<()>;
+
+    fn dump_state(&self) -> zbus::Result<String>;
+
+    fn export_metadata(&self, collection: &ObjectPath<'_>, path: &str) -> zbus::Result<()>;
+
+    fn import_metadata(&self, collection: &ObjectPath<'_>, path: &str) -> zbus::Result<u32>;
+
+    fn reindex_entries(&self, collection: &ObjectPath<'_>) -> zbus::Result<u32>;
+
+    fn reload_store(&self) -> zbus::Result<(u32, u32, u32, u32)>;
+
+    fn repair_collection(&self, collection: &ObjectPath<'_>) -> zbus::Result<(u32, u32)>;
+
+    fn adopt_directory(&self, directory: &str, label: &str) -> zbus::Result<(OwnedObjectPath, Vec<String>)>;
+
+    fn export_collection(&self, collection: &ObjectPath<'_>, path: &str) -> zbus::Result<u32>;
+
+    fn import_collection(&self, path: &str) -> zbus::Result<(OwnedObjectPath, u32)>;
+
+    fn import_mdellweg(&self, directory: &str) -> zbus::Result<Vec<(OwnedObjectPath, u32)>>;
+
+    fn eject_collection(&self, collection: &ObjectPath<'_>, target: &str) -> zbus::Result<u32>;
+
+    fn git_pull(&self) -> zbus::Result<String>;
+
+    fn git_push(&self) -> zbus::Result<String>;
+
+    fn stats(&self) -> zbus::Result<HashMap<String, u64>>;
+
+    fn list_trash(&self, collection: &ObjectPath<'_>) -> zbus::Result<Vec<(String, String, u64)>>;
+
+    fn restore_item(&self, collection: &ObjectPath<'_>, item: &str) -> zbus::Result<OwnedObjectPath>;
+
+    fn purge_trash(&self, collection: &ObjectPath<'_>, item: &str) -> zbus::Result<u32>;
+
+    fn startup_report(&self) -> zbus::Result<Vec<(String, String, String)>>;
+
+    fn list_duplicates(&self, collection: &ObjectPath<'_>) -> zbus::Result<Vec<Vec<OwnedObjectPath>>>;
+
+    fn purge_duplicates(&self, collection: &ObjectPath<'_>) -> zbus::Result<u32>;
+
+    fn get_item_full(&self, item: &ObjectPath<'_>, session: &ObjectPath<'_>) -> zbus::Result<ItemFull>;
+}
+
+#[proxy(
+    interface = "org.freedesktop.Secret.Service",
+    default_service = "org.freedesktop.secrets",
+    default_path = "/org/freedesktop/secrets"
+)]
+pub trait Service {
+    fn open_session(&self, algorithm: &str, input: &Value<'_>) -> zbus::Result<(OwnedValue, OwnedObjectPath)>;
+
+    #[zbus(property)]
+    fn collections(&self) -> zbus::Result<Vec<OwnedObjectPath>>;
+}
+
+#[proxy(
+    interface = "org.freedesktop.Secret.Collection",
+    default_service = "org.freedesktop.secrets"
+)]
+pub trait Collection {
+    #[zbus(property)]
+    fn label(&self) -> zbus::Result<String>;
+
+    #[zbus(property)]
+    fn items(&self) -> zbus::Result<Vec<OwnedObjectPath>>;
+}
+
+#[proxy(
+    interface = "org.freedesktop.Secret.Item",
+    default_service = "org.freedesktop.secrets"
+)]
+pub trait Item {
+    #[zbus(property)]
+    fn label(&self) -> zbus::Result<String>;
+
+    #[zbus(property)]
+    fn attributes(&self) -> zbus::Result<HashMap<String, String>>;
+}
+
+#[proxy(
+    interface = "me.grimsteel.PassSecretService.Item",
+    default_service = "org.freedesktop.secrets"
+)]
+pub trait ItemExt {
+    fn set_pin(&self) -> zbus::Result<bool>;
+
+    fn label_history(&self) -> zbus::Result<Vec<(String, u64)>>;
+
+    #[zbus(property)]
+    fn last_accessed(&self) -> zbus::Result<u64>;
+
+    #[zbus(property)]
+    fn last_accessed_by(&self) -> zbus::Result<String>;
+}
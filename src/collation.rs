@@ -0,0 +1,47 @@
+use std::{collections::HashMap, ffi::CString};
+
+/// use the collation rules of the user's locale (`LC_COLLATE`)
+/// call this on startup, before anything is sorted
+pub fn init() {
+    unsafe { libc::setlocale(libc::LC_COLLATE, c"".as_ptr()) };
+}
+
+/// a key which sorts in the locale's order when compared bytewise
+pub fn sort_key(string: &str) -> Vec<u8> {
+    let string = CString::new(string.replace('\0', "")).expect("nul bytes were removed");
+
+    let mut key = vec![0u8; string.as_bytes().len() * 2 + 1];
+    loop {
+        let len = unsafe { libc::strxfrm(key.as_mut_ptr().cast(), string.as_ptr(), key.len()) };
+        if len < key.len() {
+            key.truncate(len);
+            return key;
+        }
+        // too short: `len` is how much it needs
+        key.resize(len + 1, 0);
+    }
+}
+
+/// sort ids by their labels, then by id so the order is stable
+pub fn sort_by_label(ids: &mut [String], labels: &HashMap<String, String>) {
+    ids.sort_by_cached_key(|id| {
+        let label = labels.get(id).map_or("", String::as_str);
+        (sort_key(label), id.clone())
+    });
+}
+
+#[test]
+fn test_sort_by_label() {
+    let labels = HashMap::from([
+        ("a".to_owned(), "Work".to_owned()),
+        ("b".to_owned(), "Default".to_owned()),
+        ("c".to_owned(), "Work".to_owned()),
+        ("d".to_owned(), "with\0nul".to_owned()),
+    ]);
+    // no label sorts first
+    let mut ids = ["d", "c", "b", "a", "e"].map(str::to_owned);
+    sort_by_label(&mut ids, &labels);
+
+    // tests run in the C locale, which compares bytes
+    assert_eq!(ids, ["e", "b", "a", "c", "d"]);
+}
@@ -0,0 +1,630 @@
+use std::{
+    collections::HashMap,
+    env,
+    fs::read_to_string,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use serde::Deserialize;
+
+use crate::error::{Error, Result};
+
+#[derive(Deserialize, Debug)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Config {
+    pub notifications: NotificationConfig,
+    /// expose a keyed hash of each secret's value so clients can detect changes.
+    /// off by default since it reveals when two secrets have the same value
+    pub checksums: bool,
+    /// read every secret back after writing it, and fail if it doesn't match. writes through the
+    /// write queue are skipped. off by default since it decrypts everything twice
+    pub verify_writes: bool,
+    /// also write each secret's label and attributes into its pass entry, as `key: value`
+    /// lines after the secret
+    pub embed_metadata: bool,
+    /// how the label and attributes are laid out in entries
+    pub metadata_format: MetadataFormat,
+    /// make gpg-agent forget every cached passphrase when a collection is locked
+    pub forget_password_on_lock: bool,
+    /// let gpg show pinentry while GetSecret waits, instead of locking the collection so the
+    /// client unlocks it with a prompt. for clients which don't handle IsLocked
+    pub pinentry_during_reads: bool,
+    /// never let gpg show pinentry, e.g. on headless machines. secrets can only be decrypted
+    /// (including when unlocking) while gpg-agent has their passphrase cached
+    pub no_interactive: bool,
+    /// derive new items' ids from their attributes (or label), instead of making random ones,
+    /// so their object paths are the same when they're recreated or synced to another machine
+    pub stable_item_ids: bool,
+    /// what CreateItem does (without `replace`) when the collection has an item with exactly the
+    /// same attributes, for clients which add a new copy each time
+    pub duplicates: DuplicateMode,
+    /// keep the dbs with collections' labels and attributes under this directory instead of the
+    /// password store, at the same paths, e.g. so they're synced with a separate git repo
+    pub metadata_dir: Option<PathBuf>,
+    /// keep the dbs in `$XDG_DATA_HOME/pass-secret-service/metadata` instead of the password
+    /// store, e.g. when it's on a network filesystem. `metadata-dir` takes precedence
+    pub metadata_in_tmpfs: bool,
+    /// serve the entries outside the secret service's directory in a read-only collection
+    pub expose_pass_tree: bool,
+    /// also serve the parts of gnome-keyring's private D-Bus API which old clients use
+    pub gnome_keyring_compat: bool,
+    /// on start, import the unencrypted gnome-keyring keyrings in `$XDG_DATA_HOME/keyrings` as
+    /// collections. each keyring is only imported once
+    pub import_keyrings: bool,
+    /// save a report in `$XDG_STATE_HOME/pass-secret-service/crashes` if the daemon panics
+    pub crash_reports: bool,
+    /// attribute keys SearchItems ignores (e.g. `xdg:schema`), for clients which store slightly
+    /// different attributes between versions. they're still matched if nothing else is searched for
+    pub optional_attributes: Vec<String>,
+    /// sort the Collections and Items properties by label, in the locale's order.
+    /// on by default; large collections are faster to list without it
+    pub sort_by_label: bool,
+    /// log D-Bus methods which take at least this many milliseconds, with where the time went.
+    /// 0 (the default) disables it
+    pub slow_request_ms: u64,
+    /// stop working on D-Bus methods which take longer than this many milliseconds, not
+    /// counting time spent waiting for the user. 0 (the default) disables it
+    pub request_deadline_ms: u64,
+    /// whether collections keep an index from each attribute to the secrets which have it
+    pub attribute_index: IndexMode,
+    pub write_queue: WriteQueueConfig,
+    pub secret_cache: SecretCacheConfig,
+    pub trash: TrashConfig,
+    pub expiry: ExpiryConfig,
+    /// rules for proxying collections to other secret stores, checked in order
+    pub passthrough: Vec<PassthroughConfig>,
+    pub mirror: Vec<MirrorConfig>,
+    pub rotation: RotationConfig,
+    /// settings for clients, by executable path or file name
+    pub clients: HashMap<String, ClientConfig>,
+    pub high_security: HighSecurityConfig,
+    /// seconds after a read until gpg-agent forgets the passphrase, by collection id or alias
+    pub passphrase_timeouts: HashMap<String, u64>,
+    /// recipients written to a `.gpg-id` in each new collection's directory, by the alias it's
+    /// created with or `*` for the rest. new collections without one use the store's `.gpg-id`
+    pub new_collection_recipients: HashMap<String, Vec<String>>,
+    /// changes made to secrets as clients read and write them, by collection id or alias, or `*`
+    pub filters: HashMap<String, FilterConfig>,
+    pub ask_password: AskPasswordConfig,
+    pub audit: AuditConfig,
+    #[cfg(feature = "vault")]
+    pub vault: Option<VaultConfig>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            notifications: Default::default(),
+            checksums: false,
+            verify_writes: false,
+            embed_metadata: false,
+            metadata_format: Default::default(),
+            forget_password_on_lock: false,
+            pinentry_during_reads: false,
+            no_interactive: false,
+            stable_item_ids: false,
+            duplicates: Default::default(),
+            metadata_dir: None,
+            metadata_in_tmpfs: false,
+            expose_pass_tree: false,
+            gnome_keyring_compat: false,
+            import_keyrings: false,
+            crash_reports: false,
+            optional_attributes: vec![],
+            sort_by_label: true,
+            slow_request_ms: 0,
+            request_deadline_ms: 0,
+            attribute_index: Default::default(),
+            write_queue: Default::default(),
+            secret_cache: Default::default(),
+            trash: Default::default(),
+            expiry: Default::default(),
+            passthrough: vec![],
+            mirror: vec![],
+            rotation: Default::default(),
+            clients: HashMap::new(),
+            high_security: Default::default(),
+            passphrase_timeouts: HashMap::new(),
+            new_collection_recipients: HashMap::new(),
+            filters: HashMap::new(),
+            ask_password: Default::default(),
+            audit: Default::default(),
+            #[cfg(feature = "vault")]
+            vault: None,
+        }
+    }
+}
+
+/// which events should show a desktop notification
+#[derive(Deserialize, Default, Debug)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct NotificationConfig {
+    /// an application reads a secret for the first time
+    pub new_client: bool,
+    pub item_deleted: bool,
+    pub collection_deleted: bool,
+    /// gpg failed to decrypt a secret
+    pub decrypt_failed: bool,
+    /// a secret is about to expire
+    pub item_expiring: bool,
+    /// a git pull changed a collection's secrets
+    pub git_pull: bool,
+}
+
+/// warnings about secrets with an expiry attribute
+#[derive(Deserialize, Debug)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ExpiryConfig {
+    /// warn about secrets which expire within this many days
+    pub warn_days: u32,
+    /// how often to check for expiring secrets. 0 disables checking
+    pub scan_interval_hours: u32,
+}
+
+impl Default for ExpiryConfig {
+    fn default() -> Self {
+        Self {
+            warn_days: 7,
+            scan_interval_hours: 6,
+        }
+    }
+}
+
+/// re-encrypting secrets which haven't been written for a while when they're next read,
+/// so they pick up new recipients and algorithms
+#[derive(Deserialize, Default, Debug)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct RotationConfig {
+    /// re-encrypt secrets older than this many months (of 30 days). 0 disables rotation
+    pub max_age_months: u32,
+    /// overrides for collections, by id or alias
+    pub collections: HashMap<String, u32>,
+}
+
+impl RotationConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.max_age_months > 0 || self.collections.values().any(|months| *months > 0)
+    }
+
+    /// the policy for a collection with the given aliases
+    pub fn max_age_months(&self, collection_id: &str, aliases: &[String]) -> u32 {
+        self.collections
+            .get(collection_id)
+            .or_else(|| aliases.iter().find_map(|alias| self.collections.get(alias)))
+            .copied()
+            .unwrap_or(self.max_age_months)
+    }
+}
+
+/// when a collection keeps its attribute index. without one, searches check every secret
+#[derive(Deserialize, Default, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum IndexMode {
+    /// only for collections with enough secrets that checking each one is slow
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+/// what happens to an item created with the same attributes as an existing one
+#[derive(Deserialize, Default, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum DuplicateMode {
+    /// create another item, as the spec says
+    #[default]
+    Keep,
+    /// update the newest existing item instead, as if the client had passed `replace`
+    Replace,
+    /// create another item, with a `pass:version` attribute one higher than the newest one's
+    Version,
+}
+
+/// how a secret's label and attributes are written into its entry
+#[derive(Deserialize, Default, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum MetadataFormat {
+    /// `key: value` lines after the secret, like browserpass reads them. secrets can only have
+    /// one line
+    #[default]
+    Browserpass,
+    /// gopass's key-value format: the first line of the secret, `key: value` lines, then the rest
+    /// of the secret after a blank line
+    Gopass,
+}
+
+/// how the user confirms reads from high-security collections
+#[derive(Deserialize, Default, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum GateMethod {
+    /// enter the PIN set with `pass-secret-service set-pin`
+    #[default]
+    Pin,
+    /// run a program which exits successfully once the user confirms,
+    /// e.g. one which waits for a FIDO2 user presence check
+    Command,
+}
+
+/// asking the user to confirm before secrets in some collections are read,
+/// whether or not gpg-agent has the key cached
+#[derive(Deserialize, Default, Debug)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct HighSecurityConfig {
+    /// ids or aliases of the collections. `*` matches every collection
+    pub collections: Vec<String>,
+    pub method: GateMethod,
+    /// the program and arguments for the `command` method
+    pub command: Vec<String>,
+    /// pinentry program for the `pin` method. defaults to `pinentry`
+    pub pinentry: Option<String>,
+    /// don't ask again for reads from the same collection within this many seconds
+    pub grace_seconds: u64,
+}
+
+impl HighSecurityConfig {
+    pub fn pinentry(&self) -> &str {
+        self.pinentry.as_deref().unwrap_or("pinentry")
+    }
+}
+
+/// a change made to a secret's value
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Filter {
+    /// remove one trailing `\n` (or `\r\n`)
+    StripTrailingNewline,
+    /// add a `\n` if there isn't one, like `pass insert` does
+    AddTrailingNewline,
+    /// remove leading and trailing whitespace
+    Trim,
+}
+
+/// the filters applied to a collection's text secrets, in order
+#[derive(Deserialize, Default, Debug)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct FilterConfig {
+    /// applied to secrets returned to clients
+    pub read: Vec<Filter>,
+    /// applied to secrets from clients before they're stored
+    pub write: Vec<Filter>,
+}
+
+/// answering password requests from system services (`systemd-ask-password`) with secrets
+#[derive(Deserialize, Default, Debug)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct AskPasswordConfig {
+    /// directories to watch for requests. defaults to `$XDG_RUNTIME_DIR/systemd/ask-password`
+    pub directories: Vec<PathBuf>,
+    /// run to answer requests whose socket we can't write to, with `1 <socket>` appended
+    /// and the password on stdin, e.g. `["pkexec", "/usr/lib/systemd/systemd-reply-password"]`
+    pub reply_command: Vec<String>,
+    /// the only requests which are answered. the agent is disabled if there are none
+    pub answer: Vec<AskPasswordRule>,
+}
+
+impl AskPasswordConfig {
+    pub fn directories(&self) -> Vec<PathBuf> {
+        if !self.directories.is_empty() {
+            return self.directories.clone();
+        }
+        env::var_os("XDG_RUNTIME_DIR")
+            .map(|runtime_dir| Path::new(&runtime_dir).join("systemd").join("ask-password"))
+            .into_iter()
+            .collect()
+    }
+}
+
+/// a password request which is answered with a secret
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct AskPasswordRule {
+    /// the request's `Id`, e.g. `cryptsetup:/dev/sda2`
+    pub id: String,
+    /// attributes of the item to answer with. exactly one item has to match
+    pub attributes: HashMap<String, String>,
+}
+
+/// settings for a client which doesn't choose collections itself
+#[derive(Deserialize, Default, Debug)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ClientConfig {
+    /// id or alias of the collection its items go to when it creates them in the default collection
+    pub default_collection: Option<String>,
+    /// ids or aliases of the collections its searches look in. empty searches every collection
+    pub search: Vec<String>,
+}
+
+/// copying the secrets of some collections into another password store
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct MirrorConfig {
+    /// root of the other password store, which needs its own `.gpg-id`
+    pub directory: PathBuf,
+    /// ids or aliases of the collections to mirror. `*` matches every collection
+    pub collections: Vec<String>,
+    /// how often to make sure the mirror matches the store. 0 only checks on startup
+    #[serde(default = "MirrorConfig::default_reconcile_interval")]
+    pub reconcile_interval_hours: u32,
+}
+
+impl MirrorConfig {
+    fn default_reconcile_interval() -> u32 {
+        24
+    }
+}
+
+/// which kind of daemon a passthrough rule proxies to
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub enum PassthroughBackend {
+    /// another org.freedesktop.Secret.Service implementation, like gnome-keyring
+    SecretService,
+    Kwallet,
+}
+
+/// keeping some collections in another running secret store
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct PassthroughConfig {
+    pub backend: PassthroughBackend,
+    /// defaults to `org.gnome.keyring` for secret-service and `org.kde.kwalletd6` for kwallet
+    pub bus_name: Option<String>,
+    /// secret-service: alias or object path of the collection to store secrets in (default `default`)
+    /// kwallet: the wallet to store secrets in (default the network wallet)
+    pub target: Option<String>,
+    /// ids or aliases of the collections to proxy. `*` matches every collection
+    pub collections: Vec<String>,
+}
+
+/// keeping some collections in a HashiCorp Vault or OpenBao KV v2 engine
+#[cfg(feature = "vault")]
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct VaultConfig {
+    /// e.g. `https://vault.example.com:8200`
+    pub address: String,
+    /// file containing the token to authenticate with
+    pub token_file: PathBuf,
+    /// vault enterprise namespace
+    pub namespace: Option<String>,
+    /// where the KV v2 engine is mounted
+    #[serde(default = "VaultConfig::default_mount")]
+    pub mount: String,
+    /// secrets are kept under `<prefix>/<collection id>/` in the engine
+    #[serde(default = "VaultConfig::default_prefix")]
+    pub prefix: String,
+    /// ids or aliases of the collections to keep in vault. `*` matches every collection
+    pub collections: Vec<String>,
+    /// a mount and prefix for some collections, by id or alias, instead of the ones above
+    #[serde(default)]
+    pub locations: HashMap<String, VaultLocationConfig>,
+}
+
+/// where a collection's secrets are kept in vault. unset ones are taken from `[vault]`
+#[cfg(feature = "vault")]
+#[derive(Deserialize, Default, Debug)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct VaultLocationConfig {
+    pub mount: Option<String>,
+    pub prefix: Option<String>,
+}
+
+#[cfg(feature = "vault")]
+impl VaultConfig {
+    fn default_mount() -> String {
+        "secret".into()
+    }
+
+    fn default_prefix() -> String {
+        "pass-secret-service".into()
+    }
+}
+
+/// an append-only log of the operations clients make on secrets
+#[derive(Deserialize, Debug)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct AuditConfig {
+    /// where to write it, as JSON lines. relative paths are in
+    /// `$XDG_STATE_HOME/pass-secret-service`. not set (the default) disables it
+    pub path: Option<PathBuf>,
+    /// rotate the log once it's this many KiB. 0 never rotates it
+    pub max_size_kb: u64,
+    /// how many rotated logs to keep, as `<path>.1` (the newest) to `<path>.<keep>`
+    pub keep: u32,
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        Self {
+            path: None,
+            max_size_kb: 10 * 1024,
+            keep: 5,
+        }
+    }
+}
+
+impl AuditConfig {
+    /// the full path of the log, if it's enabled
+    pub fn path(&self) -> Option<PathBuf> {
+        let path = self.path.as_ref()?;
+        if path.is_absolute() {
+            Some(path.clone())
+        } else {
+            Some(state_dir()?.join(path))
+        }
+    }
+}
+
+/// retrying writes which fail while the store or gpg is unavailable
+#[derive(Deserialize, Debug)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct WriteQueueConfig {
+    pub enabled: bool,
+    /// writes fail once this many are pending
+    pub max_pending: usize,
+    /// where encrypted pending writes are kept until they succeed
+    /// defaults to `$XDG_STATE_HOME/pass-secret-service/spool`
+    pub spool_dir: Option<PathBuf>,
+}
+
+impl Default for WriteQueueConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_pending: 64,
+            spool_dir: None,
+        }
+    }
+}
+
+impl WriteQueueConfig {
+    /// the configured spool dir, or the default one
+    pub fn spool_dir(&self) -> Option<PathBuf> {
+        self.spool_dir
+            .clone()
+            .or_else(|| Some(state_dir()?.join("spool")))
+    }
+}
+
+/// keeping decrypted secrets in memory, so reading them again doesn't run gpg
+#[derive(Deserialize, Debug)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct SecretCacheConfig {
+    /// how long a decrypted secret is kept. 0 (the default) disables the cache
+    pub ttl_seconds: u64,
+    pub max_entries: usize,
+}
+
+impl Default for SecretCacheConfig {
+    fn default() -> Self {
+        Self {
+            ttl_seconds: 0,
+            max_entries: 32,
+        }
+    }
+}
+
+/// keeping deleted items for a while, so ones a client deleted by mistake can be restored
+#[derive(Deserialize, Default, Debug)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct TrashConfig {
+    /// how many days deleted items are kept before they're purged. 0 (the default) deletes
+    /// them right away
+    pub retention_days: u32,
+}
+
+/// `$XDG_STATE_HOME/pass-secret-service`
+fn state_dir() -> Option<PathBuf> {
+    let state_home = env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".local").join("state")))?;
+
+    Some(state_home.join("pass-secret-service"))
+}
+
+/// `$XDG_DATA_HOME/pass-secret-service`
+fn data_dir() -> Option<PathBuf> {
+    let data_home = env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".local").join("share")))?;
+
+    Some(data_home.join("pass-secret-service"))
+}
+
+/// whether a collection matches a list of ids, aliases, or `*`
+pub fn matches_collection(patterns: &[String], collection_id: &str, aliases: &[String]) -> bool {
+    patterns
+        .iter()
+        .any(|pattern| pattern == "*" || pattern == collection_id || aliases.contains(pattern))
+}
+
+impl Config {
+    /// the settings for a client executable, matched by path and then by file name
+    pub fn client(&self, exe: &str) -> Option<&ClientConfig> {
+        self.clients.get(exe).or_else(|| {
+            let name = Path::new(exe).file_name()?.to_str()?;
+            self.clients.get(name)
+        })
+    }
+
+    /// how long gpg-agent can cache passphrases used for a collection, if it has a timeout
+    pub fn passphrase_timeout(&self, collection_id: &str, aliases: &[String]) -> Option<Duration> {
+        self.passphrase_timeouts
+            .get(collection_id)
+            .or_else(|| aliases.iter().find_map(|alias| self.passphrase_timeouts.get(alias)))
+            .map(|seconds| Duration::from_secs(*seconds))
+    }
+
+    /// the recipients for the `.gpg-id` of a new collection created with the given alias
+    pub fn new_collection_recipients(&self, alias: Option<&str>) -> Option<&[String]> {
+        alias
+            .and_then(|alias| self.new_collection_recipients.get(alias))
+            .or_else(|| self.new_collection_recipients.get("*"))
+            .map(Vec::as_slice)
+    }
+
+    /// the filters configured for a collection with the given aliases, if any
+    pub fn filters(&self, collection_id: &str, aliases: &[String]) -> Option<&FilterConfig> {
+        self.filters
+            .get(collection_id)
+            .or_else(|| aliases.iter().find_map(|alias| self.filters.get(alias)))
+            .or_else(|| self.filters.get("*"))
+    }
+
+    /// the attributes SearchItems matches: the query without its optional keys, unless that
+    /// would leave nothing to match
+    pub fn search_attributes(&self, mut attributes: HashMap<String, String>) -> HashMap<String, String> {
+        if attributes.keys().any(|key| !self.optional_attributes.contains(key)) {
+            attributes.retain(|key, _| !self.optional_attributes.contains(key));
+        }
+        attributes
+    }
+
+    /// where the dbs are kept, if not in the password store
+    pub fn metadata_dir(&self) -> Option<PathBuf> {
+        self.metadata_dir.clone().or_else(|| {
+            self.metadata_in_tmpfs
+                .then(data_dir)
+                .flatten()
+                .map(|dir| dir.join("metadata"))
+        })
+    }
+
+    /// where crash reports are saved, if they're enabled
+    pub fn crash_report_dir(&self) -> Option<PathBuf> {
+        self.crash_reports
+            .then(state_dir)
+            .flatten()
+            .map(|dir| dir.join("crashes"))
+    }
+
+    /// the default config location: `$XDG_CONFIG_HOME/pass-secret-service/config.toml`
+    pub fn default_path() -> Option<PathBuf> {
+        let config_home = env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+
+        Some(config_home.join("pass-secret-service").join("config.toml"))
+    }
+
+    /// load the config from the given path, or the default location
+    /// a missing file at the default location is not an error
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let (path, is_default) = match path {
+            Some(path) => (path.to_owned(), false),
+            None => match Self::default_path() {
+                Some(path) => (path, true),
+                None => return Ok(Self::default()),
+            },
+        };
+
+        match read_to_string(&path) {
+            Ok(contents) => toml::from_str(&contents)
+                .map_err(|e| Error::Config(format!("{}: {e}", path.display()))),
+            Err(e) if e.kind() == ErrorKind::NotFound && is_default => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
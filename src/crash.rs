@@ -0,0 +1,167 @@
+use std::{
+    any::Any,
+    backtrace::Backtrace,
+    cell::Cell,
+    fs::{DirBuilder, OpenOptions},
+    io::{self, Write},
+    os::unix::fs::{DirBuilderExt, OpenOptionsExt},
+    panic,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        OnceLock,
+    },
+    thread,
+    time::SystemTime,
+};
+
+use log::error;
+use tokio::{sync::Notify, task::spawn_blocking};
+
+use crate::{
+    deadline,
+    error::{Error, Result},
+    timing::{self, Phase},
+};
+
+/// notified when something panics
+static PANICKED: OnceLock<Notify> = OnceLock::new();
+/// only the first panic gets a report: later ones are usually caused by it
+static REPORTED: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    /// whether a panic on this thread is turned into an error for a single request
+    static CONTAINED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// marks the current thread as contained until dropped, even when unwinding
+struct ContainedGuard;
+
+impl ContainedGuard {
+    fn enter() -> Self {
+        CONTAINED.with(|contained| contained.set(true));
+        Self
+    }
+}
+
+impl Drop for ContainedGuard {
+    fn drop(&mut self) {
+        CONTAINED.with(|contained| contained.set(false));
+    }
+}
+
+fn panicked() -> &'static Notify {
+    PANICKED.get_or_init(Notify::new)
+}
+
+/// the text a panic was raised with
+/// nothing in this codebase formats secret values into a panic
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("<non-string payload>")
+}
+
+fn write_report(dir: &Path, thread: &str, location: &str, message: &str) -> io::Result<PathBuf> {
+    DirBuilder::new().recursive(true).mode(0o700).create(dir)?;
+
+    let time = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let path = dir.join(format!("crash-{time}-{}.txt", std::process::id()));
+
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(&path)?;
+    writeln!(file, "pass-secret-service {}", env!("CARGO_PKG_VERSION"))?;
+    writeln!(file, "time: {time}")?;
+    writeln!(file, "thread: {thread}")?;
+    writeln!(file, "location: {location}")?;
+    writeln!(file, "message: {message}")?;
+    // only function names and source locations, never values
+    writeln!(file, "\n{}", Backtrace::force_capture())?;
+
+    Ok(path)
+}
+
+/// log panics without the default stderr output and save a crash report to `report_dir`
+/// if given. panics outside of [`run_blocking`] wake up [`wait_for_panic`] so the daemon
+/// can shut down cleanly
+pub fn install_panic_hook(report_dir: Option<PathBuf>) {
+    panic::set_hook(Box::new(move |info| {
+        let current = thread::current();
+        let thread = current.name().unwrap_or("<unnamed>");
+        let location = info
+            .location()
+            .map(|l| format!("{}:{}:{}", l.file(), l.line(), l.column()))
+            .unwrap_or_default();
+        let message = panic_message(info.payload());
+        let fatal = !CONTAINED.with(Cell::get);
+
+        error!("panic thread={thread:?} location={location:?} message={message:?} fatal={fatal}");
+
+        if !REPORTED.swap(true, Ordering::SeqCst) {
+            if let Some(dir) = &report_dir {
+                match write_report(dir, thread, &location, message) {
+                    Ok(path) => error!(
+                        "Saved a crash report to {}. Please attach it if you report this issue",
+                        path.display()
+                    ),
+                    Err(e) => error!("Could not save a crash report: {e}"),
+                }
+            }
+        }
+
+        if fatal {
+            panicked().notify_one();
+        }
+    }));
+}
+
+/// run blocking work (like a database transaction) on the blocking thread pool
+/// it's timed as database work, unless it's part of another phase
+/// a panic only fails this call, with [`Error::Panicked`]
+pub async fn run_blocking<F, T>(f: F) -> Result<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    // so long loops can stop when the request runs out of time
+    let deadline = deadline::current();
+    let task = spawn_blocking(move || {
+        let _contained = ContainedGuard::enter();
+        let _deadline = deadline::enter_blocking(deadline);
+        f()
+    });
+    timing::phase(Phase::Database, task).await.map_err(|e| match e.try_into_panic() {
+        Ok(payload) => Error::Panicked(panic_message(&*payload).to_owned()),
+        Err(e) => Error::Panicked(e.to_string()),
+    })
+}
+
+/// wait until something panics
+pub async fn wait_for_panic() {
+    panicked().notified().await
+}
+
+#[test]
+fn test_panic_message() {
+    let message = |payload: Box<dyn Any + Send>| panic_message(&*payload).to_owned();
+
+    assert_eq!(message(Box::new("static")), "static");
+    assert_eq!(message(Box::new(String::from("formatted"))), "formatted");
+    assert_eq!(message(Box::new(42)), "<non-string payload>");
+}
+
+#[tokio::test]
+async fn test_run_blocking_panic() {
+    assert_eq!(run_blocking(|| 1).await.unwrap(), 1);
+
+    let result = run_blocking(|| -> u32 { panic!("boom") }).await;
+    assert!(matches!(result, Err(Error::Panicked(message)) if message == "boom"));
+}
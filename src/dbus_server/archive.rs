@@ -0,0 +1,170 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::Read,
+    path::Path,
+    sync::Arc,
+};
+
+use log::{info, warn};
+use tokio::io::AsyncWriteExt;
+
+use crate::{
+    error::{Error, Result},
+    secret_store::{slugify, SecretStore},
+};
+
+use super::metadata::{collection_metadata, optional, CollectionMetadata, ItemMetadata};
+
+/// the collection's labels, attributes, and aliases in an archive, as `export-metadata` writes them
+const METADATA_ENTRY: &str = "metadata.yaml";
+/// the directory of the secrets in an archive, each in a file named after its item id
+const SECRETS_DIR: &str = "secrets";
+
+/// add a file to a tar archive being built in memory
+fn append_file(builder: &mut tar::Builder<Vec<u8>>, path: &str, contents: &[u8]) -> Result {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(contents.len() as u64);
+    header.set_mode(0o600);
+    header.set_cksum();
+    builder.append_data(&mut header, path, contents)?;
+    Ok(())
+}
+
+/// bundle a collection's metadata and decrypted secrets into a tarball, encrypted with gpg to
+/// the collection's `.gpg-id`, and write it to a new file at `path`. like GetSecret, nothing is
+/// read from a locked collection, and high-security collections and items with PINs are
+/// confirmed first
+/// returns the number of items which were exported
+pub async fn export_archive(store: &SecretStore<'_>, collection_id: Arc<String>, path: &Path) -> Result<u32> {
+    if store.is_locked(&collection_id) {
+        return Err(Error::IsLocked);
+    }
+    let metadata = collection_metadata(store, collection_id.clone()).await?;
+    store.confirm_read(&collection_id).await?;
+    for secret_id in metadata.items.keys() {
+        store.confirm_item_read(&collection_id, secret_id).await?;
+    }
+
+    let mut builder = tar::Builder::new(vec![]);
+    let yaml = serde_yaml::to_string(&metadata).expect("the metadata can be serialized");
+    append_file(&mut builder, METADATA_ENTRY, yaml.as_bytes())?;
+    for secret_id in metadata.items.keys() {
+        let secret = store.read_secret(&collection_id, secret_id, true).await?;
+        append_file(&mut builder, &format!("{SECRETS_DIR}/{secret_id}"), &secret)?;
+    }
+    let archive = builder.into_inner()?;
+
+    let encrypted = store.encrypt_for(&collection_id, archive).await?;
+    // never through an existing file (or a symlink planted in its place)
+    let mut file = tokio::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)
+        .await?;
+    file.write_all(&encrypted).await?;
+    file.flush().await?;
+    Ok(metadata.items.len() as u32)
+}
+
+/// read the metadata and secrets (by item id) out of a decrypted archive
+fn read_archive(archive: &[u8]) -> Result<(CollectionMetadata, HashMap<String, Vec<u8>>)> {
+    let invalid = |e: &dyn std::fmt::Display| Error::InvalidMetadata(format!("invalid archive: {e}"));
+
+    let mut metadata = None;
+    let mut secrets = HashMap::new();
+    for entry in tar::Archive::new(archive).entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.to_string_lossy().into_owned();
+        let mut contents = vec![];
+        entry.read_to_end(&mut contents)?;
+
+        if path == METADATA_ENTRY {
+            let yaml = String::from_utf8(contents).map_err(|e| invalid(&e))?;
+            metadata = Some(serde_yaml::from_str::<CollectionMetadata>(&yaml).map_err(|e| invalid(&e))?);
+        } else if let Some(secret_id) = path.strip_prefix(&format!("{SECRETS_DIR}/")) {
+            secrets.insert(secret_id.to_owned(), contents);
+        }
+    }
+
+    let metadata = metadata.ok_or_else(|| invalid(&format!("it has no {METADATA_ENTRY}")))?;
+    if let Some(missing) = metadata.items.keys().find(|id| !secrets.contains_key(*id)) {
+        return Err(invalid(&format!("item {missing} has no secret")));
+    }
+    Ok((metadata, secrets))
+}
+
+/// decrypt an archive written by `export_archive` and create a new collection from it. aliases
+/// which are already taken are skipped
+/// returns the new collection's id and number of items
+pub async fn import_archive(store: &SecretStore<'_>, path: &Path) -> Result<(String, u32)> {
+    let archive = store.decrypt(tokio::fs::read(path).await?).await?;
+    let (metadata, mut secrets) = read_archive(&archive)?;
+
+    let collection_id = Arc::new(store.create_collection(Some(metadata.label.clone()), None).await?);
+    if let Err(e) = import_items(store, &collection_id, &metadata.items, &mut secrets).await {
+        // remove a partial import, so it can be run again
+        store.delete_collection(collection_id).await?;
+        return Err(e);
+    }
+    if metadata.sort_order.is_some() {
+        store.set_sort_order(collection_id.clone(), metadata.sort_order).await?;
+    }
+    if metadata.color.is_some() {
+        store.set_color(collection_id.clone(), metadata.color).await?;
+    }
+
+    for alias in &metadata.aliases {
+        let alias = Arc::new(slugify(alias));
+        match optional(store.get_alias(alias.clone()).await)? {
+            Some(target) => warn!("Not setting alias {alias} for collection {collection_id}, since it's already {target}"),
+            None => store.set_alias(alias, Some(collection_id.to_string())).await?,
+        }
+    }
+
+    let count = metadata.items.len() as u32;
+    info!("Imported {count} item(s) from {} into collection {collection_id}", path.display());
+    Ok((collection_id.to_string(), count))
+}
+
+async fn import_items(
+    store: &SecretStore<'_>,
+    collection_id: &Arc<String>,
+    items: &BTreeMap<String, ItemMetadata>,
+    secrets: &mut HashMap<String, Vec<u8>>,
+) -> Result {
+    for (secret_id, item) in items {
+        let secret = secrets.remove(secret_id).unwrap_or_default();
+        store
+            .create_secret(
+                collection_id.clone(),
+                item.label.clone(),
+                secret,
+                Arc::new(HashMap::from_iter(item.attributes.clone())),
+            )
+            .await?;
+    }
+    Ok(())
+}
+
+#[test]
+fn test_read_archive() {
+    let mut builder = tar::Builder::new(vec![]);
+    append_file(&mut builder, METADATA_ENTRY, b"label: Work\nitems:\n  abc:\n    label: Mail\n").unwrap();
+    append_file(&mut builder, "secrets/abc", b"hunter2\n").unwrap();
+    let archive = builder.into_inner().unwrap();
+
+    let (metadata, secrets) = read_archive(&archive).unwrap();
+    assert_eq!(metadata.label, "Work");
+    assert_eq!(metadata.items["abc"].label.as_deref(), Some("Mail"));
+    assert_eq!(secrets["abc"], b"hunter2\n");
+
+    // every item needs its secret
+    let mut builder = tar::Builder::new(vec![]);
+    append_file(&mut builder, METADATA_ENTRY, b"label: Work\nitems:\n  abc: {}\n").unwrap();
+    assert!(read_archive(&builder.into_inner().unwrap()).is_err());
+
+    let mut builder = tar::Builder::new(vec![]);
+    append_file(&mut builder, "secrets/abc", b"hunter2\n").unwrap();
+    assert!(read_archive(&builder.into_inner().unwrap()).is_err());
+}
@@ -0,0 +1,59 @@
+use log::warn;
+use tokio::sync::broadcast::error::RecvError;
+use zbus::{object_server::SignalContext, zvariant::ObjectPath, Connection};
+
+use crate::secret_store::{ChangeKind, SecretStore};
+
+use super::{
+    manager::Manager,
+    utils::{collection_path, secret_path},
+};
+
+/// emit StoreChanged for every change the store makes, until the daemon exits.
+/// also emits ItemChanged on the collection when an item changes, however it was changed
+/// (e.g. by setting its Label or Attributes property, which doesn't have the connection)
+pub async fn publish_changes(connection: Connection, store: SecretStore<'static>) {
+    let mut changes = store.subscribe_changes();
+    let ctxt = SignalContext::new(&connection, "/org/freedesktop/secrets").unwrap();
+    loop {
+        let change = match changes.recv().await {
+            Ok(change) => change,
+            Err(RecvError::Lagged(skipped)) => {
+                warn!("Dropped {skipped} StoreChanged signals");
+                continue;
+            }
+            Err(RecvError::Closed) => return,
+        };
+
+        let Some(collection) = collection_path(&change.collection_id) else {
+            continue;
+        };
+        // "/" for changes to the collection itself
+        let item = match &change.secret_id {
+            Some(secret_id) => secret_path(&change.collection_id, secret_id),
+            None => Some(ObjectPath::from_static_str_unchecked("/")),
+        };
+        let Some(item) = item else {
+            continue;
+        };
+
+        if change.secret_id.is_some() && matches!(change.kind, ChangeKind::Changed) {
+            let result = connection
+                .emit_signal(
+                    Option::<String>::None,
+                    &collection,
+                    "org.freedesktop.Secret.Collection",
+                    "ItemChanged",
+                    &(&item,),
+                )
+                .await;
+            if let Err(e) = result {
+                warn!("Could not emit ItemChanged: {e}");
+            }
+        }
+
+        if let Err(e) = Manager::store_changed(&ctxt, collection, item, change.kind.as_str()).await {
+            warn!("Could not emit StoreChanged: {e}");
+        }
+    }
+}
@@ -1,5 +1,10 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    io,
+    sync::Arc,
+};
 
+use log::warn;
 use zbus::{
     fdo, interface,
     message::Header,
@@ -9,23 +14,63 @@ use zbus::{
 };
 
 use crate::{
+    audit::{self, Operation},
+    config::DuplicateMode,
     error::{Error, Result},
-    secret_store::SecretStore,
+    secret_store::{keep_reserved, secret_version, SecretStore, VERSION_ATTRIBUTE},
+    timing,
 };
 
 use super::{
-    item::Item, session::Session, utils::{
-        alias_path, collection_path, secret_alias_path, secret_path, time_to_int, try_interface,
+    item::Item, manager::Manager, notifications::{notify, Event}, session::Session, utils::{
+        alias_path, client_config, client_exe, collection_path, path_secret_id, secret_alias_path, secret_path, time_to_int, try_interface,
         Secret, EMPTY_PATH,
     }
 };
 
+const ITEM_LABEL_PROPERTY: &str = "org.freedesktop.Secret.Item.Label";
+const ITEM_ATTRIBUTES_PROPERTY: &str = "org.freedesktop.Secret.Item.Attributes";
+
 #[derive(Clone, Debug)]
 pub struct Collection<'a> {
     pub store: SecretStore<'a>,
     pub id: Arc<String>,
 }
 
+/// the label and attributes of a new item, from CreateItem's properties
+/// values of the wrong type are rejected instead of ignored, since an item whose attributes
+/// were dropped could never be found again
+fn item_properties(properties: &HashMap<String, Value<'_>>) -> Result<(Option<String>, HashMap<String, String>)> {
+    let label = properties
+        .get(ITEM_LABEL_PROPERTY)
+        .map(|label| {
+            label.downcast_ref::<String>().map_err(|_| {
+                Error::InvalidArgs(format!(
+                    "{ITEM_LABEL_PROPERTY} must be a string, not {}",
+                    label.value_signature()
+                ))
+            })
+        })
+        .transpose()?;
+    let attributes = properties
+        .get(ITEM_ATTRIBUTES_PROPERTY)
+        .map(|attributes| {
+            attributes
+                .downcast_ref::<Dict>()
+                .ok()
+                .and_then(|a| HashMap::<String, String>::try_from(a).ok())
+                .ok_or_else(|| {
+                    Error::InvalidArgs(format!(
+                        "{ITEM_ATTRIBUTES_PROPERTY} must be a dict of strings (a{{ss}}), not {}",
+                        attributes.value_signature()
+                    ))
+                })
+        })
+        .transpose()?
+        .unwrap_or_default();
+    Ok((label, attributes))
+}
+
 impl<'a> Collection<'a> {
     fn make_item(&self, id: String) -> Item<'a> {
         Item {
@@ -36,18 +81,43 @@ impl<'a> Collection<'a> {
     }
 }
 
-#[interface(name = "org.freedesktop.Secret.Collection")]
 impl Collection<'static> {
-    async fn delete(
-        &self,
-        #[zbus(connection)] connection: &Connection,
-        #[zbus(object_server)] object_server: &ObjectServer,
-    ) -> Result<ObjectPath> {
-        let secrets = self.store.list_secrets(&*self.id).await?;
+    /// add this collection and its extension interface to the object server
+    pub async fn register(&self, object_server: &ObjectServer, path: &ObjectPath<'_>) -> Result {
+        object_server.at(path, self.clone()).await?;
+        object_server
+            .at(
+                path,
+                CollectionExt {
+                    store: self.store.clone(),
+                    id: self.id.clone(),
+                },
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// notify clients that a collection was locked or unlocked
+    pub async fn emit_locked_changed(object_server: &ObjectServer, collection_id: &str) -> Result {
+        if let Some(path) = collection_path(collection_id) {
+            if let Some(iface) = try_interface(object_server.interface::<_, Self>(&path).await)? {
+                iface.get().await.locked_changed(iface.signal_context()).await?;
+            }
+        }
+        Ok(())
+    }
 
+    /// remove this collection, its aliases, and its secrets from the object server
+    /// the alias lock should be held while calling this. returns the aliases
+    pub(super) async fn remove_objects(
+        &self,
+        connection: &Connection,
+        object_server: &ObjectServer,
+        secrets: &[String],
+    ) -> Result<Vec<String>> {
         // remove this collection from the object server
         if let Some(path) = collection_path(&*self.id) {
-            try_interface(object_server.remove::<Self, _>(&path).await)?;
+            Self::unregister(object_server, &path).await?;
 
             // emit the collection deleted event
             connection
@@ -60,105 +130,208 @@ impl Collection<'static> {
                 )
                 .await?;
         }
-        for secret in &secrets {
+        for secret in secrets {
             if let Some(path) = secret_path(&*self.id, secret) {
-                try_interface(object_server.remove::<Item, _>(path).await)?;
+                Item::unregister(object_server, &path).await?;
             }
         }
         // remove all aliases
-        for alias in self
+        let aliases = self
             .store
             .list_aliases_for_collection(self.id.clone())
-            .await?
-        {
-            if let Some(path) = alias_path(&alias) {
-                try_interface(object_server.remove::<Self, _>(path).await)?;
+            .await?;
+        for alias in &aliases {
+            if let Some(path) = alias_path(alias) {
+                Self::unregister(object_server, &path).await?;
             }
-            for secret in &secrets {
-                if let Some(path) = secret_alias_path(&alias, secret) {
-                    try_interface(object_server.remove::<Item, _>(path).await)?;
+            for secret in secrets {
+                if let Some(path) = secret_alias_path(alias, secret) {
+                    Item::unregister(object_server, &path).await?;
                 }
             }
         }
+        Ok(aliases)
+    }
 
-        // delete the collection from the store
-        self.store.delete_collection(self.id.clone()).await?;
+    /// tell clients an alias points at collection `new` instead of `old`. None if it's unset
+    pub async fn emit_alias_changed(
+        connection: &Connection,
+        alias: &str,
+        old: Option<&str>,
+        new: Option<&str>,
+    ) -> Result {
+        let ctxt = SignalContext::new(connection, "/org/freedesktop/secrets")?;
+        let old = old.and_then(collection_path).unwrap_or(EMPTY_PATH);
+        let new = new.and_then(collection_path).unwrap_or(EMPTY_PATH);
+        Manager::alias_changed(&ctxt, alias, old, new).await?;
+        Ok(())
+    }
 
-        Ok(EMPTY_PATH)
+    /// tell clients these aliases of a collection no longer point anywhere
+    pub async fn emit_aliases_removed(
+        connection: &Connection,
+        collection_id: &str,
+        aliases: &[String],
+    ) -> Result {
+        for alias in aliases {
+            Self::emit_alias_changed(connection, alias, Some(collection_id), None).await?;
+        }
+        Ok(())
     }
 
-    async fn search_items(&self, attributes: HashMap<String, String>) -> Result<Vec<ObjectPath>> {
-        let items = self
-            .store
-            .search_collection(self.id.clone(), Arc::new(attributes))
-            .await?;
-        let paths = items.into_iter().filter_map(|item| secret_path(&*self.id, &item)).collect();
+    /// if this collection's directory was removed externally, forget about it
+    /// returns whether it still exists
+    pub async fn resync_if_deleted(
+        &self,
+        connection: &Connection,
+        object_server: &ObjectServer,
+    ) -> Result<bool> {
+        if self.store.collection_exists(&self.id).await? {
+            return Ok(true);
+        }
+
+        warn!("The directory of collection {} was removed externally", self.id);
+
+        let _aliases = self.store.lock_aliases().await;
+        let secrets = self.store.list_secrets_with_attrs(&self.id).await?;
+        self.store.hide_collection(&self.id);
+        let aliases = self.remove_objects(connection, object_server, &secrets).await?;
+        self.store.forget_collection(self.id.clone()).await?;
+        Self::emit_aliases_removed(connection, &self.id, &aliases).await?;
 
-        Ok(paths)
+        Ok(false)
     }
 
-    async fn create_item(
+    /// create an item in this collection, or replace the one with the same attributes
+    async fn add_item(
         &self,
-        properties: HashMap<String, Value<'_>>,
-        secret: Secret,
+        (label, mut attrs): (Option<String>, HashMap<String, String>),
+        secret_value: Vec<u8>,
+        content_type: String,
         replace: bool,
-        #[zbus(signal_context)] signal_context: SignalContext<'_>,
-        #[zbus(object_server)] object_server: &ObjectServer,
-        #[zbus(header)] header: Header<'_>,
-    ) -> Result<(ObjectPath, ObjectPath)> {
-        let secret_value =
-            try_interface(object_server.interface::<_, Session>(&secret.session).await)?
-                .ok_or(Error::InvalidSession)?
-                .get()
-                .await
-                .decrypt(secret, &header)?;
-
-        let label = properties
-            .get("org.freedesktop.Secret.Item.Label")
-            .and_then(|l| l.downcast_ref::<String>().ok());
-        let attrs = properties
-            .get("org.freedesktop.Secret.Item.Attributes")
-            .and_then(|a| a.downcast_ref::<Dict>().ok())
-            .and_then(|a| HashMap::<String, String>::try_from(a).ok())
-            .unwrap_or_default();
-        let attrs = Arc::new(attrs);
+        signal_context: SignalContext<'_>,
+        object_server: &ObjectServer,
+    ) -> Result<(ObjectPath<'static>, ObjectPath<'static>)> {
+        let dropped = keep_reserved(&mut attrs, &HashMap::new());
+        if !dropped.is_empty() {
+            warn!("Ignoring reserved attributes {dropped:?} on a new item in {}", self.id);
+        }
+        let secret_value = self.store.filter_write(&self.id, secret_value, &content_type).await?;
 
-        let secret_id = if replace {
+        let existing = if replace {
             // replace the secret with the matching attrs
-            let matching_secret = self
-                .store
-                .search_collection(self.id.clone(), attrs.clone())
+            self.store
+                .search_collection(self.id.clone(), Arc::new(attrs.clone()))
+                .await?
+                .into_iter()
+                .nth(0)
+        } else if self.store.config.duplicates != DuplicateMode::Keep {
+            let matching = self.store.exact_matches(self.id.clone(), &attrs).await?;
+            let newest = matching
+                .into_iter()
+                .max_by_key(|(_, _, attrs)| secret_version(attrs));
+            match (self.store.config.duplicates, newest) {
+                (DuplicateMode::Replace, Some((secret_id, _, _))) => Some(secret_id),
+                (DuplicateMode::Version, Some((_, _, newest))) => {
+                    let version = secret_version(&newest) + 1;
+                    attrs.insert(VERSION_ATTRIBUTE.to_owned(), version.to_string());
+                    None
+                }
+                _ => None,
+            }
+        } else {
+            None
+        };
+
+        if let Some(secret_id) = existing.map(Arc::new) {
+            // same as Item.SetSecret
+            if self.store.is_locked(&self.id) {
+                return Err(Error::IsLocked);
+            }
+            // update the secret/label
+            self.store
+                .set_secret(&*self.id, &*secret_id, secret_value)
                 .await?;
-            if let Some(secret_id) = matching_secret.into_iter().nth(0).map(Arc::new) {
-                // update the secret/label
+            self.store
+                .set_content_type(self.id.clone(), secret_id.clone(), content_type)
+                .await?;
+            if let Some(label) = label {
                 self.store
-                    .set_secret(&*self.id, &*secret_id, secret_value)
+                    .set_secret_label(self.id.clone(), secret_id.clone(), label)
                     .await?;
-                if let Some(label) = label {
-                    self.store
-                        .set_secret_label(self.id.clone(), secret_id.clone(), label)
-                        .await?;
-                }
+            }
 
-                let path = secret_path(&*self.id, &*secret_id).unwrap();
-                Self::item_changed(&signal_context, path.clone()).await?;
+            let path = secret_path(&*self.id, &*secret_id).unwrap();
+            Self::item_changed(&signal_context, path.clone()).await?;
 
-                // no need to add to the object server
-                return Ok((path, EMPTY_PATH));
-            } else {
-                self.store
-                    .create_secret(self.id.clone(), label, secret_value, attrs)
-                    .await?
-            }
-        } else {
+            // no need to add to the object server
+            return Ok((path, EMPTY_PATH));
+        }
+
+        let secret_id = self
+            .store
+            .create_secret(self.id.clone(), label, secret_value, Arc::new(attrs))
+            .await?;
+        let registered = async {
             self.store
-                .create_secret(self.id.clone(), label, secret_value, attrs)
-                .await?
+                .set_content_type(self.id.clone(), Arc::new(secret_id.clone()), content_type)
+                .await?;
+            self.register_item(secret_id.clone(), &signal_context, object_server)
+                .await
+        };
+        let path = match registered.await {
+            Ok(path) => path,
+            Err(e) => {
+                // the client is told it failed, so don't leave a half-made item behind
+                self.discard_item(&secret_id, object_server).await;
+                return Err(e);
+            }
         };
 
+        // no prompt needed for GPG encryption
+        Ok((path, EMPTY_PATH))
+    }
+
+    /// undo adding an item which failed partway: delete it, and remove whatever of it was
+    /// registered. errors are only logged, so the caller can return the original one
+    async fn discard_item(&self, secret_id: &str, object_server: &ObjectServer) {
+        self.store.hide_item(&self.id, secret_id);
+        if let Err(e) = self
+            .store
+            .delete_secret(self.id.clone(), Arc::new(secret_id.to_owned()))
+            .await
+        {
+            warn!("Could not delete item {secret_id} in {} after creating it failed: {e}", self.id);
+        }
+
+        let _aliases = self.store.lock_aliases().await;
+        let aliases = self
+            .store
+            .list_aliases_for_collection(self.id.clone())
+            .await
+            .unwrap_or_default();
+        let paths = aliases
+            .iter()
+            .filter_map(|alias| secret_alias_path(alias.as_str(), secret_id))
+            .chain(secret_path(self.id.as_str(), secret_id));
+        for path in paths {
+            if let Err(e) = Item::unregister(object_server, &path).await {
+                warn!("Could not remove {path} after creating it failed: {e}");
+            }
+        }
+    }
+
+    /// add a new item in this collection to the object server, under its aliases too
+    pub async fn register_item(
+        &self,
+        secret_id: String,
+        signal_context: &SignalContext<'_>,
+        object_server: &ObjectServer,
+    ) -> Result<ObjectPath<'static>> {
         let path = secret_path(&*self.id, &secret_id).unwrap();
         let item = self.make_item(secret_id);
 
+        let aliases_guard = self.store.lock_aliases().await;
         // add to all aliases too
         for alias in self
             .store
@@ -166,23 +339,372 @@ impl Collection<'static> {
             .await?
         {
             if let Some(path) = secret_alias_path(&alias, &*item.id) {
-                object_server.at(&path, item.clone()).await?;
+                item.register(object_server, &path).await?;
             }
         }
         // add the item to the object server
-        object_server.at(&path, item).await?;
+        item.register(object_server, &path).await?;
+        drop(aliases_guard);
+        // only list it once it can be used
+        self.store.show_items(&self.id, [item.id.to_string()]);
 
-        Self::item_created(&signal_context, path.clone()).await?;
+        Self::item_created(signal_context, path.clone()).await?;
+        CollectionExt::emit_item_count_changed(object_server, &self.id).await?;
 
-        // no prompt needed for GPG encryption
-        Ok((path, EMPTY_PATH))
+        Ok(path)
+    }
+
+    /// remove an item whose file was deleted outside the daemon from the object server,
+    /// and its label and attributes from the store
+    pub async fn forget_item(
+        &self,
+        secret_id: &str,
+        signal_context: &SignalContext<'_>,
+        object_server: &ObjectServer,
+    ) -> Result {
+        self.store.hide_item(&self.id, secret_id);
+        self.store
+            .forget_secret(self.id.clone(), Arc::new(secret_id.to_owned()))
+            .await?;
+
+        self.unregister_item(secret_id, signal_context, object_server).await
+    }
+
+    /// delete an item in this collection, and remove it from the object server
+    pub async fn delete_item(
+        &self,
+        secret_id: &str,
+        signal_context: &SignalContext<'_>,
+        object_server: &ObjectServer,
+    ) -> Result {
+        // stop listing it before it's deleted, and list it again if that fails
+        self.store.hide_item(&self.id, secret_id);
+        if let Err(e) = self
+            .store
+            .delete_secret(self.id.clone(), Arc::new(secret_id.to_owned()))
+            .await
+        {
+            self.store.show_items(&self.id, [secret_id.to_owned()]);
+            return Err(e);
+        }
+
+        self.unregister_item(secret_id, signal_context, object_server).await
+    }
+
+    /// remove an item which is gone from the store from the object server, under its aliases too
+    async fn unregister_item(
+        &self,
+        secret_id: &str,
+        signal_context: &SignalContext<'_>,
+        object_server: &ObjectServer,
+    ) -> Result {
+        let path = secret_path(self.id.as_str(), secret_id).unwrap();
+        let _aliases = self.store.lock_aliases().await;
+        Item::unregister(object_server, &path).await?;
+        for alias in self
+            .store
+            .list_aliases_for_collection(self.id.clone())
+            .await?
+        {
+            if let Some(path) = secret_alias_path(alias.as_str(), secret_id) {
+                Item::unregister(object_server, &path).await?;
+            }
+        }
+
+        Self::item_deleted(signal_context, path).await?;
+        CollectionExt::emit_item_count_changed(object_server, &self.id).await?;
+        Ok(())
+    }
+
+    /// the collection a client's new items go to instead of the default collection,
+    /// if it has one configured and this is the default collection
+    async fn client_default_collection(
+        &self,
+        connection: &Connection,
+        object_server: &ObjectServer,
+        header: &Header<'_>,
+    ) -> Result<Option<Self>> {
+        if header.path() != alias_path("default").as_ref() {
+            return Ok(None);
+        }
+
+        let Some(name) = client_config(connection, header, self.store.config)
+            .await
+            .and_then(|client| client.default_collection.as_deref())
+        else {
+            return Ok(None);
+        };
+        let Some(id) = self.store.resolve_collection(name).await? else {
+            warn!("Collection {name} configured for a client does not exist");
+            return Ok(None);
+        };
+        if id == *self.id {
+            return Ok(None);
+        }
+
+        let path = collection_path(&id).unwrap();
+        Ok(match try_interface(object_server.interface::<_, Self>(&path).await)? {
+            Some(collection) => Some(collection.get().await.clone()),
+            None => None,
+        })
+    }
+
+    /// point an alias at a collection, or remove it, and move its objects on the object server
+    /// the alias path keeps serving the old collection until it's switched to the new one, so
+    /// it's never missing. returns the collection the alias pointed at before, if any
+    pub async fn move_alias(
+        store: &SecretStore<'static>,
+        object_server: &ObjectServer,
+        alias: Arc<String>,
+        target: Option<&Self>,
+    ) -> Result<Option<String>> {
+        let alias_path = alias_path(&alias).unwrap();
+
+        let _aliases = store.lock_aliases().await;
+
+        let old_target = store.get_alias(alias.clone()).await.ok();
+        if old_target.as_deref() == target.map(|target| target.id.as_str()) {
+            return Ok(old_target);
+        }
+        let mut old_secrets = match &old_target {
+            Some(old_target) => store.list_secrets(old_target).await?.into_iter().collect(),
+            None => HashSet::new(),
+        };
+
+        if let Some(target) = target {
+            // add secrets under this alias before the old ones are removed
+            for secret in store.list_secrets(&target.id).await? {
+                if let Some(path) = secret_alias_path(&*alias, &secret) {
+                    // unless the old collection has a secret with the same id
+                    if old_secrets.remove(&secret) {
+                        Item::unregister(object_server, &path).await?;
+                    }
+                    target.make_item(secret).register(object_server, &path).await?;
+                }
+            }
+
+            if !target.take_over(object_server, &alias_path).await? {
+                target.register(object_server, &alias_path).await?;
+            }
+        } else {
+            Self::unregister(object_server, &alias_path).await?;
+        }
+
+        // remove the old secrets under this alias
+        for secret in old_secrets {
+            if let Some(path) = secret_alias_path(&*alias, &secret) {
+                Item::unregister(object_server, &path).await?;
+            }
+        }
+
+        // save this persistently
+        store
+            .set_alias(alias, target.map(|target| target.id.to_string()))
+            .await?;
+        Ok(old_target)
+    }
+
+    /// make the collection objects at `path` serve this collection instead, without removing
+    /// them in between. returns false if there aren't any
+    async fn take_over(&self, object_server: &ObjectServer, path: &ObjectPath<'_>) -> Result<bool> {
+        let Some(collection) = try_interface(object_server.interface::<_, Self>(path).await)? else {
+            return Ok(false);
+        };
+        if let Some(ext) = try_interface(object_server.interface::<_, CollectionExt>(path).await)? {
+            ext.get_mut().await.id = self.id.clone();
+        }
+        collection.get_mut().await.id = self.id.clone();
+
+        // its properties are the new collection's now
+        let ctxt = collection.signal_context();
+        let collection = collection.get().await;
+        collection.label_changed(ctxt).await?;
+        collection.items_changed(ctxt).await?;
+        collection.locked_changed(ctxt).await?;
+        Ok(true)
+    }
+
+    /// remove the collection at this path from the object server
+    pub async fn unregister(object_server: &ObjectServer, path: &ObjectPath<'_>) -> Result {
+        try_interface(object_server.remove::<Self, _>(path).await)?;
+        try_interface(object_server.remove::<CollectionExt, _>(path).await)?;
+        Ok(())
+    }
+}
+
+/// our extensions to the collection interface
+#[derive(Clone, Debug)]
+pub struct CollectionExt<'a> {
+    pub store: SecretStore<'a>,
+    pub id: Arc<String>,
+}
+
+impl CollectionExt<'static> {
+    /// notify clients that the item count of a collection changed
+    pub async fn emit_item_count_changed(object_server: &ObjectServer, collection_id: &str) -> Result {
+        if let Some(path) = collection_path(collection_id) {
+            if let Some(iface) =
+                try_interface(object_server.interface::<_, Self>(&path).await)?
+            {
+                iface
+                    .get()
+                    .await
+                    .item_count_changed(iface.signal_context())
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[interface(name = "me.grimsteel.PassSecretService.Collection")]
+impl CollectionExt<'static> {
+    #[zbus(property)]
+    async fn item_count(&self) -> fdo::Result<u32> {
+        let count = self.store.count_secrets(self.id.clone()).await?;
+        Ok(count.try_into().unwrap_or(u32::MAX))
+    }
+
+    /// where frontends should list this collection. those with one come first, lowest first.
+    /// 0 if it doesn't have one
+    #[zbus(property)]
+    async fn sort_order(&self) -> fdo::Result<u32> {
+        Ok(self.store.get_sort_order(self.id.clone()).await?.unwrap_or_default())
+    }
+
+    #[zbus(property)]
+    async fn set_sort_order(&mut self, order: u32) -> fdo::Result<()> {
+        let order = Some(order).filter(|order| *order != 0);
+        self.store.set_sort_order(self.id.clone(), order).await?;
+        Ok(())
+    }
+
+    /// a color frontends can show this collection with, e.g. `#3584e4`. empty if it doesn't have one
+    #[zbus(property)]
+    async fn color(&self) -> fdo::Result<String> {
+        Ok(self.store.get_color(self.id.clone()).await?.unwrap_or_default())
+    }
+
+    #[zbus(property)]
+    async fn set_color(&mut self, color: String) -> fdo::Result<()> {
+        let color = Some(color).filter(|color| !color.is_empty());
+        self.store.set_color(self.id.clone(), color).await?;
+        Ok(())
+    }
+}
+
+#[interface(name = "org.freedesktop.Secret.Collection")]
+impl Collection<'static> {
+    async fn delete(
+        &self,
+        #[zbus(connection)] connection: &Connection,
+        #[zbus(object_server)] object_server: &ObjectServer,
+        #[zbus(header)] header: Header<'_>,
+    ) -> Result<ObjectPath> {
+        timing::timed("Collection.Delete", async move {
+            if self.store.is_read_only(&self.id) {
+                return Err(Error::ReadOnly);
+            }
+            let _aliases = self.store.lock_aliases().await;
+
+            let secrets = self.store.list_secrets(&*self.id).await?;
+            let label = self
+                .store
+                .get_label(self.id.clone())
+                .await
+                .unwrap_or_else(|_| self.id.to_string());
+
+            self.store.hide_collection(&self.id);
+            let aliases = self.remove_objects(connection, object_server, &secrets).await?;
+
+            // delete the collection from the store
+            self.store.delete_collection(self.id.clone()).await?;
+            Self::emit_aliases_removed(connection, &self.id, &aliases).await?;
+            audit::record(connection, &header, Operation::DeleteCollection, &self.id, None).await;
+
+            notify(connection, &self.store, Event::CollectionDeleted { label: &label });
+
+            Ok(EMPTY_PATH)
+        })
+        .await
+    }
+
+    async fn search_items(&self, attributes: HashMap<String, String>) -> Result<Vec<ObjectPath>> {
+        timing::timed("Collection.SearchItems", async move {
+            let attributes = self.store.config.search_attributes(attributes);
+            let items = self
+                .store
+                .search_collection(self.id.clone(), Arc::new(attributes))
+                .await?;
+            let paths = items.into_iter().filter_map(|item| secret_path(&*self.id, &item)).collect();
+
+            Ok(paths)
+        })
+        .await
+    }
+
+    async fn create_item(
+        &self,
+        properties: HashMap<String, Value<'_>>,
+        secret: Secret,
+        replace: bool,
+        #[zbus(signal_context)] signal_context: SignalContext<'_>,
+        #[zbus(object_server)] object_server: &ObjectServer,
+        #[zbus(header)] header: Header<'_>,
+    ) -> Result<(ObjectPath, ObjectPath)> {
+        timing::timed("Collection.CreateItem", async move {
+            let connection = signal_context.connection().clone();
+
+            // some clients only use the default collection, so send their items where they're configured to go
+            let redirected = self
+                .client_default_collection(&connection, object_server, &header)
+                .await?;
+            let target = redirected.as_ref().unwrap_or(self);
+
+            if !target.resync_if_deleted(&connection, object_server).await? {
+                return Err(io::Error::from(io::ErrorKind::NotFound).into());
+            }
+            // this would replace an item the client couldn't read
+            if target.store.is_locked(&target.id) {
+                return Err(Error::IsLocked);
+            }
+
+            let properties = item_properties(&properties)?;
+            if properties.1.is_empty() {
+                let client = client_exe(&connection, &header)
+                    .await
+                    .or_else(|| header.sender().map(|sender| sender.to_string()))
+                    .unwrap_or_default();
+                warn!("{client} created an item with no attributes in {}, so it can only be found by its path", target.id);
+            }
+
+            let content_type = secret.content_type.clone();
+            let secret_value =
+                try_interface(object_server.interface::<_, Session>(&secret.session).await)?
+                    .ok_or(Error::InvalidSession)?
+                    .get()
+                    .await
+                    .decrypt(secret, &header)?;
+
+            let signal_context = match &redirected {
+                Some(target) => SignalContext::new(&connection, collection_path(&*target.id).unwrap())?,
+                None => signal_context,
+            };
+            let (item_path, prompt) = target
+                .add_item(properties, secret_value, content_type, replace, signal_context, object_server)
+                .await?;
+            let item = path_secret_id(&item_path);
+            audit::record(&connection, &header, Operation::CreateItem, &target.id, item.as_deref()).await;
+            Ok((item_path, prompt))
+        })
+        .await
     }
 
     #[zbus(property)]
     async fn items(&self) -> fdo::Result<Vec<ObjectPath>> {
         Ok(self
             .store
-            .list_secrets(&*self.id)
+            .sorted_secrets(&self.id)
             .await?
             .into_iter()
             // get the full path of the secret
@@ -203,22 +725,19 @@ impl Collection<'static> {
 
     #[zbus(property)]
     async fn locked(&self) -> bool {
-        // we don't support locking
-        false
+        self.store.is_locked(&self.id)
     }
 
     #[zbus(property)]
     async fn created(&self) -> fdo::Result<u64> {
-        let metadata = self.store.stat_collection(&self.id).await?;
-
-        Ok(time_to_int(metadata.created()))
+        Ok(self.store.collection_created(self.id.clone()).await?)
     }
 
     #[zbus(property)]
     async fn modified(&self) -> fdo::Result<u64> {
         let metadata = self.store.stat_collection(&self.id).await?;
 
-        Ok(time_to_int(metadata.modified()))
+        Ok(time_to_int(metadata.modified().ok()))
     }
 
     #[zbus(signal)]
@@ -228,5 +747,5 @@ impl Collection<'static> {
     async fn item_deleted(ctx: &SignalContext<'_>, path: ObjectPath<'_>) -> zbus::Result<()>;
 
     #[zbus(signal)]
-    async fn item_changed(ctx: &SignalContext<'_>, path: ObjectPath<'_>) -> zbus::Result<()>;
+    pub async fn item_changed(ctx: &SignalContext<'_>, path: ObjectPath<'_>) -> zbus::Result<()>;
 }
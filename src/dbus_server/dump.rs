@@ -0,0 +1,92 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use serde::Serialize;
+use zbus::{names::BusName, Connection};
+
+use crate::{
+    error::Result,
+    recent_log::{self, LogEntry},
+    secret_store::SecretStore,
+};
+
+use super::{session::OpenSessions, utils::name_exe};
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "kebab-case")]
+struct StateDump {
+    version: &'static str,
+    store_path: String,
+    collections: Vec<CollectionState>,
+    sessions: Vec<SessionState>,
+    /// writes waiting to be retried, if the write queue is enabled
+    queued_writes: Option<u32>,
+    /// warnings and errors, oldest first
+    recent_errors: Vec<LogEntry>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "kebab-case")]
+struct CollectionState {
+    id: String,
+    label: String,
+    aliases: Vec<String>,
+    items: u64,
+    locked: bool,
+    /// how many items have each attribute. values are left out
+    attribute_keys: BTreeMap<String, u64>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "kebab-case")]
+struct SessionState {
+    path: String,
+    client: String,
+    client_exe: Option<String>,
+}
+
+/// describe the daemon's state as JSON, for bug reports
+/// secret values, attribute values, and item labels are never included
+pub async fn dump_state(
+    connection: &Connection,
+    store: &SecretStore<'_>,
+    sessions: &OpenSessions,
+) -> Result<String> {
+    let mut collections = vec![];
+    for id in store.sorted_collections().await? {
+        let id = Arc::new(id);
+        collections.push(CollectionState {
+            label: store.get_label(id.clone()).await?,
+            aliases: store.list_aliases_for_collection(id.clone()).await?,
+            items: store.count_secrets(id.clone()).await?,
+            locked: store.is_locked(&id),
+            attribute_keys: store.count_attribute_keys(id.clone()).await?,
+            id: id.to_string(),
+        });
+    }
+
+    let open_sessions: Vec<_> = sessions
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(path, client)| (path.to_string(), client.clone()))
+        .collect();
+    let mut sessions = vec![];
+    for (path, client) in open_sessions {
+        sessions.push(SessionState {
+            path,
+            client_exe: name_exe(connection, BusName::Unique(client.as_ref().to_owned())).await,
+            client: client.to_string(),
+        });
+    }
+    sessions.sort_by(|a, b| a.path.cmp(&b.path));
+
+    let dump = StateDump {
+        version: env!("CARGO_PKG_VERSION"),
+        store_path: store.store_path().to_string_lossy().into_owned(),
+        collections,
+        sessions,
+        queued_writes: store.write_queue_status().map(|status| status.pending),
+        recent_errors: recent_log::recent(),
+    };
+    Ok(serde_json::to_string_pretty(&dump).expect("the state can be serialized"))
+}
@@ -0,0 +1,100 @@
+use std::{
+    collections::{HashMap, HashSet},
+    time::{Duration, SystemTime},
+};
+
+use log::warn;
+use zbus::{object_server::SignalContext, Connection};
+
+use crate::{error::Result, secret_store::SecretStore};
+
+use super::{
+    manager::Manager,
+    notifications::{notify, Event},
+    utils::secret_path,
+};
+
+const DAY: u64 = 24 * 60 * 60;
+
+/// check for expiring secrets on the configured schedule
+pub async fn watch_expiry(connection: Connection, store: SecretStore<'static>) {
+    let config = &store.config.expiry;
+    if config.scan_interval_hours == 0 {
+        return;
+    }
+    let interval = Duration::from_secs(u64::from(config.scan_interval_hours) * 60 * 60);
+
+    // collection -> (secret, expiry) which we've already warned about
+    let mut warned = Warned::new();
+    loop {
+        if let Err(e) = scan(&connection, &store, &mut warned).await {
+            warn!("Could not check for expiring secrets: {e}");
+        }
+        tokio::time::sleep(interval).await;
+    }
+}
+
+type Warned = HashMap<String, HashSet<(String, u64)>>;
+
+/// the expiring secrets which haven't been warned about yet. `warned` is replaced with the
+/// secrets which are expiring now, so it forgets items (and collections) which were removed or
+/// whose expiry changed
+fn not_warned(warned: &mut Warned, expiring: Vec<(String, String, u64)>) -> Vec<(String, String, u64)> {
+    let mut current = Warned::new();
+    let mut new = vec![];
+    for (collection_id, secret_id, expires) in expiring {
+        let key = (secret_id, expires);
+        if !warned.get(&collection_id).is_some_and(|w| w.contains(&key)) {
+            new.push((collection_id.clone(), key.0.clone(), expires));
+        }
+        current.entry(collection_id).or_default().insert(key);
+    }
+    *warned = current;
+    new
+}
+
+/// warn about secrets expiring within the configured number of days
+async fn scan(connection: &Connection, store: &SecretStore<'static>, warned: &mut Warned) -> Result {
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let before = now + u64::from(store.config.expiry.warn_days) * DAY;
+
+    let ctxt = SignalContext::new(connection, "/org/freedesktop/secrets")?;
+
+    let expiring = store.list_expiring_secrets(before).await?;
+    for (collection_id, secret_id, expires) in not_warned(warned, expiring) {
+        if let Some(path) = secret_path(&collection_id, &secret_id) {
+            Manager::item_expiring(&ctxt, path, expires).await?;
+        }
+
+        let label = store
+            .get_secret_label(collection_id.clone().into(), secret_id.clone().into())
+            .await
+            .unwrap_or_else(|_| secret_id.clone());
+        // whole days, rounding towards the past
+        let days = (expires as i64 - now as i64).div_euclid(DAY as i64);
+        notify(connection, store, Event::ItemExpiring { label: &label, days });
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_not_warned() {
+    let item = |c: &str, s: &str, e| (c.to_owned(), s.to_owned(), e);
+    let mut warned = Warned::new();
+
+    assert_eq!(
+        not_warned(&mut warned, vec![item("a", "x", 10), item("b", "y", 20)]),
+        [item("a", "x", 10), item("b", "y", 20)]
+    );
+    // only warned once
+    assert!(not_warned(&mut warned, vec![item("a", "x", 10), item("b", "y", 20)]).is_empty());
+
+    // collection b was removed, and x's expiry changed
+    assert_eq!(not_warned(&mut warned, vec![item("a", "x", 30)]), [item("a", "x", 30)]);
+    assert_eq!(warned.len(), 1);
+    assert_eq!(warned["a"], HashSet::from([("x".to_owned(), 30)]));
+}
@@ -0,0 +1,92 @@
+use std::collections::HashMap;
+
+use zbus::{
+    fdo, interface,
+    message::Header,
+    object_server::SignalContext,
+    zvariant::{ObjectPath, OwnedObjectPath, OwnedValue},
+    ObjectServer,
+};
+
+use crate::{error::Result, secret_store::SecretStore, timing};
+
+use super::{service::Service, utils::Secret};
+
+/// the name gnome-keyring-daemon owns besides org.freedesktop.secrets
+pub const GNOME_KEYRING_NAME: &str = "org.gnome.keyring";
+pub const DAEMON_PATH: &str = "/org/gnome/keyring/daemon";
+
+/// the methods gnome-keyring adds to the service object for its own tools, which some old
+/// clients call instead of CreateCollection. only served with `--gnome-keyring-compat`
+#[derive(Debug)]
+pub struct GnomeKeyring<'a> {
+    pub store: SecretStore<'a>,
+}
+
+#[interface(name = "org.gnome.keyring.InternalUnsupportedGuiltRiddenInterface")]
+impl GnomeKeyring<'static> {
+    /// create a collection without a prompt
+    /// its secrets are encrypted to the store's gpg keys like any other, so the master
+    /// password isn't used
+    async fn create_with_master_password(
+        &self,
+        properties: HashMap<String, OwnedValue>,
+        _master: Secret,
+        #[zbus(signal_context)] signal: SignalContext<'_>,
+        #[zbus(object_server)] object_server: &ObjectServer,
+        #[zbus(header)] header: Header<'_>,
+    ) -> Result<ObjectPath<'static>> {
+        timing::timed("GnomeKeyring.CreateWithMasterPassword", async move {
+            let label: Option<String> = properties
+                .get("org.freedesktop.Secret.Collection.Label")
+                .and_then(|v| v.downcast_ref().ok());
+            Service::add_collection(&self.store, label, "", &signal, object_server, &header).await
+        })
+        .await
+    }
+
+    /// collections are unlocked by gpg, not with a password of their own
+    async fn unlock_with_master_password(
+        &self,
+        _collection: OwnedObjectPath,
+        _master: Secret,
+    ) -> fdo::Result<()> {
+        Err(fdo::Error::NotSupported(
+            "Collections don't have master passwords. Use Unlock instead".into(),
+        ))
+    }
+
+    async fn change_with_master_password(
+        &self,
+        _collection: OwnedObjectPath,
+        _original: Secret,
+        _master: Secret,
+    ) -> fdo::Result<()> {
+        Err(fdo::Error::NotSupported(
+            "Collections don't have master passwords. Change the passphrase of the gpg key instead".into(),
+        ))
+    }
+
+    async fn change_with_prompt(&self, _collection: OwnedObjectPath) -> fdo::Result<OwnedObjectPath> {
+        Err(fdo::Error::NotSupported(
+            "Collections don't have master passwords. Change the passphrase of the gpg key instead".into(),
+        ))
+    }
+}
+
+/// gnome-keyring's daemon object, which session managers ask for the environment of its agents
+#[derive(Debug)]
+pub struct KeyringDaemon;
+
+#[interface(name = "org.gnome.keyring.Daemon")]
+impl KeyringDaemon {
+    /// variables to set for gnome-keyring's ssh agent. there isn't one here
+    async fn get_environment(&self) -> HashMap<String, String> {
+        HashMap::new()
+    }
+
+    /// where gnome-keyring's control socket is. there isn't one here, so it's empty
+    async fn get_control_directory(&self) -> String {
+        String::new()
+    }
+}
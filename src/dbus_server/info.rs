@@ -0,0 +1,168 @@
+use std::{collections::HashMap, time::Instant};
+
+use zbus::{fdo, interface, zvariant::ObjectPath};
+
+use crate::{
+    error_stats,
+    secret_store::{SecretStore, RESERVED_ATTRIBUTES, RESERVED_PREFIXES},
+};
+
+use super::utils::collection_path;
+
+/// bumped when one of our extensions changes in a way which breaks existing clients
+pub const EXTENSION_VERSION: u32 = 1;
+
+/// the extensions every build supports
+const EXTENSIONS: [&str; 10] = [
+    "views",
+    "expiry",
+    "content-types",
+    "display-hints",
+    "item-pins",
+    "last-access",
+    "store-changed",
+    "prompt-signals",
+    "reserved-attributes",
+    "get-item-full",
+];
+
+/// read-only state of the daemon
+#[derive(Debug)]
+pub struct Info<'a> {
+    pub store: SecretStore<'a>,
+    /// when the daemon started serving
+    pub started: Instant,
+}
+
+#[interface(name = "me.grimsteel.PassSecretService.Info")]
+impl Info<'static> {
+    /// the password store being served
+    #[zbus(property(emits_changed_signal = "const"))]
+    async fn store_path(&self) -> String {
+        self.store.store_path().to_string_lossy().into_owned()
+    }
+
+    /// where secrets are stored unless a collection is routed elsewhere
+    #[zbus(property(emits_changed_signal = "const"))]
+    async fn backend(&self) -> &str {
+        "pass"
+    }
+
+    /// where each collection's secrets are stored: "pass", "tree", "passthrough", or "vault"
+    #[zbus(property(emits_changed_signal = "false"))]
+    async fn collection_backends(&self) -> fdo::Result<HashMap<ObjectPath<'static>, &str>> {
+        let mut backends = HashMap::new();
+        for id in self.store.collections().await {
+            let backend = self.store.backend_name(&id).await?;
+            if let Some(path) = collection_path(id) {
+                backends.insert(path, backend);
+            }
+        }
+        Ok(backends)
+    }
+
+    /// the daemon's version
+    #[zbus(property(emits_changed_signal = "const"))]
+    async fn version(&self) -> &str {
+        env!("CARGO_PKG_VERSION")
+    }
+
+    /// seconds since the daemon started serving
+    #[zbus(property(emits_changed_signal = "false"))]
+    async fn uptime(&self) -> u64 {
+        self.started.elapsed().as_secs()
+    }
+
+    /// the last error returned to a client: (category, message, unix time). the message has
+    /// paths and email addresses removed. all empty if there hasn't been one
+    #[zbus(property(emits_changed_signal = "false"))]
+    async fn last_error(&self) -> (String, String, u64) {
+        error_stats::last().unwrap_or_default()
+    }
+
+    /// how many errors have been returned to clients, by category (e.g. `IsLocked`)
+    #[zbus(property(emits_changed_signal = "false"))]
+    async fn error_counts(&self) -> HashMap<String, u32> {
+        error_stats::counts()
+    }
+
+    /// the version of our extensions, see `EXTENSION_VERSION`
+    #[zbus(property(emits_changed_signal = "const"))]
+    async fn extension_version(&self) -> u32 {
+        EXTENSION_VERSION
+    }
+
+    /// the extensions this daemon supports, including optional ones only when they're
+    /// enabled, so clients can check for one instead of calling it and handling the error
+    #[zbus(property(emits_changed_signal = "const"))]
+    async fn extensions(&self) -> Vec<&str> {
+        let config = self.store.config;
+        let mut extensions = EXTENSIONS.to_vec();
+        if config.checksums {
+            extensions.push("checksums");
+        }
+        if config.embed_metadata {
+            extensions.push("embed-metadata");
+        }
+        if config.expose_pass_tree {
+            extensions.push("pass-tree");
+        }
+        if config.gnome_keyring_compat {
+            extensions.push("gnome-keyring-compat");
+        }
+        if self.store.write_queue_status().is_some() {
+            extensions.push("write-queue");
+        }
+        #[cfg(feature = "vault")]
+        if config.vault.is_some() {
+            extensions.push("vault");
+        }
+        extensions
+    }
+
+    /// attributes starting with these are kept for the daemon. clients can't set them
+    #[zbus(property(emits_changed_signal = "const"))]
+    async fn reserved_attribute_prefixes(&self) -> Vec<&str> {
+        RESERVED_PREFIXES.to_vec()
+    }
+
+    /// the reserved attributes the daemon uses, and what they hold
+    #[zbus(property(emits_changed_signal = "const"))]
+    async fn reserved_attributes(&self) -> HashMap<&str, &str> {
+        HashMap::from(RESERVED_ATTRIBUTES)
+    }
+
+    /// whether failed writes are queued and retried
+    #[zbus(property(emits_changed_signal = "const"))]
+    async fn write_queue_enabled(&self) -> bool {
+        self.store.write_queue_status().is_some()
+    }
+
+    /// number of writes waiting to be retried
+    #[zbus(property(emits_changed_signal = "false"))]
+    async fn queued_writes(&self) -> u32 {
+        self.store
+            .write_queue_status()
+            .map(|status| status.pending)
+            .unwrap_or_default()
+    }
+
+    /// number of queued writes which are saved in the spool dir
+    /// the rest are lost if the daemon exits
+    #[zbus(property(emits_changed_signal = "false"))]
+    async fn spooled_writes(&self) -> u32 {
+        self.store
+            .write_queue_status()
+            .map(|status| status.spooled)
+            .unwrap_or_default()
+    }
+
+    /// why the last queued write failed, or an empty string
+    #[zbus(property(emits_changed_signal = "false"))]
+    async fn last_write_error(&self) -> String {
+        self.store
+            .write_queue_status()
+            .and_then(|status| status.last_error)
+            .unwrap_or_default()
+    }
+}
@@ -1,17 +1,29 @@
 use std::{collections::HashMap, sync::Arc};
 
-use zbus::{fdo, interface, message::Header, object_server::InterfaceDeref, zvariant::ObjectPath, Connection, ObjectServer};
+use log::{info, warn};
+use zbus::{
+    fdo, interface,
+    message::Header,
+    object_server::{InterfaceDeref, SignalContext},
+    zvariant::ObjectPath,
+    Connection, ObjectServer,
+};
 
 use crate::{
+    agent,
+    audit::{self, Operation},
     error::{Error, Result},
-    secret_store::SecretStore,
+    secret_store::{keep_reserved, parse_expiry, SecretStore, EXPIRES_ATTRIBUTE},
+    timing,
 };
 
 use super::{
+    collection::{Collection, CollectionExt},
+    notifications::{notify, notify_if_new_client, Event},
     session::Session,
     utils::{
-        collection_path, secret_alias_path, secret_path, time_to_int, try_interface, Secret,
-        EMPTY_PATH,
+        client_exe, collection_path, secret_alias_path, secret_path, time_to_int, try_interface,
+        Secret, EMPTY_PATH,
     },
 };
 
@@ -41,14 +53,328 @@ impl<'a> Item<'a> {
     }
 }
 
-impl<'a> Item<'a> {
-    pub async fn read_with_session(&self, header: &Header<'_>, session: &InterfaceDeref<'_, Session>) -> Result<Secret> {
+impl Item<'static> {
+    pub async fn read_with_session(
+        &self,
+        connection: &Connection,
+        header: &Header<'_>,
+        session: &InterfaceDeref<'_, Session>,
+    ) -> Result<Secret> {
+        self.check_readable().await?;
+        self.read_checked(connection, header, session, None).await
+    }
+
+    /// make sure the secret can be read now: the collection is unlocked, and any confirmation
+    /// or PIN it needs was given
+    pub async fn check_readable(&self) -> Result {
+        if self.store.is_locked(&self.collection_id) {
+            return Err(Error::IsLocked);
+        }
+        self.store.confirm_read(&self.collection_id).await?;
+        self.store
+            .confirm_item_read(&self.collection_id, &self.id)
+            .await
+    }
+
+    /// read the secret once `check_readable` passed. `decrypted` is used instead of decrypting
+    /// it again if it was decrypted along with others
+    pub async fn read_checked(
+        &self,
+        connection: &Connection,
+        header: &Header<'_>,
+        session: &InterfaceDeref<'_, Session>,
+        decrypted: Option<Vec<u8>>,
+    ) -> Result<Secret> {
+        let can_prompt = self.store.config.pinentry_during_reads;
+        let read = match decrypted {
+            Some(value) => Ok(value),
+            None => self.store.read_secret(&self.collection_id, &self.id, can_prompt).await,
+        };
+        let secret_value = match read {
+            Ok(value) => value,
+            // instead of waiting on pinentry (and often outlasting the client's timeout), lock
+            // the collection so the client unlocks it, which asks in the background
+            Err(Error::GpgError(stderr)) if agent::needs_passphrase(&stderr) => {
+                info!(
+                    "The passphrase for collection {} isn't cached, so it's locked until a client unlocks it",
+                    self.collection_id
+                );
+                if self.store.set_locked(&self.collection_id, true) {
+                    Collection::emit_locked_changed(&connection.object_server(), &self.collection_id).await?;
+                }
+                return Err(Error::IsLocked);
+            }
+            Err(e) => {
+                if matches!(e, Error::GpgError(_)) {
+                    let label = self.label_or_id().await;
+                    notify(connection, &self.store, Event::DecryptFailed { label: &label });
+                }
+                return Err(e);
+            }
+        };
+
+        notify_if_new_client(connection, header, &self.store).await;
+
+        // look up the client now, since it may exit as soon as it has the reply,
+        // but record it in the background so the reply isn't held up
+        if let Some(sender) = header.sender() {
+            let exe = client_exe(connection, header)
+                .await
+                .unwrap_or_else(|| sender.to_string());
+            let store = self.store.clone();
+            let collection_id = self.collection_id.clone();
+            let id = self.id.clone();
+            tokio::spawn(async move {
+                if let Err(e) = store.record_access(collection_id.clone(), id.clone(), exe).await {
+                    warn!("Could not record the access to {collection_id}/{id}: {e}");
+                }
+            });
+        }
+
+        // re-encrypt it in the background if it's due for rotation
+        if self.store.config.rotation.is_enabled() {
+            let store = self.store.clone();
+            let collection_id = self.collection_id.clone();
+            let id = self.id.clone();
+            let value = secret_value.clone();
+            tokio::spawn(async move {
+                match store.rotate_if_due(&collection_id, &id, value).await {
+                    Ok(true) => info!("Rotated secret {collection_id}/{id}"),
+                    Ok(false) => {}
+                    Err(e) => warn!("Could not rotate secret {collection_id}/{id}: {e}"),
+                }
+            });
+        }
+
         let secret_value = self
             .store
-            .read_secret(&*self.collection_id, &*self.id, true)
+            .filter_read(&self.collection_id, &self.id, secret_value)
+            .await?;
+        let content_type = self
+            .store
+            .get_content_type(self.collection_id.clone(), self.id.clone())
+            .await?;
+        audit::record(connection, header, Operation::GetSecret, &self.collection_id, Some(&self.id)).await;
+        session.encrypt(secret_value, content_type, header)
+    }
+
+    /// get the label of this item, or its id if it doesn't have one
+    async fn label_or_id(&self) -> String {
+        self.store
+            .get_secret_label(self.collection_id.clone(), self.id.clone())
+            .await
+            .unwrap_or_else(|_| self.id.to_string())
+    }
+}
+
+impl Item<'static> {
+    /// add this item and its extension interface to the object server
+    pub async fn register(&self, object_server: &ObjectServer, path: &ObjectPath<'_>) -> Result {
+        object_server.at(path, self.clone()).await?;
+        object_server
+            .at(
+                path,
+                ItemExt {
+                    collection_id: self.collection_id.clone(),
+                    id: self.id.clone(),
+                    store: self.store.clone(),
+                },
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// remove the item at this path from the object server
+    pub async fn unregister(object_server: &ObjectServer, path: &ObjectPath<'_>) -> Result {
+        try_interface(object_server.remove::<Self, _>(path).await)?;
+        try_interface(object_server.remove::<ItemExt, _>(path).await)?;
+        Ok(())
+    }
+}
+
+/// our extensions to the item interface
+#[derive(Clone, Debug)]
+pub struct ItemExt<'a> {
+    pub collection_id: Arc<String>,
+    pub id: Arc<String>,
+    pub store: SecretStore<'a>,
+}
+
+#[interface(name = "me.grimsteel.PassSecretService.Item")]
+impl ItemExt<'static> {
+    /// keyed hash of the secret value, or an empty string if it's unknown
+    /// only available when checksums are enabled in the config
+    #[zbus(property(emits_changed_signal = "false"))]
+    async fn checksum(&self) -> fdo::Result<String> {
+        Ok(self
+            .store
+            .get_secret_checksum(self.collection_id.clone(), self.id.clone())
+            .await?
+            .unwrap_or_default())
+    }
+
+    /// when this item expires, in unix seconds. 0 if it doesn't
+    /// stored in the `pass:expires` attribute
+    #[zbus(property)]
+    async fn expires(&self) -> fdo::Result<u64> {
+        let attributes = self
+            .store
+            .read_secret_attrs(self.collection_id.clone(), self.id.clone())
+            .await?;
+        Ok(attributes
+            .get(EXPIRES_ATTRIBUTE)
+            .and_then(|value| parse_expiry(value))
+            .unwrap_or_default())
+    }
+
+    #[zbus(property)]
+    async fn set_expires(&mut self, expires: u64) -> fdo::Result<()> {
+        self.store.ensure_collection_exists(&self.collection_id).await?;
+        let mut attributes = self
+            .store
+            .read_secret_attrs(self.collection_id.clone(), self.id.clone())
+            .await?;
+        if expires == 0 {
+            attributes.remove(EXPIRES_ATTRIBUTE);
+        } else {
+            attributes.insert(EXPIRES_ATTRIBUTE.into(), expires.to_string());
+        }
+        self.store
+            .set_secret_attrs(self.collection_id.clone(), self.id.clone(), attributes)
+            .await?;
+        Ok(())
+    }
+
+    /// where the secret is in the password store, as `pass show` takes it
+    /// (e.g. `secret-service/default_AbCd/XyZw1234`). empty if it's stored elsewhere
+    #[zbus(property(emits_changed_signal = "const"))]
+    async fn pass_path(&self) -> fdo::Result<String> {
+        let file = self.store.secret_file(&self.collection_id, &self.id).await?;
+        Ok(file.map(|(path, _)| path).unwrap_or_default())
+    }
+
+    /// size of the encrypted secret in bytes. 0 if it's stored elsewhere
+    #[zbus(property(emits_changed_signal = "false"))]
+    async fn encrypted_size(&self) -> fdo::Result<u64> {
+        let file = self.store.secret_file(&self.collection_id, &self.id).await?;
+        Ok(file.map(|(_, size)| size).unwrap_or_default())
+    }
+
+    /// secondary attribute sets which also find this item in searches,
+    /// e.g. for clients which look it up with an older schema
+    #[zbus(property)]
+    async fn views(&self) -> fdo::Result<Vec<HashMap<String, String>>> {
+        Ok(self
+            .store
+            .read_secret_views(self.collection_id.clone(), self.id.clone())
+            .await?)
+    }
+
+    /// when this item's secret was last read, in unix seconds. 0 if it hasn't been
+    #[zbus(property(emits_changed_signal = "false"))]
+    async fn last_accessed(&self) -> fdo::Result<u64> {
+        let access = self
+            .store
+            .last_access(self.collection_id.clone(), self.id.clone())
             .await?;
+        Ok(access.map(|(time, _)| time).unwrap_or_default())
+    }
 
-        session.encrypt(secret_value, header)
+    /// the executable of the client which last read this item's secret (or its bus name if
+    /// that isn't known). empty if it hasn't been read
+    #[zbus(property(emits_changed_signal = "false"))]
+    async fn last_accessed_by(&self) -> fdo::Result<String> {
+        let access = self
+            .store
+            .last_access(self.collection_id.clone(), self.id.clone())
+            .await?;
+        Ok(access.map(|(_, exe)| exe).unwrap_or_default())
+    }
+
+    /// whether reading this item needs its own PIN, set with SetPin
+    #[zbus(property)]
+    async fn pin_protected(&self) -> fdo::Result<bool> {
+        Ok(self
+            .store
+            .has_item_pin(self.collection_id.clone(), self.id.clone())
+            .await?)
+    }
+
+    /// whether reading this item's secret now would show pinentry, because it has a PIN or
+    /// gpg-agent doesn't have the passphrase of its key cached
+    #[zbus(property(emits_changed_signal = "false"))]
+    async fn requires_prompt(&self) -> fdo::Result<bool> {
+        Ok(self
+            .store
+            .requires_prompt(&self.collection_id, &self.id)
+            .await?)
+    }
+
+    /// ask for a PIN (with pinentry) which has to be entered to read this item, after its
+    /// current one. an empty PIN removes it. returns whether the item needs a PIN now
+    async fn set_pin(&self, #[zbus(signal_context)] signal_context: SignalContext<'_>) -> Result<bool> {
+        timing::timed("ItemExt.SetPin", async move {
+            let protected = self
+                .store
+                .set_item_pin(self.collection_id.clone(), self.id.clone())
+                .await?;
+            self.pin_protected_changed(&signal_context).await?;
+            Ok(protected)
+        })
+        .await
+    }
+
+    /// the labels this item had before, with when each was changed (unix seconds), oldest first
+    async fn label_history(&self) -> Result<Vec<(String, u64)>> {
+        timing::timed("ItemExt.LabelHistory", async move {
+            self.store
+                .label_history(self.collection_id.clone(), self.id.clone())
+                .await
+        })
+        .await
+    }
+
+    /// add a view with the given attributes
+    /// returns false if the item already has it
+    async fn add_view(
+        &self,
+        mut attributes: HashMap<String, String>,
+        #[zbus(signal_context)] signal_context: SignalContext<'_>,
+    ) -> fdo::Result<bool> {
+        let dropped = keep_reserved(&mut attributes, &HashMap::new());
+        if !dropped.is_empty() {
+            warn!("Ignoring reserved attributes {dropped:?} in a view");
+        }
+        if attributes.is_empty() {
+            return Err(fdo::Error::InvalidArgs(
+                "A view needs at least one attribute".into(),
+            ));
+        }
+        let added = self
+            .store
+            .add_secret_view(self.collection_id.clone(), self.id.clone(), attributes)
+            .await?;
+        if added {
+            self.views_changed(&signal_context).await?;
+        }
+        Ok(added)
+    }
+
+    /// remove the view with exactly these attributes
+    /// returns false if the item doesn't have it
+    async fn remove_view(
+        &self,
+        attributes: HashMap<String, String>,
+        #[zbus(signal_context)] signal_context: SignalContext<'_>,
+    ) -> fdo::Result<bool> {
+        let removed = self
+            .store
+            .remove_secret_view(self.collection_id.clone(), self.id.clone(), attributes)
+            .await?;
+        if removed {
+            self.views_changed(&signal_context).await?;
+        }
+        Ok(removed)
     }
 }
 
@@ -58,31 +384,50 @@ impl Item<'static> {
         &self,
         #[zbus(connection)] connection: &Connection,
         #[zbus(object_server)] object_server: &ObjectServer,
+        #[zbus(header)] header: Header<'_>,
     ) -> Result<ObjectPath> {
-        // delete from the stoer
-        self.store
-            .delete_secret(self.collection_id.clone(), self.id.clone())
-            .await?;
+        timing::timed("Item.Delete", async move {
+            self.store.ensure_collection_exists(&self.collection_id).await?;
+            let label = self.label_or_id().await;
 
-        self.broadcast_collection_signal(connection, "ItemDeleted")
-            .await?;
+            // stop listing it before it's deleted, and list it again if that fails
+            self.store.hide_item(&self.collection_id, &self.id);
+            // delete from the stoer
+            if let Err(e) = self
+                .store
+                .delete_secret(self.collection_id.clone(), self.id.clone())
+                .await
+            {
+                self.store.show_items(&self.collection_id, [self.id.to_string()]);
+                return Err(e);
+            }
+            audit::record(connection, &header, Operation::DeleteItem, &self.collection_id, Some(&self.id)).await;
 
-        // delete the objects off of dbus
-        try_interface(object_server.remove::<Self, _>(self.path()).await)?;
+            notify(connection, &self.store, Event::ItemDeleted { label: &label });
+            CollectionExt::emit_item_count_changed(object_server, &self.collection_id).await?;
 
-        for alias in self
-            .store
-            .list_aliases_for_collection(self.collection_id.clone())
-            .await?
-        {
-            // delete from each alias
-            if let Some(path) = secret_alias_path(&*alias, &*self.id) {
-                try_interface(object_server.remove::<Self, _>(path).await)?;
+            self.broadcast_collection_signal(connection, "ItemDeleted")
+                .await?;
+
+            // delete the objects off of dbus
+            let _aliases = self.store.lock_aliases().await;
+            Self::unregister(object_server, &self.path()).await?;
+
+            for alias in self
+                .store
+                .list_aliases_for_collection(self.collection_id.clone())
+                .await?
+            {
+                // delete from each alias
+                if let Some(path) = secret_alias_path(&*alias, &*self.id) {
+                    Self::unregister(object_server, &path).await?;
+                }
             }
-        }
 
-        // no prompts required to delete
-        Ok(EMPTY_PATH)
+            // no prompts required to delete
+            Ok(EMPTY_PATH)
+        })
+        .await
     }
 
     async fn get_secret(
@@ -90,14 +435,19 @@ impl Item<'static> {
         session: ObjectPath<'_>,
         #[zbus(header)] header: Header<'_>,
         #[zbus(object_server)] object_server: &ObjectServer,
+        #[zbus(connection)] connection: &Connection,
     ) -> Result<(Secret, )> {
-        Ok((self.read_with_session(
-            &header,
-            &try_interface(object_server.interface::<_, Session>(&session).await)?
-                .ok_or(Error::InvalidSession)?
-                .get()
-                .await
-        ).await?, ))
+        timing::timed("Item.GetSecret", async move {
+            Ok((self.read_with_session(
+                connection,
+                &header,
+                &try_interface(object_server.interface::<_, Session>(&session).await)?
+                    .ok_or(Error::InvalidSession)?
+                    .get()
+                    .await
+            ).await?, ))
+        })
+        .await
     }
 
     async fn set_secret(
@@ -106,27 +456,45 @@ impl Item<'static> {
         #[zbus(connection)] connection: &Connection,
         #[zbus(header)] header: Header<'_>,
         #[zbus(object_server)] object_server: &ObjectServer,
+        #[zbus(signal_context)] signal_context: SignalContext<'_>,
     ) -> Result<()> {
-        let secret_value =
-            try_interface(object_server.interface::<_, Session>(&secret.session).await)?
-                .ok_or(Error::InvalidSession)?
-                .get()
-                .await
-                .decrypt(secret, &header)?;
+        timing::timed("Item.SetSecret", async move {
+            if self.store.is_locked(&self.collection_id) {
+                return Err(Error::IsLocked);
+            }
+            self.store.ensure_collection_exists(&self.collection_id).await?;
 
-        self.store
-            .set_secret(&*self.collection_id, &*self.id, secret_value)
-            .await?;
+            let content_type = secret.content_type.clone();
+            let secret_value =
+                try_interface(object_server.interface::<_, Session>(&secret.session).await)?
+                    .ok_or(Error::InvalidSession)?
+                    .get()
+                    .await
+                    .decrypt(secret, &header)?;
+            let secret_value = self
+                .store
+                .filter_write(&self.collection_id, secret_value, &content_type)
+                .await?;
 
-        self.broadcast_collection_signal(connection, "ItemChanged")
-            .await?;
+            self.store
+                .set_secret(&*self.collection_id, &*self.id, secret_value)
+                .await?;
+            self.store
+                .set_content_type(self.collection_id.clone(), self.id.clone(), content_type)
+                .await?;
+            audit::record(connection, &header, Operation::SetSecret, &self.collection_id, Some(&self.id)).await;
 
-        Ok(())
+            // ItemChanged is emitted for every change the store makes, see `publish_changes`
+            self.modified_changed(&signal_context).await?;
+
+            Ok(())
+        })
+        .await
     }
 
     #[zbus(property)]
     async fn locked(&self) -> bool {
-        false
+        self.store.is_locked(&self.collection_id)
     }
 
     #[zbus(property)]
@@ -141,15 +509,24 @@ impl Item<'static> {
     #[zbus(property)]
     async fn set_attributes(
         &mut self,
-        attributes: HashMap<String, String>,
-        //#[zbus(connection)] connection: &Connection
+        mut attributes: HashMap<String, String>,
     ) -> fdo::Result<()> {
+        self.store.ensure_collection_exists(&self.collection_id).await?;
+        let existing = self
+            .store
+            .read_secret_attrs(self.collection_id.clone(), self.id.clone())
+            .await?;
+        let dropped = keep_reserved(&mut attributes, &existing);
+        if !dropped.is_empty() {
+            warn!(
+                "Ignoring reserved attributes {dropped:?} set on {}/{}",
+                self.collection_id, self.id
+            );
+        }
+
         self.store
             .set_secret_attrs(self.collection_id.clone(), self.id.clone(), attributes)
             .await?;
-
-        //self.broadcast_collection_signal(connection, "ItemChanged");
-
         Ok(())
     }
 
@@ -162,35 +539,29 @@ impl Item<'static> {
     }
 
     #[zbus(property)]
-    async fn set_label(
-        &mut self,
-        label: String,
-        //#[zbus(connection)] connection: &Connection
-    ) -> fdo::Result<()> {
+    async fn set_label(&mut self, label: String) -> fdo::Result<()> {
+        self.store.ensure_collection_exists(&self.collection_id).await?;
         self.store
             .set_secret_label(self.collection_id.clone(), self.id.clone(), label)
             .await?;
-
-        //self.broadcast_collection_signal(connection, "ItemChanged");
-
         Ok(())
     }
 
     #[zbus(property)]
     async fn created(&self) -> fdo::Result<u64> {
-        let metadata = self
+        let (created, _) = self
             .store
-            .stat_secret(&*self.collection_id, &*self.id)
+            .secret_times(&*self.collection_id, &*self.id)
             .await?;
-        Ok(time_to_int(metadata.created()))
+        Ok(time_to_int(created))
     }
 
     #[zbus(property)]
     async fn modified(&self) -> fdo::Result<u64> {
-        let metadata = self
+        let (_, modified) = self
             .store
-            .stat_secret(&*self.collection_id, &*self.id)
+            .secret_times(&*self.collection_id, &*self.id)
             .await?;
-        Ok(time_to_int(metadata.modified()))
+        Ok(time_to_int(modified))
     }
 }
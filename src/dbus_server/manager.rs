@@ -0,0 +1,700 @@
+use std::{collections::HashMap, path::Path, sync::Arc, time::Instant};
+
+use zbus::{interface, message::Header, object_server::SignalContext, zvariant::ObjectPath, Connection, ObjectServer};
+
+use crate::{
+    audit::{self, Operation},
+    error::{Error, OptionNoneNotFound, Result},
+    mdellweg::import_mdellweg,
+    secret_store::{SecretStore, UnreachableId},
+    timing,
+};
+
+use super::{
+    archive::{export_archive, import_archive},
+    collection::Collection,
+    dump::dump_state,
+    item::Item,
+    metadata::{eject_collection, export_metadata, import_metadata, reindex_entries},
+    reload::{reload_store, repair_collection, report_pulled_changes, ReloadCounts},
+    service::Service,
+    session::{OpenSessions, Session},
+    utils::{collection_path, retain_searched, secret_path, time_to_int, try_interface, ItemFull},
+};
+
+/// number of secrets per collection to check for stale recipients
+pub const RECIPIENT_CHECK_SAMPLE: usize = 10;
+
+#[derive(Debug)]
+pub struct Manager<'a> {
+    pub store: SecretStore<'a>,
+    pub sessions: OpenSessions,
+    /// when the daemon started serving
+    pub started: Instant,
+    /// the collections and secrets whose ids couldn't be served when it started
+    pub unreachable: Vec<UnreachableId>,
+}
+
+impl Manager<'static> {
+    /// get the id of the collection (or alias) at the given path
+    async fn resolve_collection(
+        object_server: &ObjectServer,
+        collection: &ObjectPath<'_>,
+    ) -> Result<String> {
+        let collection = try_interface(object_server.interface::<_, Collection>(collection).await)?
+            .into_not_found()?;
+        let id = collection.get().await.id.to_string();
+        Ok(id)
+    }
+}
+
+#[interface(name = "me.grimsteel.PassSecretService.Manager")]
+impl Manager<'static> {
+    /// check a sample of each collection's secrets against its `.gpg-id`
+    /// returns (collection, secrets checked, secrets encrypted to other keys, whether it has a
+    /// `.gpg-id`)
+    async fn check_recipients(&self) -> Result<Vec<(ObjectPath<'static>, u32, u32, bool)>> {
+        timing::timed("Manager.CheckRecipients", async move {
+            Ok(self
+                .store
+                .check_recipients(RECIPIENT_CHECK_SAMPLE)
+                .await?
+                .into_iter()
+                .filter_map(|check| {
+                    Some((
+                        collection_path(check.collection_id)?,
+                        check.checked,
+                        check.stale,
+                        check.initialized,
+                    ))
+                })
+                .collect())
+        })
+        .await
+    }
+
+    /// count the items encrypted to each gpg key, without decrypting anything
+    /// returns (key id, items, user id, status), most used first. status is "valid",
+    /// "revoked", "expired", or "unknown" if the key isn't in the keyring (with an empty user id)
+    async fn key_usage(&self) -> Result<Vec<(String, u32, String, String)>> {
+        timing::timed("Manager.KeyUsage", async move {
+            Ok(self
+                .store
+                .key_usage()
+                .await?
+                .into_iter()
+                .map(|usage| {
+                    let (user_id, status) = match usage.key {
+                        Some(key) => (key.user_id, key.status),
+                        None => (String::new(), "unknown"),
+                    };
+                    (usage.key_id, usage.items, user_id, status.to_owned())
+                })
+                .collect())
+        })
+        .await
+    }
+
+    /// re-encrypt the secrets in a collection to its current `.gpg-id` recipients
+    /// returns the number of secrets which were re-encrypted
+    async fn re_encrypt(
+        &self,
+        collection: ObjectPath<'_>,
+        #[zbus(object_server)] object_server: &ObjectServer,
+    ) -> Result<u32> {
+        timing::timed("Manager.ReEncrypt", async move {
+            let id = Self::resolve_collection(object_server, &collection).await?;
+            self.store.reencrypt_collection(&id).await
+        })
+        .await
+    }
+
+    /// ask for a new PIN for high-security collections with pinentry
+    /// the current PIN has to be entered first, if one is set
+    async fn set_pin(&self) -> Result<()> {
+        timing::timed("Manager.SetPin", async move {
+            self.store.set_pin().await
+        })
+        .await
+    }
+
+    /// find secrets which are older than their collection's rotation policy, and will be
+    /// re-encrypted when they're next read
+    /// returns (item, unix time it was last encrypted)
+    async fn pending_rotation(&self) -> Result<Vec<(ObjectPath<'static>, u64)>> {
+        timing::timed("Manager.PendingRotation", async move {
+            Ok(self
+                .store
+                .pending_rotation()
+                .await?
+                .into_iter()
+                .filter_map(|(collection_id, secret_id, encrypted)| {
+                    Some((secret_path(collection_id, secret_id)?, encrypted))
+                })
+                .collect())
+        })
+        .await
+    }
+
+    /// read an item's secret, attributes, label, and created and modified times in one call,
+    /// instead of GetSecret and a Get for each property
+    async fn get_item_full(
+        &self,
+        item: ObjectPath<'_>,
+        session: ObjectPath<'_>,
+        #[zbus(object_server)] object_server: &ObjectServer,
+        #[zbus(connection)] connection: &Connection,
+        #[zbus(header)] header: Header<'_>,
+    ) -> Result<ItemFull> {
+        timing::timed("Manager.GetItemFull", async move {
+            let session_ref = try_interface(object_server.interface::<_, Session>(&session).await)?
+                .ok_or(Error::InvalidSession)?;
+            let item = try_interface(object_server.interface::<_, Item>(&item).await)?
+                .into_not_found()?
+                .get()
+                .await
+                .clone();
+
+            let secret = item
+                .read_with_session(connection, &header, &session_ref.get().await)
+                .await?;
+            let attributes = self
+                .store
+                .read_secret_attrs(item.collection_id.clone(), item.id.clone())
+                .await?;
+            let label = self
+                .store
+                .get_secret_label(item.collection_id.clone(), item.id.clone())
+                .await?;
+            let (created, modified) = self
+                .store
+                .secret_times(&item.collection_id, &item.id)
+                .await?;
+            Ok((secret, attributes, label, time_to_int(created), time_to_int(modified)))
+        })
+        .await
+    }
+
+    /// whether any item has all the given attributes
+    /// only the attribute index is read, so this is cheap enough to poll
+    async fn has_item(&self, attributes: HashMap<String, String>) -> Result<bool> {
+        timing::timed("Manager.HasItem", async move {
+            Ok(self.store.count_matching(attributes, 1).await? > 0)
+        })
+        .await
+    }
+
+    /// the number of items which have all the given attributes, in every collection
+    async fn count_items(&self, attributes: HashMap<String, String>) -> Result<u32> {
+        timing::timed("Manager.CountItems", async move {
+            let count = self.store.count_matching(attributes, u32::MAX.into()).await?;
+            Ok(count.try_into().unwrap_or(u32::MAX))
+        })
+        .await
+    }
+
+    /// find items whose label contains `label` (ignoring case), and which have each of the
+    /// attributes with a value matching it, where `*` matches anything and `?` any character.
+    /// an empty label matches any, but an empty query matches nothing
+    /// returns (unlocked, locked) like SearchItems
+    async fn find_items(
+        &self,
+        label: String,
+        attributes: HashMap<String, String>,
+    ) -> Result<(Vec<ObjectPath<'static>>, Vec<ObjectPath<'static>>)> {
+        timing::timed("Manager.FindItems", async move {
+            if label.is_empty() && attributes.is_empty() {
+                return Ok((vec![], vec![]));
+            }
+            let (unlocked, locked): (Vec<_>, Vec<_>) = self
+                .store
+                .find_secrets(&label, attributes)
+                .await?
+                .into_iter()
+                .partition(|(col, _)| !self.store.is_locked(col));
+
+            let to_paths = |collections: Vec<(String, Vec<String>)>| {
+                collections
+                    .into_iter()
+                    .flat_map(|(col, secrets)| {
+                        secrets
+                            .into_iter()
+                            .filter_map(move |secret| secret_path(&col, &secret))
+                    })
+                    .collect()
+            };
+            Ok((to_paths(unlocked), to_paths(locked)))
+        })
+        .await
+    }
+
+    /// like SearchItems, but with each item's label and attributes, so pickers don't have to read
+    /// them for every result. locked items are included, since their properties can be read
+    async fn search_items_with_info(
+        &self,
+        attributes: HashMap<String, String>,
+        #[zbus(connection)] connection: &Connection,
+        #[zbus(header)] header: Header<'_>,
+    ) -> Result<Vec<(ObjectPath<'static>, String, HashMap<String, String>)>> {
+        timing::timed("Manager.SearchItemsWithInfo", async move {
+            let attributes = self.store.config.search_attributes(attributes);
+            let mut items = self.store.search_all_collections_with_info(attributes).await?;
+            retain_searched(connection, &header, &self.store, &mut items).await?;
+
+            Ok(items
+                .into_iter()
+                .flat_map(|(col, secrets)| {
+                    secrets.into_iter().filter_map(move |(secret, label, attributes)| {
+                        Some((secret_path(&col, &secret)?, label, attributes))
+                    })
+                })
+                .collect())
+        })
+        .await
+    }
+
+    /// a snapshot of the daemon's state for bug reports, as JSON
+    /// no secret values, attribute values, or item labels are included
+    async fn dump_state(&self, #[zbus(connection)] connection: &Connection) -> Result<String> {
+        timing::timed("Manager.DumpState", async move {
+            dump_state(connection, &self.store, &self.sessions).await
+        })
+        .await
+    }
+
+    /// write the labels, attributes, and aliases of a collection (but no secrets) to a YAML file
+    async fn export_metadata(
+        &self,
+        collection: ObjectPath<'_>,
+        path: String,
+        #[zbus(object_server)] object_server: &ObjectServer,
+    ) -> Result<()> {
+        timing::timed("Manager.ExportMetadata", async move {
+            let id = Self::resolve_collection(object_server, &collection).await?;
+            export_metadata(&self.store, Arc::new(id), Path::new(&path)).await
+        })
+        .await
+    }
+
+    /// apply a YAML file written by ExportMetadata to a collection
+    /// returns the number of items which were updated
+    async fn import_metadata(
+        &self,
+        collection: ObjectPath<'_>,
+        path: String,
+        #[zbus(connection)] connection: &Connection,
+        #[zbus(object_server)] object_server: &ObjectServer,
+    ) -> Result<u32> {
+        timing::timed("Manager.ImportMetadata", async move {
+            let collection = try_interface(object_server.interface::<_, Collection>(&collection).await)?
+                .into_not_found()?
+                .get()
+                .await
+                .clone();
+            import_metadata(connection, object_server, &collection, Path::new(&path)).await
+        })
+        .await
+    }
+
+    /// write a collection's metadata and secrets to a new tarball at `path`, encrypted with gpg
+    /// to the collection's `.gpg-id`
+    /// returns the number of items which were exported
+    async fn export_collection(
+        &self,
+        collection: ObjectPath<'_>,
+        path: String,
+        #[zbus(object_server)] object_server: &ObjectServer,
+    ) -> Result<u32> {
+        timing::timed("Manager.ExportCollection", async move {
+            let id = Self::resolve_collection(object_server, &collection).await?;
+            export_archive(&self.store, Arc::new(id), Path::new(&path)).await
+        })
+        .await
+    }
+
+    /// create a new collection from a tarball written by ExportCollection
+    /// returns the collection and its number of items
+    async fn import_collection(
+        &self,
+        path: String,
+        #[zbus(connection)] connection: &Connection,
+        #[zbus(object_server)] object_server: &ObjectServer,
+    ) -> Result<(ObjectPath<'static>, u32)> {
+        timing::timed("Manager.ImportCollection", async move {
+            let (id, count) = import_archive(&self.store, Path::new(&path)).await?;
+            let aliases = self.store.list_aliases_for_collection(Arc::new(id.clone())).await?;
+
+            let path = collection_path(&id).unwrap();
+            Service::register_collection(&self.store, object_server, id, aliases).await?;
+            let signal_context = SignalContext::new(connection, "/org/freedesktop/secrets")?;
+            Service::collection_created(&signal_context, path.clone()).await?;
+            Ok((path, count))
+        })
+        .await
+    }
+
+    /// read the labels and attributes written into a collection's entries back into its index
+    /// returns the number of items which had them
+    async fn reindex_entries(
+        &self,
+        collection: ObjectPath<'_>,
+        #[zbus(connection)] connection: &Connection,
+        #[zbus(object_server)] object_server: &ObjectServer,
+    ) -> Result<u32> {
+        timing::timed("Manager.ReindexEntries", async move {
+            let collection = try_interface(object_server.interface::<_, Collection>(&collection).await)?
+                .into_not_found()?
+                .get()
+                .await
+                .clone();
+            reindex_entries(connection, object_server, &collection).await
+        })
+        .await
+    }
+
+    /// pick up collections and items which were added or removed outside the daemon (e.g. by
+    /// a git pull), and open databases which were replaced again
+    /// returns (collections added, collections removed, items added, items removed)
+    async fn reload_store(
+        &self,
+        #[zbus(connection)] connection: &Connection,
+        #[zbus(object_server)] object_server: &ObjectServer,
+    ) -> Result<ReloadCounts> {
+        timing::timed("Manager.ReloadStore", async move {
+            reload_store(connection, object_server, &self.store).await
+        })
+        .await
+    }
+
+    /// remove a collection's entries for secrets whose files are gone, and rebuild its indexes
+    /// returns (items removed, files with no entry)
+    async fn repair_collection(
+        &self,
+        collection: ObjectPath<'_>,
+        #[zbus(connection)] connection: &Connection,
+        #[zbus(object_server)] object_server: &ObjectServer,
+    ) -> Result<(u32, u32)> {
+        timing::timed("Manager.RepairCollection", async move {
+            let collection = try_interface(object_server.interface::<_, Collection>(&collection).await)?
+                .into_not_found()?
+                .get()
+                .await
+                .clone();
+            repair_collection(connection, object_server, &collection).await
+        })
+        .await
+    }
+
+    /// serve a directory elsewhere in the password store (relative to it) as a new collection,
+    /// without moving its entries. an empty label uses the directory's name
+    /// returns the collection, and the entries which were skipped since their names can't be
+    /// used in object paths
+    async fn adopt_directory(
+        &self,
+        directory: String,
+        label: String,
+        #[zbus(connection)] connection: &Connection,
+        #[zbus(object_server)] object_server: &ObjectServer,
+    ) -> Result<(ObjectPath<'static>, Vec<String>)> {
+        timing::timed("Manager.AdoptDirectory", async move {
+            let directory = Path::new(&directory);
+            let label = match label.as_str() {
+                "" => directory
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or(label),
+                _ => label,
+            };
+            let (id, skipped) = self.store.adopt_directory(directory, label).await?;
+
+            let path = collection_path(&id).unwrap();
+            Service::register_collection(&self.store, object_server, id, vec![]).await?;
+            let signal_context = SignalContext::new(connection, "/org/freedesktop/secrets")?;
+            Service::collection_created(&signal_context, path.clone()).await?;
+            Ok((path, skipped))
+        })
+        .await
+    }
+
+    /// import the collections of mdellweg's pass_secret_service in `directory` (relative to the
+    /// password store), checking every secret decrypts. returns (collection, number of items)
+    /// for each new collection
+    async fn import_mdellweg(
+        &self,
+        directory: String,
+        #[zbus(connection)] connection: &Connection,
+        #[zbus(object_server)] object_server: &ObjectServer,
+    ) -> Result<Vec<(ObjectPath<'static>, u32)>> {
+        timing::timed("Manager.ImportMdellweg", async move {
+            let imported = import_mdellweg(&self.store, Path::new(&directory)).await?;
+            let aliases = self.store.list_all_aliases().await?;
+            let signal_context = SignalContext::new(connection, "/org/freedesktop/secrets")?;
+
+            let mut collections = vec![];
+            for (id, count) in imported {
+                let path = collection_path(&id).unwrap();
+                let collection_aliases = aliases.get(&id).cloned().unwrap_or_default();
+                Service::register_collection(&self.store, object_server, id, collection_aliases).await?;
+                Service::collection_created(&signal_context, path.clone()).await?;
+                collections.push((path, count as u32));
+            }
+            Ok(collections)
+        })
+        .await
+    }
+
+    /// move a collection out of the secret service's directory to `target` (relative to the
+    /// password store), with its entries named after their labels and its metadata in a YAML
+    /// file there, and stop serving it. returns the number of items which were moved
+    async fn eject_collection(
+        &self,
+        collection: ObjectPath<'_>,
+        target: String,
+        #[zbus(connection)] connection: &Connection,
+        #[zbus(object_server)] object_server: &ObjectServer,
+    ) -> Result<u32> {
+        timing::timed("Manager.EjectCollection", async move {
+            let collection = try_interface(object_server.interface::<_, Collection>(&collection).await)?
+                .into_not_found()?
+                .get()
+                .await
+                .clone();
+            eject_collection(connection, object_server, &collection, Path::new(&target)).await
+        })
+        .await
+    }
+
+    /// run `git pull` in the password store (and the metadata directory, if it's a repo),
+    /// then reload it and report which secrets changed. returns what git printed
+    async fn git_pull(
+        &self,
+        #[zbus(connection)] connection: &Connection,
+        #[zbus(object_server)] object_server: &ObjectServer,
+    ) -> Result<String> {
+        timing::timed("Manager.GitPull", async move {
+            let head = self.store.git_head().await;
+            let output = self.store.git(&["pull"]).await?;
+            reload_store(connection, object_server, &self.store).await?;
+            if let Some(head) = head {
+                report_pulled_changes(connection, &self.store, &head).await?;
+            }
+            Ok(output)
+        })
+        .await
+    }
+
+    /// run `git push` in the password store (and the metadata directory, if it's a repo)
+    /// returns what git printed
+    async fn git_push(&self) -> Result<String> {
+        timing::timed("Manager.GitPush", async move {
+            self.store.git(&["push"]).await
+        })
+        .await
+    }
+
+    /// the items in a collection's trash: (id, label, unix time it was deleted), most
+    /// recently deleted first
+    async fn list_trash(
+        &self,
+        collection: ObjectPath<'_>,
+        #[zbus(object_server)] object_server: &ObjectServer,
+    ) -> Result<Vec<(String, String, u64)>> {
+        timing::timed("Manager.ListTrash", async move {
+            let id = Self::resolve_collection(object_server, &collection).await?;
+            self.store.list_trash(Arc::new(id)).await
+        })
+        .await
+    }
+
+    /// move an item out of its collection's trash. returns its path
+    async fn restore_item(
+        &self,
+        collection: ObjectPath<'_>,
+        item: String,
+        #[zbus(connection)] connection: &Connection,
+        #[zbus(object_server)] object_server: &ObjectServer,
+    ) -> Result<ObjectPath<'static>> {
+        timing::timed("Manager.RestoreItem", async move {
+            let collection = try_interface(object_server.interface::<_, Collection>(&collection).await)?
+                .into_not_found()?
+                .get()
+                .await
+                .clone();
+            self.store
+                .restore_secret(collection.id.clone(), Arc::new(item.clone()))
+                .await?;
+
+            let signal_context = SignalContext::new(connection, collection_path(&*collection.id).unwrap())?;
+            collection.register_item(item, &signal_context, object_server).await
+        })
+        .await
+    }
+
+    /// delete an item in a collection's trash for good, or every item in it if `item` is empty
+    /// returns the number of items which were purged
+    async fn purge_trash(
+        &self,
+        collection: ObjectPath<'_>,
+        item: String,
+        #[zbus(object_server)] object_server: &ObjectServer,
+    ) -> Result<u32> {
+        timing::timed("Manager.PurgeTrash", async move {
+            let id = Self::resolve_collection(object_server, &collection).await?;
+            self.store
+                .purge_trash(Arc::new(id), |id, _| item.is_empty() || id == item)
+                .await
+        })
+        .await
+    }
+
+    /// the groups of items in a collection with exactly the same attributes (not counting
+    /// reserved ones), each oldest first
+    async fn list_duplicates(
+        &self,
+        collection: ObjectPath<'_>,
+        #[zbus(object_server)] object_server: &ObjectServer,
+    ) -> Result<Vec<Vec<ObjectPath<'static>>>> {
+        timing::timed("Manager.ListDuplicates", async move {
+            let id = Self::resolve_collection(object_server, &collection).await?;
+            let groups = self.store.duplicate_groups(Arc::new(id.clone())).await?;
+            Ok(groups
+                .into_iter()
+                .map(|group| group.iter().filter_map(|secret| secret_path(&id, secret)).collect())
+                .collect())
+        })
+        .await
+    }
+
+    /// delete every item ListDuplicates returns except the newest of each group
+    /// returns the number of items which were deleted
+    async fn purge_duplicates(
+        &self,
+        collection: ObjectPath<'_>,
+        #[zbus(connection)] connection: &Connection,
+        #[zbus(object_server)] object_server: &ObjectServer,
+        #[zbus(header)] header: Header<'_>,
+    ) -> Result<u32> {
+        timing::timed("Manager.PurgeDuplicates", async move {
+            let collection = try_interface(object_server.interface::<_, Collection>(&collection).await)?
+                .into_not_found()?
+                .get()
+                .await
+                .clone();
+            let groups = self.store.duplicate_groups(collection.id.clone()).await?;
+
+            let signal_context = SignalContext::new(connection, collection_path(&*collection.id).unwrap())?;
+            let mut count = 0;
+            for mut group in groups {
+                group.pop();
+                for secret_id in group {
+                    collection.delete_item(&secret_id, &signal_context, object_server).await?;
+                    audit::record(connection, &header, Operation::DeleteItem, &collection.id, Some(&secret_id)).await;
+                    count += 1;
+                }
+            }
+            Ok(count)
+        })
+        .await
+    }
+
+    /// the collections and items found on start whose ids can't be part of an object path as
+    /// they are: (collection id, item id or empty for the collection, the id the item was
+    /// renamed to or empty if it's served at an escaped path instead)
+    async fn startup_report(&self) -> Vec<(String, String, String)> {
+        self.unreachable
+            .iter()
+            .map(|found| {
+                (
+                    found.collection_id.clone(),
+                    found.secret_id.clone().unwrap_or_default(),
+                    found.renamed_to.clone().unwrap_or_default(),
+                )
+            })
+            .collect()
+    }
+
+    /// counters for status bars and scripts: collections, items, locked-collections,
+    /// sessions, queued-writes, and uptime-seconds
+    async fn stats(&self) -> HashMap<&str, u64> {
+        let collections = self.store.collections().await;
+        let items = collections
+            .iter()
+            .map(|id| self.store.visible_items(id).len() as u64)
+            .sum();
+        let locked = collections.iter().filter(|id| self.store.is_locked(id)).count();
+        HashMap::from([
+            ("collections", collections.len() as u64),
+            ("items", items),
+            ("locked-collections", locked as u64),
+            ("sessions", self.sessions.lock().unwrap().len() as u64),
+            (
+                "queued-writes",
+                self.store
+                    .write_queue_status()
+                    .map(|status| status.pending.into())
+                    .unwrap_or_default(),
+            ),
+            ("uptime-seconds", self.started.elapsed().as_secs()),
+        ])
+    }
+
+    /// an item will expire soon (or already has). `expires` is in unix seconds
+    #[zbus(signal)]
+    pub async fn item_expiring(
+        ctxt: &SignalContext<'_>,
+        item: ObjectPath<'_>,
+        expires: u64,
+    ) -> zbus::Result<()>;
+
+    /// a client was given a prompt. `kind` is what it does (e.g. "unlock"), and `app` is the
+    /// executable of the client, or its bus name if that can't be read
+    #[zbus(signal)]
+    pub async fn prompt_created(
+        ctxt: &SignalContext<'_>,
+        prompt: ObjectPath<'_>,
+        kind: &str,
+        app: &str,
+    ) -> zbus::Result<()>;
+
+    /// a prompt was completed or dismissed (including when its client disconnected)
+    #[zbus(signal)]
+    pub async fn prompt_completed(
+        ctxt: &SignalContext<'_>,
+        prompt: ObjectPath<'_>,
+        kind: &str,
+        app: &str,
+        dismissed: bool,
+    ) -> zbus::Result<()>;
+
+    /// a collection or item was created, changed, or deleted, by a client or the daemon
+    /// itself. `item` is "/" if the collection itself changed. `change` is "created",
+    /// "changed", or "deleted"
+    #[zbus(signal)]
+    pub async fn store_changed(
+        ctxt: &SignalContext<'_>,
+        collection: ObjectPath<'_>,
+        item: ObjectPath<'_>,
+        change: &str,
+    ) -> zbus::Result<()>;
+
+    /// a git pull (see GitPull) added, changed, or removed secrets in a collection. these are
+    /// how many of each
+    #[zbus(signal)]
+    pub async fn git_pulled(
+        ctxt: &SignalContext<'_>,
+        collection: ObjectPath<'_>,
+        added: u32,
+        changed: u32,
+        removed: u32,
+    ) -> zbus::Result<()>;
+
+    /// an alias was pointed at another collection, or removed. `old` is "/" if it wasn't set,
+    /// and `new` is "/" if it was removed, including when the collection it pointed at is gone
+    #[zbus(signal)]
+    pub async fn alias_changed(
+        ctxt: &SignalContext<'_>,
+        alias: &str,
+        old: ObjectPath<'_>,
+        new: ObjectPath<'_>,
+    ) -> zbus::Result<()>;
+}
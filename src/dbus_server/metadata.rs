@@ -0,0 +1,256 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    io,
+    path::Path,
+    sync::Arc,
+};
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+use zbus::{object_server::SignalContext, Connection, ObjectServer};
+
+use crate::{
+    error::{Error, Result},
+    secret_store::{slugify, SecretStore},
+};
+
+use super::{
+    collection::Collection,
+    item::Item,
+    utils::{collection_path, secret_path, try_interface},
+};
+
+/// where an ejected collection's metadata is written, in its new directory
+const EJECTED_METADATA_FILE: &str = "secret-service-metadata.yaml";
+
+/// the labels, attributes, and aliases of a collection, without any secrets
+/// maps are sorted so exports of the same collection diff cleanly
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub(super) struct CollectionMetadata {
+    pub(super) label: String,
+    #[serde(default)]
+    pub(super) aliases: Vec<String>,
+    /// display hints for frontends
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(super) sort_order: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(super) color: Option<String>,
+    /// by item id, which is the name of its file in the collection's directory
+    #[serde(default)]
+    pub(super) items: BTreeMap<String, ItemMetadata>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub(super) struct ItemMetadata {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(super) label: Option<String>,
+    #[serde(default)]
+    pub(super) attributes: BTreeMap<String, String>,
+}
+
+/// `None` if the store doesn't have it
+pub(super) fn optional<T>(result: Result<T>) -> Result<Option<T>> {
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(Error::IoError(e)) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// write a collection's metadata to a YAML file
+pub async fn export_metadata(store: &SecretStore<'_>, collection_id: Arc<String>, path: &Path) -> Result {
+    let metadata = collection_metadata(store, collection_id).await?;
+    let yaml = serde_yaml::to_string(&metadata).expect("the metadata can be serialized");
+    tokio::fs::write(path, yaml).await?;
+    Ok(())
+}
+
+pub(super) async fn collection_metadata(store: &SecretStore<'_>, collection_id: Arc<String>) -> Result<CollectionMetadata> {
+    let mut aliases = store.list_aliases_for_collection(collection_id.clone()).await?;
+    aliases.sort();
+
+    let mut items = BTreeMap::new();
+    for id in store.list_secrets(&collection_id).await? {
+        let id = Arc::new(id);
+        let label = optional(store.get_secret_label(collection_id.clone(), id.clone()).await)?;
+        let attributes = optional(store.read_secret_attrs(collection_id.clone(), id.clone()).await)?
+            .unwrap_or_default()
+            .into_iter()
+            .collect();
+        items.insert(id.to_string(), ItemMetadata { label, attributes });
+    }
+
+    Ok(CollectionMetadata {
+        label: store.get_label(collection_id.clone()).await?,
+        aliases,
+        sort_order: store.get_sort_order(collection_id.clone()).await?,
+        color: store.get_color(collection_id).await?,
+        items,
+    })
+}
+
+/// move a collection out of the secret service's directory to `target` (relative to the password
+/// store) as a plain pass directory, with its entries named after their labels and its metadata
+/// in `EJECTED_METADATA_FILE` there, then stop serving it
+/// returns the number of items which were moved
+pub async fn eject_collection(
+    connection: &Connection,
+    object_server: &ObjectServer,
+    collection: &Collection<'static>,
+    target: &Path,
+) -> Result<u32> {
+    let store = &collection.store;
+    let id = collection.id.clone();
+    let mut metadata = collection_metadata(store, id.clone()).await?;
+
+    let _aliases = store.lock_aliases().await;
+    let secrets = store.list_secrets(&id).await?;
+    let names = store.eject_collection(id.clone(), target).await?;
+
+    // by their new names, so they can be imported again
+    metadata.items = metadata
+        .items
+        .into_iter()
+        .map(|(secret_id, item)| (names.get(&secret_id).cloned().unwrap_or(secret_id), item))
+        .collect();
+    let yaml = serde_yaml::to_string(&metadata).expect("the metadata can be serialized");
+    tokio::fs::write(store.store_path().join(target).join(EJECTED_METADATA_FILE), yaml).await?;
+
+    store.hide_collection(&id);
+    let aliases = collection.remove_objects(connection, object_server, &secrets).await?;
+    store.forget_collection(id.clone()).await?;
+    Collection::emit_aliases_removed(connection, &id, &aliases).await?;
+
+    Ok(names.len() as u32)
+}
+
+/// apply the metadata in a YAML file to a collection
+/// items whose secret isn't in the collection are skipped. nothing is removed
+/// returns the number of items which were updated
+pub async fn import_metadata(
+    connection: &Connection,
+    object_server: &ObjectServer,
+    collection: &Collection<'static>,
+    path: &Path,
+) -> Result<u32> {
+    let yaml = tokio::fs::read_to_string(path).await?;
+    let metadata: CollectionMetadata =
+        serde_yaml::from_str(&yaml).map_err(|e| Error::InvalidMetadata(e.to_string()))?;
+
+    let store = &collection.store;
+    let id = collection.id.clone();
+
+    store.set_label(id.clone(), metadata.label).await?;
+    if metadata.sort_order.is_some() {
+        store.set_sort_order(id.clone(), metadata.sort_order).await?;
+    }
+    if metadata.color.is_some() {
+        store.set_color(id.clone(), metadata.color).await?;
+    }
+
+    for alias in metadata.aliases {
+        let alias = Arc::new(slugify(&alias));
+        if optional(store.get_alias(alias.clone()).await)?.as_deref() != Some(id.as_str()) {
+            let old = Collection::move_alias(store, object_server, alias.clone(), Some(collection)).await?;
+            Collection::emit_alias_changed(connection, &alias, old.as_deref(), Some(&id)).await?;
+        }
+    }
+
+    // including files which aren't items yet, so this can add them
+    let secrets = store.list_secret_files(&id).await?;
+    let signal_context = SignalContext::new(connection, collection_path(&*id).unwrap())?;
+    let mut updated = 0;
+    for (secret_id, item) in metadata.items {
+        if !secrets.contains(&secret_id) {
+            warn!("Skipping metadata for item {secret_id}, which is not in collection {id}");
+            continue;
+        }
+
+        let secret_id = Arc::new(secret_id);
+        if let Some(label) = item.label {
+            store
+                .set_secret_label(id.clone(), secret_id.clone(), label)
+                .await?;
+        }
+        store
+            .set_secret_attrs(id.clone(), secret_id.clone(), HashMap::from_iter(item.attributes))
+            .await?;
+        updated += 1;
+
+        // its file may have been added (e.g. by git) since the daemon started
+        let path = secret_path(&*id, &*secret_id).unwrap();
+        if try_interface(object_server.interface::<_, Item>(&path).await)?.is_none() {
+            collection
+                .register_item(secret_id.to_string(), &signal_context, object_server)
+                .await?;
+        }
+    }
+
+    Ok(updated)
+}
+
+/// read the labels and attributes written into the entries of a collection's secrets
+/// (with `embed-metadata`) back into its index
+/// returns the number of items which had metadata in their entry
+pub async fn reindex_entries(
+    connection: &Connection,
+    object_server: &ObjectServer,
+    collection: &Collection<'static>,
+) -> Result<u32> {
+    let store = &collection.store;
+    let id = collection.id.clone();
+    let signal_context = SignalContext::new(connection, collection_path(&*id).unwrap())?;
+
+    let mut reindexed = 0;
+    for secret_id in store.list_secret_files(&id).await? {
+        let secret_id = Arc::new(secret_id);
+        if !store.reindex_secret(id.clone(), secret_id.clone()).await? {
+            continue;
+        }
+        reindexed += 1;
+
+        let path = secret_path(&*id, &*secret_id).unwrap();
+        if try_interface(object_server.interface::<_, Item>(&path).await)?.is_none() {
+            collection
+                .register_item(secret_id.to_string(), &signal_context, object_server)
+                .await?;
+        }
+    }
+
+    Ok(reindexed)
+}
+
+#[test]
+fn test_metadata_yaml() {
+    let metadata = CollectionMetadata {
+        label: "Work".to_owned(),
+        aliases: vec!["default".to_owned()],
+        sort_order: None,
+        color: None,
+        items: BTreeMap::from([(
+            "abc".to_owned(),
+            ItemMetadata {
+                label: None,
+                attributes: BTreeMap::from([("user".to_owned(), "me".to_owned())]),
+            },
+        )]),
+    };
+    let yaml = serde_yaml::to_string(&metadata).unwrap();
+    assert_eq!(
+        yaml,
+        "label: Work\naliases:\n- default\nitems:\n  abc:\n    attributes:\n      user: me\n"
+    );
+
+    let parsed: CollectionMetadata = serde_yaml::from_str(&yaml).unwrap();
+    assert_eq!(parsed.items, metadata.items);
+
+    // hand-written files can leave things out, but typos are caught
+    let parsed: CollectionMetadata = serde_yaml::from_str("label: Work").unwrap();
+    assert!(parsed.items.is_empty());
+    let parsed: CollectionMetadata =
+        serde_yaml::from_str("label: Work\nsort-order: 2\ncolor: '#3584e4'").unwrap();
+    assert_eq!((parsed.sort_order, parsed.color.as_deref()), (Some(2), Some("#3584e4")));
+    assert!(serde_yaml::from_str::<CollectionMetadata>("label: Work\nalias: [x]").is_err());
+}
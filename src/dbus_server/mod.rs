@@ -1,5 +1,19 @@
+mod archive;
+mod changes;
 mod collection;
+mod dump;
+mod expiry;
+mod gnome_keyring;
+mod info;
 mod item;
+mod manager;
+mod metadata;
+pub mod name;
+mod notifications;
+mod portal;
+mod prompt;
+mod reload;
 pub mod service;
 mod session;
-mod utils;
+mod sweep;
+pub mod utils;
@@ -0,0 +1,99 @@
+use std::io;
+
+use futures_util::StreamExt;
+use log::{info, warn};
+use zbus::{
+    fdo::{DBusProxy, RequestNameFlags, RequestNameReply},
+    names::{BusName, WellKnownName},
+    Connection,
+};
+
+use crate::{
+    error::{Error, Result},
+    systemd,
+};
+
+use super::{gnome_keyring::GNOME_KEYRING_NAME, utils::process_exe};
+
+pub const SERVICE_NAME: &str = "org.freedesktop.secrets";
+
+/// describe the process which owns the secret service name, e.g. `gnome-keyring-daemon (PID 1234)`
+async fn describe_owner(dbus: &DBusProxy<'_>) -> String {
+    let name = BusName::WellKnown(WellKnownName::from_static_str_unchecked(SERVICE_NAME));
+    let Ok(owner) = dbus.get_name_owner(name).await else {
+        return "an unknown process".into();
+    };
+    let Some(pid) = dbus
+        .get_connection_credentials(BusName::Unique(owner.as_ref()))
+        .await
+        .ok()
+        .and_then(|credentials| credentials.process_id())
+    else {
+        return owner.to_string();
+    };
+
+    // the executable can't be read if it belongs to another user
+    let exe = match process_exe(pid).await {
+        Some(exe) => exe,
+        None => tokio::fs::read_to_string(format!("/proc/{pid}/comm"))
+            .await
+            .map(|comm| comm.trim().to_owned())
+            .unwrap_or_else(|_| owner.to_string()),
+    };
+    format!("{exe} (PID {pid})")
+}
+
+/// describe the process which owns the secret service name, or None if nobody does
+pub async fn service_name_owner(connection: &Connection) -> Result<Option<String>> {
+    let dbus = DBusProxy::new(connection).await?;
+    let name = BusName::WellKnown(WellKnownName::from_static_str_unchecked(SERVICE_NAME));
+    if dbus.name_has_owner(name).await.map_err(zbus::Error::from)? {
+        Ok(Some(describe_owner(&dbus).await))
+    } else {
+        Ok(None)
+    }
+}
+
+/// take the secret service name
+/// with `queue`, wait until the current owner releases it. with `replace`, take it from
+/// the owner if it allows that
+pub async fn request_service_name(connection: &Connection, queue: bool, replace: bool) -> Result {
+    let dbus = DBusProxy::new(connection).await?;
+    // subscribe first so the signal can't be missed
+    let mut acquired = dbus.receive_name_acquired().await?;
+
+    let flags = match (queue, replace) {
+        (true, _) => Default::default(),
+        (false, true) => RequestNameFlags::ReplaceExisting | RequestNameFlags::DoNotQueue,
+        (false, false) => RequestNameFlags::DoNotQueue.into(),
+    };
+    match connection.request_name_with_flags(SERVICE_NAME, flags).await {
+        Ok(RequestNameReply::InQueue) => {}
+        Ok(_) => return Ok(()),
+        Err(zbus::Error::NameTaken) => {
+            return Err(Error::NameTaken(describe_owner(&dbus).await, replace))
+        }
+        Err(e) => return Err(e.into()),
+    }
+
+    let owner = describe_owner(&dbus).await;
+    info!("{owner} owns {SERVICE_NAME}. Waiting for it to release the name");
+    systemd::notify(&format!("STATUS=Waiting for {owner} to release {SERVICE_NAME}"));
+    while let Some(signal) = acquired.next().await {
+        if signal.args()?.name == SERVICE_NAME {
+            info!("Acquired {SERVICE_NAME}");
+            return Ok(());
+        }
+    }
+    Err(io::Error::new(io::ErrorKind::UnexpectedEof, "the bus connection closed").into())
+}
+
+/// take gnome-keyring's own name too, for clients which check for it. it's fine if this fails,
+/// since most of them only need the secret service
+pub async fn request_gnome_keyring_name(connection: &Connection) {
+    let flags = RequestNameFlags::DoNotQueue.into();
+    match connection.request_name_with_flags(GNOME_KEYRING_NAME, flags).await {
+        Ok(_) => info!("Acquired {GNOME_KEYRING_NAME}"),
+        Err(e) => warn!("Could not take {GNOME_KEYRING_NAME}: {e}"),
+    }
+}
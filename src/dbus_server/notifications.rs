@@ -0,0 +1,119 @@
+use std::collections::HashMap;
+
+use log::warn;
+use zbus::{message::Header, zvariant::Value, Connection};
+
+use crate::secret_store::SecretStore;
+
+use super::utils::client_exe;
+
+/// events which can show a desktop notification
+pub enum Event<'a> {
+    NewClient { exe: &'a str },
+    ItemDeleted { label: &'a str },
+    CollectionDeleted { label: &'a str },
+    DecryptFailed { label: &'a str },
+    /// days is negative if it already expired
+    ItemExpiring { label: &'a str, days: i64 },
+    GitPulled { label: &'a str, added: usize, changed: usize, removed: usize },
+}
+
+impl Event<'_> {
+    /// whether the user opted into notifications for this event
+    fn is_enabled(&self, store: &SecretStore) -> bool {
+        let config = &store.config.notifications;
+        match self {
+            Event::NewClient { .. } => config.new_client,
+            Event::ItemDeleted { .. } => config.item_deleted,
+            Event::CollectionDeleted { .. } => config.collection_deleted,
+            Event::DecryptFailed { .. } => config.decrypt_failed,
+            Event::ItemExpiring { .. } => config.item_expiring,
+            Event::GitPulled { .. } => config.git_pull,
+        }
+    }
+
+    /// (summary, body)
+    fn message(&self) -> (&'static str, String) {
+        match self {
+            Event::NewClient { exe } => (
+                "New application accessed secrets",
+                format!("{exe} read a secret for the first time"),
+            ),
+            Event::ItemDeleted { label } => ("Secret deleted", format!("\"{label}\" was deleted")),
+            Event::CollectionDeleted { label } => (
+                "Collection deleted",
+                format!("\"{label}\" and all of its secrets were deleted"),
+            ),
+            Event::DecryptFailed { label } => {
+                ("Decryption failed", format!("Could not decrypt \"{label}\""))
+            }
+            Event::ItemExpiring { label, days } => (
+                "Secret expiring",
+                match days {
+                    ..=-1 => format!("\"{label}\" has expired"),
+                    0 => format!("\"{label}\" expires today"),
+                    1 => format!("\"{label}\" expires tomorrow"),
+                    _ => format!("\"{label}\" expires in {days} days"),
+                },
+            ),
+            Event::GitPulled { label, added, changed, removed } => (
+                "Secrets changed by git pull",
+                format!("\"{label}\": {added} added, {changed} changed, {removed} removed"),
+            ),
+        }
+    }
+}
+
+/// show a desktop notification for this event if it's enabled
+/// failures are logged rather than returned, and the notification is sent in the background
+pub fn notify(connection: &Connection, store: &SecretStore, event: Event<'_>) {
+    if !event.is_enabled(store) {
+        return;
+    }
+
+    let (summary, body) = event.message();
+    let connection = connection.clone();
+
+    tokio::spawn(async move {
+        let result = connection
+            .call_method(
+                Some("org.freedesktop.Notifications"),
+                "/org/freedesktop/Notifications",
+                Some("org.freedesktop.Notifications"),
+                "Notify",
+                &(
+                    "pass-secret-service",
+                    0u32,
+                    "dialog-password",
+                    summary,
+                    body,
+                    Vec::<&str>::new(),
+                    HashMap::<&str, Value>::new(),
+                    -1i32,
+                ),
+            )
+            .await;
+
+        if let Err(e) = result {
+            warn!("Could not show notification: {e}");
+        }
+    });
+}
+
+/// notify if the client which sent this message hasn't read secrets before
+pub async fn notify_if_new_client(connection: &Connection, header: &Header<'_>, store: &SecretStore<'_>) {
+    // don't bother looking up the client if we aren't going to notify
+    if !store.config.notifications.new_client {
+        return;
+    }
+
+    let Some(exe) = client_exe(connection, header).await else {
+        return;
+    };
+
+    match store.record_client(exe.clone()).await {
+        Ok(true) => notify(connection, store, Event::NewClient { exe: &exe }),
+        Ok(false) => {}
+        Err(e) => warn!("Could not record client {exe}: {e}"),
+    }
+}
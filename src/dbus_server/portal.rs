@@ -0,0 +1,143 @@
+use std::{collections::HashMap, fs::File, io::Write, sync::Arc};
+
+use log::{info, warn};
+use rand::RngCore;
+use zbus::{
+    interface,
+    message::Header,
+    object_server::SignalContext,
+    zvariant::{ObjectPath, OwnedFd, OwnedValue},
+    Connection,
+};
+
+use crate::{
+    audit::{self, Operation},
+    error::{Error, Result},
+    secret_store::SecretStore,
+    timing,
+};
+
+use super::{
+    collection::Collection,
+    utils::{collection_path, try_interface},
+};
+
+/// where xdg-desktop-portal looks for its backends' interfaces
+pub const PORTAL_PATH: &str = "/org/freedesktop/portal/desktop";
+/// the schema of the items holding apps' secrets. the same as gnome-keyring's, so items imported
+/// from it keep working
+const PORTAL_SCHEMA: &str = "org.freedesktop.impl.portal.Secret";
+/// how many random bytes an app's secret has
+const SECRET_LEN: usize = 64;
+
+/// the responses of a portal request
+const RESPONSE_SUCCESS: u32 = 0;
+const RESPONSE_CANCELLED: u32 = 1;
+const RESPONSE_FAILED: u32 = 2;
+
+/// the backend of the Secret portal, which gives each sandboxed (e.g. Flatpak) app a secret of
+/// its own. apps use it to encrypt their secrets themselves (libsecret does this in a sandbox),
+/// so the secret is all that's stored here
+#[derive(Debug)]
+pub struct Portal<'a> {
+    pub store: SecretStore<'a>,
+}
+
+#[interface(name = "org.freedesktop.impl.portal.Secret")]
+impl Portal<'static> {
+    /// write the app's secret to `fd`, generating it in the default collection the first time
+    async fn retrieve_secret(
+        &self,
+        _handle: ObjectPath<'_>,
+        app_id: String,
+        fd: OwnedFd,
+        _options: HashMap<String, OwnedValue>,
+        #[zbus(connection)] connection: &Connection,
+        #[zbus(header)] header: Header<'_>,
+    ) -> (u32, HashMap<String, OwnedValue>) {
+        timing::timed("Portal.RetrieveSecret", async move {
+            let written = match self.app_secret(&app_id, connection, &header).await {
+                Ok(secret) => File::from(std::os::fd::OwnedFd::from(fd))
+                    .write_all(&secret)
+                    .map_err(Error::from),
+                Err(e) => Err(e),
+            };
+            let response = match written {
+                Ok(()) => RESPONSE_SUCCESS,
+                Err(Error::IsLocked) => {
+                    info!("Not giving {app_id} its secret, since the default collection is still locked");
+                    RESPONSE_CANCELLED
+                }
+                Err(e) => {
+                    warn!("Could not retrieve the secret of {app_id}: {e}");
+                    RESPONSE_FAILED
+                }
+            };
+            (response, HashMap::new())
+        })
+        .await
+    }
+
+    #[zbus(property, name = "version")]
+    fn version(&self) -> u32 {
+        1
+    }
+}
+
+impl Portal<'_> {
+    /// read the app's secret from the default collection (unlocking it first), or create it
+    async fn app_secret(&self, app_id: &str, connection: &Connection, header: &Header<'_>) -> Result<Vec<u8>> {
+        let collection_id = Arc::new(self.store.get_alias(Arc::new("default".to_owned())).await?);
+        if self.store.is_locked(&collection_id) {
+            // there's no client to unlock it, so ask for the passphrase like Unlock does
+            if self.store.unlock_collections(vec![collection_id.clone()]).await?.is_empty() {
+                return Err(Error::IsLocked);
+            }
+            Collection::emit_locked_changed(&connection.object_server(), &collection_id).await?;
+        }
+        self.store.confirm_read(&collection_id).await?;
+
+        let attributes = Arc::new(HashMap::from([
+            ("app_id".to_owned(), app_id.to_owned()),
+            ("xdg:schema".to_owned(), PORTAL_SCHEMA.to_owned()),
+        ]));
+        let found = self
+            .store
+            .search_collection(collection_id.clone(), attributes.clone())
+            .await?;
+        if let Some(secret_id) = found.first() {
+            self.store.confirm_item_read(&collection_id, secret_id).await?;
+            let secret = self.store.read_secret(&collection_id, secret_id, true).await?;
+            audit::record(connection, header, Operation::GetSecret, &collection_id, Some(secret_id)).await;
+            return Ok(secret);
+        }
+
+        let mut secret = vec![0; SECRET_LEN];
+        rand::thread_rng().fill_bytes(&mut secret);
+        let secret_id = self
+            .store
+            .create_secret(
+                collection_id.clone(),
+                Some(format!("Application key for {app_id}")),
+                secret.clone(),
+                attributes,
+            )
+            .await?;
+        // binary, so it's never filtered
+        self.store
+            .set_content_type(collection_id.clone(), Arc::new(secret_id.clone()), "application/octet-stream".to_owned())
+            .await?;
+        audit::record(connection, header, Operation::CreateItem, &collection_id, Some(&secret_id)).await;
+
+        // serve the new item like one created with CreateItem
+        let path = collection_path(&*collection_id).unwrap();
+        let object_server = connection.object_server();
+        if let Some(collection) = try_interface(object_server.interface::<_, Collection>(&path).await)? {
+            let collection = collection.get().await.clone();
+            let signal_context = SignalContext::new(connection, path)?;
+            collection.register_item(secret_id, &signal_context, &object_server).await?;
+        }
+        info!("Created the secret of {app_id} in collection {collection_id}");
+        Ok(secret)
+    }
+}
@@ -0,0 +1,229 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use futures_util::StreamExt;
+use log::warn;
+use tokio::{select, sync::Notify};
+use zbus::{
+    fdo::DBusProxy,
+    interface,
+    names::OwnedUniqueName,
+    object_server::SignalContext,
+    zvariant::{OwnedObjectPath, Value},
+    Connection,
+};
+
+use crate::{error::Result, secret_store::SecretStore, timing};
+
+use super::{collection::Collection, manager::Manager, utils::try_interface};
+
+/// set to 1 to complete prompts without asking, e.g. to unlock collections without
+/// a passphrase in tests. never set this outside a test environment
+pub const AUTO_ACCEPT_VAR: &str = "PASS_SECRET_SERVICE_AUTO_ACCEPT_PROMPTS";
+
+pub fn auto_accept() -> bool {
+    std::env::var_os(AUTO_ACCEPT_VAR).is_some_and(|value| value == "1")
+}
+
+/// what happens when a prompt is completed
+#[derive(Debug, Clone)]
+pub enum PromptAction {
+    /// unlock the collections of these objects
+    /// (object, id of its collection)
+    Unlock(Vec<(OwnedObjectPath, Arc<String>)>),
+}
+
+impl PromptAction {
+    /// the prompt type announced in PromptCreated and PromptCompleted
+    pub fn kind(&self) -> &'static str {
+        match self {
+            PromptAction::Unlock(_) => "unlock",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Prompt<'a> {
+    pub store: SecretStore<'a>,
+    pub path: OwnedObjectPath,
+    pub action: PromptAction,
+    /// the executable of the client which requested it, or its bus name
+    pub app: String,
+    started: AtomicBool,
+    /// notified once Completed has been sent
+    finished: Arc<Notify>,
+}
+
+impl Prompt<'static> {
+    /// a prompt for `client`. it's removed if the client disconnects without finishing it
+    pub fn new(
+        store: SecretStore<'static>,
+        path: OwnedObjectPath,
+        action: PromptAction,
+        client: OwnedUniqueName,
+        app: String,
+        connection: Connection,
+    ) -> Self {
+        let finished = Arc::new(Notify::new());
+
+        let finished_2 = finished.clone();
+        let path_2 = path.clone();
+        let kind = action.kind();
+        let app_2 = app.clone();
+        tokio::spawn(async move {
+            let dbus = DBusProxy::new(&connection).await?;
+            let mut name_gone_stream = dbus
+                .receive_name_owner_changed_with_args(&[(0, client.as_str()), (2, "")])
+                .await?;
+
+            select! {
+                _ = finished_2.notified() => {
+                    // already removed
+                },
+                _ = name_gone_stream.next() => {
+                    try_interface(connection.object_server().remove::<Self, _>(&path_2).await)?;
+                    let ctxt = SignalContext::new(&connection, "/org/freedesktop/secrets")?;
+                    Manager::prompt_completed(&ctxt, path_2.as_ref(), kind, &app_2, true).await?;
+                }
+            }
+
+            zbus::Result::Ok(())
+        });
+
+        Self {
+            store,
+            path,
+            action,
+            app,
+            started: AtomicBool::new(false),
+            finished,
+        }
+    }
+
+    /// run the action, then send its result to the client
+    async fn complete(
+        connection: &Connection,
+        store: SecretStore<'static>,
+        path: &OwnedObjectPath,
+        action: PromptAction,
+        app: &str,
+        finished: &Notify,
+    ) -> Result {
+        let kind = action.kind();
+        let result = match action {
+            PromptAction::Unlock(objects) => Self::unlock(connection, &store, objects).await?,
+        };
+
+        Self::finish(connection, path, kind, app, false, result, finished).await
+    }
+
+    /// send Completed and remove this prompt
+    async fn finish(
+        connection: &Connection,
+        path: &OwnedObjectPath,
+        kind: &str,
+        app: &str,
+        dismissed: bool,
+        result: Value<'_>,
+        finished: &Notify,
+    ) -> Result {
+        finished.notify_one();
+        let ctxt = SignalContext::new(connection, path)?;
+        Self::completed(&ctxt, dismissed, result).await?;
+        try_interface(connection.object_server().remove::<Self, _>(path).await)?;
+
+        let ctxt = SignalContext::new(connection, "/org/freedesktop/secrets")?;
+        Manager::prompt_completed(&ctxt, path.as_ref(), kind, app, dismissed).await?;
+        Ok(())
+    }
+
+    /// the result of a dismissed prompt
+    fn dismissed_result() -> Value<'static> {
+        Value::from(Vec::<OwnedObjectPath>::new())
+    }
+
+    /// unlock the collections, returning the objects which were unlocked
+    async fn unlock(
+        connection: &Connection,
+        store: &SecretStore<'static>,
+        objects: Vec<(OwnedObjectPath, Arc<String>)>,
+    ) -> Result<Value<'static>> {
+        let mut collection_ids: Vec<_> = objects.iter().map(|(_, id)| id.clone()).collect();
+        collection_ids.sort();
+        collection_ids.dedup();
+
+        let unlocked = if auto_accept() {
+            collection_ids
+                .into_iter()
+                .filter(|id| store.set_locked(id, false))
+                .collect()
+        } else {
+            store.unlock_collections(collection_ids).await?
+        };
+        let object_server = connection.object_server();
+        for id in unlocked {
+            Collection::emit_locked_changed(&object_server, &id).await?;
+        }
+
+        let unlocked: Vec<_> = objects
+            .into_iter()
+            .filter(|(_, id)| !store.is_locked(id))
+            .map(|(object, _)| object)
+            .collect();
+
+        Ok(Value::from(unlocked))
+    }
+}
+
+#[interface(name = "org.freedesktop.Secret.Prompt")]
+impl Prompt<'static> {
+    async fn prompt(&self, _window_id: String, #[zbus(connection)] connection: &Connection) {
+        // a prompt can only be used once
+        if self.started.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        // gpg may wait on pinentry for a while, so don't block the method call
+        let connection = connection.clone();
+        let store = self.store.clone();
+        let path = self.path.clone();
+        let action = self.action.clone();
+        let app = self.app.clone();
+        let finished = self.finished.clone();
+        tokio::spawn(async move {
+            let kind = action.kind();
+            let completed = Self::complete(&connection, store, &path, action, &app, &finished);
+            if let Err(e) = timing::timed("Prompt.Prompt", completed).await {
+                warn!("Could not complete prompt: {e}");
+
+                // don't leave the client waiting for Completed
+                let result = Self::dismissed_result();
+                if let Err(e) = Self::finish(&connection, &path, kind, &app, true, result, &finished).await {
+                    warn!("Could not dismiss prompt: {e}");
+                }
+            }
+        });
+    }
+
+    async fn dismiss(&self, #[zbus(connection)] connection: &Connection) -> Result<()> {
+        if self.started.swap(true, Ordering::SeqCst) {
+            return Ok(());
+        }
+
+        Self::finish(
+            connection,
+            &self.path,
+            self.action.kind(),
+            &self.app,
+            true,
+            Self::dismissed_result(),
+            &self.finished,
+        )
+        .await
+    }
+
+    #[zbus(signal)]
+    async fn completed(ctxt: &SignalContext<'_>, dismissed: bool, result: Value<'_>) -> zbus::Result<()>;
+}
@@ -0,0 +1,165 @@
+use std::{collections::HashSet, sync::Arc};
+
+use log::{info, warn};
+use tokio::sync::broadcast::error::RecvError;
+use zbus::{object_server::SignalContext, Connection, ObjectServer};
+
+use crate::{
+    error::Result,
+    secret_store::SecretStore,
+};
+
+use super::{
+    collection::Collection,
+    manager::Manager,
+    notifications::{notify, Event},
+    service::Service,
+    utils::{collection_path, secret_path, try_interface},
+};
+
+/// what changed: (collections added, collections removed, items added, items removed)
+pub type ReloadCounts = (u32, u32, u32, u32);
+
+/// pick up changes made to the store outside the daemon, e.g. by a git pull, and serve them
+pub async fn reload_store(
+    connection: &Connection,
+    object_server: &ObjectServer,
+    store: &SecretStore<'static>,
+) -> Result<ReloadCounts> {
+    let added = store.reload().await?;
+    let (mut collections_removed, mut items_added, mut items_removed) = (0, 0, 0);
+
+    for id in store.collections().await {
+        if added.contains(&id) {
+            continue;
+        }
+        // it isn't served, since its id can't be part of a path
+        let Some(path) = collection_path(&id) else {
+            continue;
+        };
+        let Some(collection) = try_interface(object_server.interface::<_, Collection>(&path).await)?
+        else {
+            continue;
+        };
+        let collection = collection.get().await.clone();
+        if !collection.resync_if_deleted(connection, object_server).await? {
+            collections_removed += 1;
+            continue;
+        }
+
+        let stored: HashSet<_> = store.list_secrets(&id).await?.into_iter().collect();
+        let visible: HashSet<_> = store.visible_items(&id).into_iter().collect();
+        let signal_context = SignalContext::new(connection, path)?;
+        for secret_id in stored.difference(&visible) {
+            collection
+                .register_item(secret_id.clone(), &signal_context, object_server)
+                .await?;
+            items_added += 1;
+        }
+        for secret_id in visible.difference(&stored) {
+            collection
+                .forget_item(secret_id, &signal_context, object_server)
+                .await?;
+            items_removed += 1;
+        }
+    }
+
+    let mut aliases = store.list_all_aliases().await?;
+    let signal_context = SignalContext::new(connection, "/org/freedesktop/secrets")?;
+    for id in &added {
+        let collection_aliases = aliases.remove(id).unwrap_or_default();
+        Service::register_collection(store, object_server, id.clone(), collection_aliases).await?;
+        if let Some(path) = collection_path(id) {
+            Service::collection_created(&signal_context, path).await?;
+        }
+    }
+
+    Ok((added.len() as u32, collections_removed, items_added, items_removed))
+}
+
+/// forget collections whose directory was found to be removed when one of their items was
+/// changed, until the daemon exits. the item's own method can't always do it, since property
+/// setters don't get the connection
+pub async fn watch_lost_collections(connection: Connection, store: SecretStore<'static>) {
+    let mut lost = store.subscribe_lost_collections();
+    loop {
+        let id = match lost.recv().await {
+            Ok(id) => id,
+            // each is reported again the next time it's written to
+            Err(RecvError::Lagged(_)) => continue,
+            Err(RecvError::Closed) => return,
+        };
+        if let Err(e) = forget_lost_collection(&connection, &id).await {
+            warn!("Could not forget collection {id}, whose directory was removed: {e}");
+        }
+    }
+}
+
+/// stop serving a collection if its directory is gone
+async fn forget_lost_collection(connection: &Connection, id: &str) -> Result {
+    let Some(path) = collection_path(id) else {
+        return Ok(());
+    };
+    let object_server = connection.object_server();
+    // it may have been forgotten already
+    if let Some(collection) = try_interface(object_server.interface::<_, Collection>(&path).await)? {
+        let collection = collection.get().await.clone();
+        collection.resync_if_deleted(connection, &object_server).await?;
+    }
+    Ok(())
+}
+
+/// report the secrets which changed since the password store was at commit `head`, after a git
+/// pull was reloaded: ItemChanged for each changed item (reloading already announced the added
+/// and removed ones), and a summary of each collection as GitPulled, in the log, and as a
+/// notification
+pub async fn report_pulled_changes(connection: &Connection, store: &SecretStore<'static>, head: &str) -> Result {
+    let manager_context = SignalContext::new(connection, "/org/freedesktop/secrets")?;
+    for (collection_id, changes) in store.changes_since(head).await? {
+        // it isn't served, since its id can't be part of a path
+        let Some(path) = collection_path(&collection_id) else {
+            continue;
+        };
+
+        let visible: HashSet<_> = store.visible_items(&collection_id).into_iter().collect();
+        let signal_context = SignalContext::new(connection, path.clone())?;
+        for secret_id in changes.changed.iter().filter(|id| visible.contains(*id)) {
+            Collection::item_changed(&signal_context, secret_path(&collection_id, secret_id).unwrap()).await?;
+        }
+
+        let (added, changed, removed) = (changes.added.len(), changes.changed.len(), changes.removed.len());
+        info!("git pull changed collection {collection_id}: {added} added, {changed} changed, {removed} removed");
+        Manager::git_pulled(&manager_context, path, added as u32, changed as u32, removed as u32).await?;
+
+        let label = store
+            .get_label(Arc::new(collection_id.clone()))
+            .await
+            .unwrap_or(collection_id);
+        notify(connection, store, Event::GitPulled { label: &label, added, changed, removed });
+    }
+    Ok(())
+}
+
+/// remove a collection's entries for secrets whose files are gone (e.g. after its db was merged
+/// by git), and rebuild its indexes
+/// returns (items removed, files with no entry)
+pub async fn repair_collection(
+    connection: &Connection,
+    object_server: &ObjectServer,
+    collection: &Collection<'static>,
+) -> Result<(u32, u32)> {
+    let store = &collection.store;
+    let (removed, unindexed) = store.repair_collection(&collection.id).await?;
+
+    let visible: HashSet<_> = store.visible_items(&collection.id).into_iter().collect();
+    let signal_context = SignalContext::new(connection, collection_path(&collection.id).unwrap())?;
+    for secret_id in &removed {
+        if visible.contains(secret_id) {
+            collection
+                .forget_item(secret_id, &signal_context, object_server)
+                .await?;
+        }
+    }
+
+    Ok((removed.len() as u32, unindexed))
+}
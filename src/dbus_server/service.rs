@@ -1,104 +1,362 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::Instant,
+};
 
+use log::{info, warn};
 use nanoid::nanoid;
 use zbus::{
     fdo, interface, message::Header, object_server::SignalContext, zvariant::{ObjectPath, OwnedObjectPath, OwnedValue, Value}, Connection, ObjectServer
 };
 
 use crate::{
+    agent,
+    ask_password::watch_requests,
+    audit::{self, Operation},
+    config::Config,
     error::{Error, OptionNoneNotFound, Result},
+    keyring_file::{import_keyrings, keyrings_dir},
     pass::PasswordStore,
     secret_store::{slugify, SecretStore, NANOID_ALPHABET},
+    timing,
 };
 
 use super::{
+    changes::publish_changes,
     collection::Collection,
+    expiry::watch_expiry,
+    gnome_keyring::{GnomeKeyring, KeyringDaemon, DAEMON_PATH},
+    info::Info,
     item::Item,
-    session::{Session, SessionAlgorithm},
+    prompt::{auto_accept, Prompt, PromptAction, AUTO_ACCEPT_VAR},
+    reload::watch_lost_collections,
+    manager::{Manager, RECIPIENT_CHECK_SAMPLE},
+    name::SERVICE_NAME,
+    portal::{Portal, PORTAL_PATH},
+    session::{OpenSessions, Session, SessionAlgorithm},
+    sweep::watch_stale_items,
     utils::{
-        alias_path, collection_path, secret_alias_path, secret_path, session_path, try_interface, Secret, EMPTY_PATH
+        alias_path, client_exe, collection_path, prompt_path, retain_searched, secret_alias_path, secret_path, session_path, try_interface, Secret, EMPTY_PATH
     },
 };
 
 #[derive(Debug)]
 pub struct Service<'a> {
-    store: SecretStore<'a>
+    store: SecretStore<'a>,
+    sessions: OpenSessions,
 }
 
 impl Service<'static> {
-    pub async fn init(connection: Connection, pass: &'static PasswordStore) -> Result<Self> {
-        let store = SecretStore::new(pass).await?;
+    pub async fn init(
+        connection: Connection,
+        pass: &'static PasswordStore,
+        config: &'static Config,
+    ) -> Result<Self> {
+        let store = SecretStore::new(pass, config).await?;
+        let sessions = OpenSessions::default();
+        if auto_accept() {
+            warn!("{AUTO_ACCEPT_VAR} is set: prompts will unlock collections without a passphrase");
+        }
 
         {
             let object_server = connection.object_server();
 
             let mut aliases = store.list_all_aliases().await?;
 
-            // initialize the default store if necessary
-            if !aliases.contains_key("default") {
+            // create the default collection on first run (or if its alias was removed or
+            // points at a collection which is gone), since many clients only use the alias
+            let collections = store.collections().await;
+            let default = store.get_alias(Arc::new("default".into())).await.ok();
+            if !default.is_some_and(|id| collections.contains(&id)) {
                 let id = store
                     .create_collection(Some("Default".into()), Some("default".into()))
                     .await?;
-                aliases.insert(id, vec!["default".into()]);
+                info!("Created the default collection {id}");
+                let collection_aliases = aliases.entry(id).or_default();
+                if !collection_aliases.iter().any(|alias| alias == "default") {
+                    collection_aliases.push("default".into());
+                }
+            }
+
+            // migrate from gnome-keyring before the collections are registered
+            if let Some(dir) = config.import_keyrings.then(keyrings_dir).flatten() {
+                if let Err(e) = import_keyrings(&store, &dir).await {
+                    warn!("Could not import keyrings from {}: {e}", dir.display());
+                }
             }
 
+            // rename the secrets which couldn't be served, and leave out the collections
+            let unreachable = store.fix_unreachable_ids().await?;
+
             // add existing collections
             for collection in store.collections().await {
-                let collection_aliases = aliases.remove(&collection).into_iter().flatten();
-                let path = collection_path(&collection).unwrap();
+                let collection_aliases = aliases.remove(&collection).unwrap_or_default();
+                Self::register_collection(&store, &object_server, collection, collection_aliases)
+                    .await?;
+            }
 
-                let collection_id = Arc::new(collection);
+            // the rest point at collections which are gone
+            for (target, stale) in aliases {
+                for alias in stale {
+                    warn!("Removing alias {alias}, since collection {target} no longer exists");
+                    store.remove_alias_if_target(Arc::new(alias), target.clone()).await?;
+                }
+            }
 
-                let secrets: Vec<_> = store
-                    .list_secrets(&*collection_id)
-                    .await?
-                    .into_iter()
-                    .map(|id| Item {
+            let started = Instant::now();
+            object_server
+                .at(
+                    "/org/freedesktop/secrets",
+                    Manager {
                         store: store.clone(),
-                        id: Arc::new(id),
-                        collection_id: collection_id.clone(),
-                    })
-                    .collect();
+                        sessions: sessions.clone(),
+                        started,
+                        unreachable,
+                    },
+                )
+                .await?;
 
-                // add the collection secrets
-                for secret in &secrets {
-                    if let Some(path) = secret_path(&*collection_id, &*secret.id) {
-                        object_server.at(path, secret.clone()).await?;
-                    }
-                }
+            object_server
+                .at(
+                    "/org/freedesktop/secrets",
+                    Info {
+                        store: store.clone(),
+                        started,
+                    },
+                )
+                .await?;
 
-                let c = Collection {
-                    store: store.clone(),
-                    id: collection_id,
-                };
+            if config.gnome_keyring_compat {
+                object_server
+                    .at(
+                        "/org/freedesktop/secrets",
+                        GnomeKeyring {
+                            store: store.clone(),
+                        },
+                    )
+                    .await?;
+                object_server.at(DAEMON_PATH, KeyringDaemon).await?;
+            }
+
+            // for xdg-desktop-portal, if it's configured to use this as its Secret backend
+            object_server
+                .at(
+                    PORTAL_PATH,
+                    Portal {
+                        store: store.clone(),
+                    },
+                )
+                .await?;
+        }
 
-                // add the aliases
-                for alias in collection_aliases {
-                    if let Some(path) = alias_path(&alias) {
-                        object_server.at(path, c.clone()).await?;
+        // tell clients about every change to the store
+        tokio::spawn(publish_changes(connection.clone(), store.clone()));
+
+        // warn about secrets which are about to expire
+        tokio::spawn(watch_expiry(connection.clone(), store.clone()));
+
+        // stop serving items whose secrets are gone
+        tokio::spawn(watch_stale_items(connection.clone(), store.clone()));
+
+        // and collections whose directory was removed
+        tokio::spawn(watch_lost_collections(connection.clone(), store.clone()));
+
+        // retry writes which failed while the store was unavailable
+        let queue_store = store.clone();
+        tokio::spawn(async move { queue_store.retry_queued_writes().await });
+
+        // forget cached secrets once they expire
+        let cache_store = store.clone();
+        tokio::spawn(async move { cache_store.expire_cached_secrets().await });
+
+        // purge deleted secrets once they've been in the trash long enough
+        let trash_store = store.clone();
+        tokio::spawn(async move { trash_store.purge_old_trash().await });
+
+        // answer password requests from system services
+        tokio::spawn(watch_requests(store.clone()));
+
+        // copy secrets to any mirrors
+        let mirror_store = store.clone();
+        tokio::spawn(async move { mirror_store.run_mirrors().await });
+
+        // warn about secrets encrypted to old keys in the background
+        let check_store = store.clone();
+        tokio::spawn(async move {
+            match check_store.check_recipients(RECIPIENT_CHECK_SAMPLE).await {
+                Ok(checks) => {
+                    for check in checks.iter().filter(|c| !c.initialized) {
+                        warn!(
+                            "Collection {} has no .gpg-id, so its secrets can't be encrypted",
+                            check.collection_id
+                        );
                     }
-                    // add the secrets under the alias
-                    for secret in &secrets {
-                        if let Some(path) = secret_alias_path(&alias, &*secret.id) {
-                            object_server.at(path, secret.clone()).await?;
-                        }
+                    for check in checks.into_iter().filter(|c| c.stale > 0) {
+                        warn!(
+                            "{} of {} sampled secrets in collection {} are not encrypted to its current .gpg-id recipients. Run `pass-secret-service re-encrypt {}` to re-encrypt them",
+                            check.stale, check.checked, check.collection_id, check.collection_id
+                        );
                     }
                 }
-                // add the collection
-                object_server.at(path, c).await?;
+                Err(e) => warn!("Could not check secret recipients: {e}"),
             }
+        });
+
+        Ok(Service { store, sessions })
+    }
+
+    /// stop serving clients before the daemon exits. the databases are closed once the
+    /// store is dropped
+    pub async fn shutdown(connection: &Connection) -> Result {
+        // so D-Bus activation can start another instance while this one stops
+        connection.release_name(SERVICE_NAME).await?;
+
+        let object_server = connection.object_server();
+        let (sessions, queue) = {
+            let service = object_server
+                .interface::<_, Self>("/org/freedesktop/secrets")
+                .await?;
+            let service = service.get().await;
+            let sessions: Vec<_> = service.sessions.lock().unwrap().keys().cloned().collect();
+            (sessions, service.store.write_queue_status())
+        };
+
+        for path in sessions {
+            try_interface(object_server.remove::<Session, _>(&path).await)?;
         }
 
-        Ok(Service {
-            store
-        })
+        // spooled writes are retried on the next start, but the rest are only in memory
+        if let Some(queue) = queue {
+            let unsaved = queue.pending.saturating_sub(queue.spooled);
+            if unsaved > 0 {
+                warn!("{unsaved} queued writes which couldn't be encrypted yet are lost");
+            }
+        }
+        Ok(())
     }
 
-    fn make_collection(&self, name: String) -> Collection<'static> {
-        Collection {
-            id: Arc::new(name),
-            store: self.store.clone(),
+    /// get the id of the collection an object belongs to
+    /// the object can be a collection, alias, or item
+    async fn resolve_collection_id(
+        object_server: &ObjectServer,
+        path: &ObjectPath<'_>,
+    ) -> Result<Option<Arc<String>>> {
+        if let Some(collection) =
+            try_interface(object_server.interface::<_, Collection>(path).await)?
+        {
+            return Ok(Some(collection.get().await.id.clone()));
         }
+        if let Some(item) = try_interface(object_server.interface::<_, Item>(path).await)? {
+            return Ok(Some(item.get().await.collection_id.clone()));
+        }
+        Ok(None)
+    }
+
+    /// serve an existing collection, its items, and its aliases
+    pub(super) async fn register_collection(
+        store: &SecretStore<'static>,
+        object_server: &ObjectServer,
+        collection: String,
+        aliases: Vec<String>,
+    ) -> Result {
+        let Some(path) = collection_path(&collection) else {
+            return Ok(());
+        };
+
+        let collection_id = Arc::new(collection);
+
+        let secrets: Vec<_> = store
+            .list_secrets(&*collection_id)
+            .await?
+            .into_iter()
+            .map(|id| Item {
+                store: store.clone(),
+                id: Arc::new(id),
+                collection_id: collection_id.clone(),
+            })
+            .collect();
+
+        // add the collection secrets
+        for secret in &secrets {
+            if let Some(path) = secret_path(&*collection_id, &*secret.id) {
+                secret.register(object_server, &path).await?;
+            }
+        }
+
+        let c = Collection {
+            store: store.clone(),
+            id: collection_id,
+        };
+
+        // add the aliases
+        for alias in aliases {
+            if let Some(path) = alias_path(&alias) {
+                c.register(object_server, &path).await?;
+            }
+            // add the secrets under the alias
+            for secret in &secrets {
+                if let Some(path) = secret_alias_path(&alias, &*secret.id) {
+                    secret.register(object_server, &path).await?;
+                }
+            }
+        }
+        // add the collection
+        c.register(object_server, &path).await?;
+        store.show_items(&c.id, secrets.iter().map(|secret| secret.id.to_string()));
+        Ok(())
+    }
+
+    /// create a collection and serve it (and its alias, if it's given)
+    /// returns its path, which is the existing collection's if the alias is already used
+    pub(super) async fn add_collection(
+        store: &SecretStore<'static>,
+        label: Option<String>,
+        alias: &str,
+        signal: &SignalContext<'_>,
+        object_server: &ObjectServer,
+        header: &Header<'_>,
+    ) -> Result<ObjectPath<'static>> {
+        // slugify the alias and handle the case where it's empty
+        let alias = slugify(alias);
+
+        let alias = if alias.is_empty() { None } else { Some(alias) };
+
+        let _aliases = store.lock_aliases().await;
+
+        let id = store.create_collection(label, alias.clone()).await?;
+        let collection_path = collection_path(&id).unwrap();
+
+        // if the collection here doesn't exist, create it and handle alises
+        // the only reason it might exist is if they supplied an existing alias
+        if try_interface(
+            object_server
+                .interface::<_, Collection>(&collection_path)
+                .await,
+        )?
+        .is_none()
+        {
+            let c = Collection {
+                id: Arc::new(id.clone()),
+                store: store.clone(),
+            };
+
+            c.register(object_server, &collection_path).await?;
+
+            // if they supplied an alias, handle it
+            if let Some(alias) = alias {
+                let alias_path = alias_path(&alias).unwrap();
+                // remove the alias at this point
+                Collection::unregister(object_server, &alias_path).await?;
+
+                c.register(object_server, &alias_path).await?;
+            }
+
+            Self::collection_created(signal, collection_path.clone()).await?;
+            audit::record(signal.connection(), header, Operation::CreateCollection, &id, None).await;
+        }
+
+        Ok(collection_path)
     }
 }
 
@@ -121,7 +379,8 @@ impl Service<'static> {
                     SessionAlgorithm::Plain,
                     client_name,
                     path.clone().into(),
-                    connection.clone()
+                    connection.clone(),
+                    self.sessions.clone(),
                 );
                 object_server.at(&path, session).await?;
                 Ok(("".into(), path))
@@ -139,73 +398,132 @@ impl Service<'static> {
         alias: String,
         #[zbus(signal_context)] signal: SignalContext<'_>,
         #[zbus(object_server)] object_server: &ObjectServer,
+        #[zbus(header)] header: Header<'_>,
     ) -> Result<(ObjectPath, ObjectPath)> {
-        // stringify the labelg
-        let label: Option<String> = properties
-            .get("org.freedesktop.Secret.Collection.Label")
-            .and_then(|v| v.downcast_ref().ok());
-
-        // slugify the alias and handle the case where it's empty
-        let alias = slugify(&alias);
+        timing::timed("Service.CreateCollection", async move {
+            // stringify the labelg
+            let label: Option<String> = properties
+                .get("org.freedesktop.Secret.Collection.Label")
+                .and_then(|v| v.downcast_ref().ok());
 
-        let alias = if alias == "" { None } else { Some(alias) };
+            let collection_path =
+                Self::add_collection(&self.store, label, &alias, &signal, object_server, &header)
+                    .await?;
+            Ok((collection_path, EMPTY_PATH))
+        })
+        .await
+    }
 
-        let id = self.store.create_collection(label, alias.clone()).await?;
-        let collection_path = collection_path(&id).unwrap();
+    async fn search_items(
+        &self,
+        attributes: HashMap<String, String>,
+        #[zbus(connection)] connection: &Connection,
+        #[zbus(header)] header: Header<'_>,
+    ) -> Result<(Vec<ObjectPath>, Vec<ObjectPath>)> {
+        timing::timed("Service.SearchItems", async move {
+            let attributes = self.store.config.search_attributes(attributes);
+            let mut items = self.store.search_all_collections(attributes).await?;
 
-        // if the collection here doesn't exist, create it and handle alises
-        // the only reason it might exist is if they supplied an existing alias
-        if try_interface(
-            object_server
-                .interface::<_, Collection>(&collection_path)
-                .await,
-        )?
-        .is_none()
-        {
-            let c = self.make_collection(id);
+            // only look in the collections this client is configured to search
+            retain_searched(connection, &header, &self.store, &mut items).await?;
 
-            object_server.at(&collection_path, c.clone()).await?;
+            let (unlocked, locked) = items
+                .into_iter()
+                .partition::<Vec<_>, _>(|(col, _)| !self.store.is_locked(col));
 
-            // if they supplied an alias, handle it
-            if let Some(alias) = alias {
-                let alias_path = alias_path(&alias).unwrap();
-                // remove the alias at this point
-                try_interface(object_server.remove::<Collection, _>(&alias_path).await)?;
+            let to_paths = |collections: Vec<(String, Vec<String>)>| {
+                collections
+                    .into_iter()
+                    .flat_map(|(col, secrets)| {
+                        secrets
+                            .into_iter()
+                            .filter_map(move |secret| secret_path(&col, &secret))
+                    })
+                    .collect()
+            };
+            Ok((to_paths(unlocked), to_paths(locked)))
+        })
+        .await
+    }
 
-                object_server.at(&alias_path, c).await?;
+    async fn lock(
+        &self,
+        objects: Vec<OwnedObjectPath>,
+        #[zbus(object_server)] object_server: &ObjectServer,
+    ) -> Result<(Vec<OwnedObjectPath>, ObjectPath)> {
+        timing::timed("Service.Lock", async move {
+            let mut locked = vec![];
+            let mut newly_locked = false;
+            for object in objects {
+                if let Some(id) = Self::resolve_collection_id(object_server, &object).await? {
+                    if self.store.set_locked(&id, true) {
+                        Collection::emit_locked_changed(object_server, &id).await?;
+                        newly_locked = true;
+                    }
+                    locked.push(object);
+                }
             }
 
-            Self::collection_created(&signal, collection_path.clone()).await?;
-        }
-
-        Ok((collection_path, EMPTY_PATH))
+            // otherwise a cached passphrase would unlock it without asking
+            if newly_locked && self.store.config.forget_password_on_lock {
+                if let Err(e) = agent::reload().await {
+                    warn!("Could not make gpg-agent forget cached passphrases: {e}");
+                }
+            }
+            // no prompt required to lock
+            Ok((locked, EMPTY_PATH))
+        })
+        .await
     }
 
-    async fn search_items(
+    async fn unlock(
         &self,
-        attributes: HashMap<String, String>,
-    ) -> Result<(Vec<ObjectPath>, Vec<ObjectPath>)> {
-        let items = self.store.search_all_collections(attributes).await?;
-        let paths = items
-            .into_iter()
-            .flat_map(|(col, secrets)| {
-                secrets
-                    .into_iter()
-                    .filter_map(move |secret| secret_path(&col, &secret))
-            })
-            .collect();
-        // we don't support locking
-        Ok((paths, vec![]))
-    }
+        objects: Vec<OwnedObjectPath>,
+        #[zbus(connection)] connection: &Connection,
+        #[zbus(object_server)] object_server: &ObjectServer,
+        #[zbus(header)] header: Header<'_>,
+    ) -> Result<(Vec<OwnedObjectPath>, ObjectPath)> {
+        timing::timed("Service.Unlock", async move {
+            let mut unlocked = vec![];
+            let mut needs_unlock = vec![];
+            for object in objects {
+                if let Some(id) = Self::resolve_collection_id(object_server, &object).await? {
+                    if self.store.is_locked(&id) {
+                        needs_unlock.push((object, id));
+                    } else {
+                        unlocked.push(object);
+                    }
+                }
+            }
 
-    async fn lock(&self, _objects: Vec<OwnedObjectPath>) -> (Vec<ObjectPath>, ObjectPath) {
-        // we don't support locking
-        (vec![], EMPTY_PATH)
-    }
+            if needs_unlock.is_empty() {
+                return Ok((unlocked, EMPTY_PATH));
+            }
 
-    async fn unlock(&self, objects: Vec<OwnedObjectPath>) -> (Vec<OwnedObjectPath>, ObjectPath) {
-        // we don't support locking - just say all of them were unlocked
-        (objects, EMPTY_PATH)
+            // one prompt covers every collection
+            let path = prompt_path(nanoid!(8, &NANOID_ALPHABET)).unwrap();
+            let sender = header.sender().unwrap().to_owned();
+            let app = client_exe(connection, &header)
+                .await
+                .unwrap_or_else(|| sender.to_string());
+            let action = PromptAction::Unlock(needs_unlock);
+            let kind = action.kind();
+            let prompt = Prompt::new(
+                self.store.clone(),
+                path.clone().into(),
+                action,
+                sender.into(),
+                app.clone(),
+                connection.clone(),
+            );
+            object_server.at(&path, prompt).await?;
+
+            let ctxt = SignalContext::new(connection, "/org/freedesktop/secrets")?;
+            Manager::prompt_created(&ctxt, path.clone(), kind, &app).await?;
+
+            Ok((unlocked, path))
+        })
+        .await
     }
 
     async fn get_secrets(
@@ -213,115 +531,1409 @@ impl Service<'static> {
         items: Vec<ObjectPath<'_>>,
         session: ObjectPath<'_>,
         #[zbus(object_server)] object_server: &ObjectServer,
+        #[zbus(connection)] connection: &Connection,
         #[zbus(header)] header: Header<'_>
     ) -> Result<HashMap<OwnedObjectPath, Secret>> {
-        let session_ref = try_interface(object_server.interface::<_, Session>(&session).await)?
-            .ok_or(Error::InvalidSession)?;
-        let session = session_ref.get().await;
+        timing::timed("Service.GetSecrets", async move {
+            let session_ref = try_interface(object_server.interface::<_, Session>(&session).await)?
+                .ok_or(Error::InvalidSession)?;
+            let session = session_ref.get().await;
 
-        let mut results = HashMap::with_capacity(items.len());
+            let mut readable = Vec::with_capacity(items.len());
+            for item_path in items {
+                let item = try_interface(object_server.interface::<_, Item>(&item_path).await)?
+                    .into_not_found()?
+                    .get()
+                    .await
+                    .clone();
+                item.check_readable().await?;
+                readable.push((item_path, item));
+            }
 
-        for item_path in items {
-            let item_ref = try_interface(object_server.interface::<_, Item>(&item_path).await)?
-                .into_not_found()?;
-            let secret = item_ref.get().await.read_with_session(&header, &session).await?;
-            results.insert(item_path.into(), secret);
-        }
+            // decrypt each collection's secrets with one gpg process, instead of one each
+            let mut by_collection: HashMap<Arc<String>, Vec<String>> = HashMap::new();
+            for (_, item) in &readable {
+                by_collection.entry(item.collection_id.clone()).or_default().push(item.id.to_string());
+            }
+            let can_prompt = self.store.config.pinentry_during_reads;
+            let mut decrypted = HashMap::new();
+            for (collection_id, ids) in by_collection {
+                let secrets = self.store.read_secrets(&collection_id, &ids, can_prompt).await;
+                decrypted.extend(secrets.into_iter().map(|(id, secret)| ((collection_id.clone(), id), secret)));
+            }
+
+            let mut results = HashMap::with_capacity(readable.len());
+            for (item_path, item) in readable {
+                let secret = decrypted.remove(&(item.collection_id.clone(), item.id.to_string()));
+                let secret = item.read_checked(connection, &header, &session, secret).await?;
+                results.insert(item_path.into(), secret);
+            }
 
-        Ok(results)
+            Ok(results)
+        })
+        .await
     }
 
-    async fn read_alias(&self, name: String) -> Result<ObjectPath> {
-        let alias = slugify(&name);
+    async fn read_alias(
+        &self,
+        name: String,
+        #[zbus(connection)] connection: &Connection,
+        #[zbus(object_server)] object_server: &ObjectServer,
+    ) -> Result<ObjectPath> {
+        timing::timed("Service.ReadAlias", async move {
+            let alias = Arc::new(slugify(&name));
+            let target = match self.store.get_alias(alias.clone()).await {
+                Ok(target) => target,
+                // "/" if there's no such alias
+                Err(Error::IoError(e)) if e.kind() == std::io::ErrorKind::NotFound => return Ok(EMPTY_PATH),
+                Err(e) => return Err(e),
+            };
+
+            let Some(path) = collection_path(target.clone()) else {
+                return Ok(EMPTY_PATH);
+            };
+            // make sure the collection wasn't removed behind our back. if its directory is
+            // gone, forgetting it removes its aliases too
+            if let Some(collection) = try_interface(object_server.interface::<_, Collection>(&path).await)? {
+                let collection = collection.get().await.clone();
+                if collection.resync_if_deleted(connection, object_server).await? {
+                    return Ok(path);
+                }
+            }
 
-        if let Some(target) = collection_path(self.store.get_alias(Arc::new(alias)).await?) {
-            Ok(target)
-        } else {
+            // the alias points at a collection we don't have
+            let _aliases = self.store.lock_aliases().await;
+            if self.store.remove_alias_if_target(alias.clone(), target.clone()).await? {
+                warn!("Removed alias {alias}, since collection {target} no longer exists");
+                if let Some(alias_path) = alias_path(&alias) {
+                    Collection::unregister(object_server, &alias_path).await?;
+                }
+                Collection::emit_aliases_removed(connection, &target, &[alias.to_string()]).await?;
+            }
             Ok(EMPTY_PATH)
-        }
+        })
+        .await
     }
 
     async fn set_alias(
         &self,
         name: String,
         collection: OwnedObjectPath,
+        #[zbus(connection)] connection: &Connection,
         #[zbus(object_server)] object_server: &ObjectServer,
     ) -> Result<()> {
-        let alias = Arc::new(slugify(&name));
+        timing::timed("Service.SetAlias", async move {
+            let alias = Arc::new(slugify(&name));
 
-        let alias_path = alias_path(&alias).unwrap();
+            // find the new target before changing anything
+            let target = if collection.as_ref() == EMPTY_PATH {
+                None
+            } else {
+                Some(
+                    try_interface(object_server.interface::<_, Collection>(&collection).await)?
+                        .into_not_found()?
+                        .get()
+                        .await
+                        .to_owned(),
+                )
+            };
 
-        let collection = collection.as_ref();
+            let new = target.as_ref().map(|target| target.id.to_string());
+            let old = Collection::move_alias(&self.store, object_server, alias.clone(), target.as_ref()).await?;
+            if old != new {
+                Collection::emit_alias_changed(connection, &alias, old.as_deref(), new.as_deref()).await?;
+            }
+            Ok(())
+        })
+        .await
+    }
 
-        // remove the alias at this point
-        try_interface(object_server.remove::<Collection, _>(&alias_path).await)?;
+    #[zbus(property)]
+    async fn collections(&self) -> fdo::Result<Vec<ObjectPath>> {
+        Ok(self
+            .store
+            .sorted_collections()
+            .await?
+            .into_iter()
+            .filter_map(collection_path)
+            .collect())
+    }
 
-        // remove all secrets under this alias
-        if let Ok(old_target) = self.store.get_alias(alias.clone()).await {
-            let secrets = self.store.list_secrets(&old_target).await?;
+    // signals
 
-            for secret in secrets {
-                if let Some(path) = secret_alias_path(&*alias, &secret) {
-                    try_interface(object_server.remove::<Item, _>(&path).await)?;
+    #[zbus(signal)]
+    pub async fn collection_created(ctx: &SignalContext<'_>, path: ObjectPath<'_>) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn collection_deleted(ctx: &SignalContext<'_>, path: ObjectPath<'_>) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn collection_modified(ctx: &SignalContext<'_>, path: ObjectPath<'_>)
+        -> zbus::Result<()>;
+}
+
+/// a service in an empty password store, and a client connected to it
+#[cfg(test)]
+async fn test_service() -> (Connection, Connection, SecretStore<'static>, std::path::PathBuf) {
+    test_service_with(Config::default()).await
+}
+
+/// the same, with the given config
+#[cfg(test)]
+async fn test_service_with(
+    config: Config,
+) -> (Connection, Connection, SecretStore<'static>, std::path::PathBuf) {
+    let dir = std::env::temp_dir().join(format!(
+        "pass-secret-service-test-{}",
+        nanoid!(8, &NANOID_ALPHABET)
+    ));
+    let pass = Box::leak(Box::new(PasswordStore::with_directory(dir.clone())));
+    let config = Box::leak(Box::new(config));
+
+    let (server_stream, client_stream) = tokio::net::UnixStream::pair().unwrap();
+    let (server, client) = tokio::try_join!(
+        zbus::connection::Builder::unix_stream(server_stream)
+            .server(zbus::Guid::generate())
+            .unwrap()
+            .p2p()
+            .build(),
+        zbus::connection::Builder::unix_stream(client_stream)
+            .p2p()
+            .build(),
+    )
+    .unwrap();
+
+    let service = Service::init(server.clone(), pass, config).await.unwrap();
+    let store = service.store.clone();
+    server
+        .object_server()
+        .at("/org/freedesktop/secrets", service)
+        .await
+        .unwrap();
+
+    (server, client, store, dir)
+}
+
+#[cfg(test)]
+async fn call_service<B, R>(client: &Connection, method: &str, body: &B) -> zbus::Result<R>
+where
+    B: serde::Serialize + zbus::zvariant::DynamicType,
+    R: for<'d> zbus::zvariant::DynamicDeserialize<'d>,
+{
+    client
+        .call_method(
+            None::<&str>,
+            "/org/freedesktop/secrets",
+            Some("org.freedesktop.Secret.Service"),
+            method,
+            body,
+        )
+        .await?
+        .body()
+        .deserialize()
+}
+
+#[cfg(test)]
+async fn call_manager<B, R>(client: &Connection, method: &str, body: &B) -> zbus::Result<R>
+where
+    B: serde::Serialize + zbus::zvariant::DynamicType,
+    R: for<'d> zbus::zvariant::DynamicDeserialize<'d>,
+{
+    client
+        .call_method(
+            None::<&str>,
+            "/org/freedesktop/secrets",
+            Some("me.grimsteel.PassSecretService.Manager"),
+            method,
+            body,
+        )
+        .await?
+        .body()
+        .deserialize()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_concurrent_alias_changes() {
+    let (server, client, store, dir) = test_service().await;
+
+    // collections to point the alias at
+    let mut targets = vec![];
+    for i in 0..4 {
+        let label = HashMap::from([(
+            "org.freedesktop.Secret.Collection.Label",
+            Value::from(format!("Target {i}")),
+        )]);
+        let (path, _): (OwnedObjectPath, OwnedObjectPath) =
+            call_service(&client, "CreateCollection", &(label, "")).await.unwrap();
+        targets.push(path);
+    }
+
+    let tasks: Vec<_> = (0..60)
+        .map(|i| {
+            let client = client.clone();
+            let target = targets[i % targets.len()].clone();
+            tokio::spawn(async move {
+                match i % 3 {
+                    0 => call_service::<_, ()>(&client, "SetAlias", &("default", target))
+                        .await
+                        .unwrap(),
+                    1 => call_service::<_, ()>(&client, "SetAlias", &("default", EMPTY_PATH))
+                        .await
+                        .unwrap(),
+                    _ => {
+                        let properties = HashMap::<&str, Value>::new();
+                        call_service::<_, (OwnedObjectPath, OwnedObjectPath)>(
+                            &client,
+                            "CreateCollection",
+                            &(properties, "default"),
+                        )
+                        .await
+                        .unwrap();
+                    }
                 }
+            })
+        })
+        .collect();
+    for task in tasks {
+        task.await.unwrap();
+    }
+
+    // the db and the object server have to agree on the alias
+    let stored = store.get_alias(Arc::new("default".into())).await.ok();
+    let registered = try_interface(
+        server
+            .object_server()
+            .interface::<_, Collection>("/org/freedesktop/secrets/aliases/default")
+            .await,
+    )
+    .unwrap();
+    let registered = match registered {
+        Some(collection) => Some(collection.get().await.id.to_string()),
+        None => None,
+    };
+    assert_eq!(stored, registered);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_repoint_alias() {
+    use futures_util::StreamExt;
+
+    let (_server, client, _store, dir) = test_service().await;
+    let mut messages = zbus::MessageStream::from(&client);
+
+    let mut paths = vec![];
+    for (label, alias) in [("A", "shared"), ("B", "")] {
+        let label = HashMap::from([("org.freedesktop.Secret.Collection.Label", Value::from(label))]);
+        let (path, _): (OwnedObjectPath, OwnedObjectPath) =
+            call_service(&client, "CreateCollection", &(label, alias)).await.unwrap();
+        paths.push(path);
+    }
+
+    // the alias path is readable the whole time it's being switched
+    let reading = Arc::new(std::sync::atomic::AtomicBool::new(true));
+    let reader = {
+        let client = client.clone();
+        let reading = reading.clone();
+        tokio::spawn(async move {
+            while reading.load(std::sync::atomic::Ordering::SeqCst) {
+                client
+                    .call_method(
+                        None::<&str>,
+                        "/org/freedesktop/secrets/aliases/shared",
+                        Some("org.freedesktop.DBus.Properties"),
+                        "Get",
+                        &("org.freedesktop.Secret.Collection", "Label"),
+                    )
+                    .await
+                    .unwrap();
             }
+        })
+    };
+    for target in [1, 0, 1] {
+        call_service::<_, ()>(&client, "SetAlias", &("shared", &paths[target])).await.unwrap();
+    }
+    reading.store(false, std::sync::atomic::Ordering::SeqCst);
+    reader.await.unwrap();
+
+    let label: zbus::zvariant::OwnedValue = client
+        .call_method(
+            None::<&str>,
+            "/org/freedesktop/secrets/aliases/shared",
+            Some("org.freedesktop.DBus.Properties"),
+            "Get",
+            &("org.freedesktop.Secret.Collection", "Label"),
+        )
+        .await
+        .unwrap()
+        .body()
+        .deserialize()
+        .unwrap();
+    assert_eq!(String::try_from(label).unwrap(), "B");
+
+    // each change was signalled with where the alias pointed before
+    let mut changes = vec![];
+    while changes.len() < 3 {
+        let message = messages.next().await.unwrap().unwrap();
+        if message.header().member().is_some_and(|member| member == "AliasChanged") {
+            let (alias, old, new): (String, OwnedObjectPath, OwnedObjectPath) =
+                message.body().deserialize().unwrap();
+            assert_eq!(alias, "shared");
+            changes.push((old, new));
         }
+    }
+    let (a, b) = (paths[0].clone(), paths[1].clone());
+    assert_eq!(changes, [(a.clone(), b.clone()), (b.clone(), a.clone()), (a, b)]);
 
-        let target_collection_id = if collection == EMPTY_PATH {
-            None
-        } else {
-            let collection_interface = object_server
-                .interface::<_, Collection>(&collection)
-                .await?
-                .get()
-                .await
-                .to_owned();
-            object_server.at(&alias_path, collection_interface).await?;
-
-            // get just the ID
-            let collection_id = collection
-                .strip_prefix("/org/freedesktop/secrets/collection/")
-                .map(|s| s.to_string());
-
-            if let Some(id) = &collection_id {
-                // add secrets under this alias
-                for secret in self.store.list_secrets(&id).await? {
-                    if let Some(path) = secret_alias_path(&*alias, &secret) {
-                        if let Some(item) =
-                            try_interface(object_server.interface::<_, Item>(&path).await)?
-                        {
-                            object_server.at(&path, item.get().await.to_owned()).await?;
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_set_alias_invalid_target() {
+    let (server, client, store, dir) = test_service().await;
+
+    let missing = ObjectPath::from_static_str_unchecked("/org/freedesktop/secrets/collection/missing");
+    assert!(call_service::<_, ()>(&client, "SetAlias", &("default", missing))
+        .await
+        .is_err());
+
+    // the old alias is still there
+    let stored = store.get_alias(Arc::new("default".into())).await.unwrap();
+    let registered = server
+        .object_server()
+        .interface::<_, Collection>("/org/freedesktop/secrets/aliases/default")
+        .await
+        .unwrap();
+    assert_eq!(*registered.get().await.id, stored);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_create_item_in_removed_collection() {
+    let (_server, client, store, dir) = test_service().await;
+
+    let label = HashMap::from([(
+        "org.freedesktop.Secret.Collection.Label",
+        Value::from("Removed"),
+    )]);
+    let (collection, _): (OwnedObjectPath, OwnedObjectPath) =
+        call_service(&client, "CreateCollection", &(label, "")).await.unwrap();
+    let collection_id = collection.rsplit('/').next().unwrap().to_owned();
+
+    // removed behind the daemon's back
+    let collection_dir = dir.join(crate::secret_store::PASS_SUBDIR).join(&collection_id);
+    std::fs::remove_dir_all(&collection_dir).unwrap();
+
+    let properties = HashMap::<&str, Value>::new();
+    // the collection is checked before the session
+    let secret = Secret {
+        session: EMPTY_PATH.into(),
+        parameters: vec![],
+        value: b"value".to_vec(),
+        content_type: "text/plain".into(),
+    };
+    let result = client
+        .call_method(
+            None::<&str>,
+            &collection,
+            Some("org.freedesktop.Secret.Collection"),
+            "CreateItem",
+            &(properties, secret, false),
+        )
+        .await;
+    let Err(zbus::Error::MethodError(name, _, _)) = result else {
+        panic!("expected an error, got {result:?}");
+    };
+    assert_eq!(name.as_str(), "org.freedesktop.Secret.Error.NoSuchObject");
+
+    // the directory isn't recreated, and the collection is gone
+    assert!(!collection_dir.exists());
+    assert!(!store.collections().await.contains(&collection_id));
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_collection_backends() {
+    let (_server, client, store, dir) = test_service_with(Config {
+        expose_pass_tree: true,
+        ..Default::default()
+    })
+    .await;
+    let collection: OwnedObjectPath = call_service(&client, "ReadAlias", &("default",)).await.unwrap();
+
+    let backends: HashMap<OwnedObjectPath, String> = client
+        .call_method(
+            None::<&str>,
+            "/org/freedesktop/secrets",
+            Some("org.freedesktop.DBus.Properties"),
+            "Get",
+            &("me.grimsteel.PassSecretService.Info", "CollectionBackends"),
+        )
+        .await
+        .unwrap()
+        .body()
+        .deserialize::<OwnedValue>()
+        .unwrap()
+        .try_into()
+        .unwrap();
+    assert_eq!(backends.len(), store.collections().await.len());
+    assert_eq!(backends[&collection], "pass");
+    assert!(backends.values().any(|backend| backend == "tree"));
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_create_item_in_locked_collection() {
+    let (_server, client, store, dir) = test_service().await;
+    let collection: OwnedObjectPath = call_service(&client, "ReadAlias", &("default",)).await.unwrap();
+    let collection_id = collection.rsplit('/').next().unwrap().to_owned();
+    let _: (Vec<OwnedObjectPath>, OwnedObjectPath) =
+        call_service(&client, "Lock", &(vec![&collection],)).await.unwrap();
+
+    // the lock is checked before the session
+    let secret = Secret {
+        session: EMPTY_PATH.into(),
+        parameters: vec![],
+        value: b"value".to_vec(),
+        content_type: "text/plain".into(),
+    };
+    let properties = HashMap::from([(
+        "org.freedesktop.Secret.Item.Attributes",
+        Value::from(HashMap::from([("user", "me")])),
+    )]);
+    let result = client
+        .call_method(
+            None::<&str>,
+            &collection,
+            Some("org.freedesktop.Secret.Collection"),
+            "CreateItem",
+            &(properties, secret, true),
+        )
+        .await;
+    let Err(zbus::Error::MethodError(name, _, _)) = result else {
+        panic!("expected an error, got {result:?}");
+    };
+    assert_eq!(name.as_str(), "org.freedesktop.Secret.Error.IsLocked");
+    assert_eq!(store.count_secrets(Arc::new(collection_id)).await.unwrap(), 0);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_replace_item_in_locked_collection() {
+    let (_server, client, store, dir) = test_service().await;
+    let collection: OwnedObjectPath = call_service(&client, "ReadAlias", &("default",)).await.unwrap();
+    let collection_id = collection.rsplit('/').next().unwrap().to_owned();
+    let attributes = HashMap::from([("user".to_owned(), "me".to_owned())]);
+    let secret_id = store
+        .create_test_secret(Arc::new(collection_id.clone()), Arc::new(attributes))
+        .await
+        .unwrap();
+    let _: (Vec<OwnedObjectPath>, OwnedObjectPath) =
+        call_service(&client, "Lock", &(vec![&collection],)).await.unwrap();
+
+    let secret = Secret {
+        session: EMPTY_PATH.into(),
+        parameters: vec![],
+        value: b"replaced".to_vec(),
+        content_type: "text/plain".into(),
+    };
+    let properties = HashMap::from([(
+        "org.freedesktop.Secret.Item.Attributes",
+        Value::from(HashMap::from([("user", "me")])),
+    )]);
+    let result = client
+        .call_method(
+            None::<&str>,
+            &collection,
+            Some("org.freedesktop.Secret.Collection"),
+            "CreateItem",
+            &(properties, secret, true),
+        )
+        .await;
+    let Err(zbus::Error::MethodError(name, _, _)) = result else {
+        panic!("expected an error, got {result:?}");
+    };
+    assert_eq!(name.as_str(), "org.freedesktop.Secret.Error.IsLocked");
+
+    // the existing item is untouched
+    let file = dir
+        .join(crate::secret_store::PASS_SUBDIR)
+        .join(&collection_id)
+        .join(format!("{secret_id}.gpg"));
+    assert_eq!(std::fs::read(file).unwrap(), b"not encrypted");
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_change_item_in_removed_collection() {
+    let (server, client, store, dir) = test_service().await;
+
+    let label = HashMap::from([(
+        "org.freedesktop.Secret.Collection.Label",
+        Value::from("Removed"),
+    )]);
+    let (collection_path, _): (OwnedObjectPath, OwnedObjectPath) =
+        call_service(&client, "CreateCollection", &(label, "")).await.unwrap();
+    let collection = server
+        .object_server()
+        .interface::<_, Collection>(&collection_path)
+        .await
+        .unwrap()
+        .get()
+        .await
+        .clone();
+    let id = store.create_test_secret(collection.id.clone(), Default::default()).await.unwrap();
+    let ctxt = SignalContext::new(&server, collection_path.as_ref()).unwrap();
+    let path = collection
+        .register_item(id, &ctxt, &server.object_server())
+        .await
+        .unwrap();
+
+    // removed behind the daemon's back
+    let collection_dir = dir.join(crate::secret_store::PASS_SUBDIR).join(&*collection.id);
+    std::fs::remove_dir_all(&collection_dir).unwrap();
+
+    // setting a property doesn't get the connection, so the collection is forgotten afterwards
+    let result = client
+        .call_method(
+            None::<&str>,
+            &path,
+            Some("org.freedesktop.DBus.Properties"),
+            "Set",
+            &("org.freedesktop.Secret.Item", "Label", Value::from("Renamed")),
+        )
+        .await;
+    assert!(result.is_err(), "expected an error, got {result:?}");
+    tokio::time::timeout(std::time::Duration::from_secs(5), async {
+        while store.collections().await.contains(&*collection.id) {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("the collection wasn't forgotten");
+    assert!(server.object_server().interface::<_, Item>(&path).await.is_err());
+    assert!(!collection_dir.exists());
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_create_item_with_invalid_attributes() {
+    let (_server, client, _store, dir) = test_service().await;
+    let collection: OwnedObjectPath = call_service(&client, "ReadAlias", &("default",)).await.unwrap();
+
+    // the properties are checked before the session
+    let secret = Secret {
+        session: EMPTY_PATH.into(),
+        parameters: vec![],
+        value: b"value".to_vec(),
+        content_type: "text/plain".into(),
+    };
+    let attributes = HashMap::from([("user", Value::from("me")), ("port", Value::from(22u32))]);
+    let label = HashMap::from([("org.freedesktop.Secret.Item.Label", Value::from(1u32))]);
+    for properties in [
+        HashMap::from([("org.freedesktop.Secret.Item.Attributes", Value::from(attributes))]),
+        label,
+    ] {
+        let result = client
+            .call_method(
+                None::<&str>,
+                &collection,
+                Some("org.freedesktop.Secret.Collection"),
+                "CreateItem",
+                &(properties, &secret, false),
+            )
+            .await;
+        let Err(zbus::Error::MethodError(name, _, _)) = result else {
+            panic!("expected an error, got {result:?}");
+        };
+        assert_eq!(name.as_str(), "org.freedesktop.DBus.Error.InvalidArgs");
+    }
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_read_alias_of_removed_collection() {
+    use futures_util::StreamExt;
+
+    let (server, client, store, dir) = test_service().await;
+    let mut messages = zbus::MessageStream::from(&client);
+
+    let label = HashMap::from([(
+        "org.freedesktop.Secret.Collection.Label",
+        Value::from("Work"),
+    )]);
+    let (collection, _): (OwnedObjectPath, OwnedObjectPath) =
+        call_service(&client, "CreateCollection", &(label, "work")).await.unwrap();
+    let collection_id = collection.rsplit('/').next().unwrap().to_owned();
+
+    // removed behind the daemon's back
+    std::fs::remove_dir_all(dir.join(crate::secret_store::PASS_SUBDIR).join(&collection_id)).unwrap();
+
+    let target: OwnedObjectPath = call_service(&client, "ReadAlias", &("work",)).await.unwrap();
+    assert_eq!(target.as_str(), "/");
+    assert!(store.get_alias(Arc::new("work".into())).await.is_err());
+    assert!(server
+        .object_server()
+        .interface::<_, Collection>("/org/freedesktop/secrets/aliases/work")
+        .await
+        .is_err());
+
+    // an alias left pointing at a collection which was never loaded
+    store.set_alias(Arc::new("stale".into()), Some("gone".into())).await.unwrap();
+    let target: OwnedObjectPath = call_service(&client, "ReadAlias", &("stale",)).await.unwrap();
+    assert_eq!(target.as_str(), "/");
+    assert!(store.get_alias(Arc::new("stale".into())).await.is_err());
+
+    // both were signalled
+    let mut removed = vec![];
+    while removed.len() < 2 {
+        let message = messages.next().await.unwrap().unwrap();
+        let header = message.header();
+        if header.member().is_some_and(|member| member == "AliasChanged") {
+            let (alias, _, collection): (String, OwnedObjectPath, OwnedObjectPath) =
+                message.body().deserialize().unwrap();
+            assert_eq!(collection.as_str(), "/");
+            removed.push(alias);
+        }
+    }
+    assert_eq!(removed, ["work", "stale"]);
+
+    // and they stay removed
+    let target: OwnedObjectPath = call_service(&client, "ReadAlias", &("work",)).await.unwrap();
+    assert_eq!(target.as_str(), "/");
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_items_consistent_during_changes() {
+    let (server, client, store, dir) = test_service().await;
+
+    let label = HashMap::from([(
+        "org.freedesktop.Secret.Collection.Label",
+        Value::from("Busy"),
+    )]);
+    let (collection_path, _): (OwnedObjectPath, OwnedObjectPath) =
+        call_service(&client, "CreateCollection", &(label, "")).await.unwrap();
+    let collection = server
+        .object_server()
+        .interface::<_, Collection>(&collection_path)
+        .await
+        .unwrap()
+        .get()
+        .await
+        .clone();
+    let attributes = HashMap::from([("busy".to_owned(), "yes".to_owned())]);
+
+    // items which have been (or are being) deleted. they're the only ones which can be
+    // missing from the object server after being listed
+    let deleted = Arc::new(std::sync::Mutex::new(std::collections::HashSet::<OwnedObjectPath>::new()));
+    let writing = Arc::new(std::sync::atomic::AtomicUsize::new(4));
+
+    let writers: Vec<_> = (0..4)
+        .map(|_| {
+            let (server, client, store) = (server.clone(), client.clone(), store.clone());
+            let (collection, collection_path) = (collection.clone(), collection_path.clone());
+            let (attributes, deleted, writing) = (Arc::new(attributes.clone()), deleted.clone(), writing.clone());
+            tokio::spawn(async move {
+                let ctxt = SignalContext::new(&server, collection_path.as_ref()).unwrap();
+                for i in 0..15 {
+                    let id = store
+                        .create_test_secret(collection.id.clone(), attributes.clone())
+                        .await
+                        .unwrap();
+                    // the secret is on disk a while before its object is, as when CreateItem encrypts
+                    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+                    let path = collection
+                        .register_item(id, &ctxt, &server.object_server())
+                        .await
+                        .unwrap();
+                    if i % 2 == 0 {
+                        deleted.lock().unwrap().insert(path.clone().into());
+                        client
+                            .call_method(None::<&str>, &path, Some("org.freedesktop.Secret.Item"), "Delete", &())
+                            .await
+                            .unwrap();
+                    }
+                }
+                writing.fetch_sub(1, std::sync::atomic::Ordering::SeqCst);
+            })
+        })
+        .collect();
+
+    let readers: Vec<_> = (0..4)
+        .map(|_| {
+            let (client, collection_path) = (client.clone(), collection_path.clone());
+            let (attributes, deleted, writing) = (attributes.clone(), deleted.clone(), writing.clone());
+            tokio::spawn(async move {
+                let properties = fdo::PropertiesProxy::builder(&client)
+                    .destination("org.freedesktop.secrets")
+                    .unwrap()
+                    .path(collection_path)
+                    .unwrap()
+                    .build()
+                    .await
+                    .unwrap();
+                // paths which have been checked already
+                let mut usable = std::collections::HashSet::new();
+                // until the writers are done
+                while writing.load(std::sync::atomic::Ordering::SeqCst) > 0 {
+                    let items = properties
+                        .get("org.freedesktop.Secret.Collection".try_into().unwrap(), "Items")
+                        .await
+                        .unwrap();
+                    let mut paths = Vec::<OwnedObjectPath>::try_from(items).unwrap();
+                    let (unlocked, locked): (Vec<OwnedObjectPath>, Vec<OwnedObjectPath>) =
+                        call_service(&client, "SearchItems", &(&attributes,)).await.unwrap();
+                    paths.extend(unlocked);
+                    paths.extend(locked);
+
+                    for path in paths {
+                        if usable.contains(&path) {
+                            continue;
                         }
+                        let label = client
+                            .call_method(
+                                None::<&str>,
+                                &path,
+                                Some("org.freedesktop.DBus.Properties"),
+                                "Get",
+                                &("org.freedesktop.Secret.Item", "Label"),
+                            )
+                            .await;
+                        assert!(
+                            label.is_ok() || deleted.lock().unwrap().contains(&path),
+                            "{path} was listed before it could be used"
+                        );
+                        usable.insert(path);
                     }
                 }
-            }
+            })
+        })
+        .collect();
 
-            collection_id
-        };
-        // save this persistently
-        self.store.set_alias(alias, target_collection_id).await?;
-        Ok(())
+    for task in writers.into_iter().chain(readers) {
+        task.await.unwrap();
     }
 
-    #[zbus(property)]
-    async fn collections(&self) -> Vec<ObjectPath> {
-        self.store
-            .collections()
+    // everything which is left is listed
+    let remaining = store.sorted_secrets(&collection.id).await.unwrap();
+    // each writer deleted 8 of its 15
+    assert_eq!(remaining.len(), 4 * 7);
+    assert_eq!(store.list_secrets(&collection.id).await.unwrap().len(), remaining.len());
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_gnome_keyring_compat() {
+    let (_server, client, store, dir) = test_service_with(Config {
+        gnome_keyring_compat: true,
+        ..Default::default()
+    })
+    .await;
+
+    let properties = HashMap::from([(
+        "org.freedesktop.Secret.Collection.Label",
+        Value::from("Legacy"),
+    )]);
+    let master = Secret {
+        session: EMPTY_PATH.into(),
+        parameters: vec![],
+        value: b"ignored".to_vec(),
+        content_type: "text/plain".into(),
+    };
+    let path: OwnedObjectPath = client
+        .call_method(
+            None::<&str>,
+            "/org/freedesktop/secrets",
+            Some("org.gnome.keyring.InternalUnsupportedGuiltRiddenInterface"),
+            "CreateWithMasterPassword",
+            &(properties, master),
+        )
+        .await
+        .unwrap()
+        .body()
+        .deserialize()
+        .unwrap();
+    let id = path.as_str().rsplit('/').next().unwrap().to_owned();
+    assert!(store.collections().await.contains(&id));
+
+    // the new collection is served like any other
+    let collections: Vec<OwnedObjectPath> = client
+        .call_method(
+            None::<&str>,
+            "/org/freedesktop/secrets",
+            Some("org.freedesktop.DBus.Properties"),
+            "Get",
+            &("org.freedesktop.Secret.Service", "Collections"),
+        )
+        .await
+        .unwrap()
+        .body()
+        .deserialize::<zbus::zvariant::OwnedValue>()
+        .unwrap()
+        .try_into()
+        .unwrap();
+    assert!(collections.contains(&path));
+
+    let environment: HashMap<String, String> = client
+        .call_method(
+            None::<&str>,
+            "/org/gnome/keyring/daemon",
+            Some("org.gnome.keyring.Daemon"),
+            "GetEnvironment",
+            &(),
+        )
+        .await
+        .unwrap()
+        .body()
+        .deserialize()
+        .unwrap();
+    assert!(environment.is_empty());
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_export_locked_collection() {
+    let (_server, client, _store, dir) = test_service().await;
+    let collection: OwnedObjectPath = call_service(&client, "ReadAlias", &("default",)).await.unwrap();
+    let _: (Vec<OwnedObjectPath>, OwnedObjectPath) =
+        call_service(&client, "Lock", &(vec![&collection],)).await.unwrap();
+
+    let path = dir.join("export.tar.gpg");
+    let result = call_manager::<_, u32>(&client, "ExportCollection", &(&collection, path.to_str().unwrap())).await;
+    let Err(zbus::Error::MethodError(name, _, _)) = result else {
+        panic!("expected an error, got {result:?}");
+    };
+    assert_eq!(name.as_str(), "org.freedesktop.Secret.Error.IsLocked");
+    assert!(!path.exists());
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_portal_retrieve_secret() {
+    use std::io::Read;
+
+    let (_server, client, store, dir) = test_service().await;
+
+    let version: u32 = client
+        .call_method(
+            None::<&str>,
+            PORTAL_PATH,
+            Some("org.freedesktop.DBus.Properties"),
+            "Get",
+            &("org.freedesktop.impl.portal.Secret", "version"),
+        )
+        .await
+        .unwrap()
+        .body()
+        .deserialize::<zbus::zvariant::OwnedValue>()
+        .unwrap()
+        .try_into()
+        .unwrap();
+    assert_eq!(version, 1);
+
+    let (mut reader, writer) = std::os::unix::net::UnixStream::pair().unwrap();
+    let handle = ObjectPath::from_static_str_unchecked("/org/freedesktop/portal/desktop/request/1_1/t");
+    let (response, _): (u32, HashMap<String, OwnedValue>) = client
+        .call_method(
+            None::<&str>,
+            PORTAL_PATH,
+            Some("org.freedesktop.impl.portal.Secret"),
+            "RetrieveSecret",
+            &(
+                handle,
+                "org.example.App",
+                zbus::zvariant::Fd::from(std::os::fd::OwnedFd::from(writer)),
+                HashMap::<String, Value>::new(),
+            ),
+        )
+        .await
+        .unwrap()
+        .body()
+        .deserialize()
+        .unwrap();
+
+    // the store has no gpg keys, so the secret can't be created
+    assert_eq!(response, 2);
+    // nothing was written before the fd was closed
+    let mut written = vec![];
+    reader.read_to_end(&mut written).unwrap();
+    assert!(written.is_empty());
+    let default = store.get_alias(Arc::new("default".to_owned())).await.unwrap();
+    assert!(store.list_secrets(&default).await.unwrap().is_empty());
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_reload_store() {
+    let (server, client, store, dir) = test_service().await;
+    let reload = || async {
+        call_manager::<_, (u32, u32, u32, u32)>(&client, "ReloadStore", &()).await.unwrap()
+    };
+
+    let label = HashMap::from([(
+        "org.freedesktop.Secret.Collection.Label",
+        Value::from("Synced"),
+    )]);
+    let (collection, _): (OwnedObjectPath, OwnedObjectPath) =
+        call_service(&client, "CreateCollection", &(label, "")).await.unwrap();
+    let collection_id = collection.rsplit('/').next().unwrap().to_owned();
+    let collection_dir = dir.join(crate::secret_store::PASS_SUBDIR).join(&collection_id);
+    assert_eq!(reload().await, (0, 0, 0, 0));
+
+    // other files in the collection's dir aren't items
+    std::fs::write(collection_dir.join("backup.gpg"), b"not encrypted").unwrap();
+    assert_eq!(reload().await, (0, 0, 0, 0));
+    assert!(store.visible_items(&collection_id).is_empty());
+
+    // an entry pulled from elsewhere, with its attributes
+    let pulled = store
+        .create_test_secret(Arc::new(collection_id.clone()), Default::default())
+        .await
+        .unwrap();
+    assert_eq!(reload().await, (0, 0, 1, 0));
+    let item = format!("{collection}/{pulled}");
+    assert!(server.object_server().interface::<_, Item>(item.as_str()).await.is_ok());
+    assert!(store.visible_items(&collection_id).contains(&pulled));
+
+    std::fs::remove_file(collection_dir.join(format!("{pulled}.gpg"))).unwrap();
+    assert_eq!(reload().await, (0, 0, 0, 1));
+    assert!(server.object_server().interface::<_, Item>(item.as_str()).await.is_err());
+
+    // and a collection removed elsewhere
+    std::fs::remove_dir_all(&collection_dir).unwrap();
+    assert_eq!(reload().await, (0, 1, 0, 0));
+    assert!(!store.collections().await.contains(&collection_id));
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_trash() {
+    let (server, client, store, dir) = test_service_with(Config {
+        trash: crate::config::TrashConfig { retention_days: 30 },
+        ..Default::default()
+    })
+    .await;
+    let label = HashMap::from([(
+        "org.freedesktop.Secret.Collection.Label",
+        Value::from("Trash"),
+    )]);
+    let (collection_path, _): (OwnedObjectPath, OwnedObjectPath) =
+        call_service(&client, "CreateCollection", &(label, "")).await.unwrap();
+    let collection = server
+        .object_server()
+        .interface::<_, Collection>(&collection_path)
+        .await
+        .unwrap()
+        .get()
+        .await
+        .clone();
+    let attributes = HashMap::from([("app".to_owned(), "trash".to_owned())]);
+    let id = store
+        .create_test_secret(collection.id.clone(), Arc::new(attributes.clone()))
+        .await
+        .unwrap();
+    let ctxt = SignalContext::new(&server, collection_path.as_ref()).unwrap();
+    let path = collection
+        .register_item(id.clone(), &ctxt, &server.object_server())
+        .await
+        .unwrap();
+    let collection_dir = dir.join(crate::secret_store::PASS_SUBDIR).join(&*collection.id);
+    let delete = || async {
+        client
+            .call_method(None::<&str>, &path, Some("org.freedesktop.Secret.Item"), "Delete", &())
             .await
+            .unwrap();
+    };
+
+    // deleting moves it to the trash, where searches don't find it
+    delete().await;
+    assert!(!collection_dir.join(format!("{id}.gpg")).exists());
+    assert!(collection_dir.join(".trash").join(format!("{id}.gpg")).exists());
+    assert_eq!(store.count_matching(attributes.clone(), 10).await.unwrap(), 0);
+    assert_eq!(store.count_secrets(collection.id.clone()).await.unwrap(), 0);
+    let trash: Vec<(String, String, u64)> =
+        call_manager(&client, "ListTrash", &(&collection_path,)).await.unwrap();
+    assert_eq!(trash.len(), 1);
+    assert_eq!((trash[0].0.as_str(), trash[0].1.as_str()), (id.as_str(), "Test"));
+
+    // restoring brings back its object and attributes
+    let restored: OwnedObjectPath =
+        call_manager(&client, "RestoreItem", &(&collection_path, &id)).await.unwrap();
+    assert_eq!(restored.as_ref(), path);
+    assert!(server.object_server().interface::<_, Item>(&path).await.is_ok());
+    assert_eq!(store.count_matching(attributes.clone(), 10).await.unwrap(), 1);
+    assert_eq!(store.count_secrets(collection.id.clone()).await.unwrap(), 1);
+    // it's no longer in the trash
+    let again: zbus::Result<OwnedObjectPath> =
+        call_manager(&client, "RestoreItem", &(&collection_path, &id)).await;
+    assert!(again.is_err());
+
+    // and purging deletes it for good
+    delete().await;
+    let purged: u32 = call_manager(&client, "PurgeTrash", &(&collection_path, "")).await.unwrap();
+    assert_eq!(purged, 1);
+    assert!(!collection_dir.join(".trash").join(format!("{id}.gpg")).exists());
+    assert!(store.list_trash(collection.id.clone()).await.unwrap().is_empty());
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_optional_attributes() {
+    let (server, client, store, dir) = test_service_with(Config {
+        optional_attributes: vec!["xdg:schema".into()],
+        ..Default::default()
+    })
+    .await;
+    let collection_id = store.get_alias(Arc::new("default".into())).await.unwrap();
+    let collection_path = collection_path(&collection_id).unwrap();
+    let collection = server
+        .object_server()
+        .interface::<_, Collection>(&collection_path)
+        .await
+        .unwrap()
+        .get()
+        .await
+        .clone();
+    let stored = HashMap::from([
+        ("xdg:schema".to_owned(), "org.example.Password".to_owned()),
+        ("user".to_owned(), "me".to_owned()),
+    ]);
+    let id = store
+        .create_test_secret(collection.id.clone(), Arc::new(stored))
+        .await
+        .unwrap();
+    let ctxt = SignalContext::new(&server, collection_path.as_ref()).unwrap();
+    let path = collection
+        .register_item(id, &ctxt, &server.object_server())
+        .await
+        .unwrap();
+
+    // a newer version of the client uses another schema
+    let query = HashMap::from([("xdg:schema", "org.example.Password2"), ("user", "me")]);
+    let (unlocked, _): (Vec<OwnedObjectPath>, Vec<OwnedObjectPath>) =
+        call_service(&client, "SearchItems", &(&query,)).await.unwrap();
+    assert_eq!(unlocked, [path.clone().into()]);
+
+    // but a query of only optional attributes still has to match them
+    let query = HashMap::from([("xdg:schema", "org.example.Password2")]);
+    let (unlocked, _): (Vec<OwnedObjectPath>, Vec<OwnedObjectPath>) =
+        call_service(&client, "SearchItems", &(&query,)).await.unwrap();
+    assert!(unlocked.is_empty());
+
+    // FindItems matches labels and wildcards
+    let query = HashMap::from([("xdg:schema", "org.example.*")]);
+    let (unlocked, _): (Vec<OwnedObjectPath>, Vec<OwnedObjectPath>) =
+        call_manager(&client, "FindItems", &("tes", &query)).await.unwrap();
+    assert_eq!(unlocked, [path.into()]);
+    let (unlocked, _): (Vec<OwnedObjectPath>, Vec<OwnedObjectPath>) =
+        call_manager(&client, "FindItems", &("other", &query)).await.unwrap();
+    assert!(unlocked.is_empty());
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_search_items_with_info() {
+    let (server, client, store, dir) = test_service().await;
+    let collection_id = store.get_alias(Arc::new("default".into())).await.unwrap();
+    let collection_path = collection_path(&collection_id).unwrap();
+    let collection = server
+        .object_server()
+        .interface::<_, Collection>(&collection_path)
+        .await
+        .unwrap()
+        .get()
+        .await
+        .clone();
+    let attributes = HashMap::from([
+        ("service".to_owned(), "example.com".to_owned()),
+        ("user".to_owned(), "me".to_owned()),
+    ]);
+    let id = store
+        .create_test_secret(collection.id.clone(), Arc::new(attributes.clone()))
+        .await
+        .unwrap();
+    store
+        .set_secret_label(collection.id.clone(), Arc::new(id.clone()), "Example".to_owned())
+        .await
+        .unwrap();
+    let ctxt = SignalContext::new(&server, collection_path.as_ref()).unwrap();
+    let path = collection
+        .register_item(id, &ctxt, &server.object_server())
+        .await
+        .unwrap();
+
+    let query = HashMap::from([("user", "me")]);
+    let found: Vec<(OwnedObjectPath, String, HashMap<String, String>)> =
+        call_manager(&client, "SearchItemsWithInfo", &(&query,)).await.unwrap();
+    assert_eq!(found, [(path.into(), "Example".to_owned(), attributes)]);
+
+    let query = HashMap::from([("user", "someone else")]);
+    let found: Vec<(OwnedObjectPath, String, HashMap<String, String>)> =
+        call_manager(&client, "SearchItemsWithInfo", &(&query,)).await.unwrap();
+    assert!(found.is_empty());
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_purge_duplicates() {
+    let (server, client, store, dir) = test_service().await;
+    let collection_id = store.get_alias(Arc::new("default".into())).await.unwrap();
+    let collection_path = collection_path(&collection_id).unwrap();
+    let collection = server
+        .object_server()
+        .interface::<_, Collection>(&collection_path)
+        .await
+        .unwrap()
+        .get()
+        .await
+        .clone();
+    let ctxt = SignalContext::new(&server, collection_path.as_ref()).unwrap();
+    let mut paths = vec![];
+    for attributes in [
+        vec![("user", "me")],
+        vec![("user", "me"), (crate::secret_store::VERSION_ATTRIBUTE, "2")],
+        vec![("user", "someone else")],
+    ] {
+        let attributes = attributes
             .into_iter()
-            .filter_map(collection_path)
-            .collect()
+            .map(|(k, v)| (k.to_owned(), v.to_owned()))
+            .collect();
+        let id = store
+            .create_test_secret(collection.id.clone(), Arc::new(attributes))
+            .await
+            .unwrap();
+        let path = collection
+            .register_item(id, &ctxt, &server.object_server())
+            .await
+            .unwrap();
+        paths.push(OwnedObjectPath::from(path));
     }
 
-    // signals
+    let attributes = HashMap::from([("user".to_owned(), "me".to_owned())]);
+    assert_eq!(store.exact_matches(collection.id.clone(), &attributes).await.unwrap().len(), 2);
 
-    #[zbus(signal)]
-    async fn collection_created(ctx: &SignalContext<'_>, path: ObjectPath<'_>) -> zbus::Result<()>;
+    let groups: Vec<Vec<OwnedObjectPath>> =
+        call_manager(&client, "ListDuplicates", &(&collection_path,)).await.unwrap();
+    assert_eq!(groups, [paths[..2].to_vec()]);
 
-    #[zbus(signal)]
-    async fn collection_deleted(ctx: &SignalContext<'_>, path: ObjectPath<'_>) -> zbus::Result<()>;
+    // the newest version is kept
+    let count: u32 = call_manager(&client, "PurgeDuplicates", &(&collection_path,)).await.unwrap();
+    assert_eq!(count, 1);
+    assert!(server.object_server().interface::<_, Item>(&paths[0]).await.is_err());
+    assert!(server.object_server().interface::<_, Item>(&paths[1]).await.is_ok());
+    assert!(server.object_server().interface::<_, Item>(&paths[2]).await.is_ok());
+    let groups: Vec<Vec<OwnedObjectPath>> =
+        call_manager(&client, "ListDuplicates", &(&collection_path,)).await.unwrap();
+    assert!(groups.is_empty());
 
-    #[zbus(signal)]
-    async fn collection_modified(ctx: &SignalContext<'_>, path: ObjectPath<'_>)
-        -> zbus::Result<()>;
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_sweep_stale_items() {
+    let (server, _client, store, dir) = test_service().await;
+    let collection_id = store.get_alias(Arc::new("default".into())).await.unwrap();
+    let collection_path = collection_path(&collection_id).unwrap();
+    let collection = server
+        .object_server()
+        .interface::<_, Collection>(&collection_path)
+        .await
+        .unwrap()
+        .get()
+        .await
+        .clone();
+    let ctxt = SignalContext::new(&server, collection_path.as_ref()).unwrap();
+    let mut paths = vec![];
+    for user in ["me", "someone else"] {
+        let attributes = HashMap::from([("user".to_owned(), user.to_owned())]);
+        let id = store
+            .create_test_secret(collection.id.clone(), Arc::new(attributes))
+            .await
+            .unwrap();
+        let path = collection
+            .register_item(id.clone(), &ctxt, &server.object_server())
+            .await
+            .unwrap();
+        paths.push((id, path));
+    }
+
+    assert_eq!(super::sweep::sweep(&server, &store).await.unwrap(), 0);
+
+    // e.g. its metadata couldn't be written after the file was
+    let (stale_id, stale_path) = &paths[0];
+    std::fs::remove_file(
+        dir.join(crate::secret_store::PASS_SUBDIR)
+            .join(&*collection_id)
+            .join(format!("{stale_id}.gpg")),
+    )
+    .unwrap();
+    assert_eq!(super::sweep::sweep(&server, &store).await.unwrap(), 1);
+    assert!(server.object_server().interface::<_, Item>(stale_path).await.is_err());
+    assert!(server.object_server().interface::<_, Item>(&paths[1].1).await.is_ok());
+    assert_eq!(store.visible_items(&collection_id), [paths[1].0.clone()]);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_fix_unreachable_ids() {
+    let (server, client, store, dir) = test_service().await;
+    let collection_id = Arc::new(store.get_alias(Arc::new("default".into())).await.unwrap());
+    let collection_dir = dir.join(crate::secret_store::PASS_SUBDIR).join(&*collection_id);
+    // e.g. made with pass, then given metadata with import-metadata
+    for (secret_id, user) in [("work.email", "me"), ("work_email", "someone else")] {
+        std::fs::write(collection_dir.join(format!("{secret_id}.gpg")), b"not encrypted").unwrap();
+        let attributes = HashMap::from([("user".to_owned(), user.to_owned())]);
+        store
+            .set_secret_attrs(collection_id.clone(), Arc::new(secret_id.to_owned()), attributes)
+            .await
+            .unwrap();
+    }
+    store
+        .set_secret_label(collection_id.clone(), Arc::new("work.email".to_owned()), "Work".to_owned())
+        .await
+        .unwrap();
+
+    let found = store.fix_unreachable_ids().await.unwrap();
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].secret_id.as_deref(), Some("work.email"));
+    // the escaped id was taken
+    let renamed = Arc::new(found[0].renamed_to.clone().unwrap());
+    assert!(renamed.starts_with("work_email_"));
+    assert!(!collection_dir.join("work.email.gpg").exists());
+    assert!(collection_dir.join(format!("{renamed}.gpg")).exists());
+    assert_eq!(store.get_secret_label(collection_id.clone(), renamed.clone()).await.unwrap(), "Work");
+    assert_eq!(
+        store.read_secret_attrs(collection_id.clone(), renamed.clone()).await.unwrap(),
+        HashMap::from([("user".to_owned(), "me".to_owned())])
+    );
+    assert!(store.fix_unreachable_ids().await.unwrap().is_empty());
+
+    let (_, _, added, _): (u32, u32, u32, u32) = call_manager(&client, "ReloadStore", &()).await.unwrap();
+    assert_eq!(added, 2);
+    let path = secret_path(collection_id.as_str(), renamed.as_str()).unwrap();
+    assert!(server.object_server().interface::<_, Item>(&path).await.is_ok());
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_adopt_directory() {
+    let (server, client, store, dir) = test_service().await;
+    std::fs::create_dir_all(dir.join("email")).unwrap();
+    std::fs::write(dir.join("email/work.gpg"), b"not encrypted").unwrap();
+    std::fs::write(dir.join("email/github.com.gpg"), b"not encrypted").unwrap();
+
+    let (collection, skipped): (OwnedObjectPath, Vec<String>) =
+        call_manager(&client, "AdoptDirectory", &("email", "")).await.unwrap();
+    assert_eq!(skipped, ["github.com"]);
+    let id = Arc::new(collection.as_str().rsplit('/').next().unwrap().to_owned());
+    assert_eq!(store.get_label(id.clone()).await.unwrap(), "email");
+    let item = format!("{}/work", collection.as_str());
+    assert!(server.object_server().interface::<_, Item>(item.as_str()).await.is_ok());
+    assert_eq!(
+        store.get_secret_label(id.clone(), Arc::new("work".to_owned())).await.unwrap(),
+        "work"
+    );
+    let link = dir.join(crate::secret_store::PASS_SUBDIR).join(&*id);
+    assert!(link.symlink_metadata().unwrap().is_symlink());
+
+    // only other dirs in the store
+    let collections = store.collections().await.len();
+    for directory in ["secret-service", "../email", "missing", "email/work.gpg"] {
+        let result: zbus::Result<(OwnedObjectPath, Vec<String>)> =
+            call_manager(&client, "AdoptDirectory", &(directory, "")).await;
+        assert!(result.is_err(), "{directory}");
+    }
+    assert_eq!(store.collections().await.len(), collections);
+
+    // deleting it only removes the link
+    store.delete_collection(id).await.unwrap();
+    assert!(!link.exists());
+    assert!(dir.join("email/work.gpg").exists());
+}
+
+#[tokio::test]
+async fn test_eject_collection() {
+    let (server, client, store, dir) = test_service().await;
+    let label = HashMap::from([(
+        "org.freedesktop.Secret.Collection.Label",
+        Value::from("Eject"),
+    )]);
+    let (collection_path, _): (OwnedObjectPath, OwnedObjectPath) =
+        call_service(&client, "CreateCollection", &(label, "")).await.unwrap();
+    let collection = server
+        .object_server()
+        .interface::<_, Collection>(&collection_path)
+        .await
+        .unwrap()
+        .get()
+        .await
+        .clone();
+    let ctxt = SignalContext::new(&server, collection_path.as_ref()).unwrap();
+    let mut ids = vec![];
+    for _ in 0..3 {
+        let id = store
+            .create_test_secret(collection.id.clone(), Default::default())
+            .await
+            .unwrap();
+        collection
+            .register_item(id.clone(), &ctxt, &server.object_server())
+            .await
+            .unwrap();
+        ids.push(id);
+    }
+    store
+        .set_secret_label(collection.id.clone(), Arc::new(ids[2].clone()), "work/email".to_owned())
+        .await
+        .unwrap();
+
+    // only new directories outside the secret service's
+    std::fs::create_dir(dir.join("taken")).unwrap();
+    for target in ["taken", "secret-service/x", ".hidden", "../outside"] {
+        let result: zbus::Result<u32> =
+            call_manager(&client, "EjectCollection", &(&collection_path, target)).await;
+        assert!(result.is_err(), "{target}");
+    }
+
+    let count: u32 = call_manager(&client, "EjectCollection", &(&collection_path, "ejected/eject"))
+        .await
+        .unwrap();
+    assert_eq!(count, 3);
+    let ejected = dir.join("ejected/eject");
+    for name in ["Test.gpg", "Test (2).gpg", "work-email.gpg", "secret-service-metadata.yaml"] {
+        assert!(ejected.join(name).exists(), "{name}");
+    }
+    assert!(!ejected.join("attributes.redb").exists());
+    let yaml = std::fs::read_to_string(ejected.join("secret-service-metadata.yaml")).unwrap();
+    assert!(yaml.contains("work-email:\n    label: work/email"));
+
+    // and it's no longer served
+    assert!(!dir.join(crate::secret_store::PASS_SUBDIR).join(&*collection.id).exists());
+    assert!(!store.collections().await.contains(&*collection.id));
+    let item = format!("{}/{}", collection_path.as_str(), ids[0]);
+    assert!(server.object_server().interface::<_, Item>(item.as_str()).await.is_err());
+    assert!(server
+        .object_server()
+        .interface::<_, Collection>(&collection_path)
+        .await
+        .is_err());
 }
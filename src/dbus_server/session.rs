@@ -1,3 +1,8 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
 use tokio::{select, sync::oneshot::{self, Sender}, task};
 use zbus::{
     fdo::{self, DBusProxy}, interface, message::Header, names::OwnedUniqueName, zvariant::OwnedObjectPath, Connection, ObjectServer
@@ -12,6 +17,9 @@ pub enum SessionAlgorithm {
     Plain,
 }
 
+/// the open sessions, with the client which opened each
+pub type OpenSessions = Arc<Mutex<HashMap<OwnedObjectPath, OwnedUniqueName>>>;
+
 pub struct Session {
     alg: SessionAlgorithm,
     client_name: OwnedUniqueName,
@@ -23,9 +31,14 @@ impl Session {
         alg: SessionAlgorithm,
         client_name: OwnedUniqueName,
         path: OwnedObjectPath,
-        connection: Connection
+        connection: Connection,
+        open_sessions: OpenSessions,
     ) -> Self {
         let (tx, rx) = oneshot::channel();
+        open_sessions
+            .lock()
+            .unwrap()
+            .insert(path.clone(), client_name.clone());
 
         let name_str = client_name.to_string();
         let path_2 = path.clone();
@@ -50,6 +63,7 @@ impl Session {
                     object_server.remove::<Self, _>(&path_2).await?;
                 }
             }
+            open_sessions.lock().unwrap().remove(&path_2);
 
             zbus::Result::Ok(())
         });
@@ -73,7 +87,7 @@ impl Session {
         }
     }
 
-    pub fn encrypt(&self, secret: Vec<u8>, header: &Header<'_>) -> Result<Secret> {
+    pub fn encrypt(&self, secret: Vec<u8>, content_type: String, header: &Header<'_>) -> Result<Secret> {
         // make sure they're allowed to do this
         if !header.sender().is_some_and(|s| self.client_name == *s) {
             return Err(Error::PermissionDenied);
@@ -84,7 +98,7 @@ impl Session {
                 session: self.path.clone(),
                 parameters: vec![],
                 value: secret,
-                content_type: "text/plain".into(),
+                content_type,
             }),
         }
     }
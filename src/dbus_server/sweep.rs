@@ -0,0 +1,58 @@
+use std::time::Duration;
+
+use log::{info, warn};
+use zbus::{object_server::SignalContext, Connection};
+
+use crate::{error::Result, secret_store::SecretStore};
+
+use super::{
+    collection::Collection,
+    utils::{collection_path, try_interface},
+};
+
+/// how often the items being served are checked against the store
+const SWEEP_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// stop serving items whose secrets are gone, on a schedule
+pub async fn watch_stale_items(connection: Connection, store: SecretStore<'static>) {
+    let mut interval = tokio::time::interval(SWEEP_INTERVAL);
+    // the first tick is immediate, and every item was just registered from the store
+    interval.tick().await;
+    loop {
+        interval.tick().await;
+        match sweep(&connection, &store).await {
+            Ok(0) => {}
+            Ok(count) => info!("Stopped serving {count} item(s) whose secrets are gone"),
+            Err(e) => warn!("Could not check for stale items: {e}"),
+        }
+    }
+}
+
+/// remove the items whose secrets are gone (e.g. since adding them failed partway) from the
+/// object server. returns how many were removed
+pub async fn sweep(connection: &Connection, store: &SecretStore<'static>) -> Result<u32> {
+    let object_server = connection.object_server();
+    let mut removed = 0;
+    for id in store.collections().await {
+        let Some(path) = collection_path(&id) else {
+            continue;
+        };
+        let Some(collection) = try_interface(object_server.interface::<_, Collection>(&path).await)?
+        else {
+            continue;
+        };
+        let collection = collection.get().await.clone();
+
+        let stale = store.stale_items(&id).await?;
+        let signal_context = SignalContext::new(connection, path)?;
+        for secret_id in stale {
+            warn!("Item {secret_id} in collection {id} has no secret, so it's no longer served");
+            collection
+                .forget_item(&secret_id, &signal_context, &object_server)
+                .await?;
+            removed += 1;
+        }
+    }
+
+    Ok(removed)
+}
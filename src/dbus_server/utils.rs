@@ -1,34 +1,111 @@
-use std::{fmt::Display, io, time::SystemTime};
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    fmt::Display,
+    time::SystemTime,
+};
 
 use serde::{Deserialize, Serialize};
-use zbus::zvariant::{ObjectPath, OwnedObjectPath, Type};
+use tokio::fs::read_link;
+use zbus::{
+    fdo::DBusProxy,
+    message::Header,
+    names::BusName,
+    zvariant::{ObjectPath, OwnedObjectPath, Type},
+    Connection,
+};
+
+use crate::{
+    config::{ClientConfig, Config},
+    error::Result,
+    secret_store::{is_valid_id, SecretStore},
+};
 
 pub const EMPTY_PATH: ObjectPath = ObjectPath::from_static_str_unchecked("/");
 
+/// the start of path elements for ids which were escaped
+const ESCAPED_PREFIX: &str = "_x_";
+
+/// turn a collection, item, or alias id into an object path element, so any name on disk (e.g.
+/// `github.com`, from another tool) can be served. ids of letters, digits, and underscores are
+/// used as they are. others get a prefix, and like systemd's bus path escaping, every byte which
+/// isn't a letter or digit becomes `_` and its hex value. ids which would look escaped are
+/// escaped too, so the mapping is reversible
+pub fn escape_id(id: &str) -> Cow<'_, str> {
+    if is_valid_id(id) && !id.starts_with(ESCAPED_PREFIX) {
+        return Cow::Borrowed(id);
+    }
+    let mut escaped = ESCAPED_PREFIX.to_owned();
+    for b in id.bytes() {
+        if b.is_ascii_alphanumeric() {
+            escaped.push(b as char);
+        } else {
+            escaped.push_str(&format!("_{b:02x}"));
+        }
+    }
+    Cow::Owned(escaped)
+}
+
+/// the id an object path element was made from by `escape_id`
+pub fn unescape_id(element: &str) -> Option<String> {
+    let Some(escaped) = element.strip_prefix(ESCAPED_PREFIX) else {
+        return Some(element.to_owned());
+    };
+    let mut bytes = vec![];
+    let mut rest = escaped.as_bytes();
+    while let Some((&b, tail)) = rest.split_first() {
+        if b == b'_' {
+            let hex = std::str::from_utf8(tail.get(..2)?).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(b);
+            rest = tail;
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
 pub fn collection_path<T: Display>(collection_id: T) -> Option<ObjectPath<'static>> {
+    let collection_id = collection_id.to_string();
     ObjectPath::try_from(format!(
-        "/org/freedesktop/secrets/collection/{collection_id}"
+        "/org/freedesktop/secrets/collection/{}",
+        escape_id(&collection_id)
     ))
     .ok()
 }
 pub fn secret_path<T: Display>(collection_id: T, secret_id: T) -> Option<ObjectPath<'static>> {
+    let (collection_id, secret_id) = (collection_id.to_string(), secret_id.to_string());
     ObjectPath::try_from(format!(
-        "/org/freedesktop/secrets/collection/{collection_id}/{secret_id}"
+        "/org/freedesktop/secrets/collection/{}/{}",
+        escape_id(&collection_id),
+        escape_id(&secret_id)
     ))
     .ok()
 }
 pub fn secret_alias_path<T: Display>(alias: T, secret_id: T) -> Option<ObjectPath<'static>> {
+    let (alias, secret_id) = (alias.to_string(), secret_id.to_string());
     ObjectPath::try_from(format!(
-        "/org/freedesktop/secrets/aliases/{alias}/{secret_id}"
+        "/org/freedesktop/secrets/aliases/{}/{}",
+        escape_id(&alias),
+        escape_id(&secret_id)
     ))
     .ok()
 }
 pub fn alias_path<T: Display>(alias: T) -> Option<ObjectPath<'static>> {
-    ObjectPath::try_from(format!("/org/freedesktop/secrets/aliases/{alias}")).ok()
+    let alias = alias.to_string();
+    ObjectPath::try_from(format!("/org/freedesktop/secrets/aliases/{}", escape_id(&alias))).ok()
+}
+/// the id of the item at an item path
+pub fn path_secret_id(path: &str) -> Option<String> {
+    unescape_id(path.rsplit('/').next()?)
 }
 pub fn session_path<T: Display>(session_id: T) -> Option<ObjectPath<'static>> {
     ObjectPath::try_from(format!("/org/freedesktop/secrets/session/{session_id}")).ok()
 }
+pub fn prompt_path<T: Display>(prompt_id: T) -> Option<ObjectPath<'static>> {
+    ObjectPath::try_from(format!("/org/freedesktop/secrets/prompt/{prompt_id}")).ok()
+}
 pub fn try_interface<T>(result: zbus::Result<T>) -> zbus::Result<Option<T>> {
     match result {
         Ok(v) => Ok(Some(v)),
@@ -37,8 +114,64 @@ pub fn try_interface<T>(result: zbus::Result<T>) -> zbus::Result<Option<T>> {
     }
 }
 
-pub fn time_to_int(time: io::Result<SystemTime>) -> u64 {
-    time.ok()
+/// get the path of the executable which sent a message
+pub async fn client_exe(connection: &Connection, header: &Header<'_>) -> Option<String> {
+    let sender = header.sender()?.to_owned();
+    name_exe(connection, BusName::Unique(sender)).await
+}
+
+/// the path of the executable of the client which owns a bus name
+pub async fn name_exe(connection: &Connection, name: BusName<'_>) -> Option<String> {
+    let dbus = DBusProxy::new(connection).await.ok()?;
+    let pid = dbus.get_connection_unix_process_id(name).await.ok()?;
+
+    process_exe(pid).await
+}
+
+/// the path of a process's executable
+pub async fn process_exe(pid: u32) -> Option<String> {
+    read_link(format!("/proc/{pid}/exe"))
+        .await
+        .ok()
+        .map(|exe| exe.to_string_lossy().into_owned())
+}
+
+/// the settings for the client which sent a message, if it has any
+pub async fn client_config<'c>(
+    connection: &Connection,
+    header: &Header<'_>,
+    config: &'c Config,
+) -> Option<&'c ClientConfig> {
+    // don't bother looking up the client if nothing is configured
+    if config.clients.is_empty() {
+        return None;
+    }
+
+    config.client(&client_exe(connection, header).await?)
+}
+
+/// leave out the search results (by collection id) from collections the client which sent a
+/// message isn't configured to search
+pub async fn retain_searched<T>(
+    connection: &Connection,
+    header: &Header<'_>,
+    store: &SecretStore<'_>,
+    items: &mut HashMap<String, T>,
+) -> Result {
+    if let Some(client) = client_config(connection, header, store.config).await {
+        if !client.search.is_empty() {
+            let mut searched = HashSet::new();
+            for name in &client.search {
+                searched.extend(store.resolve_collection(name).await?);
+            }
+            items.retain(|col, _| searched.contains(col));
+        }
+    }
+    Ok(())
+}
+
+pub fn time_to_int(time: Option<SystemTime>) -> u64 {
+    time
         // return 0 for times before the epoch or for platforms where this isn't supported
         .and_then(|t| t.duration_since(SystemTime::UNIX_EPOCH).ok())
         .map(|t| t.as_secs())
@@ -52,3 +185,26 @@ pub struct Secret {
     pub value: Vec<u8>,
     pub content_type: String,
 }
+
+/// what GetItemFull returns: the secret, attributes, label, and created and modified times
+pub type ItemFull = (Secret, HashMap<String, String>, String, u64, u64);
+
+#[test]
+fn test_escape_id() {
+    assert_eq!(escape_id("default_luRm"), "default_luRm");
+    assert_eq!(escape_id("github.com"), "_x_github_2ecom");
+    assert_eq!(escape_id("_x_github_2ecom"), "_x__5fx_5fgithub_5f2ecom");
+    assert_eq!(escape_id(""), "_x_");
+
+    for id in ["default_luRm", "github.com", "_x_github_2ecom", "a b/c", "ü", "", "_x_"] {
+        let escaped = escape_id(id);
+        assert!(ObjectPath::try_from(format!("/{escaped}")).is_ok());
+        assert_eq!(unescape_id(&escaped).as_deref(), Some(id));
+    }
+    assert_eq!(unescape_id("_x_abc_2"), None);
+    assert_eq!(unescape_id("_x__ff"), None);
+
+    let path = secret_path("work.email", "github.com").unwrap();
+    assert_eq!(path.as_str(), "/org/freedesktop/secrets/collection/_x_work_2eemail/_x_github_2ecom");
+    assert_eq!(path_secret_id(&path).as_deref(), Some("github.com"));
+}
@@ -0,0 +1,124 @@
+use std::{
+    cell::Cell,
+    future::Future,
+    sync::OnceLock,
+    time::{Duration, Instant},
+};
+
+use crate::error::{Error, Result};
+
+tokio::task_local! {
+    /// when the current D-Bus request gives up. None once it has changed something
+    static DEADLINE: Cell<Option<Instant>>;
+}
+
+thread_local! {
+    /// the deadline of the request a blocking task is running for
+    static BLOCKING_DEADLINE: Cell<Option<Instant>> = const { Cell::new(None) };
+}
+
+/// how long each request may take
+static BUDGET: OnceLock<Duration> = OnceLock::new();
+
+/// stop working on requests which take longer than `budget`. zero disables it
+pub fn init(budget: Duration) {
+    if !budget.is_zero() {
+        let _ = BUDGET.set(budget);
+    }
+}
+
+/// run a D-Bus method with the request budget, so the work it does can be abandoned once the
+/// client has given up
+pub async fn scoped<F: Future>(f: F) -> F::Output {
+    match BUDGET.get() {
+        Some(budget) => DEADLINE.scope(Cell::new(Some(Instant::now() + *budget)), f).await,
+        None => f.await,
+    }
+}
+
+/// the deadline of the current request, if it has one
+pub fn current() -> Option<Instant> {
+    DEADLINE
+        .try_with(Cell::get)
+        .unwrap_or_else(|_| BLOCKING_DEADLINE.with(Cell::get))
+}
+
+/// fail with `DeadlineExceeded` if the current request is out of time
+/// called between steps of work which can take a while, where stopping is safe
+pub fn check() -> Result {
+    match current() {
+        Some(deadline) if Instant::now() >= deadline => Err(Error::DeadlineExceeded),
+        _ => Ok(()),
+    }
+}
+
+/// give the request more time, e.g. for as long as it waited for the user
+pub fn extend(by: Duration) {
+    let _ = DEADLINE.try_with(|deadline| deadline.set(deadline.get().map(|d| d + by)));
+}
+
+/// let the request finish however long it takes, since it has changed something
+/// stopping partway through could leave e.g. a secret's file without its attributes
+pub fn disarm() {
+    let _ = DEADLINE.try_with(|deadline| deadline.set(None));
+}
+
+/// make `deadline` the current one on this (blocking) thread until the guard is dropped
+pub fn enter_blocking(deadline: Option<Instant>) -> BlockingGuard {
+    BLOCKING_DEADLINE.with(|current| current.set(deadline));
+    BlockingGuard
+}
+
+pub struct BlockingGuard;
+
+impl Drop for BlockingGuard {
+    fn drop(&mut self) {
+        BLOCKING_DEADLINE.with(|current| current.set(None));
+    }
+}
+
+/// run `f` until the current request's deadline
+/// dropping it cancels it, so a future which owns a child process should kill it on drop
+pub async fn until_deadline<F: Future>(f: F) -> Result<F::Output> {
+    match current() {
+        Some(deadline) => tokio::time::timeout_at(deadline.into(), f)
+            .await
+            .map_err(|_| Error::DeadlineExceeded),
+        None => Ok(f.await),
+    }
+}
+
+#[tokio::test]
+async fn test_deadline() {
+    // no request
+    assert!(check().is_ok());
+    assert!(until_deadline(async {}).await.is_ok());
+
+    let deadline = Instant::now() + Duration::from_millis(50);
+    DEADLINE
+        .scope(Cell::new(Some(deadline)), async {
+            assert!(check().is_ok());
+            let slow = tokio::time::sleep(Duration::from_secs(10));
+            assert!(matches!(until_deadline(slow).await, Err(Error::DeadlineExceeded)));
+            assert!(matches!(check(), Err(Error::DeadlineExceeded)));
+
+            extend(Duration::from_secs(10));
+            assert!(check().is_ok());
+
+            disarm();
+            assert!(current().is_none());
+            extend(Duration::from_secs(10));
+            assert!(current().is_none());
+        })
+        .await;
+
+    // blocking threads get it from the request which started them
+    let passed = Some(Instant::now() - Duration::from_secs(1));
+    let result = tokio::task::spawn_blocking(move || {
+        let _deadline = enter_blocking(passed);
+        check()
+    })
+    .await
+    .unwrap();
+    assert!(matches!(result, Err(Error::DeadlineExceeded)));
+}
@@ -0,0 +1,105 @@
+use std::{io, path::Path};
+
+use zbus::Connection;
+
+use crate::{
+    agent,
+    config::Config,
+    dbus_server::name::{service_name_owner, SERVICE_NAME},
+    error::{Error, Result},
+    pass::PasswordStore,
+    secret_store::PASS_SUBDIR,
+};
+
+/// check everything the daemon needs to start, print a report, and return whether it can
+/// nothing is changed, and the service name isn't requested
+pub async fn run(config_path: Option<&Path>, use_pass_cli: bool) -> bool {
+    let mut ok = true;
+    let mut report = |name: &str, result: Result<String>| match result {
+        Ok(detail) => println!("ok    {name}: {detail}"),
+        Err(e) => {
+            println!("FAIL  {name}: {e}");
+            ok = false;
+        }
+    };
+
+    report("config", check_config(config_path));
+
+    let pass = match PasswordStore::from_env() {
+        Ok(pass) => pass,
+        Err(e) => {
+            report("store", Err(e));
+            return false;
+        }
+    };
+    report("store", check_store(&pass).await);
+    report("gpg", check_keys(&pass).await);
+    report("gpg-agent", agent::launch().await.map(|_| "running".into()));
+    if use_pass_cli {
+        report("pass", check_pass_cli().await);
+    }
+    report("bus", check_bus().await);
+
+    ok
+}
+
+fn check_config(path: Option<&Path>) -> Result<String> {
+    Config::load(path)?;
+    Ok(match path.map(Path::to_owned).or_else(Config::default_path) {
+        Some(path) if path.exists() => format!("loaded {}", path.display()),
+        _ => "using the defaults".into(),
+    })
+}
+
+async fn check_store(pass: &PasswordStore) -> Result<String> {
+    if !pass.directory.is_dir() {
+        return Err(Error::NotInitialized(format!("{} is not a directory", pass.directory.display())));
+    }
+    let recipients = pass.get_recipients(PASS_SUBDIR).await?;
+    Ok(format!(
+        "{} (encrypting to {})",
+        pass.directory.display(),
+        recipients.join(", ")
+    ))
+}
+
+/// every recipient needs a public key, and we need a secret key to read anything
+async fn check_keys(pass: &PasswordStore) -> Result<String> {
+    let recipients = pass.get_recipients(PASS_SUBDIR).await?;
+    let keys = pass.recipient_key_ids(&recipients).await?;
+    if keys.len() < recipients.len() {
+        return Err(Error::GpgError(format!(
+            "only {} of {} recipients have a public key",
+            keys.len(),
+            recipients.len()
+        )));
+    }
+
+    let key_ids: Vec<_> = keys.into_iter().flatten().collect();
+    if pass.keygrips(&key_ids).await?.is_empty() {
+        return Err(Error::GpgError(
+            "none of the recipients' secret keys are available, so secrets can't be read".into(),
+        ));
+    }
+    Ok(format!("keys found for {} recipient(s)", recipients.len()))
+}
+
+async fn check_pass_cli() -> Result<String> {
+    let output = tokio::process::Command::new("pass")
+        .arg("version")
+        .output()
+        .await?;
+    if output.status.success() {
+        Ok("found".into())
+    } else {
+        Err(io::Error::other("`pass version` failed").into())
+    }
+}
+
+async fn check_bus() -> Result<String> {
+    let connection = Connection::session().await?;
+    Ok(match service_name_owner(&connection).await? {
+        Some(owner) => format!("connected. {SERVICE_NAME} is owned by {owner}"),
+        None => format!("connected. {SERVICE_NAME} is available"),
+    })
+}
@@ -10,16 +10,49 @@ use zbus::{
     DBusError, Message,
 };
 
+use crate::error_stats;
+
 #[derive(Debug)]
 pub enum Error {
     IoError(io::Error),
     DbusError(zbus::Error),
-    RedbError(redb::Error),
+    // boxed, since redb's error is much larger than the rest
+    RedbError(Box<redb::Error>),
     GpgError(String),
-    // pass is not initialized
-    NotInitialized,
+    // gpg-agent couldn't be reached, even after starting it again
+    AgentUnavailable(String),
+    // the pass CLI failed (with its error output)
+    Pass(String),
+    // git failed in the password store (with its output)
+    Git(String),
+    Config(String),
+    // an imported metadata or keyring file couldn't be parsed
+    InvalidMetadata(String),
+    // a secret's label and attributes can't be written into its entry (with why)
+    CannotEmbedMetadata(String),
+    // a remote secret backend failed
+    Backend(String),
+    // another daemon is using the store (with its pid, if known)
+    AlreadyRunning(Option<u32>),
+    // another process owns org.freedesktop.secrets (its description, and whether replacing it was tried)
+    NameTaken(String, bool),
+    // pass is not initialized (with where it was looked for)
+    NotInitialized(String),
+    // a `.gpg-id` has no recipients, or one which isn't allowed
+    InvalidGpgId(String),
     InvalidSession,
+    // a method was called with arguments of the wrong type (with which)
+    InvalidArgs(String),
     PermissionDenied,
+    IsLocked,
+    // the collection's secrets can't be changed
+    ReadOnly,
+    // a secret didn't match what was written, even after retrying
+    WriteVerificationFailed,
+    // the request ran past its deadline, so its work was abandoned
+    DeadlineExceeded,
+    // blocking work panicked (with the panic message)
+    Panicked(String),
 }
 
 impl From<io::Error> for Error {
@@ -36,12 +69,13 @@ impl From<zbus::Error> for Error {
 
 impl From<redb::Error> for Error {
     fn from(value: redb::Error) -> Self {
-        Self::RedbError(value)
+        Self::RedbError(Box::new(value))
     }
 }
 
 impl DBusError for Error {
     fn create_reply(&self, msg: &Header<'_>) -> zbus::Result<Message> {
+        error_stats::record(self);
         let name = self.name();
         #[allow(deprecated)]
         let msg = message::Builder::error(msg, name)?;
@@ -51,6 +85,17 @@ impl DBusError for Error {
             Error::DbusError(e) => msg.build(&(e.to_string(),)),
             Error::RedbError(e) => msg.build(&(e.to_string(),)),
             Error::GpgError(e) => msg.build(&(e,)),
+            Error::AgentUnavailable(e) => msg.build(&(e,)),
+            Error::Pass(e) => msg.build(&(e,)),
+            Error::Git(e) => msg.build(&(e,)),
+            Error::Config(e) => msg.build(&(e,)),
+            Error::InvalidMetadata(e) => msg.build(&(e,)),
+            Error::CannotEmbedMetadata(e) => msg.build(&(e,)),
+            Error::Backend(e) => msg.build(&(e,)),
+            Error::Panicked(e) => msg.build(&(e,)),
+            Error::InvalidGpgId(e) => msg.build(&(e,)),
+            Error::InvalidArgs(e) => msg.build(&(e,)),
+            Error::NotInitialized(e) => msg.build(&(e,)),
             _ => msg.build(&()),
         }
     }
@@ -64,9 +109,25 @@ impl DBusError for Error {
             Error::DbusError(_) => "org.freedesktop.zbus.Error",
             Error::RedbError(_) => "me.grimsteel.PassSecretService.ReDBError",
             Error::GpgError(_) => "me.grimsteel.PassSecretService.GPGError",
-            Error::NotInitialized => "me.grimsteel.PassSecretService.PassNotInitialized",
+            Error::AgentUnavailable(_) => "me.grimsteel.PassSecretService.AgentUnavailable",
+            Error::Pass(_) => "me.grimsteel.PassSecretService.PassError",
+            Error::Git(_) => "me.grimsteel.PassSecretService.GitError",
+            Error::Config(_) => "me.grimsteel.PassSecretService.ConfigError",
+            Error::InvalidMetadata(_) => "me.grimsteel.PassSecretService.InvalidMetadata",
+            Error::CannotEmbedMetadata(_) => "me.grimsteel.PassSecretService.CannotEmbedMetadata",
+            Error::Backend(_) => "me.grimsteel.PassSecretService.BackendError",
+            Error::AlreadyRunning(_) => "me.grimsteel.PassSecretService.AlreadyRunning",
+            Error::NameTaken(..) => "me.grimsteel.PassSecretService.NameTaken",
+            Error::NotInitialized(_) => "me.grimsteel.PassSecretService.PassNotInitialized",
+            Error::InvalidGpgId(_) => "me.grimsteel.PassSecretService.InvalidGpgId",
             Error::InvalidSession => "org.freedesktop.Secret.Error.NoSession",
+            Error::InvalidArgs(_) => "org.freedesktop.DBus.Error.InvalidArgs",
             Error::PermissionDenied => "org.freedesktop.DBus.Error.AccessDenied",
+            Error::IsLocked => "org.freedesktop.Secret.Error.IsLocked",
+            Error::ReadOnly => "me.grimsteel.PassSecretService.ReadOnly",
+            Error::WriteVerificationFailed => "me.grimsteel.PassSecretService.WriteVerificationFailed",
+            Error::DeadlineExceeded => "me.grimsteel.PassSecretService.DeadlineExceeded",
+            Error::Panicked(_) => "me.grimsteel.PassSecretService.InternalError",
         })
     }
 
@@ -74,6 +135,17 @@ impl DBusError for Error {
         match self {
             Error::DbusError(zbus::Error::MethodError(_, desc, _)) => desc.as_deref(),
             Error::GpgError(e) => Some(e.as_str()),
+            Error::AgentUnavailable(e) => Some(e.as_str()),
+            Error::Pass(e) => Some(e.as_str()),
+            Error::Git(e) => Some(e.as_str()),
+            Error::Config(e) => Some(e.as_str()),
+            Error::InvalidMetadata(e) => Some(e.as_str()),
+            Error::CannotEmbedMetadata(e) => Some(e.as_str()),
+            Error::Backend(e) => Some(e.as_str()),
+            Error::Panicked(e) => Some(e.as_str()),
+            Error::InvalidGpgId(e) => Some(e.as_str()),
+            Error::InvalidArgs(e) => Some(e.as_str()),
+            Error::NotInitialized(e) => Some(e.as_str()),
             _ => None,
         }
     }
@@ -85,20 +157,57 @@ impl Display for Error {
             Error::IoError(e) => write!(f, "I/O Error: {e}"),
             Error::DbusError(e) => write!(f, "D-Bus Error: {e}"),
             Error::GpgError(e) => write!(f, "GPG Error; {e}"),
+            Error::AgentUnavailable(e) => write!(f, "Could not reach gpg-agent: {e}"),
+            Error::Pass(e) => write!(f, "pass Error: {e}"),
+            Error::Git(e) => write!(f, "git Error: {e}"),
             Error::RedbError(e) => write!(f, "ReDB Error: {e}"),
-            Error::NotInitialized => write!(f, "Pass is not initialized"),
+            Error::Config(e) => write!(f, "Config Error: {e}"),
+            Error::InvalidMetadata(e) => write!(f, "Invalid metadata: {e}"),
+            Error::CannotEmbedMetadata(e) => write!(f, "Could not write metadata into the entry: {e}"),
+            Error::Backend(e) => write!(f, "Backend Error: {e}"),
+            Error::AlreadyRunning(Some(pid)) => write!(
+                f,
+                "Another instance (PID {pid}) is using this password store. Stop it or pass --takeover"
+            ),
+            Error::AlreadyRunning(None) => write!(
+                f,
+                "Another instance is using this password store. Stop it or pass --takeover"
+            ),
+            Error::NameTaken(owner, false) => write!(
+                f,
+                "{owner} already provides org.freedesktop.secrets. Stop it, or pass --replace or --queue. \
+                To stop gnome-keyring providing it, copy /etc/xdg/autostart/gnome-keyring-secrets.desktop \
+                to ~/.config/autostart and add `Hidden=true`"
+            ),
+            Error::NameTaken(owner, true) => write!(
+                f,
+                "{owner} already provides org.freedesktop.secrets, and doesn't allow replacing it. \
+                Stop it, or pass --queue to wait for it to exit"
+            ),
+            Error::NotInitialized(e) => write!(f, "Pass is not initialized: {e}"),
+            Error::InvalidGpgId(e) => write!(f, "Invalid .gpg-id: {e}"),
             Error::InvalidSession => write!(f, "Invalid secret service session"),
+            Error::InvalidArgs(e) => write!(f, "Invalid arguments: {e}"),
             Error::PermissionDenied => write!(f, "Access denied"),
+            Error::IsLocked => write!(f, "Object is locked"),
+            Error::ReadOnly => write!(f, "Secrets in this collection can't be changed"),
+            Error::WriteVerificationFailed => {
+                write!(f, "Secret did not match what was written after retrying")
+            }
+            Error::DeadlineExceeded => write!(f, "The request took too long and was cancelled"),
+            Error::Panicked(e) => write!(f, "Internal error: {e}"),
         }
     }
 }
 
 impl From<Error> for fdo::Error {
     fn from(value: Error) -> Self {
+        error_stats::record(&value);
         match value {
             Error::IoError(err) => Self::IOError(format!("{err}")),
             Error::DbusError(err) => Self::ZBus(err),
             Error::PermissionDenied => Self::AccessDenied("Access denied".into()),
+            Error::InvalidArgs(e) => Self::InvalidArgs(e),
             err => Self::Failed(format!("{err}")),
         }
     }
@@ -0,0 +1,103 @@
+use std::{
+    collections::HashMap,
+    sync::{Mutex, PoisonError},
+    time::SystemTime,
+};
+
+use zbus::DBusError;
+
+use crate::error::Error;
+
+/// the longest message kept for the last error
+const MAX_MESSAGE_LEN: usize = 200;
+
+static STATS: Mutex<ErrorStats> = Mutex::new(ErrorStats {
+    last: None,
+    counts: None,
+});
+
+struct ErrorStats {
+    /// (category, redacted message, unix seconds)
+    last: Option<(String, String, u64)>,
+    /// by category. `None` until the first error, since a `HashMap` can't be made in a static
+    counts: Option<HashMap<String, u32>>,
+}
+
+/// the category an error is counted under: the last part of its D-Bus error name, e.g. `IsLocked`
+fn category(error: &Error) -> String {
+    let name = error.name();
+    name.rsplit('.').next().unwrap_or(&name).to_owned()
+}
+
+/// an error message without paths (which can name collections and items) or email addresses
+/// (e.g. gpg user ids), since any client can read it
+fn redact(message: &str) -> String {
+    let first_line = message.lines().next().unwrap_or_default();
+    let mut redacted = first_line
+        .split(' ')
+        .map(|word| {
+            // but not abbreviations like I/O
+            let is_path = word.contains('/') && !word.chars().all(|c| c == '/' || c.is_ascii_uppercase());
+            if is_path || word.contains('@') {
+                "<redacted>"
+            } else {
+                word
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ");
+    if redacted.len() > MAX_MESSAGE_LEN {
+        let mut end = MAX_MESSAGE_LEN;
+        while !redacted.is_char_boundary(end) {
+            end -= 1;
+        }
+        redacted.truncate(end);
+        redacted.push('…');
+    }
+    redacted
+}
+
+/// count an error which was returned to a client
+pub fn record(error: &Error) {
+    let category = category(error);
+    let time = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+
+    let mut stats = STATS.lock().unwrap_or_else(PoisonError::into_inner);
+    *stats
+        .counts
+        .get_or_insert_with(HashMap::new)
+        .entry(category.clone())
+        .or_default() += 1;
+    stats.last = Some((category, redact(&error.to_string()), time));
+}
+
+/// the last error returned to a client: (category, redacted message, unix seconds)
+pub fn last() -> Option<(String, String, u64)> {
+    STATS.lock().unwrap_or_else(PoisonError::into_inner).last.clone()
+}
+
+/// how many errors of each category were returned to clients
+pub fn counts() -> HashMap<String, u32> {
+    STATS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .counts
+        .clone()
+        .unwrap_or_default()
+}
+
+#[test]
+fn test_redact() {
+    assert_eq!(
+        redact("I/O Error: No such file /home/me/.password-store/x.gpg"),
+        "I/O Error: No such file <redacted>"
+    );
+    assert_eq!(
+        redact("GPG Error; no public key for me@example.com\ngpg: more details"),
+        "GPG Error; no public key for <redacted>"
+    );
+    assert_eq!(redact(&"é".repeat(150)).chars().count(), MAX_MESSAGE_LEN / 2 + 1);
+}
@@ -0,0 +1,78 @@
+use std::process::Stdio;
+
+use argon2::{
+    password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
+use log::warn;
+use tokio::process::Command;
+
+use crate::error::Result;
+
+/// hash a PIN with argon2id and a random salt, as a PHC string
+/// PINs are short, so this is deliberately slow to guess
+pub fn hash_pin(pin: &str) -> String {
+    let salt: [u8; 16] = rand::random();
+    let salt = SaltString::encode_b64(&salt).expect("16 bytes is a valid salt");
+    Argon2::default()
+        .hash_password(pin.as_bytes(), &salt)
+        .expect("the default parameters are valid")
+        .to_string()
+}
+
+/// check a PIN against one stored by `hash_pin`
+pub fn pin_matches(stored: &str, pin: &str) -> bool {
+    PasswordHash::new(stored).is_ok_and(|hash| {
+        Argon2::default()
+            .verify_password(pin.as_bytes(), &hash)
+            .is_ok()
+    })
+}
+
+/// check a PIN against a high-security PIN stored by older versions: a random blake3 key
+/// followed by the keyed hash. it's only used to replace one with `hash_pin`'s
+pub fn legacy_pin_matches(stored: &[u8], pin: &str) -> bool {
+    let Some((key, hash)) = stored.split_first_chunk::<{ blake3::KEY_LEN }>() else {
+        return false;
+    };
+    // constant time
+    blake3::keyed_hash(key, pin.as_bytes()) == *hash
+}
+
+/// run the configured confirmation command, returning whether it succeeded
+pub async fn run_command(command: &[String], collection_id: &str) -> Result<bool> {
+    let Some((program, args)) = command.split_first() else {
+        warn!("The high-security command is not configured");
+        return Ok(false);
+    };
+
+    let status = Command::new(program)
+        .args(args)
+        .env("PASS_SECRET_SERVICE_COLLECTION", collection_id)
+        .stdin(Stdio::null())
+        .status()
+        .await?;
+    Ok(status.success())
+}
+
+#[test]
+fn test_pin_matches() {
+    let stored = hash_pin("1234");
+    assert!(stored.starts_with("$argon2id$"));
+    assert!(pin_matches(&stored, "1234"));
+    assert!(!pin_matches(&stored, "4321"));
+    assert!(!pin_matches("not a hash", "1234"));
+    // a new salt each time
+    assert_ne!(hash_pin("1234"), stored);
+}
+
+#[test]
+fn test_legacy_pin_matches() {
+    let key: [u8; blake3::KEY_LEN] = rand::random();
+    let hash = blake3::keyed_hash(&key, b"1234");
+    let stored = [key.as_slice(), hash.as_bytes()].concat();
+    assert!(legacy_pin_matches(&stored, "1234"));
+    assert!(!legacy_pin_matches(&stored, "12345"));
+    assert!(!legacy_pin_matches(&stored[..10], "1234"));
+    assert!(!legacy_pin_matches(hash_pin("1234").as_bytes(), "1234"));
+}
@@ -0,0 +1,262 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    env,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use log::{info, warn};
+
+use crate::{
+    error::{Error, Result},
+    secret_store::SecretStore,
+};
+
+/// the start of keyring files which are encrypted with a password
+const ENCRYPTED_HEADER: &[u8] = b"GnomeKeyring\n\r\0\n";
+
+/// a keyring file gnome-keyring saved without encryption, since its password is empty
+#[derive(Debug, Default, PartialEq)]
+pub struct Keyring {
+    pub label: Option<String>,
+    pub items: Vec<KeyringItem>,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct KeyringItem {
+    pub label: Option<String>,
+    pub secret: Vec<u8>,
+    pub attributes: HashMap<String, String>,
+}
+
+/// where gnome-keyring keeps its keyrings: `$XDG_DATA_HOME/keyrings`
+pub fn keyrings_dir() -> Option<PathBuf> {
+    let data_home = env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".local").join("share")))?;
+
+    Some(data_home.join("keyrings"))
+}
+
+/// undo GKeyFile's escaping of string values
+fn unescape(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('s') => unescaped.push(' '),
+            Some('n') => unescaped.push('\n'),
+            Some('t') => unescaped.push('\t'),
+            Some('r') => unescaped.push('\r'),
+            Some(other) => unescaped.push(other),
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// parse a keyring file in gnome-keyring's textual format
+/// items are `[<id>]` groups, and their attributes `[<id>:attribute<n>]` groups
+pub fn parse(contents: &[u8]) -> Result<Keyring> {
+    if contents.starts_with(ENCRYPTED_HEADER) {
+        return Err(Error::InvalidMetadata(
+            "the keyring is encrypted with a password".to_owned(),
+        ));
+    }
+    let contents = std::str::from_utf8(contents)
+        .map_err(|_| Error::InvalidMetadata("the keyring is not a text file".to_owned()))?;
+
+    // group -> key -> raw value, with groups in order
+    let mut groups: Vec<(&str, HashMap<&str, &str>)> = vec![];
+    for (n, line) in contents.lines().enumerate() {
+        let line = line.trim_start();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(group) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            groups.push((group, HashMap::new()));
+        } else if let (Some((key, value)), Some((_, entries))) = (line.split_once('='), groups.last_mut()) {
+            entries.insert(key.trim_end(), value.trim_start());
+        } else {
+            return Err(Error::InvalidMetadata(format!("line {} of the keyring is invalid", n + 1)));
+        }
+    }
+
+    let mut keyring = Keyring::default();
+    // by item id, so attributes can be added to their item
+    let mut items = BTreeMap::new();
+    for (group, entries) in &groups {
+        if *group == "keyring" {
+            keyring.label = entries.get("display-name").map(|v| unescape(v));
+        } else if let Some((id, _)) = group.split_once(':') {
+            // attributes (and ACLs, which are ignored) come after their item
+            if !group[id.len() + 1..].starts_with("attribute") {
+                continue;
+            }
+            let item: &mut KeyringItem = items.get_mut(id).ok_or_else(|| {
+                Error::InvalidMetadata(format!("the keyring has an attribute for a missing item {id}"))
+            })?;
+            if let (Some(name), Some(value)) = (entries.get("name"), entries.get("value")) {
+                item.attributes.insert(unescape(name), unescape(value));
+            }
+        } else {
+            // secrets are written without escaping, unless they aren't text
+            let secret = match (entries.get("secret"), entries.get("binary-secret")) {
+                (Some(secret), _) => secret.as_bytes().to_vec(),
+                (None, Some(hex)) => decode_hex(hex).ok_or_else(|| {
+                    Error::InvalidMetadata(format!("item {group} of the keyring has an invalid secret"))
+                })?,
+                (None, None) => vec![],
+            };
+            items.insert(
+                *group,
+                KeyringItem {
+                    label: entries.get("display-name").map(|v| unescape(v)),
+                    secret,
+                    attributes: HashMap::new(),
+                },
+            );
+        }
+    }
+    keyring.items = items.into_values().collect();
+
+    Ok(keyring)
+}
+
+/// import the unencrypted keyrings in `dir` as collections, each with an item for each of its
+/// items. keyrings which were imported before are skipped, so this can run on every start
+/// returns the ids of the new collections
+pub async fn import_keyrings(store: &SecretStore<'_>, dir: &Path) -> Result<Vec<String>> {
+    let mut entries = match tokio::fs::read_dir(dir).await {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut files = vec![];
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        if path.extension().is_some_and(|ext| ext == "keyring") {
+            files.push(path);
+        }
+    }
+    files.sort();
+
+    let mut imported = vec![];
+    for path in files {
+        let name = path.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+        if store.keyring_imported(Arc::new(name.clone())).await? {
+            continue;
+        }
+
+        let keyring = match parse(&tokio::fs::read(&path).await?) {
+            Ok(keyring) => keyring,
+            Err(e) => {
+                warn!(
+                    "Not importing keyring {}: {e}. To import it, set an empty password for it (e.g. in Seahorse) so gnome-keyring saves it unencrypted, and restart",
+                    path.display()
+                );
+                continue;
+            }
+        };
+
+        let count = keyring.items.len();
+        let label = keyring.label.unwrap_or_else(|| name.clone());
+        let collection_id = Arc::new(store.create_collection(Some(label), None).await?);
+        let mut result = Ok(());
+        for item in keyring.items {
+            result = store
+                .create_secret(collection_id.clone(), item.label, item.secret, Arc::new(item.attributes))
+                .await
+                .map(drop);
+            if result.is_err() {
+                break;
+            }
+        }
+
+        // remove a partial import, so it's tried again
+        if let Err(e) = result {
+            warn!("Could not import keyring {}: {e}", path.display());
+            store.delete_collection(collection_id).await?;
+            continue;
+        }
+
+        store
+            .record_imported_keyring(Arc::new(name), collection_id.clone())
+            .await?;
+        info!(
+            "Imported {count} item(s) from keyring {} into collection {collection_id}",
+            path.display()
+        );
+        imported.push(collection_id.to_string());
+    }
+
+    Ok(imported)
+}
+
+#[test]
+fn test_parse_keyring() {
+    let keyring = parse(
+        b"[keyring]
+display-name=Login\\sitems
+ctime=0
+lock-on-idle=false
+
+[1]
+item-type=0
+display-name=Password for me on example.com
+secret=hunter2
+mtime=1700000000
+
+[1:attribute0]
+name=xdg:schema
+type=string
+value=org.gnome.keyring.NetworkPassword
+
+[1:attribute1]
+name=port
+type=uint32
+value=22
+
+[1:acl0]
+display-name=seahorse
+
+[2]
+display-name=Key
+binary-secret=00ff10
+",
+    )
+    .unwrap();
+
+    assert_eq!(keyring.label.as_deref(), Some("Login items"));
+    assert_eq!(keyring.items.len(), 2);
+    assert_eq!(keyring.items[0].label.as_deref(), Some("Password for me on example.com"));
+    assert_eq!(keyring.items[0].secret, b"hunter2");
+    assert_eq!(
+        keyring.items[0].attributes,
+        HashMap::from([
+            ("xdg:schema".to_owned(), "org.gnome.keyring.NetworkPassword".to_owned()),
+            ("port".to_owned(), "22".to_owned()),
+        ])
+    );
+    assert_eq!(keyring.items[1].secret, [0x00, 0xff, 0x10]);
+    assert!(keyring.items[1].attributes.is_empty());
+
+    assert!(parse(b"GnomeKeyring\n\r\0\n\x00\x01").is_err());
+    assert!(parse(b"[keyring]\n[1:attribute0]\nname=x\nvalue=y").is_err());
+    assert!(parse(b"[1]\nbinary-secret=abc").is_err());
+}
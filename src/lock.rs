@@ -0,0 +1,122 @@
+use std::{
+    fs::File,
+    io::{self, Read, Seek, Write},
+    os::fd::AsRawFd,
+    path::Path,
+    time::Duration,
+};
+
+use log::warn;
+
+use crate::{
+    error::{Error, Result},
+    pass::PasswordStore,
+    secret_store::PASS_SUBDIR,
+};
+
+const LOCK_FILE: &str = "daemon.lock";
+/// how long to wait for the other instance to exit when taking over
+const TAKEOVER_TIMEOUT: Duration = Duration::from_secs(5);
+const TAKEOVER_POLL: Duration = Duration::from_millis(100);
+
+/// an exclusive lock on the store, held for as long as this is alive
+#[derive(Debug)]
+pub struct DaemonLock {
+    _file: File,
+}
+
+/// try to take an exclusive advisory lock on the file without blocking
+/// returns false if another process holds it
+fn try_lock(file: &File) -> io::Result<bool> {
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } == 0 {
+        return Ok(true);
+    }
+    let err = io::Error::last_os_error();
+    if err.kind() == io::ErrorKind::WouldBlock {
+        Ok(false)
+    } else {
+        Err(err)
+    }
+}
+
+/// read the pid the lock holder wrote into the file
+fn read_pid(file: &mut File) -> Option<u32> {
+    let mut contents = String::new();
+    file.rewind().ok()?;
+    file.read_to_string(&mut contents).ok()?;
+    contents.trim().parse().ok()
+}
+
+fn is_alive(pid: u32) -> bool {
+    unsafe { libc::kill(pid as libc::pid_t, 0) == 0 }
+}
+
+/// the name of a program's executable, without the ` (deleted)` Linux adds once it's replaced
+/// (e.g. by an upgrade)
+fn exe_name(exe: &Path) -> Option<String> {
+    let name = exe.file_name()?.to_string_lossy();
+    Some(name.strip_suffix(" (deleted)").unwrap_or(&name).to_owned())
+}
+
+/// whether `pid` runs the same program as us, so a pid which was reused since the lock file was
+/// written isn't signalled
+fn is_daemon(pid: u32) -> bool {
+    let Ok(exe) = std::fs::read_link(format!("/proc/{pid}/exe")) else {
+        return false;
+    };
+    let ours = std::env::current_exe().ok();
+    exe_name(&exe).is_some() && exe_name(&exe) == ours.as_deref().and_then(exe_name)
+}
+
+impl DaemonLock {
+    /// lock the store so only one daemon uses it at a time
+    /// with `takeover`, ask the other instance to exit and wait for it to release the lock
+    pub async fn acquire(pass: &PasswordStore, takeover: bool) -> Result<Self> {
+        let path = Path::new(PASS_SUBDIR).join(LOCK_FILE);
+        let mut file = pass.open_file(&path).await?.into_std().await;
+
+        if !try_lock(&file)? {
+            let pid = read_pid(&mut file);
+            if !takeover {
+                return Err(Error::AlreadyRunning(pid));
+            }
+
+            match pid.filter(|pid| is_alive(*pid)) {
+                Some(pid) if is_daemon(pid) => {
+                    warn!("Stopping the other instance (PID {pid})");
+                    unsafe { libc::kill(pid as libc::pid_t, libc::SIGTERM) };
+                }
+                Some(pid) => warn!("Not stopping PID {pid}, since it isn't pass-secret-service"),
+                None => {}
+            }
+
+            // the file is never replaced while it's locked, or two instances could each hold a
+            // lock on a different file
+            let mut waited = Duration::ZERO;
+            while !try_lock(&file)? {
+                if waited >= TAKEOVER_TIMEOUT {
+                    return Err(Error::AlreadyRunning(read_pid(&mut file)));
+                }
+                tokio::time::sleep(TAKEOVER_POLL).await;
+                waited += TAKEOVER_POLL;
+            }
+        }
+
+        // record our pid for the next instance's error message
+        file.set_len(0)?;
+        file.rewind()?;
+        write!(file, "{}", std::process::id())?;
+
+        Ok(Self { _file: file })
+    }
+}
+
+#[test]
+fn test_is_daemon() {
+    assert!(is_daemon(std::process::id()));
+    // init is a different program
+    assert!(!is_daemon(1));
+    assert!(!is_daemon(u32::MAX));
+
+    assert_eq!(exe_name(Path::new("/usr/bin/pass-secret-service (deleted)")).as_deref(), Some("pass-secret-service"));
+}
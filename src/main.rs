@@ -1,29 +1,140 @@
-use dbus_server::service::Service;
+use std::{process::ExitCode, time::Duration};
+
+use clap::Parser;
+use cli::Args;
+use config::Config;
+use dbus_server::{
+    name::{request_gnome_keyring_name, request_service_name},
+    service::Service,
+};
+use env_logger::Env;
+use lock::DaemonLock;
+use log::{error, info, warn};
 use pass::PasswordStore;
+use systemd::ShutdownSignals;
+use tokio::select;
 use zbus::Connection;
 
+mod agent;
+mod ask_password;
+mod audit;
+mod cli;
+mod collation;
+mod config;
+mod crash;
 mod dbus_server;
+mod deadline;
+mod dry_start;
 mod error;
+mod error_stats;
+mod gate;
+mod keyring_file;
+mod lock;
+mod mdellweg;
+mod mirror;
+mod openpgp;
 mod pass;
+mod pass_tree;
+mod passthrough;
+mod pinentry;
+mod recent_log;
 mod redb_imps;
+mod secret_cache;
 mod secret_store;
+mod systemd;
+mod timing;
+#[cfg(feature = "vault")]
+mod vault;
+mod write_queue;
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let pass = Box::leak(Box::new(PasswordStore::from_env()?));
+async fn main() -> Result<ExitCode, Box<dyn std::error::Error>> {
+    recent_log::init(&mut env_logger::Builder::from_env(
+        Env::default().default_filter_or("info"),
+    ));
+
+    let args = Args::parse();
+
+    if let Some(command) = args.command {
+        cli::run(command).await?;
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    if args.dry_start {
+        let ok = dry_start::run(args.config.as_deref(), args.use_pass_cli).await;
+        return Ok(if ok { ExitCode::SUCCESS } else { ExitCode::FAILURE });
+    }
+
+    let mut pass = PasswordStore::from_env()?;
+    pass.use_cli = args.use_pass_cli;
+    pass.override_modes(args.file_mode, args.dir_mode)?;
+    pass.check_location().await;
+    let pass = Box::leak(Box::new(pass));
+    let config = Box::leak(Box::new(Config::load(args.config.as_deref())?));
+    config.verify_writes |= args.verify_writes;
+    config.forget_password_on_lock |= args.forget_password_on_lock;
+    config.no_interactive |= args.no_interactive;
+    config.expose_pass_tree |= args.expose_pass_tree;
+    config.gnome_keyring_compat |= args.gnome_keyring_compat;
+    config.metadata_in_tmpfs |= args.metadata_in_tmpfs;
+    config.import_keyrings |= args.import_keyrings;
+
+    crash::install_panic_hook(config.crash_report_dir());
+    if config.sort_by_label {
+        collation::init();
+    }
+    timing::init(Duration::from_millis(config.slow_request_ms));
+    deadline::init(Duration::from_millis(config.request_deadline_ms));
+    audit::init(&config.audit)?;
+
+    // held until we exit
+    let _lock = match DaemonLock::acquire(pass, args.takeover).await {
+        Ok(lock) => lock,
+        Err(e) => {
+            error!("{e}");
+            std::process::exit(1);
+        }
+    };
+
+    // stop cleanly instead of being killed, so the databases are closed
+    let mut shutdown_signals = ShutdownSignals::listen()?;
 
     let connection = Connection::session().await?;
 
-    let service = Service::init(connection.clone(), pass).await?;
+    let service = Service::init(connection.clone(), pass, config).await?;
 
     connection
         .object_server()
         .at("/org/freedesktop/secrets", service)
         .await?;
 
-    connection.request_name("org.freedesktop.secrets").await?;
+    if let Err(e) = request_service_name(&connection, args.queue, args.replace).await {
+        error!("{e}");
+        return Ok(ExitCode::FAILURE);
+    }
+    if config.gnome_keyring_compat {
+        request_gnome_keyring_name(&connection).await;
+    }
+
+    systemd::notify(&format!(
+        "READY=1\nSTATUS=Serving {}",
+        pass.directory.display()
+    ));
 
-    loop {
-        std::future::pending::<()>().await;
+    // returning drops the runtime and everything it owns, which closes the databases cleanly
+    select! {
+        // a panic leaves the daemon in an unknown state, so stop
+        _ = crash::wait_for_panic() => {
+            error!("Shutting down after a panic");
+            Ok(ExitCode::FAILURE)
+        }
+        signal = shutdown_signals.recv() => {
+            info!("Received {signal}, shutting down");
+            systemd::notify("STOPPING=1");
+            if let Err(e) = Service::shutdown(&connection).await {
+                warn!("Could not shut down cleanly: {e}");
+            }
+            Ok(ExitCode::SUCCESS)
+        }
     }
 }
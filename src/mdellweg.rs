@@ -0,0 +1,273 @@
+use std::{
+    collections::HashMap,
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use log::{info, warn};
+use serde_json::Value;
+
+use crate::{
+    error::{Error, Result},
+    secret_store::SecretStore,
+};
+
+/// where mdellweg's pass_secret_service keeps its collections, relative to the password store
+pub const MDELLWEG_SUBDIR: &str = "secret_service";
+
+/// a collection of mdellweg's pass_secret_service: a directory with a `.properties` JSON file,
+/// and for each item, its secret in `<name>.gpg` and its label and attributes in
+/// `<name>.properties`. aliases are in a `.aliases` JSON file next to the collections
+#[derive(Debug, Default, PartialEq)]
+pub struct MdellwegCollection {
+    /// name of its directory
+    pub name: String,
+    pub label: Option<String>,
+    pub aliases: Vec<String>,
+    pub items: Vec<MdellwegItem>,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct MdellwegItem {
+    /// name of its entry, without `.gpg`
+    pub name: String,
+    pub label: Option<String>,
+    pub attributes: HashMap<String, String>,
+}
+
+/// parse a `.properties` file, returning the label and attributes
+/// they're stored under their own names, or (in older versions) the D-Bus property names
+fn parse_properties(contents: &[u8], interface: &str) -> Result<(Option<String>, HashMap<String, String>)> {
+    let invalid = |e: serde_json::Error| Error::InvalidMetadata(format!("invalid properties: {e}"));
+    let properties: HashMap<String, Value> = serde_json::from_slice(contents).map_err(invalid)?;
+    let get = |name: &str| {
+        properties
+            .get(name)
+            .or_else(|| properties.get(&format!("org.freedesktop.Secret.{interface}.{}", capitalize(name))))
+    };
+
+    let label = get("label").and_then(Value::as_str).map(str::to_owned);
+    let attributes = match get("attributes") {
+        Some(attributes) => serde_json::from_value(attributes.clone()).map_err(invalid)?,
+        None => HashMap::new(),
+    };
+    Ok((label, attributes))
+}
+
+fn capitalize(name: &str) -> String {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .map(|first| first.to_uppercase().chain(chars).collect())
+        .unwrap_or_default()
+}
+
+/// read a file, or None if it doesn't exist
+async fn read_optional(path: &Path) -> Result<Option<Vec<u8>>> {
+    match tokio::fs::read(path).await {
+        Ok(contents) => Ok(Some(contents)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// read the collections in a directory of mdellweg's pass_secret_service, in order of name
+pub async fn read_layout(dir: &Path) -> Result<Vec<MdellwegCollection>> {
+    // alias -> collection name
+    let aliases: HashMap<String, String> = match read_optional(&dir.join(".aliases")).await? {
+        Some(contents) => serde_json::from_slice(&contents)
+            .map_err(|e| Error::InvalidMetadata(format!("invalid .aliases: {e}")))?,
+        None => HashMap::new(),
+    };
+
+    let mut collections = vec![];
+    let mut entries = tokio::fs::read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.starts_with('.') || !entry.file_type().await?.is_dir() {
+            continue;
+        }
+        let path = entry.path();
+        let label = match read_optional(&path.join(".properties")).await? {
+            Some(contents) => parse_properties(&contents, "Collection")?.0,
+            None => None,
+        };
+
+        let mut items = vec![];
+        let mut files = tokio::fs::read_dir(&path).await?;
+        while let Some(file) = files.next_entry().await? {
+            let file_name = file.file_name().to_string_lossy().into_owned();
+            let Some(item_name) = file_name.strip_suffix(".gpg") else {
+                continue;
+            };
+            let (label, attributes) = match read_optional(&path.join(format!("{item_name}.properties"))).await? {
+                Some(contents) => parse_properties(&contents, "Item")
+                    .map_err(|e| Error::InvalidMetadata(format!("{name}/{item_name}: {e}")))?,
+                None => (None, HashMap::new()),
+            };
+            items.push(MdellwegItem {
+                name: item_name.to_owned(),
+                label,
+                attributes,
+            });
+        }
+        items.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let mut collection_aliases: Vec<_> = aliases
+            .iter()
+            .filter(|(_, target)| **target == name)
+            .map(|(alias, _)| alias.clone())
+            .collect();
+        collection_aliases.sort();
+        collections.push(MdellwegCollection {
+            name,
+            label,
+            aliases: collection_aliases,
+            items,
+        });
+    }
+    collections.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Ok(collections)
+}
+
+/// import the collections of mdellweg's pass_secret_service in `directory` (relative to the
+/// password store). every secret is decrypted first, and read back once it's stored, so a
+/// collection is only kept if all of its secrets made it. aliases which are already taken
+/// are skipped. the original directory isn't changed
+/// returns (new collection id, number of items) for each collection
+pub async fn import_mdellweg(store: &SecretStore<'_>, directory: &Path) -> Result<Vec<(String, usize)>> {
+    let dir = store.store_directory(directory).await?;
+    let mut imported = vec![];
+    for collection in read_layout(&dir).await? {
+        let collection_dir = directory.join(&collection.name);
+        let mut secrets = vec![];
+        for item in &collection.items {
+            let path: PathBuf = collection_dir.join(&item.name);
+            let secret = store.read_store_entry(&path).await.map_err(|e| {
+                Error::InvalidMetadata(format!("could not decrypt {}: {e}", path.display()))
+            })?;
+            secrets.push(secret);
+        }
+
+        let label = collection.label.clone().unwrap_or_else(|| collection.name.clone());
+        let collection_id = Arc::new(store.create_collection(Some(label), None).await?);
+        if let Err(e) = import_items(store, &collection_id, &collection, secrets).await {
+            // remove a partial import, so it can be run again
+            store.delete_collection(collection_id).await?;
+            return Err(e);
+        }
+
+        for alias in &collection.aliases {
+            let alias = Arc::new(alias.clone());
+            match store.get_alias(alias.clone()).await {
+                Ok(target) => warn!("Not setting alias {alias} for collection {collection_id}, since it's already {target}"),
+                Err(Error::IoError(e)) if e.kind() == io::ErrorKind::NotFound => {
+                    store.set_alias(alias, Some(collection_id.to_string())).await?;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        let count = collection.items.len();
+        info!(
+            "Imported {count} item(s) from {} into collection {collection_id}",
+            collection_dir.display()
+        );
+        imported.push((collection_id.to_string(), count));
+    }
+
+    Ok(imported)
+}
+
+/// store a collection's items, and check each of them decrypts to the original secret
+async fn import_items(
+    store: &SecretStore<'_>,
+    collection_id: &Arc<String>,
+    collection: &MdellwegCollection,
+    secrets: Vec<Vec<u8>>,
+) -> Result {
+    for (item, secret) in collection.items.iter().zip(secrets) {
+        let label = item.label.clone().unwrap_or_else(|| item.name.clone());
+        let secret_id = store
+            .create_secret(collection_id.clone(), Some(label), secret.clone(), Arc::new(item.attributes.clone()))
+            .await?;
+        if store.read_secret(collection_id, &secret_id, true).await? != secret {
+            return Err(Error::InvalidMetadata(format!(
+                "{}/{} didn't decrypt to the same secret once it was imported",
+                collection.name, item.name
+            )));
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_parse_properties() {
+    let (label, attributes) =
+        parse_properties(br#"{"label": "GitHub", "attributes": {"service": "github"}, "created": 1}"#, "Item").unwrap();
+    assert_eq!(label.as_deref(), Some("GitHub"));
+    assert_eq!(attributes, HashMap::from([("service".to_owned(), "github".to_owned())]));
+
+    let (label, attributes) = parse_properties(
+        br#"{"org.freedesktop.Secret.Item.Label": "Old", "org.freedesktop.Secret.Item.Attributes": {"a": "b"}}"#,
+        "Item",
+    )
+    .unwrap();
+    assert_eq!(label.as_deref(), Some("Old"));
+    assert_eq!(attributes.len(), 1);
+
+    assert_eq!(parse_properties(b"{}", "Collection").unwrap(), (None, HashMap::new()));
+    assert!(parse_properties(b"[]", "Item").is_err());
+    assert!(parse_properties(br#"{"attributes": {"a": 1}}"#, "Item").is_err());
+}
+
+#[tokio::test]
+async fn test_read_layout() {
+    use nanoid::nanoid;
+
+    let dir = std::env::temp_dir().join(format!(
+        "pass-secret-service-test-{}",
+        nanoid!(8, &crate::secret_store::NANOID_ALPHABET)
+    ));
+    let login = dir.join("login");
+    tokio::fs::create_dir_all(&login).await.unwrap();
+    tokio::fs::create_dir_all(dir.join("empty")).await.unwrap();
+    tokio::fs::write(dir.join(".aliases"), r#"{"default": "login"}"#).await.unwrap();
+    tokio::fs::write(login.join(".properties"), r#"{"label": "Login"}"#).await.unwrap();
+    tokio::fs::write(login.join("abc.gpg"), b"").await.unwrap();
+    tokio::fs::write(login.join("abc.properties"), r#"{"label": "Mail", "attributes": {"user": "me"}}"#)
+        .await
+        .unwrap();
+    tokio::fs::write(login.join("def.gpg"), b"").await.unwrap();
+
+    let collections = read_layout(&dir).await.unwrap();
+    tokio::fs::remove_dir_all(&dir).await.unwrap();
+
+    assert_eq!(
+        collections,
+        [
+            MdellwegCollection {
+                name: "empty".to_owned(),
+                ..Default::default()
+            },
+            MdellwegCollection {
+                name: "login".to_owned(),
+                label: Some("Login".to_owned()),
+                aliases: vec!["default".to_owned()],
+                items: vec![
+                    MdellwegItem {
+                        name: "abc".to_owned(),
+                        label: Some("Mail".to_owned()),
+                        attributes: HashMap::from([("user".to_owned(), "me".to_owned())]),
+                    },
+                    MdellwegItem {
+                        name: "def".to_owned(),
+                        ..Default::default()
+                    },
+                ],
+            },
+        ]
+    );
+}
@@ -0,0 +1,293 @@
+use std::{
+    collections::HashSet,
+    future::pending,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use log::{info, warn};
+use tokio::{
+    fs::try_exists,
+    sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+    time::{interval_at, Instant, Interval},
+};
+
+use crate::{
+    config::{matches_collection, MirrorConfig},
+    error::Result,
+    pass::PasswordStore,
+    secret_store::{SecretStore, PASS_SUBDIR},
+};
+
+#[derive(Debug)]
+enum MirrorJob {
+    Write {
+        collection_id: String,
+        secret_id: String,
+        value: Vec<u8>,
+    },
+    Delete {
+        collection_id: String,
+        secret_id: String,
+    },
+    DeleteCollection {
+        collection_id: String,
+    },
+}
+
+/// what a reconciliation pass changed
+#[derive(Debug, Default)]
+struct Reconciled {
+    written: u32,
+    deleted: u32,
+    failed: u32,
+}
+
+/// copies the secrets of some collections into a second password store, encrypted to
+/// that store's `.gpg-id`. writes are applied in the background, in order, and a
+/// periodic reconciliation pass fixes anything which was missed
+#[derive(Debug)]
+pub struct Mirror {
+    pass: PasswordStore,
+    /// ids or aliases of the mirrored collections
+    collections: Vec<String>,
+    reconcile_interval: Option<Duration>,
+    jobs: UnboundedSender<MirrorJob>,
+    /// taken when the mirror starts running
+    receiver: Mutex<Option<UnboundedReceiver<MirrorJob>>>,
+}
+
+fn secret_path(collection_id: &str, secret_id: &str) -> PathBuf {
+    Path::new(PASS_SUBDIR).join(collection_id).join(secret_id)
+}
+
+/// wait for the next tick, or forever if there's no interval
+async fn tick(interval: &mut Option<Interval>) {
+    match interval {
+        Some(interval) => {
+            interval.tick().await;
+        }
+        None => pending().await,
+    }
+}
+
+impl Mirror {
+    pub fn new(pass: &PasswordStore, config: &MirrorConfig) -> Self {
+        let (jobs, receiver) = unbounded_channel();
+        let hours = u64::from(config.reconcile_interval_hours);
+
+        Self {
+            pass: pass.in_directory(config.directory.clone()),
+            collections: config.collections.clone(),
+            reconcile_interval: (hours > 0).then(|| Duration::from_secs(hours * 60 * 60)),
+            jobs,
+            receiver: Mutex::new(Some(receiver)),
+        }
+    }
+
+    /// whether a collection (with the given aliases) is mirrored here
+    pub fn serves(&self, collection_id: &str, aliases: &[String]) -> bool {
+        matches_collection(&self.collections, collection_id, aliases)
+    }
+
+    pub fn write(&self, collection_id: &str, secret_id: &str, value: Vec<u8>) {
+        self.send(MirrorJob::Write {
+            collection_id: collection_id.to_owned(),
+            secret_id: secret_id.to_owned(),
+            value,
+        });
+    }
+
+    pub fn delete(&self, collection_id: &str, secret_id: &str) {
+        self.send(MirrorJob::Delete {
+            collection_id: collection_id.to_owned(),
+            secret_id: secret_id.to_owned(),
+        });
+    }
+
+    pub fn delete_collection(&self, collection_id: &str) {
+        self.send(MirrorJob::DeleteCollection {
+            collection_id: collection_id.to_owned(),
+        });
+    }
+
+    fn send(&self, job: MirrorJob) {
+        // only fails if the mirror isn't running, in which case reconciliation catches up
+        let _ = self.jobs.send(job);
+    }
+
+    /// whether the mirror's directory exists (e.g. the drive it's on is mounted)
+    /// we don't create it, so nothing is written to the mount point while it's missing
+    async fn is_available(&self) -> bool {
+        try_exists(&self.pass.directory).await.unwrap_or(false)
+    }
+
+    async fn apply(&self, job: MirrorJob) -> Result {
+        match job {
+            MirrorJob::Write {
+                collection_id,
+                secret_id,
+                value,
+            } => {
+                self.pass
+                    .write_password(secret_path(&collection_id, &secret_id), value)
+                    .await
+            }
+            MirrorJob::Delete {
+                collection_id,
+                secret_id,
+            } => {
+                self.pass
+                    .delete_password(secret_path(&collection_id, &secret_id))
+                    .await
+            }
+            MirrorJob::DeleteCollection { collection_id } => {
+                let dir = Path::new(PASS_SUBDIR).join(collection_id);
+                if self.pass.file_exists(&dir).await? {
+                    self.pass.remove_dir(dir).await?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// ids of the secrets in a mirrored collection
+    async fn list_mirrored(&self, collection_id: &str) -> Result<HashSet<String>> {
+        let dir = Path::new(PASS_SUBDIR).join(collection_id);
+        if !self.pass.file_exists(&dir).await? {
+            return Ok(HashSet::new());
+        }
+
+        Ok(self
+            .pass
+            .list_items(dir)
+            .await?
+            .into_iter()
+            .filter(|(file_type, _)| file_type.is_file())
+            .filter_map(|(_, name)| name.strip_suffix(".gpg").map(str::to_owned))
+            .collect())
+    }
+
+    /// make the mirror match the store: copy secrets which are missing or older,
+    /// and remove secrets and collections which no longer exist
+    async fn reconcile(&self, store: &SecretStore<'_>) -> Result<Reconciled> {
+        let mut result = Reconciled::default();
+        let collections = store.collections().await;
+
+        for collection_id in &collections {
+            let aliases = store
+                .list_aliases_for_collection(Arc::new(collection_id.clone()))
+                .await?;
+            if !self.serves(collection_id, &aliases) {
+                continue;
+            }
+
+            let mut mirrored = self.list_mirrored(collection_id).await?;
+            for secret_id in store.list_secrets(collection_id).await? {
+                let path = secret_path(collection_id, &secret_id);
+                let is_current = mirrored.remove(&secret_id) && {
+                    let (_, modified) = store.secret_times(collection_id, &secret_id).await?;
+                    let file = Path::new(PASS_SUBDIR)
+                        .join(collection_id)
+                        .join(format!("{secret_id}.gpg"));
+                    let mirror_modified = self
+                        .pass
+                        .stat_file(file)
+                        .await?
+                        .modified()
+                        .ok();
+                    // without a modification time, we can only check that it exists
+                    modified.is_none() || mirror_modified >= modified
+                };
+                if is_current {
+                    continue;
+                }
+
+                let copied = async {
+                    let value = store.read_secret(collection_id, &secret_id, false).await?;
+                    self.pass.write_password(&path, value).await
+                }
+                .await;
+                match copied {
+                    Ok(()) => result.written += 1,
+                    Err(e) => {
+                        warn!("Could not mirror secret {collection_id}/{secret_id}: {e}");
+                        result.failed += 1;
+                    }
+                }
+            }
+
+            // anything left was deleted from the store
+            for secret_id in mirrored {
+                self.pass
+                    .delete_password(secret_path(collection_id, &secret_id))
+                    .await?;
+                result.deleted += 1;
+            }
+        }
+
+        // remove collections which were deleted
+        if self.pass.file_exists(PASS_SUBDIR).await? {
+            for (file_type, name) in self.pass.list_items(PASS_SUBDIR).await? {
+                if file_type.is_dir() && !collections.contains(&name) {
+                    self.pass.remove_dir(Path::new(PASS_SUBDIR).join(name)).await?;
+                }
+            }
+        }
+
+        Ok(result)
+    }
+
+    async fn reconcile_logged(&self, store: &SecretStore<'_>) {
+        let directory = self.pass.directory.display();
+        if !self.is_available().await {
+            warn!("Mirror {directory} is not available, skipping reconciliation");
+            return;
+        }
+
+        match self.reconcile(store).await {
+            Ok(Reconciled {
+                written: 0,
+                deleted: 0,
+                failed: 0,
+            }) => {}
+            Ok(result) => info!(
+                "Reconciled mirror {directory}: {} written, {} deleted, {} failed",
+                result.written, result.deleted, result.failed
+            ),
+            Err(e) => warn!("Could not reconcile mirror {directory}: {e}"),
+        }
+    }
+
+    /// apply writes as they happen, and reconcile on startup and then periodically
+    pub async fn run(&self, store: &SecretStore<'_>) {
+        let Some(mut jobs) = self.receiver.lock().unwrap().take() else {
+            return;
+        };
+
+        let mut interval = self
+            .reconcile_interval
+            .map(|period| interval_at(Instant::now() + period, period));
+
+        self.reconcile_logged(store).await;
+
+        loop {
+            tokio::select! {
+                job = jobs.recv() => {
+                    let Some(job) = job else {
+                        return;
+                    };
+                    if !self.is_available().await {
+                        // the next reconciliation will catch up
+                        continue;
+                    }
+                    if let Err(e) = self.apply(job).await {
+                        warn!("Could not update mirror {}: {e}", self.pass.directory.display());
+                    }
+                }
+                _ = tick(&mut interval) => self.reconcile_logged(store).await,
+            }
+        }
+    }
+}
@@ -0,0 +1,158 @@
+use std::collections::HashSet;
+
+const TAG_PKESK: u8 = 1;
+const TAG_SKESK: u8 = 3;
+
+/// key id used by gpg's --throw-keyids
+const ANONYMOUS_KEY_ID: &str = "0000000000000000";
+
+/// parse an OpenPGP packet header
+/// returns (tag, body length, header length)
+fn parse_header(data: &[u8]) -> Option<(u8, usize, usize)> {
+    let first = *data.first()?;
+    if first & 0x80 == 0 {
+        // not a packet
+        return None;
+    }
+
+    if first & 0x40 != 0 {
+        // new format
+        let tag = first & 0x3f;
+        match *data.get(1)? as usize {
+            len @ 0..=191 => Some((tag, len, 2)),
+            len @ 192..=223 => Some((tag, ((len - 192) << 8) + *data.get(2)? as usize + 192, 3)),
+            255 => Some((
+                tag,
+                u32::from_be_bytes(data.get(2..6)?.try_into().ok()?) as usize,
+                6,
+            )),
+            // partial lengths are never used for session key packets
+            _ => None,
+        }
+    } else {
+        // old format
+        let tag = (first >> 2) & 0x0f;
+        match first & 0x03 {
+            0 => Some((tag, *data.get(1)? as usize, 2)),
+            1 => Some((
+                tag,
+                u16::from_be_bytes(data.get(1..3)?.try_into().ok()?) as usize,
+                3,
+            )),
+            2 => Some((
+                tag,
+                u32::from_be_bytes(data.get(1..5)?.try_into().ok()?) as usize,
+                5,
+            )),
+            // indeterminate length
+            _ => None,
+        }
+    }
+}
+
+/// get the key id out of a public-key encrypted session key packet
+fn pkesk_key_id(body: &[u8]) -> Option<String> {
+    let key_id = match *body.first()? {
+        3 => body.get(1..9)?,
+        6 => match *body.get(1)? as usize {
+            // anonymous recipient
+            0 => return Some(ANONYMOUS_KEY_ID.into()),
+            len => {
+                // key version followed by the fingerprint
+                let fingerprint = body.get(3..2 + len)?;
+                if *body.get(2)? == 4 {
+                    // v4 key ids are the end of the fingerprint
+                    fingerprint.get(fingerprint.len().checked_sub(8)?..)?
+                } else {
+                    fingerprint.get(..8)?
+                }
+            }
+        },
+        _ => return None,
+    };
+
+    Some(key_id.iter().map(|b| format!("{b:02X}")).collect())
+}
+
+/// get the ids of the keys an encrypted message was encrypted to, without decrypting it
+/// returns None if the message isn't a binary OpenPGP message
+pub fn recipient_key_ids(mut data: &[u8]) -> Option<Vec<String>> {
+    let mut key_ids = vec![];
+
+    while !data.is_empty() {
+        let (tag, len, header_len) = parse_header(data)?;
+        let body = data.get(header_len..header_len + len)?;
+
+        match tag {
+            TAG_PKESK => key_ids.push(pkesk_key_id(body)?),
+            TAG_SKESK => {}
+            // the session key packets always come first
+            _ => break,
+        }
+
+        data = &data[header_len + len..];
+    }
+
+    Some(key_ids)
+}
+
+/// check whether a message encrypted to `key_ids` needs to be re-encrypted for the given recipients
+/// each recipient is the set of key ids (primary and subkeys) belonging to it
+pub fn is_stale(key_ids: &[String], recipients: &[HashSet<String>]) -> bool {
+    // we can't tell who anonymous recipients are
+    if key_ids.iter().any(|id| id == ANONYMOUS_KEY_ID) {
+        return false;
+    }
+
+    // encrypted to a key which is no longer a recipient
+    key_ids
+        .iter()
+        .any(|id| !recipients.iter().any(|r| r.contains(id)))
+        // or a recipient can't decrypt it
+        || recipients
+            .iter()
+            .any(|r| !key_ids.iter().any(|id| r.contains(id)))
+}
+
+#[test]
+fn test_recipient_key_ids() {
+    // old format PKESK v3, followed by a new format SEIPD packet
+    let mut message = vec![0x84, 12, 3, 0x39, 0x56, 0x27, 0xE1, 0x20, 0xC7, 0xEA, 0x32, 1, 0, 0];
+    // new format PKESK v6 for a v4 key, with a 216 byte body
+    message.extend_from_slice(&[0xC1, 192, 24, 6, 21, 4]);
+    message.extend_from_slice(&[0xAA; 12]);
+    message.extend_from_slice(&[0x42, 0x3F, 0xAB, 0xD0, 0xCD, 0x82, 0x19, 0xBB]);
+    // algorithm and session key
+    message.extend_from_slice(&[0; 193]);
+    message.extend_from_slice(&[0xD2, 3, 1, 2, 3]);
+
+    assert_eq!(
+        recipient_key_ids(&message).unwrap(),
+        vec!["395627E120C7EA32", "423FABD0CD8219BB"]
+    );
+
+    // armored messages aren't supported
+    assert_eq!(recipient_key_ids(b"-----BEGIN PGP MESSAGE-----"), None);
+    // truncated
+    assert_eq!(recipient_key_ids(&message[..8]), None);
+}
+
+#[test]
+fn test_is_stale() {
+    let a = HashSet::from(["A1".to_string(), "A2".to_string()]);
+    let b = HashSet::from(["B1".to_string()]);
+
+    let only_a = std::slice::from_ref(&a);
+
+    // up to date
+    assert!(!is_stale(&["A2".into()], only_a));
+    assert!(!is_stale(&["A2".into(), "B1".into()], &[a.clone(), b.clone()]));
+    // encrypted to an old key
+    assert!(is_stale(&["B1".into()], only_a));
+    // a recipient was removed
+    assert!(is_stale(&["A2".into(), "B1".into()], only_a));
+    // a recipient was added
+    assert!(is_stale(&["A2".into()], &[a.clone(), b]));
+    // anonymous recipients are never stale
+    assert!(!is_stale(&[ANONYMOUS_KEY_ID.into()], only_a));
+}
@@ -1,26 +1,228 @@
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     env,
+    ffi::{CStr, CString, OsStr},
     fs::{FileType, Metadata},
     io::{self, ErrorKind},
-    path::{Path, PathBuf},
+    os::unix::{ffi::OsStrExt, fs::MetadataExt},
+    path::{Component, Path, PathBuf},
     process::Stdio,
+    sync::Arc,
 };
 use tokio::{
     fs::{
-        metadata, read, read_dir, read_to_string, remove_dir_all, remove_file, DirBuilder, File,
-        OpenOptions,
+        canonicalize, metadata, read, read_dir, read_to_string, remove_dir_all, remove_file, rename,
+        symlink, symlink_metadata, try_exists, DirBuilder, File, OpenOptions,
     },
     io::AsyncWriteExt,
     process::Command,
 };
 
-use crate::error::{Error, Result};
+use log::{info, warn};
+use nanoid::nanoid;
+
+use crate::{
+    agent,
+    deadline,
+    error::{Error, Result},
+    openpgp,
+    secret_store::{NANOID_ALPHABET, PASS_SUBDIR},
+    timing::{self, Phase},
+};
+
+/// read the recipients from the contents of a `.gpg-id`, like pass does:
+/// one per line, ignoring blank lines and everything after a `#`
+/// recipients which gpg could read as an option are rejected
+fn parse_gpg_id(contents: &str) -> Result<Vec<String>> {
+    check_recipients(
+        contents
+            .lines()
+            .map(|line| line.split('#').next().unwrap_or_default().trim())
+            .filter(|line| !line.is_empty()),
+    )
+}
+
+/// read the recipients from `$PASSWORD_STORE_KEY`, which pass splits on whitespace
+fn parse_store_key(value: &str) -> Result<Vec<String>> {
+    check_recipients(value.split_whitespace())
+}
+
+/// reject recipients which gpg could read as an option, and an empty list
+fn check_recipients<'a>(recipients: impl Iterator<Item = &'a str>) -> Result<Vec<String>> {
+    let recipients: Vec<_> = recipients
+        .map(|recipient| {
+            if recipient.starts_with('-') || recipient.chars().any(char::is_control) {
+                Err(Error::InvalidGpgId(format!("invalid recipient {recipient:?}")))
+            } else {
+                Ok(recipient.to_owned())
+            }
+        })
+        .collect::<Result<_>>()?;
+
+    if recipients.is_empty() {
+        return Err(Error::InvalidGpgId("no recipients".to_owned()));
+    }
+    Ok(recipients)
+}
+
+/// find the keygrips of the given keys in `gpg --list-secret-keys --with-colons --with-keygrip`
+fn parse_keygrips(output: &str, key_ids: &[String]) -> Vec<String> {
+    let mut keygrips = vec![];
+    let mut wanted = false;
+    for line in output.lines() {
+        let fields: Vec<_> = line.split(':').collect();
+        match fields[..] {
+            // the grp line follows the key it belongs to
+            ["sec" | "ssb", _, _, _, key_id, ..] => {
+                wanted = key_ids.iter().any(|id| id.eq_ignore_ascii_case(key_id));
+            }
+            ["grp", _, _, _, _, _, _, _, _, keygrip, ..] if wanted => {
+                keygrips.push(keygrip.to_owned());
+                wanted = false;
+            }
+            _ => {}
+        }
+    }
+    keygrips
+}
+
+/// what gpg knows about a key
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyInfo {
+    /// the first user id of the key, or of its primary key for subkeys
+    pub user_id: String,
+    /// "valid", "revoked", or "expired". subkeys of a revoked or expired primary key are too
+    pub status: &'static str,
+}
+
+/// find what gpg knows about every key (including subkeys) in `gpg --list-keys --with-colons`
+fn parse_key_info(output: &str) -> HashMap<String, KeyInfo> {
+    fn status(validity: &str) -> &'static str {
+        match validity {
+            "r" => "revoked",
+            "e" => "expired",
+            _ => "valid",
+        }
+    }
+
+    // (user id, status) of each primary key
+    let mut primaries: Vec<(String, &'static str)> = vec![];
+    // (key id, index of its primary key, status)
+    let mut keys = vec![];
+    for line in output.lines() {
+        let fields: Vec<_> = line.split(':').collect();
+        match fields[..] {
+            ["pub", validity, _, _, key_id, ..] => {
+                primaries.push((String::new(), status(validity)));
+                keys.push((key_id, primaries.len() - 1, status(validity)));
+            }
+            ["sub", validity, _, _, key_id, ..] if !primaries.is_empty() => {
+                keys.push((key_id, primaries.len() - 1, status(validity)));
+            }
+            // the uid lines follow the primary key they belong to
+            ["uid", _, _, _, _, _, _, _, _, user_id, ..] => {
+                if let Some((primary_user_id, _)) = primaries.last_mut() {
+                    if primary_user_id.is_empty() {
+                        *primary_user_id = user_id.to_owned();
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    keys.into_iter()
+        .map(|(key_id, primary, status)| {
+            let (user_id, primary_status) = &primaries[primary];
+            let status = if *primary_status == "valid" { status } else { primary_status };
+            (key_id.to_uppercase(), KeyInfo { user_id: user_id.clone(), status })
+        })
+        .collect()
+}
+
+/// the home directory in the passwd database, which $HOME may not match
+/// (e.g. when the daemon is started by systemd with a different environment)
+fn passwd_home() -> Option<PathBuf> {
+    let mut passwd: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut result = std::ptr::null_mut();
+    let mut buf = vec![0; 4096];
+    let status = unsafe {
+        libc::getpwuid_r(libc::getuid(), &mut passwd, buf.as_mut_ptr(), buf.len(), &mut result)
+    };
+    if status != 0 || result.is_null() || passwd.pw_dir.is_null() {
+        return None;
+    }
+    let home = unsafe { CStr::from_ptr(passwd.pw_dir) };
+    Some(PathBuf::from(OsStr::from_bytes(home.to_bytes())))
+}
+
+/// the kind of network filesystem a path (or its nearest existing parent) is on, if it's on one.
+/// locks aren't reliable on these, so redb's databases can be corrupted there
+pub fn network_filesystem(path: &Path) -> Option<&'static str> {
+    let path = path.ancestors().find(|path| path.exists())?;
+    let path = CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut stat: libc::statfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statfs(path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+    // the magic numbers from statfs(2)
+    #[allow(clippy::unnecessary_cast)]
+    match stat.f_type as u32 {
+        0x6969 => Some("NFS"),
+        0x517b | 0xff534d42 | 0xfe534d42 => Some("SMB"),
+        0x5346414f => Some("AFS"),
+        0x00c36400 => Some("Ceph"),
+        0x01021997 => Some("9P"),
+        0x73757245 => Some("Coda"),
+        // e.g. sshfs. local FUSE filesystems can't be told apart, but their locking is no better
+        0x65735546 => Some("a FUSE filesystem"),
+        _ => None,
+    }
+}
+
+/// whether a directory is a tmpfs (or ramfs) which only we can get into, so files written there
+/// briefly never reach a disk or another user
+pub fn private_tmpfs(path: &Path) -> bool {
+    let Ok(metadata) = std::fs::symlink_metadata(path) else {
+        return false;
+    };
+    if !metadata.is_dir() || metadata.uid() != unsafe { libc::getuid() } || metadata.mode() & 0o077 != 0 {
+        return false;
+    }
+    let Ok(path) = CString::new(path.as_os_str().as_bytes()) else {
+        return false;
+    };
+    let mut stat: libc::statfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statfs(path.as_ptr(), &mut stat) } != 0 {
+        return false;
+    }
+    // the magic numbers from statfs(2)
+    #[allow(clippy::unnecessary_cast)]
+    let filesystem = stat.f_type as u32;
+    matches!(filesystem, 0x01021994 | 0x858458f6)
+}
+
+/// whether a store directory has anything in it besides what we created
+async fn has_content(directory: &Path) -> bool {
+    let Ok(mut entries) = read_dir(directory).await else {
+        return false;
+    };
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        if entry.file_name() != PASS_SUBDIR {
+            return true;
+        }
+    }
+    false
+}
 
 #[derive(Debug)]
 pub struct PasswordStore {
     pub directory: PathBuf,
+    /// write and delete passwords with `pass insert` and `pass rm`, so its extensions and git integration run
+    pub use_cli: bool,
     gpg_opts: Option<String>,
+    /// recipients which are used instead of every `.gpg-id`, like pass does
+    store_key: Option<String>,
     file_mode: u32,
     dir_mode: u32,
 }
@@ -40,6 +242,7 @@ impl PasswordStore {
             });
 
         let gpg_opts = env.remove("PASSWORD_STORE_GPG_OPTS");
+        let store_key = env.remove("PASSWORD_STORE_KEY");
 
         let umask = env
             .get("PASSWORD_STORE_UMASK")
@@ -53,12 +256,95 @@ impl PasswordStore {
 
         Ok(Self {
             directory,
+            use_cli: false,
             gpg_opts,
+            store_key,
             dir_mode,
             file_mode,
         })
     }
 
+    /// create files and directories with these modes instead of the ones from
+    /// `PASSWORD_STORE_UMASK`, e.g. so a group can read the store. the owner has to keep
+    /// read and write access (and search access to directories), and files can't be executable
+    pub fn override_modes(&mut self, file_mode: Option<u32>, dir_mode: Option<u32>) -> Result {
+        if let Some(mode) = file_mode {
+            if mode & !0o666 != 0 || mode & 0o600 != 0o600 {
+                return Err(Error::Config(format!(
+                    "file mode {mode:03o} has to be readable and writable by the owner, and not executable"
+                )));
+            }
+            self.file_mode = mode;
+        }
+        if let Some(mode) = dir_mode {
+            if mode & !0o777 != 0 || mode & 0o700 != 0o700 {
+                return Err(Error::Config(format!(
+                    "directory mode {mode:03o} has to be readable, writable, and searchable by the owner"
+                )));
+            }
+            self.dir_mode = mode;
+        }
+        if file_mode.is_some() || dir_mode.is_some() {
+            info!(
+                "Creating files with mode {:03o} and directories with mode {:03o}",
+                self.file_mode, self.dir_mode
+            );
+        }
+        Ok(())
+    }
+
+    /// log where the store is. if it's empty but the default location for $HOME or the
+    /// passwd home directory isn't, the daemon was probably started with a different
+    /// environment than `pass` uses, so warn about it
+    pub async fn check_location(&self) {
+        info!("Using the password store at {}", self.directory.display());
+        if has_content(&self.directory).await {
+            return;
+        }
+
+        let homes = env::var_os("HOME").map(PathBuf::from).into_iter().chain(passwd_home());
+        let mut checked = vec![self.directory.clone()];
+        for home in homes {
+            let other = home.join(".password-store");
+            if checked.contains(&other) {
+                continue;
+            }
+            if has_content(&other).await {
+                warn!(
+                    "The password store at {} is empty, but {} isn't. Check $HOME and $PASSWORD_STORE_DIR in the daemon's environment",
+                    self.directory.display(),
+                    other.display()
+                );
+            }
+            checked.push(other);
+        }
+    }
+
+    /// a store in another directory with the same options as this one
+    pub fn in_directory(&self, directory: PathBuf) -> Self {
+        Self {
+            directory,
+            use_cli: self.use_cli,
+            gpg_opts: self.gpg_opts.clone(),
+            store_key: self.store_key.clone(),
+            dir_mode: self.dir_mode,
+            file_mode: self.file_mode,
+        }
+    }
+
+    /// a store in the given directory with the default options
+    #[cfg(test)]
+    pub fn with_directory(directory: PathBuf) -> Self {
+        Self {
+            directory,
+            use_cli: false,
+            gpg_opts: None,
+            store_key: None,
+            dir_mode: 0o700,
+            file_mode: 0o600,
+        }
+    }
+
     fn get_full_secret_path(&self, path: impl AsRef<Path>) -> PathBuf {
         let mut path = self.directory.join(path);
 
@@ -82,58 +368,227 @@ impl PasswordStore {
         command
             .stdin(Stdio::piped())
             .stdout(Stdio::piped())
-            .stderr(Stdio::piped());
+            .stderr(Stdio::piped())
+            // if the request runs out of time
+            .kill_on_drop(true);
 
         command
     }
 
     /// Read a single password at the given path
     pub async fn read_password(&self, path: impl AsRef<Path>, can_prompt: bool) -> Result<Vec<u8>> {
-        let contents = read(self.get_full_secret_path(path)).await?;
+        let contents = timing::phase(Phase::Files, read(self.get_full_secret_path(path))).await?;
+
+        self.decrypt(contents, can_prompt).await
+    }
+
+    /// decrypt many passwords with one gpg process, so far fewer are spawned and gpg-agent asks
+    /// for the passphrase at most once. gpg can only decrypt several files into other files, so
+    /// they're copied into a directory in `scratch`, which has to be a private tmpfs (like
+    /// `$XDG_RUNTIME_DIR`), and the decrypted files are removed as soon as they're read
+    /// returns each password, or None if it couldn't be read or decrypted
+    pub async fn read_passwords(
+        &self,
+        paths: &[PathBuf],
+        scratch: &Path,
+        can_prompt: bool,
+    ) -> Result<Vec<Option<Vec<u8>>>> {
+        if !private_tmpfs(scratch) {
+            return Err(io::Error::new(
+                ErrorKind::PermissionDenied,
+                format!("{} isn't a tmpfs only we can read", scratch.display()),
+            )
+            .into());
+        }
+        let dir = scratch.join(format!("pass-secret-service-{}", nanoid!(12, &NANOID_ALPHABET)));
+        DirBuilder::new().mode(0o700).create(&dir).await?;
+        let result = self.decrypt_files(paths, &dir, can_prompt).await;
+        if let Err(e) = remove_dir_all(&dir).await {
+            warn!("Could not remove {}: {e}", dir.display());
+        }
+        result
+    }
+
+    async fn decrypt_files(&self, paths: &[PathBuf], dir: &Path, can_prompt: bool) -> Result<Vec<Option<Vec<u8>>>> {
+        let mut files = vec![];
+        for (i, path) in paths.iter().enumerate() {
+            let file = dir.join(format!("{i}.gpg"));
+            // missing ones are left out, and gpg skips them
+            if timing::phase(Phase::Files, tokio::fs::copy(self.get_full_secret_path(path), &file)).await.is_ok() {
+                files.push(file);
+            }
+        }
 
         let mut command = self.make_gpg_process();
+        if !can_prompt {
+            command.arg("--pinentry-mode=error");
+        }
+        // gpg goes on with the next file if one fails
+        command.args(["--batch", "--yes", "--decrypt-files"]).args(&files);
+        timing::phase(Phase::Gpg, deadline::until_deadline(command.output())).await??;
+
+        let mut passwords = Vec::with_capacity(paths.len());
+        for i in 0..paths.len() {
+            passwords.push(read(dir.join(i.to_string())).await.ok());
+        }
+        Ok(passwords)
+    }
 
+    /// decrypt an encrypted password
+    pub async fn decrypt(&self, contents: Vec<u8>, can_prompt: bool) -> Result<Vec<u8>> {
+        let mut args = vec![];
         if !can_prompt {
             // don't activate pinentry if we can't prompt
-            command.arg("--pinentry-mode=error");
+            args.push("--pinentry-mode=error".to_owned());
         }
+        args.extend(["--decrypt".to_owned(), "-".to_owned()]);
 
-        command.arg("--decrypt").arg("-");
+        self.run_gpg(&args, contents).await
+    }
 
-        let mut process = command.spawn()?;
+    /// run gpg, writing `input` to it, and return what it outputs
+    /// if it couldn't reach gpg-agent (e.g. the agent was restarted), the agent is started
+    /// again and gpg is retried once
+    async fn run_gpg(&self, args: &[String], input: Vec<u8>) -> Result<Vec<u8>> {
+        let input = Arc::<[u8]>::from(input);
+        let mut relaunched = false;
+        loop {
+            let mut process = self.make_gpg_process().args(args).spawn()?;
 
-        let mut stdin = process.stdin.take().expect("child has stdin");
+            let mut stdin = process.stdin.take().expect("child has stdin");
+            let contents = input.clone();
+            tokio::task::spawn(async move { stdin.write_all(&contents).await });
 
-        tokio::task::spawn(async move { stdin.write_all(&contents).await });
+            let output =
+                timing::phase(Phase::Gpg, deadline::until_deadline(process.wait_with_output()))
+                    .await??;
+            if output.status.success() {
+                return Ok(output.stdout);
+            }
 
-        let output = process.wait_with_output().await?;
-        if output.status.success() {
-            // gpg decrypted successfully
-            Ok(output.stdout)
-        } else {
-            Err(Error::GpgError(
-                String::from_utf8_lossy(&output.stderr).into_owned(),
-            ))
+            let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+            if !agent::is_unreachable(&stderr) {
+                return Err(Error::GpgError(stderr));
+            }
+            if relaunched {
+                return Err(Error::AgentUnavailable(stderr.trim().to_owned()));
+            }
+            warn!("gpg could not reach gpg-agent, starting it again");
+            timing::phase(Phase::Gpg, agent::launch()).await?;
+            relaunched = true;
         }
     }
 
+    /// read the `.gpg-id` which applies to `dir`: the closest one in it or its parents, up to
+    /// the store's root. only the root's is used for dirs outside the store (or with `..` in
+    /// them), so one elsewhere (like `~/.gpg-id`) is never picked up
     async fn get_gpg_id(&self, dir: impl AsRef<Path>) -> Result<String> {
-        for component in dir.as_ref().ancestors() {
-            let gpg_id_path = component.join(".gpg-id");
-            match read_to_string(gpg_id_path).await {
+        let relative = match dir.as_ref().strip_prefix(&self.directory) {
+            Ok(relative) if relative.components().all(|c| matches!(c, Component::Normal(_))) => relative,
+            _ => Path::new(""),
+        };
+
+        let mut searched = vec![];
+        for ancestor in relative.ancestors() {
+            let gpg_id_path = self.directory.join(ancestor).join(".gpg-id");
+            match read_to_string(&gpg_id_path).await {
                 Ok(value) => return Ok(value),
                 // not found, continue
                 Err(ref e) if e.kind() == io::ErrorKind::NotFound => {}
                 Err(e) => Err(e)?,
             }
+            searched.push(gpg_id_path.display().to_string());
+        }
+        // we couldn't find a gpg key
+        Err(Error::NotInitialized(format!("no .gpg-id in {}", searched.join(", "))))
+    }
+
+    /// get the recipients from the `.gpg-id` which applies to the given dir
+    /// (or `$PASSWORD_STORE_KEY`, if it's set)
+    pub async fn get_recipients(&self, dir: impl AsRef<Path>) -> Result<Vec<String>> {
+        match &self.store_key {
+            Some(key) => parse_store_key(key),
+            None => parse_gpg_id(&self.get_gpg_id(self.directory.join(dir)).await?),
+        }
+    }
+
+    /// look up the ids of every key (including subkeys) belonging to each recipient
+    pub async fn recipient_key_ids(&self, recipients: &[String]) -> Result<Vec<HashSet<String>>> {
+        let mut command = self.make_gpg_process();
+        command
+            .arg("--list-keys")
+            .arg("--with-colons")
+            // recipients are never options
+            .arg("--")
+            .args(recipients);
+        let output =
+            timing::phase(Phase::Gpg, deadline::until_deadline(command.output())).await??;
+
+        if !output.status.success() {
+            return Err(Error::GpgError(
+                String::from_utf8_lossy(&output.stderr).into_owned(),
+            ));
+        }
 
-            // at the root pass dir
-            if component == self.directory {
-                break;
+        let mut keys = Vec::<HashSet<String>>::new();
+        for line in String::from_utf8_lossy(&output.stdout).lines() {
+            let mut fields = line.split(':');
+            match (fields.next(), fields.nth(3)) {
+                // a new primary key
+                (Some("pub"), Some(key_id)) => keys.push(HashSet::from([key_id.to_owned()])),
+                (Some("sub"), Some(key_id)) => {
+                    if let Some(key) = keys.last_mut() {
+                        key.insert(key_id.to_owned());
+                    }
+                }
+                _ => {}
             }
         }
-        // we couldn't find a gpg key
-        return Err(Error::NotInitialized);
+
+        Ok(keys)
+    }
+
+    /// look up every key in the public keyring, by key id
+    pub async fn key_info(&self) -> Result<HashMap<String, KeyInfo>> {
+        let mut command = self.make_gpg_process();
+        command.arg("--list-keys").arg("--with-colons");
+        let output =
+            timing::phase(Phase::Gpg, deadline::until_deadline(command.output())).await??;
+
+        if !output.status.success() {
+            return Err(Error::GpgError(
+                String::from_utf8_lossy(&output.stderr).into_owned(),
+            ));
+        }
+
+        Ok(parse_key_info(&String::from_utf8_lossy(&output.stdout)))
+    }
+
+    /// look up the keygrips gpg-agent uses for the given secret keys
+    /// keys without a secret key are skipped
+    pub async fn keygrips(&self, key_ids: &[String]) -> Result<Vec<String>> {
+        let mut command = self.make_gpg_process();
+        command
+            .arg("--list-secret-keys")
+            .arg("--with-colons")
+            .arg("--with-keygrip");
+        let output =
+            timing::phase(Phase::Gpg, deadline::until_deadline(command.output())).await??;
+
+        if !output.status.success() {
+            return Err(Error::GpgError(
+                String::from_utf8_lossy(&output.stderr).into_owned(),
+            ));
+        }
+
+        Ok(parse_keygrips(&String::from_utf8_lossy(&output.stdout), key_ids))
+    }
+
+    /// get the ids of the keys a password was encrypted to, without decrypting it
+    pub async fn read_password_key_ids(&self, path: impl AsRef<Path>) -> Result<Option<Vec<String>>> {
+        let contents = timing::phase(Phase::Files, read(self.get_full_secret_path(path))).await?;
+
+        Ok(openpgp::recipient_key_ids(&contents))
     }
 
     async fn ensure_dirs(&self, dir: impl AsRef<Path>) -> Result {
@@ -145,76 +600,213 @@ impl PasswordStore {
             .await?)
     }
 
+    /// run the pass CLI on this store, with `input` on stdin
+    async fn run_pass(&self, args: &[&str], names: &[&Path], input: Option<Vec<u8>>) -> Result {
+        // it always changes the store
+        deadline::disarm();
+        let mut process = Command::new("pass")
+            .env("PASSWORD_STORE_DIR", &self.directory)
+            // pass only takes a umask, so --file-mode only applies through the directory mode
+            .env("PASSWORD_STORE_UMASK", format!("{:03o}", !self.dir_mode & 0o777))
+            .args(args)
+            // names are never options
+            .arg("--")
+            .args(names)
+            .stdin(if input.is_some() { Stdio::piped() } else { Stdio::null() })
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        if let Some(input) = input {
+            let mut stdin = process.stdin.take().expect("child has stdin");
+            tokio::task::spawn(async move { stdin.write_all(&input).await });
+        }
+
+        // pass runs gpg
+        let output = timing::phase(Phase::Gpg, process.wait_with_output()).await?;
+        if output.status.success() {
+            Ok(())
+        } else {
+            Err(Error::Pass(
+                String::from_utf8_lossy(&output.stderr).trim().to_owned(),
+            ))
+        }
+    }
+
+    /// run git in this store, like `pass git` does. returns what it printed
+    pub async fn run_git(&self, args: &[&str]) -> Result<String> {
+        // a pull changes the store, and stopping git partway would leave its lock files
+        deadline::disarm();
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(&self.directory)
+            .args(args)
+            // fail instead of waiting for credentials nobody can type
+            .env("GIT_TERMINAL_PROMPT", "0")
+            .stdin(Stdio::null())
+            .output()
+            .await?;
+
+        let mut printed = String::from_utf8_lossy(&output.stdout).into_owned();
+        printed.push_str(&String::from_utf8_lossy(&output.stderr));
+        if output.status.success() {
+            Ok(printed)
+        } else {
+            Err(Error::Git(printed.trim().to_owned()))
+        }
+    }
+
     /// write a single password
     pub async fn write_password(&self, path: impl AsRef<Path>, value: Vec<u8>) -> Result {
+        if self.use_cli {
+            // multiline passes stdin straight to gpg, so any value can be stored
+            return self
+                .run_pass(&["insert", "--multiline", "--force"], &[path.as_ref()], Some(value))
+                .await;
+        }
+
+        let encrypted = self.encrypt_password(&path, value).await?;
+        self.write_encrypted(path, &encrypted).await
+    }
+
+    /// encrypt a password to the recipients of the directory it will be written to
+    pub async fn encrypt_password(&self, path: impl AsRef<Path>, value: Vec<u8>) -> Result<Vec<u8>> {
         let full_path = self.get_full_secret_path(path);
 
         let dir = full_path.parent().expect("path is a file");
 
-        self.ensure_dirs(dir).await?;
-
-        let gpg_id = self.get_gpg_id(dir).await?;
+        let recipients = self.get_recipients(dir).await?;
+        self.encrypt_to(&recipients, value).await
+    }
 
-        let mut process = self
-            .make_gpg_process()
-            .arg("--recipient")
-            .arg(gpg_id)
-            .arg("--encrypt")
-            .arg("-")
-            .spawn()?;
+    /// encrypt a value to the given recipients
+    pub async fn encrypt_to(&self, recipients: &[String], value: Vec<u8>) -> Result<Vec<u8>> {
+        let recipients = check_recipients(recipients.iter().map(String::as_str))?;
+        // one argument each, so a recipient can't be read as an option
+        let mut args: Vec<_> = recipients
+            .iter()
+            .map(|recipient| format!("--recipient={recipient}"))
+            .collect();
+        // like pass: only the listed recipients, even if gpg.conf has `encrypt-to` keys
+        args.extend([
+            "--no-encrypt-to".to_owned(),
+            "--compress-algo=none".to_owned(),
+            "--encrypt".to_owned(),
+            "-".to_owned(),
+        ]);
 
-        let mut stdin = process.stdin.take().expect("child has stdin");
+        self.run_gpg(&args, value).await
+    }
 
-        tokio::task::spawn(async move { stdin.write_all(&value).await });
+    /// write an already encrypted password
+    pub async fn write_encrypted(&self, path: impl AsRef<Path>, encrypted: &[u8]) -> Result {
+        deadline::disarm();
+        let full_path = self.get_full_secret_path(path);
 
-        let output = process.wait_with_output().await?;
-        if output.status.success() {
-            // encryption successful
+        timing::phase(Phase::Files, async {
+            self.ensure_dirs(full_path.parent().expect("path is a file"))
+                .await?;
 
             let mut file = OpenOptions::new()
                 .write(true)
                 .create(true)
+                .truncate(true)
                 .mode(self.file_mode)
-                .open(full_path)
+                .open(&full_path)
                 .await?;
 
-            file.write_all(&output.stdout).await?;
+            file.write_all(encrypted).await?;
 
             Ok(())
-        } else {
-            Err(Error::GpgError(
-                String::from_utf8_lossy(&output.stderr).into_owned(),
-            ))
-        }
+        })
+        .await
     }
 
     pub async fn delete_password(&self, path: impl AsRef<Path>) -> Result {
-        let full_path = self.get_full_secret_path(path);
-        match remove_file(full_path).await {
+        deadline::disarm();
+        let full_path = self.get_full_secret_path(&path);
+        if self.use_cli {
+            // pass fails if it doesn't exist
+            if !try_exists(full_path).await? {
+                return Ok(());
+            }
+            return self.run_pass(&["rm", "--force"], &[path.as_ref()], None).await;
+        }
+        match timing::phase(Phase::Files, remove_file(full_path)).await {
             Ok(_) => Ok(()),
             Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
             Err(e) => Err(e.into()),
         }
     }
 
+    /// move a password, replacing any password at `to`
+    pub async fn move_password(&self, from: impl AsRef<Path>, to: impl AsRef<Path>) -> Result {
+        deadline::disarm();
+        if self.use_cli {
+            return self
+                .run_pass(&["mv", "--force"], &[from.as_ref(), to.as_ref()], None)
+                .await;
+        }
+
+        let (from, to) = (self.get_full_secret_path(from), self.get_full_secret_path(to));
+        timing::phase(Phase::Files, async {
+            self.ensure_dirs(to.parent().expect("path is a file")).await?;
+            Ok(rename(from, to).await?)
+        })
+        .await
+    }
+
+    /// move a dir of passwords, e.g. a collection out of the secret service's dir. with the
+    /// pass CLI, they're re-encrypted if the new dir has other recipients
+    pub async fn move_dir(&self, from: impl AsRef<Path>, to: impl AsRef<Path>) -> Result {
+        deadline::disarm();
+        if self.use_cli {
+            return self.run_pass(&["mv"], &[from.as_ref(), to.as_ref()], None).await;
+        }
+
+        let (from, to) = (self.directory.join(from), self.directory.join(to));
+        timing::phase(Phase::Files, async {
+            self.ensure_dirs(to.parent().expect("path is not the store")).await?;
+            Ok(rename(from, to).await?)
+        })
+        .await
+    }
+
     /****** Some useful FS utilities ******/
 
     /// list the file and directories inside a parent directory
     pub async fn list_items(&self, dir: impl AsRef<Path>) -> Result<Vec<(FileType, String)>> {
         let dir = self.directory.join(dir);
-        self.ensure_dirs(&dir).await?;
+        timing::phase(Phase::Files, async {
+            self.ensure_dirs(&dir).await?;
 
-        let mut dir_items = read_dir(dir).await?;
+            let mut dir_items = read_dir(dir).await?;
 
-        let mut items = vec![];
+            let mut items = vec![];
 
-        while let Some(item) = dir_items.next_entry().await? {
-            let file_type = item.file_type().await?;
-            let name = item.file_name().to_string_lossy().into_owned();
-            items.push((file_type, name));
-        }
+            while let Some(item) = dir_items.next_entry().await? {
+                let file_type = item.file_type().await?;
+                let name = item.file_name().to_string_lossy().into_owned();
+                items.push((file_type, name));
+            }
+
+            Ok(items)
+        })
+        .await
+    }
 
-        Ok(items)
+    /// names of the dirs in a dir, including symlinks to dirs (e.g. adopted collections)
+    pub async fn list_dirs(&self, dir: impl AsRef<Path>) -> Result<Vec<String>> {
+        let full = self.directory.join(&dir);
+        let mut dirs = vec![];
+        for (file_type, name) in self.list_items(dir).await? {
+            if file_type.is_dir()
+                || (file_type.is_symlink() && metadata(full.join(&name)).await.is_ok_and(|m| m.is_dir()))
+            {
+                dirs.push(name);
+            }
+        }
+        Ok(dirs)
     }
 
     /// open a file for writing
@@ -226,6 +818,7 @@ impl PasswordStore {
         Ok(OpenOptions::new()
             .write(true)
             .create(true)
+            .truncate(false)
             .read(true)
             .mode(self.file_mode)
             .open(path)
@@ -241,13 +834,330 @@ impl PasswordStore {
         Ok(metadata(path).await?)
     }
 
+    /// check whether a file exists
+    pub async fn file_exists(&self, file_path: impl AsRef<Path>) -> Result<bool> {
+        Ok(try_exists(self.directory.join(file_path)).await?)
+    }
+
+    /// whether a path is a symlink, e.g. an adopted collection
+    pub async fn is_symlink(&self, path: impl AsRef<Path>) -> Result<bool> {
+        Ok(symlink_metadata(self.directory.join(path)).await?.is_symlink())
+    }
+
+    /// rename a file, returning an error if the destination already exists
+    pub async fn rename_file(&self, from: impl AsRef<Path>, to: impl AsRef<Path>) -> Result {
+        let to = self.directory.join(to);
+        if try_exists(&to).await? {
+            return Err(io::Error::from(ErrorKind::AlreadyExists).into());
+        }
+        Ok(rename(self.directory.join(from), to).await?)
+    }
+
+    /// remove a file
+    pub async fn delete_file(&self, file_path: impl AsRef<Path>) -> Result {
+        Ok(remove_file(self.directory.join(file_path)).await?)
+    }
+
     /// make a dir and all its parents
     pub async fn make_dir(&self, dir: impl AsRef<Path>) -> Result {
         self.ensure_dirs(self.directory.join(dir)).await
     }
 
-    /// recursively remove a dir
+    /// write a `.gpg-id` in a dir, so its entries are encrypted to these recipients instead of
+    /// the ones it inherits
+    pub async fn write_gpg_id(&self, dir: impl AsRef<Path>, recipients: &[String]) -> Result {
+        let contents = format!("{}\n", recipients.join("\n"));
+        // the same checks as when it's read
+        parse_gpg_id(&contents)?;
+
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(self.file_mode)
+            .open(self.directory.join(dir).join(".gpg-id"))
+            .await?;
+        file.write_all(contents.as_bytes()).await?;
+        Ok(())
+    }
+
+    /// the full path of a dir in the store. refuses anything which could be outside of it:
+    /// absolute paths, `..`, the store itself, and dirs whose parent is a symlink out of the store
+    async fn dir_in_store(&self, dir: &Path) -> Result<PathBuf> {
+        let outside = || {
+            io::Error::new(
+                ErrorKind::PermissionDenied,
+                format!("{} is outside the password store", dir.display()),
+            )
+        };
+        if dir.as_os_str().is_empty() || !dir.components().all(|c| matches!(c, Component::Normal(_))) {
+            return Err(outside().into());
+        }
+
+        let full = self.directory.join(dir);
+        // the dir itself can be a symlink (it isn't followed), but where it is has to be in the store
+        let root = canonicalize(&self.directory).await?;
+        let parent = canonicalize(full.parent().unwrap_or(&self.directory)).await?;
+        if !parent.starts_with(&root) {
+            return Err(outside().into());
+        }
+        Ok(full)
+    }
+
+    /// check that `target` (relative to the store) is a dir in it, following any symlinks
+    pub async fn check_dir_in_store(&self, target: &Path) -> Result {
+        let outside = || {
+            io::Error::new(
+                ErrorKind::PermissionDenied,
+                format!("{} is outside the password store", target.display()),
+            )
+        };
+        if target.as_os_str().is_empty() || !target.components().all(|c| matches!(c, Component::Normal(_))) {
+            return Err(outside().into());
+        }
+        let root = canonicalize(&self.directory).await?;
+        let full = canonicalize(self.directory.join(target)).await?;
+        if !full.starts_with(&root) || full == root {
+            return Err(outside().into());
+        }
+        if !metadata(&full).await?.is_dir() {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                format!("{} is not a directory", target.display()),
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    /// make `link` a symlink to `target`, a dir in the store (both relative to it)
+    pub async fn link_dir(&self, link: &Path, target: &Path) -> Result {
+        self.check_dir_in_store(target).await?;
+        let link = self.directory.join(link);
+        self.ensure_dirs(link.parent().expect("link is not the store")).await?;
+        // relative, so it still works if the store is moved
+        let depth = link.strip_prefix(&self.directory).map_or(0, |link| link.components().count());
+        let relative = Path::new("..").join(target);
+        let relative = (2..depth).fold(relative, |path, _| Path::new("..").join(path));
+        Ok(symlink(relative, link).await?)
+    }
+
+    /// recursively remove a dir. a symlink to a dir is removed without touching what it points to
     pub async fn remove_dir(&self, dir: impl AsRef<Path>) -> Result {
-        Ok(remove_dir_all(self.directory.join(dir)).await?)
+        deadline::disarm();
+        let full = self.dir_in_store(dir.as_ref()).await?;
+        if self.use_cli {
+            return self.run_pass(&["rm", "--recursive", "--force"], &[dir.as_ref()], None).await;
+        }
+
+        if symlink_metadata(&full).await?.is_symlink() {
+            warn!("Removing the symlink {} without removing what it points to", full.display());
+            return Ok(remove_file(full).await?);
+        }
+        // this doesn't follow symlinks inside the dir either
+        Ok(remove_dir_all(full).await?)
     }
 }
+
+#[test]
+fn test_parse_gpg_id() {
+    assert_eq!(
+        parse_gpg_id("ABCDEF0123456789\n\n# backup key\nJohn Doe <john@example.com> # work\n").unwrap(),
+        ["ABCDEF0123456789", "John Doe <john@example.com>"]
+    );
+
+    // options
+    assert!(parse_gpg_id("--output=/tmp/stolen").is_err());
+    assert!(parse_gpg_id("ABCDEF0123456789\n-o/tmp/stolen").is_err());
+    assert!(parse_gpg_id("  --homedir /tmp/evil").is_err());
+    // control characters, which could end a line in gpg's output or hide text
+    assert!(parse_gpg_id("john@example.com\x1b[2K").is_err());
+    assert!(parse_gpg_id("john@example.com\0--yes").is_err());
+    // no recipients
+    assert!(parse_gpg_id("# nothing\n\n").is_err());
+
+    assert_eq!(
+        parse_store_key(" ABCDEF0123456789  john@example.com\n").unwrap(),
+        ["ABCDEF0123456789", "john@example.com"]
+    );
+    assert!(parse_store_key("ABCDEF0123456789 --output=/tmp/stolen").is_err());
+    assert!(parse_store_key("").is_err());
+}
+
+#[test]
+fn test_override_modes() {
+    let mut pass = PasswordStore::with_directory(PathBuf::from("/nonexistent"));
+    pass.override_modes(Some(0o640), Some(0o750)).unwrap();
+    assert_eq!((pass.file_mode, pass.dir_mode), (0o640, 0o750));
+    pass.override_modes(None, None).unwrap();
+    assert_eq!((pass.file_mode, pass.dir_mode), (0o640, 0o750));
+
+    assert!(pass.override_modes(Some(0o400), None).is_err());
+    assert!(pass.override_modes(Some(0o700), None).is_err());
+    assert!(pass.override_modes(None, Some(0o600)).is_err());
+    assert!(pass.override_modes(None, Some(0o1777)).is_err());
+}
+
+#[test]
+fn test_parse_keygrips() {
+    let output = "\
+sec:u:255:22:1111111111111111:1700000000:::u:::scESC:::+:::ed25519:::0:
+fpr:::::::::AAAA1111111111111111:
+grp:::::::::0123456789ABCDEF0123456789ABCDEF01234567:
+uid:u::::1700000000::HASH::Test <test@example.com>::::::::::0:
+ssb:u:255:18:2222222222222222:1700000000::::::e:::+:::cv25519::
+fpr:::::::::BBBB2222222222222222:
+grp:::::::::89ABCDEF0123456789ABCDEF0123456789ABCDEF:
+";
+    assert_eq!(
+        parse_keygrips(output, &["2222222222222222".to_owned()]),
+        ["89ABCDEF0123456789ABCDEF0123456789ABCDEF"]
+    );
+    // key ids from the message can be lowercase
+    assert_eq!(parse_keygrips(output, &["1111111111111111".to_lowercase()]).len(), 1);
+    assert!(parse_keygrips(output, &["3333333333333333".to_owned()]).is_empty());
+}
+
+#[test]
+fn test_parse_key_info() {
+    let output = "\
+pub:r:255:22:1111111111111111:1700000000:::u:::sc:::::ed25519:::0:
+fpr:::::::::AAAA1111111111111111:
+uid:r::::1700000000::HASH::Old <old@example.com>::::::::::0:
+sub:r:255:18:2222222222222222:1700000000::::::e:::::cv25519::
+fpr:::::::::BBBB2222222222222222:
+pub:u:255:22:3333333333333333:1700000000:::u:::scESC:::::ed25519:::0:
+fpr:::::::::CCCC3333333333333333:
+uid:u::::1700000000::HASH::New <new@example.com>::::::::::0:
+uid:u::::1700000000::HASH::New (work) <new@work.example>::::::::::0:
+sub:e:255:18:4444444444444444:1700000000:1710000000:::::e:::::cv25519::
+fpr:::::::::DDDD4444444444444444:
+";
+    let keys = parse_key_info(output);
+    assert_eq!(keys.len(), 4);
+    // subkeys get the user id of their primary key, and its status if it's revoked
+    assert_eq!(
+        keys["2222222222222222"],
+        KeyInfo { user_id: "Old <old@example.com>".into(), status: "revoked" }
+    );
+    assert_eq!(
+        keys["3333333333333333"],
+        KeyInfo { user_id: "New <new@example.com>".into(), status: "valid" }
+    );
+    assert_eq!(keys["4444444444444444"].status, "expired");
+}
+
+#[tokio::test]
+async fn test_remove_dir_stays_in_store() {
+    use std::{fs, os::unix::fs::symlink};
+
+    let dir = env::temp_dir().join(format!("pass-secret-service-remove-{}", std::process::id()));
+    let home = dir.join("home");
+    let store_dir = dir.join("store");
+    fs::create_dir_all(home.join("documents")).unwrap();
+    fs::create_dir_all(store_dir.join("secret-service/collection")).unwrap();
+    let pass = PasswordStore::with_directory(store_dir.clone());
+
+    // a symlinked collection is unlinked without following it
+    symlink(&home, store_dir.join("secret-service/linked")).unwrap();
+    pass.remove_dir("secret-service/linked").await.unwrap();
+    assert!(!store_dir.join("secret-service/linked").exists());
+    assert!(home.join("documents").is_dir());
+
+    // dirs reached through a symlink out of the store are refused
+    symlink(&home, store_dir.join("secret-service/escape")).unwrap();
+    assert!(pass.remove_dir("secret-service/escape/documents").await.is_err());
+    assert!(home.join("documents").is_dir());
+
+    // as are paths which aren't in the store
+    assert!(pass.remove_dir("../home/documents").await.is_err());
+    assert!(pass.remove_dir(&home).await.is_err());
+    assert!(pass.remove_dir("").await.is_err());
+    assert!(home.join("documents").is_dir());
+
+    pass.remove_dir("secret-service/collection").await.unwrap();
+    assert!(!store_dir.join("secret-service/collection").exists());
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_get_gpg_id_stays_in_store() {
+    let dir = env::temp_dir().join(format!("pass-secret-service-find-gpg-id-{}", std::process::id()));
+    let store_dir = dir.join("store");
+    std::fs::create_dir_all(store_dir.join("work/email")).unwrap();
+    std::fs::create_dir_all(dir.join("elsewhere")).unwrap();
+    // e.g. ~/.gpg-id, above the store
+    std::fs::write(dir.join(".gpg-id"), "outside@example.com\n").unwrap();
+    let pass = PasswordStore::with_directory(store_dir.clone());
+
+    let Err(Error::NotInitialized(e)) = pass.get_gpg_id(store_dir.join("work/email")).await else {
+        panic!("the .gpg-id above the store was used");
+    };
+    for searched in ["work/email/.gpg-id", "work/.gpg-id", ".gpg-id"] {
+        assert!(e.contains(&store_dir.join(searched).display().to_string()), "{e}");
+    }
+    assert!(!e.contains(&dir.join(".gpg-id").display().to_string()), "{e}");
+    assert!(pass.get_gpg_id(dir.join("elsewhere")).await.is_err());
+    assert!(pass.get_gpg_id(store_dir.join("../elsewhere")).await.is_err());
+
+    std::fs::write(store_dir.join(".gpg-id"), "root@example.com\n").unwrap();
+    std::fs::write(store_dir.join("work/.gpg-id"), "work@example.com\n").unwrap();
+    assert_eq!(pass.get_gpg_id(store_dir.join("work/email")).await.unwrap(), "work@example.com\n");
+    assert_eq!(pass.get_gpg_id(&store_dir).await.unwrap(), "root@example.com\n");
+    // paths outside the store fall back to its root
+    assert_eq!(pass.get_gpg_id(dir.join("elsewhere")).await.unwrap(), "root@example.com\n");
+    assert_eq!(pass.get_gpg_id(store_dir.join("../elsewhere")).await.unwrap(), "root@example.com\n");
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_read_passwords_cleans_up() {
+    use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+
+    let dir = env::temp_dir().join(format!("pass-secret-service-batch-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("store/work")).unwrap();
+    // an unencrypted OpenPGP literal data packet, which gpg "decrypts" without any keys
+    std::fs::write(dir.join("store/work/a.gpg"), b"\xcb\x0bb\0\0\0\0\0value").unwrap();
+    let pass = PasswordStore::with_directory(dir.join("store"));
+    let paths = [PathBuf::from("work/a"), PathBuf::from("work/missing")];
+
+    // nothing is decrypted where other users can look
+    let shared = dir.join("shared");
+    std::fs::create_dir(&shared).unwrap();
+    std::fs::set_permissions(&shared, std::fs::Permissions::from_mode(0o755)).unwrap();
+    assert!(pass.read_passwords(&paths, &shared, false).await.is_err());
+    assert_eq!(std::fs::read_dir(&shared).unwrap().count(), 0);
+
+    let scratch = Path::new("/dev/shm").join(format!("pass-secret-service-batch-{}", std::process::id()));
+    // /dev/shm is a tmpfs on most systems
+    if std::fs::DirBuilder::new().mode(0o700).create(&scratch).is_ok() && private_tmpfs(&scratch) {
+        // gpg may not be installed, but nothing decrypted may be left behind either way
+        if let Ok(passwords) = pass.read_passwords(&paths, &scratch, false).await {
+            assert_eq!(passwords, [Some(b"value".to_vec()), None]);
+        }
+        assert_eq!(std::fs::read_dir(&scratch).unwrap().count(), 0);
+    }
+
+    let _ = std::fs::remove_dir(scratch);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_write_gpg_id() {
+    let dir = env::temp_dir().join(format!("pass-secret-service-gpg-id-{}", std::process::id()));
+    let pass = PasswordStore::with_directory(dir.clone());
+    pass.make_dir("work").await.unwrap();
+    std::fs::write(dir.join(".gpg-id"), "root@example.com\n").unwrap();
+
+    let recipients = vec!["ABCDEF0123456789".to_owned(), "work@example.com".to_owned()];
+    pass.write_gpg_id("work", &recipients).await.unwrap();
+    assert_eq!(pass.get_recipients("work").await.unwrap(), recipients);
+    assert_eq!(pass.get_recipients("").await.unwrap(), ["root@example.com"]);
+
+    assert!(pass.write_gpg_id("work", &["--homedir".to_owned()]).await.is_err());
+    assert!(pass.write_gpg_id("work", &[]).await.is_err());
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
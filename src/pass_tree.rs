@@ -0,0 +1,90 @@
+use std::{collections::HashMap, path::Path};
+
+use crate::{error::Result, pass::PasswordStore, secret_store::PASS_SUBDIR};
+
+/// alias of the collection the rest of the password store is exposed in
+pub const ALIAS: &str = "pass_tree";
+
+/// attributes every entry has, derived from its path
+pub const PATH_ATTRIBUTE: &str = "pass:path";
+pub const NAME_ATTRIBUTE: &str = "pass:name";
+pub const DIRECTORY_ATTRIBUTE: &str = "pass:directory";
+
+/// the item id of an entry. ids are used in object paths, so everything but letters and
+/// digits is escaped as `_` and two hex digits
+pub fn entry_id(path: &str) -> String {
+    let mut id = String::with_capacity(path.len());
+    for byte in path.bytes() {
+        if byte.is_ascii_alphanumeric() {
+            id.push(byte as char);
+        } else {
+            id.push_str(&format!("_{byte:02x}"));
+        }
+    }
+    id
+}
+
+/// the path of the entry with this item id
+pub fn entry_path(id: &str) -> Option<String> {
+    let mut bytes = vec![];
+    let mut rest = id.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte == b'_' {
+            let hex = std::str::from_utf8(tail.get(..2)?).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(byte);
+            rest = tail;
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+/// the attributes an entry can be found by
+pub fn entry_attributes(path: &str) -> HashMap<String, String> {
+    let (directory, name) = path.rsplit_once('/').unwrap_or(("", path));
+    HashMap::from([
+        (PATH_ATTRIBUTE.to_owned(), path.to_owned()),
+        (NAME_ATTRIBUTE.to_owned(), name.to_owned()),
+        (DIRECTORY_ATTRIBUTE.to_owned(), directory.to_owned()),
+    ])
+}
+
+/// paths (without `.gpg`) of the entries in the password store outside the secret service's
+/// directory. hidden directories like `.git` are skipped
+pub async fn list_entries(pass: &PasswordStore) -> Result<Vec<String>> {
+    let mut entries = vec![];
+    let mut directories = vec![String::new()];
+    while let Some(directory) = directories.pop() {
+        for (file_type, name) in pass.list_items(Path::new(&directory)).await? {
+            if name.starts_with('.') {
+                continue;
+            }
+            let path = if directory.is_empty() {
+                name
+            } else {
+                format!("{directory}/{name}")
+            };
+
+            if file_type.is_dir() && path != PASS_SUBDIR {
+                directories.push(path);
+            } else if let Some(entry) = path.strip_suffix(".gpg").filter(|_| file_type.is_file()) {
+                entries.push(entry.to_owned());
+            }
+        }
+    }
+    entries.sort();
+    Ok(entries)
+}
+
+#[test]
+fn test_entry_id() {
+    assert_eq!(entry_id("email/work.example"), "email_2fwork_2eexample");
+    assert_eq!(entry_id("a_b"), "a_5fb");
+    for path in ["email/work.example", "a_b", "ünïcode/x y"] {
+        assert_eq!(entry_path(&entry_id(path)).as_deref(), Some(path));
+    }
+    assert_eq!(entry_path("bad_2"), None);
+    assert_eq!(entry_path("bad_zz"), None);
+}
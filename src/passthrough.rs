@@ -0,0 +1,485 @@
+use std::{
+    collections::HashMap,
+    io,
+    time::{Duration, SystemTime},
+};
+
+use futures_util::StreamExt;
+use zbus::{
+    proxy,
+    proxy::{Builder, CacheProperties, ProxyDefault},
+    zvariant::{ObjectPath, OwnedObjectPath, OwnedValue, Value},
+    Connection,
+};
+
+use crate::{
+    config::{matches_collection, PassthroughBackend, PassthroughConfig},
+    error::{Error, Result},
+};
+
+const APP_ID: &str = "pass-secret-service";
+/// attributes identifying our secrets in the other secret service
+const COLLECTION_ATTRIBUTE: &str = "pass-secret-service:collection";
+const ITEM_ATTRIBUTE: &str = "pass-secret-service:item";
+
+/// (session, parameters, value, content type)
+type RemoteSecret = (OwnedObjectPath, Vec<u8>, Vec<u8>, String);
+
+#[proxy(interface = "org.freedesktop.Secret.Service")]
+trait RemoteService {
+    fn open_session(
+        &self,
+        algorithm: &str,
+        input: &Value<'_>,
+    ) -> zbus::Result<(OwnedValue, OwnedObjectPath)>;
+
+    fn unlock(
+        &self,
+        objects: &[&ObjectPath<'_>],
+    ) -> zbus::Result<(Vec<OwnedObjectPath>, OwnedObjectPath)>;
+
+    fn read_alias(&self, name: &str) -> zbus::Result<OwnedObjectPath>;
+}
+
+#[proxy(interface = "org.freedesktop.Secret.Collection")]
+trait RemoteCollection {
+    fn search_items(&self, attributes: HashMap<&str, &str>) -> zbus::Result<Vec<OwnedObjectPath>>;
+
+    fn create_item(
+        &self,
+        properties: HashMap<&str, Value<'_>>,
+        secret: &RemoteSecret,
+        replace: bool,
+    ) -> zbus::Result<(OwnedObjectPath, OwnedObjectPath)>;
+}
+
+#[proxy(interface = "org.freedesktop.Secret.Item")]
+trait RemoteItem {
+    fn get_secret(&self, session: &ObjectPath<'_>) -> zbus::Result<RemoteSecret>;
+
+    fn set_secret(&self, secret: &RemoteSecret) -> zbus::Result<()>;
+
+    fn delete(&self) -> zbus::Result<OwnedObjectPath>;
+
+    #[zbus(property)]
+    fn attributes(&self) -> zbus::Result<HashMap<String, String>>;
+
+    #[zbus(property)]
+    fn created(&self) -> zbus::Result<u64>;
+
+    #[zbus(property)]
+    fn modified(&self) -> zbus::Result<u64>;
+}
+
+#[proxy(interface = "org.freedesktop.Secret.Session")]
+trait RemoteSession {
+    fn close(&self) -> zbus::Result<()>;
+}
+
+#[proxy(interface = "org.freedesktop.Secret.Prompt")]
+trait RemotePrompt {
+    fn prompt(&self, window_id: &str) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    fn completed(&self, dismissed: bool, result: Value<'_>) -> zbus::Result<()>;
+}
+
+#[proxy(interface = "org.kde.KWallet")]
+trait KWallet {
+    #[zbus(name = "networkWallet")]
+    fn network_wallet(&self) -> zbus::Result<String>;
+
+    #[zbus(name = "open")]
+    fn open(&self, wallet: &str, w_id: i64, appid: &str) -> zbus::Result<i32>;
+
+    #[zbus(name = "close")]
+    fn close(&self, handle: i32, force: bool, appid: &str) -> zbus::Result<i32>;
+
+    #[zbus(name = "hasFolder")]
+    fn has_folder(&self, handle: i32, folder: &str, appid: &str) -> zbus::Result<bool>;
+
+    #[zbus(name = "createFolder")]
+    fn create_folder(&self, handle: i32, folder: &str, appid: &str) -> zbus::Result<bool>;
+
+    #[zbus(name = "hasEntry")]
+    fn has_entry(&self, handle: i32, folder: &str, key: &str, appid: &str) -> zbus::Result<bool>;
+
+    #[zbus(name = "readEntry")]
+    fn read_entry(&self, handle: i32, folder: &str, key: &str, appid: &str) -> zbus::Result<Vec<u8>>;
+
+    #[zbus(name = "writeEntry")]
+    fn write_entry(
+        &self,
+        handle: i32,
+        folder: &str,
+        key: &str,
+        value: &[u8],
+        appid: &str,
+    ) -> zbus::Result<i32>;
+
+    #[zbus(name = "removeEntry")]
+    fn remove_entry(&self, handle: i32, folder: &str, key: &str, appid: &str) -> zbus::Result<i32>;
+
+    #[zbus(name = "entryList")]
+    fn entry_list(&self, handle: i32, folder: &str, appid: &str) -> zbus::Result<Vec<String>>;
+}
+
+fn not_found() -> Error {
+    io::Error::from(io::ErrorKind::NotFound).into()
+}
+
+fn time_from_int(secs: u64) -> Option<SystemTime> {
+    // 0 means unknown
+    (secs > 0).then(|| SystemTime::UNIX_EPOCH + Duration::from_secs(secs))
+}
+
+/// proxies the secrets of some collections to another running secret store
+/// only the values are stored there: labels and attributes are still kept locally
+#[derive(Debug)]
+pub struct PassthroughStore {
+    connection: Connection,
+    backend: PassthroughBackend,
+    bus_name: String,
+    /// the collection alias/path (secret service) or wallet (kwallet)
+    target: Option<String>,
+    /// ids or aliases of the collections which are proxied
+    collections: Vec<String>,
+}
+
+impl PassthroughStore {
+    pub fn new(connection: Connection, config: &PassthroughConfig) -> Result<Self> {
+        let bus_name = config
+            .bus_name
+            .clone()
+            .unwrap_or_else(|| match config.backend {
+                PassthroughBackend::SecretService => "org.gnome.keyring".into(),
+                PassthroughBackend::Kwallet => "org.kde.kwalletd6".into(),
+            });
+
+        if bus_name == "org.freedesktop.secrets" {
+            // that's us
+            return Err(Error::Config(
+                "passthrough bus-name can't be org.freedesktop.secrets".into(),
+            ));
+        }
+
+        Ok(Self {
+            connection,
+            backend: config.backend,
+            bus_name,
+            target: config.target.clone(),
+            collections: config.collections.clone(),
+        })
+    }
+
+    /// whether a collection (with the given aliases) is proxied by this store
+    pub fn serves(&self, collection_id: &str, aliases: &[String]) -> bool {
+        matches_collection(&self.collections, collection_id, aliases)
+    }
+
+    async fn proxy<'p, P>(&'p self, path: ObjectPath<'p>) -> Result<P>
+    where
+        P: From<zbus::Proxy<'p>> + ProxyDefault,
+    {
+        Ok(Builder::new(&self.connection)
+            .destination(self.bus_name.as_str())?
+            .path(path)?
+            .cache_properties(CacheProperties::No)
+            .build()
+            .await?)
+    }
+
+    async fn service(&self) -> Result<RemoteServiceProxy<'_>> {
+        self.proxy(ObjectPath::from_static_str_unchecked("/org/freedesktop/secrets"))
+            .await
+    }
+
+    pub async fn read(&self, collection_id: &str, secret_id: &str) -> Result<Vec<u8>> {
+        match self.backend {
+            PassthroughBackend::SecretService => {
+                let service = self.service().await?;
+                let item = self
+                    .find_item(&service, collection_id, secret_id)
+                    .await?
+                    .ok_or_else(not_found)?;
+                self.unlock(&service, &item).await?;
+
+                let item: RemoteItemProxy = self.proxy(item.into_inner()).await?;
+                let session = self.open_session(&service).await?;
+                let secret = item.get_secret(&session).await;
+                self.close_session(session).await;
+                Ok(secret?.2)
+            }
+            PassthroughBackend::Kwallet => {
+                let (kwallet, handle) = self.open_wallet().await?;
+                let folder = Self::folder(collection_id);
+                let result = async {
+                    if !kwallet.has_entry(handle, &folder, secret_id, APP_ID).await? {
+                        return Err(not_found());
+                    }
+                    Ok(kwallet.read_entry(handle, &folder, secret_id, APP_ID).await?)
+                }
+                .await;
+                Self::close_wallet(&kwallet, handle).await;
+                result
+            }
+        }
+    }
+
+    pub async fn write(&self, collection_id: &str, secret_id: &str, value: Vec<u8>) -> Result {
+        match self.backend {
+            PassthroughBackend::SecretService => {
+                let service = self.service().await?;
+                match self.find_item(&service, collection_id, secret_id).await? {
+                    Some(item) => {
+                        self.unlock(&service, &item).await?;
+
+                        let item: RemoteItemProxy = self.proxy(item.into_inner()).await?;
+                        let session = self.open_session(&service).await?;
+                        let result = item
+                            .set_secret(&(session.clone(), vec![], value, "text/plain".into()))
+                            .await;
+                        self.close_session(session).await;
+                        Ok(result?)
+                    }
+                    None => {
+                        let collection = self.remote_collection(&service).await?;
+                        self.unlock(&service, &collection).await?;
+
+                        let collection: RemoteCollectionProxy =
+                            self.proxy(collection.into_inner()).await?;
+                        let attributes = HashMap::from([
+                            (COLLECTION_ATTRIBUTE, collection_id),
+                            (ITEM_ATTRIBUTE, secret_id),
+                        ]);
+                        let properties = HashMap::from([
+                            (
+                                "org.freedesktop.Secret.Item.Label",
+                                Value::from(format!("{APP_ID}: {collection_id}/{secret_id}")),
+                            ),
+                            ("org.freedesktop.Secret.Item.Attributes", Value::from(attributes)),
+                        ]);
+
+                        let session = self.open_session(&service).await?;
+                        let result = collection
+                            .create_item(
+                                properties,
+                                &(session.clone(), vec![], value, "text/plain".into()),
+                                true,
+                            )
+                            .await;
+                        self.close_session(session).await;
+                        let (_, prompt) = result?;
+                        self.complete_prompt(prompt).await
+                    }
+                }
+            }
+            PassthroughBackend::Kwallet => {
+                let (kwallet, handle) = self.open_wallet().await?;
+                let folder = Self::folder(collection_id);
+                let result = async {
+                    if !kwallet.has_folder(handle, &folder, APP_ID).await? {
+                        kwallet.create_folder(handle, &folder, APP_ID).await?;
+                    }
+                    match kwallet
+                        .write_entry(handle, &folder, secret_id, &value, APP_ID)
+                        .await?
+                    {
+                        0 => Ok(()),
+                        code => Err(Error::Backend(format!(
+                            "KWallet could not write the secret ({code})"
+                        ))),
+                    }
+                }
+                .await;
+                Self::close_wallet(&kwallet, handle).await;
+                result
+            }
+        }
+    }
+
+    pub async fn delete(&self, collection_id: &str, secret_id: &str) -> Result {
+        match self.backend {
+            PassthroughBackend::SecretService => {
+                let service = self.service().await?;
+                let Some(item) = self.find_item(&service, collection_id, secret_id).await? else {
+                    return Ok(());
+                };
+                let item: RemoteItemProxy = self.proxy(item.into_inner()).await?;
+                let prompt = item.delete().await?;
+                self.complete_prompt(prompt).await
+            }
+            PassthroughBackend::Kwallet => {
+                let (kwallet, handle) = self.open_wallet().await?;
+                // fails if it's already gone, which is fine
+                let result = kwallet
+                    .remove_entry(handle, &Self::folder(collection_id), secret_id, APP_ID)
+                    .await;
+                Self::close_wallet(&kwallet, handle).await;
+                result?;
+                Ok(())
+            }
+        }
+    }
+
+    pub async fn list(&self, collection_id: &str) -> Result<Vec<String>> {
+        match self.backend {
+            PassthroughBackend::SecretService => {
+                let service = self.service().await?;
+                let collection: RemoteCollectionProxy = self
+                    .proxy(self.remote_collection(&service).await?.into_inner())
+                    .await?;
+                let items = collection
+                    .search_items(HashMap::from([(COLLECTION_ATTRIBUTE, collection_id)]))
+                    .await?;
+
+                let mut ids = vec![];
+                for item in items {
+                    let item: RemoteItemProxy = self.proxy(item.into_inner()).await?;
+                    if let Some(id) = item.attributes().await?.remove(ITEM_ATTRIBUTE) {
+                        ids.push(id);
+                    }
+                }
+                Ok(ids)
+            }
+            PassthroughBackend::Kwallet => {
+                let (kwallet, handle) = self.open_wallet().await?;
+                let folder = Self::folder(collection_id);
+                let result = async {
+                    if !kwallet.has_folder(handle, &folder, APP_ID).await? {
+                        return Ok(vec![]);
+                    }
+                    Ok(kwallet.entry_list(handle, &folder, APP_ID).await?)
+                }
+                .await;
+                Self::close_wallet(&kwallet, handle).await;
+                result
+            }
+        }
+    }
+
+    /// when a secret was created and last updated
+    pub async fn times(
+        &self,
+        collection_id: &str,
+        secret_id: &str,
+    ) -> Result<(Option<SystemTime>, Option<SystemTime>)> {
+        match self.backend {
+            PassthroughBackend::SecretService => {
+                let service = self.service().await?;
+                let item = self
+                    .find_item(&service, collection_id, secret_id)
+                    .await?
+                    .ok_or_else(not_found)?;
+                let item: RemoteItemProxy = self.proxy(item.into_inner()).await?;
+                Ok((
+                    time_from_int(item.created().await?),
+                    time_from_int(item.modified().await?),
+                ))
+            }
+            // kwallet doesn't keep track of this
+            PassthroughBackend::Kwallet => Ok((None, None)),
+        }
+    }
+
+    /// the collection secrets are stored in: an object path or an alias (`default` if unset)
+    async fn remote_collection(&self, service: &RemoteServiceProxy<'_>) -> Result<OwnedObjectPath> {
+        let target = self.target.as_deref().unwrap_or("default");
+        if target.starts_with('/') {
+            return Ok(ObjectPath::try_from(target)
+                .map_err(zbus::Error::from)?
+                .into());
+        }
+
+        let path = service.read_alias(target).await?;
+        if path.as_str() == "/" {
+            return Err(Error::Backend(format!(
+                "{} has no collection with the alias {target}",
+                self.bus_name
+            )));
+        }
+        Ok(path)
+    }
+
+    async fn find_item(
+        &self,
+        service: &RemoteServiceProxy<'_>,
+        collection_id: &str,
+        secret_id: &str,
+    ) -> Result<Option<OwnedObjectPath>> {
+        let collection: RemoteCollectionProxy = self
+            .proxy(self.remote_collection(service).await?.into_inner())
+            .await?;
+        let items = collection
+            .search_items(HashMap::from([
+                (COLLECTION_ATTRIBUTE, collection_id),
+                (ITEM_ATTRIBUTE, secret_id),
+            ]))
+            .await?;
+        Ok(items.into_iter().next())
+    }
+
+    /// values are sent unencrypted, like they are to clients of this service
+    async fn open_session(&self, service: &RemoteServiceProxy<'_>) -> Result<OwnedObjectPath> {
+        let (_, session) = service.open_session("plain", &Value::from("")).await?;
+        Ok(session)
+    }
+
+    async fn close_session(&self, session: OwnedObjectPath) {
+        if let Ok(session) = self.proxy::<RemoteSessionProxy>(session.into_inner()).await {
+            let _ = session.close().await;
+        }
+    }
+
+    /// unlock an object in the other service, which might show a prompt
+    async fn unlock(&self, service: &RemoteServiceProxy<'_>, object: &ObjectPath<'_>) -> Result {
+        let (_, prompt) = service.unlock(&[object]).await?;
+        self.complete_prompt(prompt).await
+    }
+
+    /// show a prompt returned by the other service and wait for the user to complete it
+    async fn complete_prompt(&self, prompt: OwnedObjectPath) -> Result {
+        if prompt.as_str() == "/" {
+            return Ok(());
+        }
+
+        let prompt: RemotePromptProxy = self.proxy(prompt.into_inner()).await?;
+        // subscribe first so we can't miss the signal
+        let mut completed = prompt.receive_completed().await?;
+        prompt.prompt("").await?;
+
+        match completed.next().await {
+            Some(signal) if !signal.args()?.dismissed => Ok(()),
+            _ => Err(Error::PermissionDenied),
+        }
+    }
+
+    /// open the configured wallet (or the network wallet), which might show a prompt
+    /// the handle has to be given back with `close_wallet`
+    async fn open_wallet(&self) -> Result<(KWalletProxy<'_>, i32)> {
+        // kwalletd5 and kwalletd6 each serve /modules/<their name>
+        let module = self.bus_name.rsplit('.').next().unwrap_or_default();
+        let path = ObjectPath::try_from(format!("/modules/{module}")).map_err(zbus::Error::from)?;
+        let kwallet: KWalletProxy = self.proxy(path).await?;
+
+        let wallet = match &self.target {
+            Some(wallet) => wallet.clone(),
+            None => kwallet.network_wallet().await?,
+        };
+
+        match kwallet.open(&wallet, 0, APP_ID).await? {
+            // the user didn't open it
+            ..=-1 => Err(Error::PermissionDenied),
+            handle => Ok((kwallet, handle)),
+        }
+    }
+
+    /// give back a handle from `open_wallet`. the wallet stays open if other applications have it
+    async fn close_wallet(kwallet: &KWalletProxy<'_>, handle: i32) {
+        let _ = kwallet.close(handle, false, APP_ID).await;
+    }
+
+    fn folder(collection_id: &str) -> String {
+        format!("{APP_ID}/{collection_id}")
+    }
+}
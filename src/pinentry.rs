@@ -0,0 +1,108 @@
+use std::{io, process::Stdio};
+
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines},
+    process::{ChildStdout, Command},
+};
+
+use crate::error::Result;
+
+/// escape a string for an assuan command
+fn escape(string: &str) -> String {
+    string
+        .replace('%', "%25")
+        .replace('\n', "%0A")
+        .replace('\r', "%0D")
+}
+
+/// decode the %XX escapes in an assuan data line
+fn unescape(data: &str) -> String {
+    let mut bytes = Vec::with_capacity(data.len());
+    let mut rest = data.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let decoded = (byte == b'%')
+            .then(|| tail.get(..2))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match decoded {
+            Some(decoded) => {
+                bytes.push(decoded);
+                rest = &tail[2..];
+            }
+            None => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// read responses until OK or ERR, returning the data lines
+/// returns None if the user cancelled
+async fn read_response(lines: &mut Lines<BufReader<ChildStdout>>) -> Result<Option<String>> {
+    let mut data = String::new();
+    while let Some(line) = lines.next_line().await? {
+        if line == "OK" || line.starts_with("OK ") {
+            return Ok(Some(data));
+        } else if let Some(line) = line.strip_prefix("D ") {
+            data.push_str(&unescape(line));
+        } else if line.starts_with("ERR ") {
+            return Ok(None);
+        }
+        // ignore status and comment lines
+    }
+    Err(io::Error::new(io::ErrorKind::UnexpectedEof, "pinentry exited unexpectedly").into())
+}
+
+/// ask for a PIN with pinentry
+/// returns None if the user cancelled
+pub async fn get_pin(
+    program: &str,
+    description: &str,
+    prompt: &str,
+    error: Option<&str>,
+) -> Result<Option<String>> {
+    let mut process = Command::new(program)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()?;
+
+    let mut stdin = process.stdin.take().expect("child has stdin");
+    let mut lines = BufReader::new(process.stdout.take().expect("child has stdout")).lines();
+
+    // greeting
+    read_response(&mut lines).await?;
+
+    let mut commands = vec![
+        "SETTITLE pass-secret-service".to_owned(),
+        format!("SETDESC {}", escape(description)),
+        format!("SETPROMPT {}", escape(prompt)),
+    ];
+    if let Some(error) = error {
+        commands.push(format!("SETERROR {}", escape(error)));
+    }
+    for command in commands {
+        stdin.write_all(format!("{command}\n").as_bytes()).await?;
+        read_response(&mut lines).await?;
+    }
+
+    stdin.write_all(b"GETPIN\n").await?;
+    let pin = read_response(&mut lines).await?;
+
+    let _ = stdin.write_all(b"BYE\n").await;
+    let _ = process.wait().await;
+
+    Ok(pin)
+}
+
+#[test]
+fn test_assuan_escaping() {
+    assert_eq!(escape("100%\nsure"), "100%25%0Asure");
+    assert_eq!(unescape("100%25%0Asure"), "100%\nsure");
+    // not an escape
+    assert_eq!(unescape("50%"), "50%");
+    assert_eq!(unescape("%zz1"), "%zz1");
+}
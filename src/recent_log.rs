@@ -0,0 +1,74 @@
+use std::{
+    collections::VecDeque,
+    sync::{Mutex, PoisonError},
+    time::SystemTime,
+};
+
+use log::{Level, Log, Metadata, Record};
+use serde::Serialize;
+
+/// how many warnings and errors to keep
+const CAPACITY: usize = 50;
+
+static RECENT: Mutex<VecDeque<LogEntry>> = Mutex::new(VecDeque::new());
+
+/// a warning or error the daemon logged
+#[derive(Serialize, Clone, Debug)]
+pub struct LogEntry {
+    /// unix seconds
+    pub time: u64,
+    pub level: String,
+    pub target: String,
+    pub message: String,
+}
+
+/// passes records on to env_logger, keeping the most recent warnings and errors
+struct RecordingLogger(env_logger::Logger);
+
+impl Log for RecordingLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.0.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if record.level() <= Level::Warn && self.0.matches(record) {
+            let entry = LogEntry {
+                time: SystemTime::now()
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .map(|d| d.as_secs())
+                    .unwrap_or_default(),
+                level: record.level().to_string(),
+                target: record.target().to_owned(),
+                message: record.args().to_string(),
+            };
+            // this runs in the panic hook too, so ignore poisoning
+            let mut recent = RECENT.lock().unwrap_or_else(PoisonError::into_inner);
+            if recent.len() == CAPACITY {
+                recent.pop_front();
+            }
+            recent.push_back(entry);
+        }
+        self.0.log(record);
+    }
+
+    fn flush(&self) {
+        self.0.flush();
+    }
+}
+
+/// set up logging, keeping recent warnings and errors for state dumps
+pub fn init(builder: &mut env_logger::Builder) {
+    let logger = builder.build();
+    log::set_max_level(logger.filter());
+    log::set_boxed_logger(Box::new(RecordingLogger(logger))).expect("the logger is only set once");
+}
+
+/// the most recent warnings and errors, oldest first
+pub fn recent() -> Vec<LogEntry> {
+    RECENT
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .iter()
+        .cloned()
+        .collect()
+}
@@ -131,10 +131,15 @@ where
         let mut buf = Vec::with_capacity(len * 2);
         encode_int(len, &mut buf).unwrap();
 
-        for (k, v) in value.into_iter() {
-            let key_bytes = K::as_bytes(k);
+        // sorted by key, so the same map is always stored the same way
+        let mut entries: Vec<_> = value
+            .iter()
+            .map(|(k, v)| (K::as_bytes(k), V::as_bytes(v)))
+            .collect();
+        entries.sort_by(|(a, _), (b, _)| a.as_ref().cmp(b.as_ref()));
+
+        for (key_bytes, val_bytes) in &entries {
             let key_ref = key_bytes.as_ref();
-            let val_bytes = V::as_bytes(v);
             let val_ref = val_bytes.as_ref();
 
             // we need to encode the length if it's not fixed
@@ -169,3 +174,15 @@ fn test_redb_hashmap() {
     let deserialized = RedbHashMap::<&str, u8>::from_bytes(&serialized);
     assert_eq!(map, deserialized);
 }
+
+#[test]
+fn test_redb_hashmap_order() {
+    // maps with the same entries are stored the same way, whatever order they were built in
+    let keys = "abcdefghijklmnopqrstuvwxyz";
+    let a: HashMap<&str, &str> = (0..26).map(|n| (&keys[n..n + 1], "x")).collect();
+    let b: HashMap<&str, &str> = (0..26).rev().map(|n| (&keys[n..n + 1], "x")).collect();
+    assert_eq!(
+        RedbHashMap::<&str, &str>::as_bytes(&a),
+        RedbHashMap::<&str, &str>::as_bytes(&b)
+    );
+}
@@ -0,0 +1,141 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant, SystemTime},
+};
+
+use zeroize::Zeroizing;
+
+/// how often expired entries are removed
+const SWEEP_INTERVAL: Duration = Duration::from_secs(1);
+
+/// when an entry's file was modified, and its size. a cached value is only used while the
+/// file still matches, so entries changed outside the daemon (e.g. by a git pull) are read again
+pub type FileVersion = (SystemTime, u64);
+
+#[derive(Debug)]
+struct CachedEntry {
+    /// zeroed when it's dropped
+    value: Zeroizing<Vec<u8>>,
+    version: FileVersion,
+    expires: Instant,
+}
+
+/// decrypted entries, kept for a while so clients which poll a secret don't run gpg each time
+#[derive(Debug)]
+pub struct SecretCache {
+    ttl: Duration,
+    max_entries: usize,
+    /// (collection id, secret id) -> entry
+    entries: Mutex<HashMap<(String, String), CachedEntry>>,
+}
+
+impl SecretCache {
+    pub fn new(ttl: Duration, max_entries: usize) -> Self {
+        Self {
+            ttl,
+            max_entries,
+            entries: Default::default(),
+        }
+    }
+
+    /// the cached value of a secret, if it hasn't expired and its file hasn't changed
+    pub fn get(&self, collection_id: &str, secret_id: &str, version: FileVersion) -> Option<Vec<u8>> {
+        let mut entries = self.entries.lock().unwrap();
+        let key = (collection_id.to_owned(), secret_id.to_owned());
+        let entry = entries.get(&key)?;
+        if entry.version == version && entry.expires > Instant::now() {
+            return Some(entry.value.to_vec());
+        }
+        entries.remove(&key);
+        None
+    }
+
+    pub fn insert(&self, collection_id: &str, secret_id: &str, version: FileVersion, value: &[u8]) {
+        if self.max_entries == 0 {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        let key = (collection_id.to_owned(), secret_id.to_owned());
+        if !entries.contains_key(&key) && entries.len() >= self.max_entries {
+            // make room by dropping the one which expires first
+            let oldest = entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.expires)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                entries.remove(&oldest);
+            }
+        }
+        entries.insert(
+            key,
+            CachedEntry {
+                value: Zeroizing::new(value.to_vec()),
+                version,
+                expires: Instant::now() + self.ttl,
+            },
+        );
+    }
+
+    pub fn remove(&self, collection_id: &str, secret_id: &str) {
+        self.entries
+            .lock()
+            .unwrap()
+            .remove(&(collection_id.to_owned(), secret_id.to_owned()));
+    }
+
+    /// forget every secret from a collection, e.g. when it's locked
+    pub fn clear_collection(&self, collection_id: &str) {
+        self.entries
+            .lock()
+            .unwrap()
+            .retain(|(collection, _), _| collection != collection_id);
+    }
+
+    fn remove_expired(&self) {
+        let now = Instant::now();
+        self.entries.lock().unwrap().retain(|_, entry| entry.expires > now);
+    }
+
+    /// remove entries as they expire, forever
+    pub async fn run(&self) {
+        let mut interval = tokio::time::interval(SWEEP_INTERVAL);
+        loop {
+            interval.tick().await;
+            self.remove_expired();
+        }
+    }
+}
+
+#[test]
+fn test_secret_cache() {
+    let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1000);
+    let cache = SecretCache::new(Duration::from_secs(60), 2);
+
+    cache.insert("default", "a", (modified, 5), b"hunter2");
+    assert_eq!(cache.get("default", "a", (modified, 5)).unwrap(), b"hunter2");
+    assert!(cache.get("work", "a", (modified, 5)).is_none());
+    // the file changed
+    assert!(cache.get("default", "a", (modified, 6)).is_none());
+    assert!(cache.get("default", "a", (modified, 5)).is_none());
+
+    // the entry which expires first makes room
+    cache.insert("default", "a", (modified, 5), b"1");
+    cache.insert("default", "b", (modified, 5), b"2");
+    cache.insert("work", "c", (modified, 5), b"3");
+    assert!(cache.get("default", "a", (modified, 5)).is_none());
+    assert!(cache.get("default", "b", (modified, 5)).is_some());
+
+    cache.clear_collection("work");
+    assert!(cache.get("work", "c", (modified, 5)).is_none());
+    assert!(cache.get("default", "b", (modified, 5)).is_some());
+    cache.remove("default", "b");
+    assert!(cache.get("default", "b", (modified, 5)).is_none());
+
+    let expired = SecretCache::new(Duration::ZERO, 2);
+    expired.insert("default", "a", (modified, 5), b"1");
+    assert!(expired.get("default", "a", (modified, 5)).is_none());
+    expired.insert("default", "a", (modified, 5), b"1");
+    expired.remove_expired();
+    assert!(expired.entries.lock().unwrap().is_empty());
+}
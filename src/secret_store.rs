@@ -1,17 +1,51 @@
-use std::{borrow::Cow, collections::HashMap, fmt::Debug, fs::Metadata, io, path::Path, sync::Arc};
+use std::{
+    borrow::Cow,
+    cmp::Reverse,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    fmt::Debug,
+    fs::Metadata,
+    future::Future,
+    io::{self, Write},
+    ops::ControlFlow,
+    path::{Component, Path, PathBuf},
+    sync::{Arc, Mutex as SyncMutex, RwLock as SyncRwLock},
+    time::{Duration, Instant, SystemTime},
+};
 
+use futures_util::future::join_all;
+use log::{error, info, warn};
 use nanoid::nanoid;
 use redb::{
-    Database, MultimapTableDefinition, ReadableMultimapTable, ReadableTable, TableDefinition,
+    Database, Key, MultimapTable, MultimapTableDefinition, MultimapTableHandle, ReadTransaction,
+    ReadableMultimapTable, ReadableTable, ReadableTableMetadata, TableDefinition, TableHandle,
+    Value, WriteTransaction,
 };
-use tokio::{sync::RwLock, task::spawn_blocking};
+use tokio::sync::{broadcast, Mutex, MutexGuard, OwnedMutexGuard, RwLock};
+use zbus::Connection;
 
 use crate::{
-    error::{raise_nonexistent_table, IntoResult, OptionNoneNotFound, Result},
-    pass::PasswordStore,
+    agent,
+    collation::sort_by_label,
+    config::{matches_collection, Config, Filter, FilterConfig, GateMethod, IndexMode, MetadataFormat},
+    crash::run_blocking,
+    deadline,
+    error::{raise_nonexistent_table, Error, IntoResult, OptionNoneNotFound, Result},
+    gate::{self, hash_pin, legacy_pin_matches, pin_matches},
+    mirror::Mirror,
+    openpgp,
+    pass::{network_filesystem, private_tmpfs, KeyInfo, PasswordStore},
+    pass_tree,
+    pinentry::get_pin,
+    passthrough::PassthroughStore,
     redb_imps::RedbHashMap,
+    secret_cache::{FileVersion, SecretCache},
+    timing::{self, Phase},
+    write_queue::{QueueStatus, WriteQueue},
 };
 
+#[cfg(feature = "vault")]
+use crate::vault::{VaultLocation, VaultStore};
+
 // Collection tables
 
 // (key, value) --> secrets
@@ -20,6 +54,46 @@ const ATTRIBUTES_TABLE: MultimapTableDefinition<(&str, &str), &str> =
 const ATTRIBUTES_TABLE_REVERSE: TableDefinition<&str, RedbHashMap<&str, &str>> =
     TableDefinition::new("attributes-reverse");
 
+// (secret id, index) -> secondary attribute sets ("views") which also find the secret
+const VIEWS_TABLE: TableDefinition<(&str, u32), RedbHashMap<&str, &str>> =
+    TableDefinition::new("views");
+// (key, value) --> views
+const VIEW_ATTRIBUTES_TABLE: MultimapTableDefinition<(&str, &str), (&str, u32)> =
+    MultimapTableDefinition::new("view-attributes");
+
+// collection-wide values, like the item count
+const METADATA_TABLE: TableDefinition<&str, u64> = TableDefinition::new("metadata");
+const ITEM_COUNT_KEY: &str = "item-count";
+// unix seconds. not set for collections created before it was recorded
+const CREATED_KEY: &str = "created";
+// 0 if the collection doesn't keep the (key, value) --> secrets table, and searches scan
+// the reverse one instead. not set for collections from before it was optional, which keep it
+const INDEXED_KEY: &str = "attribute-index";
+/// with `attribute-index = "auto"`, collections with this many secrets get an index.
+/// it's dropped again once they have fewer than half as many
+const INDEX_MIN_ITEMS: u64 = 64;
+// secret id -> keyed hash of the plaintext
+const CHECKSUMS_TABLE: TableDefinition<&str, &[u8]> = TableDefinition::new("checksums");
+// secret id -> content type the client gave it. not set for text/plain
+const CONTENT_TYPES_TABLE: TableDefinition<&str, &str> = TableDefinition::new("content-types");
+pub const DEFAULT_CONTENT_TYPE: &str = "text/plain";
+// secret id -> argon2 hash of the PIN needed to read it
+const ITEM_PINS_TABLE: TableDefinition<&str, &str> = TableDefinition::new("item-pins");
+// secret id -> (unix seconds, exe of the client) of the last successful read
+const LAST_ACCESS_TABLE: TableDefinition<&str, (u64, &str)> = TableDefinition::new("last-access");
+// (secret id, index) -> (unix seconds it was changed, label) of a secret's previous labels
+const LABEL_HISTORY_TABLE: TableDefinition<(&str, u32), (u64, &str)> =
+    TableDefinition::new("label-history");
+/// how many previous labels are kept for each secret
+const LABEL_HISTORY_LIMIT: usize = 32;
+// secret id -> (unix seconds it was deleted, its attributes) of secrets in the trash
+// their labels and other details are kept in the other tables until they're purged
+const TRASH_TABLE: TableDefinition<&str, (u64, RedbHashMap<&str, &str>)> = TableDefinition::new("trash");
+/// the dir in a collection's dir which deleted secrets' files are moved to
+const TRASH_DIR: &str = ".trash";
+/// how often secrets are purged from the trash once they're older than its retention period
+const TRASH_PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
 // collection id --> label
 const LABELS_TABLE: TableDefinition<&str, &str> = TableDefinition::new("labels");
 // collection alias -> id
@@ -27,9 +101,47 @@ const ALIASES_TABLE: TableDefinition<&str, &str> = TableDefinition::new("aliases
 // id -> alises
 const ALIASES_TABLE_REVERSE: MultimapTableDefinition<&str, &str> =
     MultimapTableDefinition::new("aliases_reverse");
+// collection id -> position the user wants it listed at
+const SORT_ORDER_TABLE: TableDefinition<&str, u32> = TableDefinition::new("sort-order");
+// collection id -> color tag for frontends
+const COLORS_TABLE: TableDefinition<&str, &str> = TableDefinition::new("colors");
+// client executable -> unix time first seen
+const KNOWN_CLIENTS_TABLE: TableDefinition<&str, u64> = TableDefinition::new("known_clients");
+// keyring file name (without .keyring) -> id of the collection it was imported into
+const IMPORTED_KEYRINGS_TABLE: TableDefinition<&str, &str> = TableDefinition::new("imported-keyrings");
+// key name -> random key
+const KEYS_TABLE: TableDefinition<&str, &[u8]> = TableDefinition::new("keys");
+const CHECKSUM_KEY: &str = "checksum";
+// random key followed by the keyed hash of the high-security PIN
+const PIN_KEY: &str = "pin";
 
-const PASS_SUBDIR: &'static str = "secret-service";
+pub const PASS_SUBDIR: &'static str = "secret-service";
 const ATTRIBUTES_DB: &'static str = "attributes.redb";
+const COLLECTIONS_DB: &str = "collections.redb";
+/// what's encrypted and decrypted to unlock a collection which has no secrets
+const UNLOCK_CHECK: &[u8] = b"pass-secret-service unlock check";
+
+/// attribute holding when a secret expires, as unix seconds or `YYYY-MM-DD` (UTC)
+pub const EXPIRES_ATTRIBUTE: &str = "pass:expires";
+
+/// attribute numbering the copies of an item created with `duplicates = "version"`
+pub const VERSION_ATTRIBUTE: &str = "pass:version";
+
+/// the line holding a secret's label in its entry, when its metadata is embedded
+const LABEL_ATTRIBUTE: &str = "pass:label";
+
+/// attributes starting with these are for the daemon, and clients can't set them directly
+pub const RESERVED_PREFIXES: [&str; 2] = ["pass:", "me.grimsteel."];
+
+/// the reserved attributes the daemon uses, and what they hold
+pub const RESERVED_ATTRIBUTES: [(&str, &str); 6] = [
+    (EXPIRES_ATTRIBUTE, "when the item expires. set with the Expires property"),
+    (VERSION_ATTRIBUTE, "which copy of an item with the same attributes this is, counting from 1"),
+    (LABEL_ATTRIBUTE, "the item's label, in entries with embedded metadata"),
+    (pass_tree::PATH_ATTRIBUTE, "the entry's path, in the pass tree collection"),
+    (pass_tree::NAME_ATTRIBUTE, "the entry's file name, in the pass tree collection"),
+    (pass_tree::DIRECTORY_ATTRIBUTE, "the entry's directory, in the pass tree collection"),
+];
 
 pub const NANOID_ALPHABET: [char; 63] = [
     'a', 'b', 'c', 'd', 'e', 'f', 'g', 'h', 'i', 'j', 'k', 'l', 'm', 'n', 'o', 'p', 'q', 'r', 's',
@@ -39,12 +151,104 @@ pub const NANOID_ALPHABET: [char; 63] = [
 ];
 
 type RedbResult<T> = std::result::Result<T, redb::Error>;
+/// the (key, value) --> secrets table, open for writing
+type IndexTable<'txn> = MultimapTable<'txn, (&'static str, &'static str), &'static str>;
+/// label and attributes of a secret found in the legacy db
+type LegacySecret = (Option<String>, Option<HashMap<String, String>>);
+
+/// (secret id, label, attributes)
+pub type SecretInfo = (String, String, HashMap<String, String>);
+
+/// a collection or secret whose id can't be part of an object path as it is, found on start
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnreachableId {
+    pub collection_id: String,
+    /// None for the collection itself
+    pub secret_id: Option<String>,
+    /// the id the secret was given instead, if it could be renamed. otherwise it's served at an
+    /// escaped path
+    pub renamed_to: Option<String>,
+}
+
+/// like `raise_nonexistent_table`, but for tables that may legitimately be missing
+fn open_legacy_table<T>(result: std::result::Result<T, redb::TableError>) -> Result<Option<T>> {
+    match result {
+        Ok(t) => Ok(Some(t)),
+        Err(redb::TableError::TableDoesNotExist(_)) => Ok(None),
+        Err(e) => Err(e).into_result(),
+    }
+}
+
+/// move the dbs from the password store to the separate metadata directory, keeping their
+/// paths, for stores which kept them together before it was configured
+/// returns how many were moved
+async fn move_metadata(pass: &PasswordStore, metadata: &PasswordStore) -> Result<u32> {
+    let mut dbs = vec![Path::new(PASS_SUBDIR).join(COLLECTIONS_DB)];
+    for (file_type, id) in pass.list_items(PASS_SUBDIR).await? {
+        if file_type.is_dir() {
+            dbs.push(Path::new(PASS_SUBDIR).join(id).join(ATTRIBUTES_DB));
+        }
+    }
+
+    let mut moved = 0;
+    for db in dbs {
+        if !pass.file_exists(&db).await? || metadata.file_exists(&db).await? {
+            continue;
+        }
+        metadata.make_dir(db.parent().unwrap()).await?;
+        // they may be on different filesystems
+        tokio::fs::copy(pass.directory.join(&db), metadata.directory.join(&db)).await?;
+        tokio::fs::remove_file(pass.directory.join(&db)).await?;
+        moved += 1;
+    }
+    Ok(moved)
+}
+
 /// open a db contained within the given PasswordStore
 async fn open_db(pass: &PasswordStore, path: impl AsRef<Path>) -> Result<Database> {
     let db_file = pass.open_file(path).await?.into_std().await;
     Ok(redb::Builder::new()
         .create_file(db_file)
-        .map_err(|e| Into::<redb::Error>::into(e))?)
+        .map_err(Into::<redb::Error>::into)?)
+}
+
+/// open a db again if its file was replaced (e.g. by a git pull)
+/// returns None if it wasn't, since the one which is open is still current
+async fn reopen_db(pass: &PasswordStore, path: impl AsRef<Path>) -> Result<Option<Database>> {
+    match open_db(pass, path).await {
+        Ok(db) => Ok(Some(db)),
+        // redb locks the file, so the same one can't be opened twice
+        Err(Error::RedbError(e)) if matches!(*e, redb::Error::DatabaseAlreadyOpen) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// read the whole sort order table
+fn read_sort_orders(db: &Database) -> Result<HashMap<String, u32>> {
+    let tx = db.begin_read().into_result()?;
+    let table = raise_nonexistent_table!(tx.open_table(SORT_ORDER_TABLE), Ok(HashMap::new()));
+    table
+        .iter()
+        .into_result()?
+        .map(|entry| {
+            let (id, order) = entry.into_result()?;
+            Ok((id.value().to_owned(), order.value()))
+        })
+        .collect()
+}
+
+/// read a whole labels table: collection ids or secret ids to labels
+fn read_labels(db: &Database) -> Result<HashMap<String, String>> {
+    let tx = db.begin_read().into_result()?;
+    let table = raise_nonexistent_table!(tx.open_table(LABELS_TABLE), Ok(HashMap::new()));
+    table
+        .iter()
+        .into_result()?
+        .map(|entry| {
+            let (id, label) = entry.into_result()?;
+            Ok((id.value().to_owned(), label.value().to_owned()))
+        })
+        .collect()
 }
 
 /// convert a string to a valid ASCII slug
@@ -71,700 +275,5759 @@ pub fn slugify(string: &str) -> String {
     unsafe { String::from_utf8_unchecked(slugified) }
 }
 
-/// search a collection for the given attributes
-/// returns a vec of secret IDs
-pub fn search_collection(attrs: &HashMap<String, String>, db: &Database) -> Result<Vec<String>> {
-    if attrs.len() == 0 {
-        return Ok(vec![]);
-    };
+/// days since the unix epoch of a date in the proleptic gregorian calendar
+pub(crate) fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    // years start in march so the leap day is at the end
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
 
-    let tx = db.begin_read().into_result()?;
-    let attributes = raise_nonexistent_table!(tx.open_multimap_table(ATTRIBUTES_TABLE), Ok(vec![]));
-    let attributes_reverse = raise_nonexistent_table!(tx.open_table(ATTRIBUTES_TABLE_REVERSE), Ok(vec![]));
+/// parse the value of the expiry attribute into unix seconds
+pub fn parse_expiry(value: &str) -> Option<u64> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse() {
+        return Some(seconds);
+    }
 
-    let mut attr_iter = attrs.into_iter();
+    let mut parts = value.splitn(3, '-');
+    let year: i64 = parts.next()?.parse().ok()?;
+    let month: i64 = parts.next()?.parse().ok()?;
+    let day: i64 = parts.next()?.parse().ok()?;
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
 
-    // get the secrets which fit the first K/V attr pair
-    let initial_matches = attributes
-        .get(
-            attr_iter
-                .next()
-                .map(|(k, v)| (k.as_str(), v.as_str()))
-                .unwrap(),
-        )
-        .into_result()?;
+    let days = days_from_civil(year, month, day);
+    (days * 86400).try_into().ok()
+}
 
-    // filter the items from there
-    Ok(initial_matches
-        .map(|r| -> RedbResult<_> {
-            let secret_id_guard = r?;
-            let secret_id = secret_id_guard.value();
-            // get the attributes for this secret
-            if let Some(secret_attrs) = attributes_reverse.get(secret_id)? {
-                let secret_attrs = secret_attrs.value();
-                // make sure it's a subset of the remaining `attrs`
-                for (k, v) in attr_iter.clone() {
-                    if secret_attrs.get(k.as_str()) != Some(&v.as_str()) {
-                        return Ok(None);
-                    };
-                }
-                Ok(Some(secret_id.to_owned()))
-            } else {
-                Ok(None)
+/// the read filters for a secret stored with `content_type`, which is None if it wasn't stored
+/// through the API (e.g. an entry made with `pass insert`). only text is filtered: with no
+/// filters configured, just the newline pass adds to its entries is removed
+pub fn read_filters<'a>(configured: Option<&'a FilterConfig>, content_type: Option<&str>) -> &'a [Filter] {
+    match content_type {
+        None => configured.map_or(&[Filter::StripTrailingNewline], |filters| &filters.read),
+        Some(DEFAULT_CONTENT_TYPE) => configured.map_or(&[], |filters| &filters.read),
+        Some(_) => &[],
+    }
+}
+
+/// the write filters for a secret a client stores with `content_type`. only text is filtered
+pub fn write_filters<'a>(configured: Option<&'a FilterConfig>, content_type: &str) -> &'a [Filter] {
+    match (configured, content_type) {
+        (Some(filters), "" | DEFAULT_CONTENT_TYPE) => &filters.write,
+        _ => &[],
+    }
+}
+
+/// apply filters to a secret's value, in order
+pub fn apply_filters(filters: &[Filter], mut value: Vec<u8>) -> Vec<u8> {
+    for filter in filters {
+        match filter {
+            Filter::StripTrailingNewline => {
+                if value.ends_with(b"\r\n") {
+                    value.truncate(value.len() - 2);
+                } else if value.ends_with(b"\n") {
+                    value.pop();
+                }
             }
-        })
-        .filter_map(|item| item.transpose())
-        .collect::<RedbResult<Vec<_>>>()?)
+            Filter::AddTrailingNewline => {
+                if !value.ends_with(b"\n") {
+                    value.push(b'\n');
+                }
+            }
+            Filter::Trim => {
+                let start = value.iter().position(|b| !b.is_ascii_whitespace());
+                let end = value.iter().rposition(|b| !b.is_ascii_whitespace());
+                value = match (start, end) {
+                    (Some(start), Some(end)) => value[start..=end].to_vec(),
+                    _ => vec![],
+                };
+            }
+        }
+    }
+    value
 }
 
-#[derive(Debug, Clone)]
-pub struct SecretStore<'a> {
-    pass: &'a PasswordStore,
-    collection_dbs: Arc<RwLock<HashMap<String, Database>>>,
-    db: Arc<Database>,
+/// whether an id can be part of an object path as it is
+pub fn is_valid_id(id: &str) -> bool {
+    !id.is_empty() && id.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_')
 }
 
-impl<'a> SecretStore<'a> {
-    pub async fn new(pass: &'a PasswordStore) -> Result<Self> {
-        let collections = Self::get_current_collections(pass).await?;
+/// an id for a secret whose id can't be part of an object path: its other characters replaced
+/// with `_`, and a random suffix if that's taken
+fn escaped_id(id: &str, taken: &HashSet<String>) -> String {
+    let escaped: String = id
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '_' { c } else { '_' })
+        .collect();
+    let mut candidate = escaped.clone();
+    while candidate.is_empty() || taken.contains(&candidate) {
+        candidate = format!("{escaped}_{}", nanoid!(4, &NANOID_ALPHABET));
+    }
+    candidate
+}
+
+pub fn is_reserved(key: &str) -> bool {
+    RESERVED_PREFIXES.iter().any(|prefix| key.starts_with(prefix))
+}
 
-        let db = open_db(&pass, &format!("{PASS_SUBDIR}/collections.redb")).await?;
+/// which copy of an item with the same attributes a secret is. secrets without a version are the first
+pub fn secret_version(attributes: &HashMap<String, String>) -> u64 {
+    attributes
+        .get(VERSION_ATTRIBUTE)
+        .and_then(|version| version.parse().ok())
+        .unwrap_or(1)
+}
 
-        let store = Self {
-            pass,
-            collection_dbs: Arc::new(RwLock::new(collections)),
-            db: Arc::new(db),
-        };
+/// the attributes which make two secrets duplicates: all but the reserved ones, in order
+fn identifying_attributes(attributes: &HashMap<String, String>) -> BTreeMap<&str, &str> {
+    attributes
+        .iter()
+        .filter(|(key, _)| !is_reserved(key))
+        .map(|(key, value)| (key.as_str(), value.as_str()))
+        .collect()
+}
 
-        Ok(store)
+/// replace the reserved attributes a client sent with the ones the item already has
+/// returns the ones which were dropped because they didn't match
+pub fn keep_reserved(
+    attributes: &mut HashMap<String, String>,
+    existing: &HashMap<String, String>,
+) -> Vec<String> {
+    let mut dropped = vec![];
+    attributes.retain(|key, value| {
+        if !is_reserved(key) {
+            return true;
+        }
+        // clients which write back the attributes they read are fine
+        if existing.get(key) != Some(value) {
+            dropped.push(key.clone());
+        }
+        false
+    });
+    attributes.extend(
+        existing
+            .iter()
+            .filter(|(key, _)| is_reserved(key))
+            .map(|(key, value)| (key.clone(), value.clone())),
+    );
+    dropped.sort();
+    dropped
+}
+
+/// a secret's label and attributes, as written into its pass entry
+#[derive(Debug, PartialEq)]
+pub struct EntryMetadata {
+    /// only missing in entries which were written by something else, e.g. gopass
+    pub label: Option<String>,
+    pub attributes: HashMap<String, String>,
+}
+
+/// add a secret's label and attributes to its value as `key: value` lines after its first line
+/// for browserpass, the secret can't have more than one line. for gopass, the rest of it goes
+/// after them, separated by a blank line
+pub fn embed_metadata(mut value: Vec<u8>, metadata: &EntryMetadata, format: MetadataFormat) -> Result<Vec<u8>> {
+    if std::str::from_utf8(&value).is_err() {
+        return Err(Error::CannotEmbedMetadata("the secret isn't text".to_owned()));
+    }
+    if value.ends_with(b"\n") {
+        value.pop();
+    }
+    let body = match value.iter().position(|b| *b == b'\n') {
+        Some(newline) if format == MetadataFormat::Gopass => value.split_off(newline + 1),
+        Some(_) => {
+            return Err(Error::CannotEmbedMetadata(
+                "the secret has more than one line".to_owned(),
+            ))
+        }
+        None => vec![],
+    };
+    if value.ends_with(b"\n") {
+        value.pop();
     }
 
-    async fn get_current_collections(pass: &PasswordStore) -> Result<HashMap<String, Database>> {
-        let mut collections = HashMap::new();
+    // sorted so the entry diffs cleanly
+    let mut attributes: Vec<_> = metadata.attributes.iter().collect();
+    attributes.sort();
 
-        for (_, id) in pass
-            .list_items(PASS_SUBDIR)
-            .await?
-            .into_iter()
-            .filter(|(file_type, _)| file_type.is_dir())
+    let lines = metadata
+        .label
+        .as_deref()
+        .map(|label| (LABEL_ATTRIBUTE, label))
+        .into_iter()
+        .chain(attributes.into_iter().map(|(k, v)| (k.as_str(), v.as_str())));
+    for (i, (key, attr_value)) in lines.enumerate() {
+        if key.is_empty()
+            || key.contains(": ")
+            || key.contains('\n')
+            || attr_value.contains('\n')
+            || (i > 0 && key == LABEL_ATTRIBUTE)
         {
-            // make the DB for this collection
-            let db_path = Path::new(PASS_SUBDIR).join(&id).join(ATTRIBUTES_DB);
-            let db = open_db(&pass, db_path).await?;
-            collections.insert(id, db);
+            return Err(Error::CannotEmbedMetadata(format!(
+                "{key:?} can't be written as a line"
+            )));
         }
-
-        Ok(collections)
+        value.push(b'\n');
+        value.extend_from_slice(format!("{key}: {attr_value}").as_bytes());
     }
+    value.push(b'\n');
+    if !body.is_empty() {
+        value.push(b'\n');
+        value.extend_from_slice(&body);
+        value.push(b'\n');
+    }
+    Ok(value)
+}
 
-    pub async fn get_label(&self, collection_id: Arc<String>) -> Result<String> {
-        let db = self.db.clone();
-        spawn_blocking(move || {
-            let tx = db.begin_read().into_result()?;
-            let table = raise_nonexistent_table!(tx.open_table(LABELS_TABLE));
-            let label = table
-                .get(collection_id.as_str())
-                .into_result()?
-                .into_not_found()?;
-
-            Ok(label.value().to_owned())
-        })
-        .await
-        .unwrap()
+/// what to write to a secret's entry: its value with the metadata embedded, or just its value if
+/// the metadata can't be, in which case it's only kept in the db
+fn entry_contents(value: Vec<u8>, metadata: &EntryMetadata, format: MetadataFormat) -> Vec<u8> {
+    match embed_metadata(value.clone(), metadata, format) {
+        Ok(entry) => entry,
+        Err(e) => {
+            info!("Writing a secret without its metadata: {e}");
+            value
+        }
     }
+}
 
-    pub async fn set_label(&self, collection_id: Arc<String>, label: String) -> Result {
-        let db = self.db.clone();
-        Ok(spawn_blocking(move || -> RedbResult<_> {
-            let tx = db.begin_write()?;
-            let mut table = tx.open_table(LABELS_TABLE)?;
-            table.insert(collection_id.as_str(), &*label)?;
-            drop(table);
-            tx.commit()?;
-            Ok(())
-        })
-        .await
-        .unwrap()?)
+/// split an entry written by `embed_metadata` into the secret and its metadata
+/// returns None if the entry doesn't have any
+pub fn split_metadata(entry: &[u8], format: MetadataFormat) -> Option<(Vec<u8>, EntryMetadata)> {
+    let newline = entry.iter().position(|b| *b == b'\n')?;
+    let rest = std::str::from_utf8(&entry[newline + 1..]).ok()?;
+
+    let mut label = None;
+    let mut attributes = HashMap::new();
+    let mut body = "";
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        let start = offset;
+        offset += line.len();
+        let line = line.strip_suffix('\n').unwrap_or(line);
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        match (format, line.split_once(": ").filter(|(key, _)| !key.is_empty())) {
+            (_, Some((LABEL_ATTRIBUTE, value))) => label = Some(value.to_owned()),
+            (_, Some((key, value))) => {
+                attributes.insert(key.to_owned(), value.to_owned());
+            }
+            (MetadataFormat::Browserpass, None) if line.is_empty() => {}
+            (MetadataFormat::Browserpass, None) => return None,
+            // gopass's older YAML entries
+            (MetadataFormat::Gopass, None) if start == 0 && line == "---" => {}
+            // the rest of the secret. a blank line separates it from the metadata
+            (MetadataFormat::Gopass, None) if line.is_empty() => {
+                body = &rest[offset..];
+                break;
+            }
+            (MetadataFormat::Gopass, None) => {
+                body = &rest[start..];
+                break;
+            }
+        }
     }
 
-    /// returns a hashmap of collection id to vec of aliases
-    pub async fn list_all_aliases(&self) -> Result<HashMap<String, Vec<String>>> {
-        let db = self.db.clone();
-        Ok(spawn_blocking(move || -> Result<_> {
-            // open the aliases table
-            let tx = db.begin_read().into_result()?;
-            let table = raise_nonexistent_table!(tx.open_multimap_table(ALIASES_TABLE_REVERSE), Ok(HashMap::new()));
-            Ok(table
-                .iter()
-                .into_result()?
-                .map(|i| {
-                    let (target, aliases) = i?;
-                    let aliases = aliases
-                        .map(|a| RedbResult::Ok(a?.value().to_owned()))
-                        .collect::<RedbResult<Vec<_>>>()?;
-                    Ok((target.value().to_owned(), aliases))
-                })
-                .collect::<RedbResult<_>>()?)
-        })
-        .await
-        .unwrap()?)
+    let metadata = match format {
+        MetadataFormat::Browserpass => EntryMetadata {
+            label: Some(label?),
+            attributes,
+        },
+        MetadataFormat::Gopass if label.is_none() && attributes.is_empty() => return None,
+        MetadataFormat::Gopass => EntryMetadata { label, attributes },
+    };
+    let mut secret = entry[..newline].to_vec();
+    let body = body.trim_end_matches('\n');
+    if !body.is_empty() {
+        secret.push(b'\n');
+        secret.extend_from_slice(body.as_bytes());
     }
+    Some((secret, metadata))
+}
 
-    /// list the aliases that point to a collection
-    pub async fn list_aliases_for_collection(
-        &self,
-        collection_id: Arc<String>,
-    ) -> Result<Vec<String>> {
-        let db = self.db.clone();
-        spawn_blocking(move || -> Result<_> {
-            let tx = db.begin_read().into_result()?;
-            
-            let aliases_reverse =
-                raise_nonexistent_table!(tx.open_multimap_table(ALIASES_TABLE_REVERSE));
+/// the ids of the secrets which a git pull added, changed, and removed in a collection
+#[derive(Debug, Default, PartialEq)]
+pub struct PulledChanges {
+    pub added: Vec<String>,
+    pub changed: Vec<String>,
+    pub removed: Vec<String>,
+}
 
-            // get the aliases for this collection and stringify each one
-            Ok(aliases_reverse
-                .get(collection_id.as_str())
-                .into_result()?
-                .map(|el| Ok(el?.value().to_owned()))
-                .collect::<RedbResult<Vec<_>>>()?)
-        })
-        .await
-        .unwrap()
-    }
+/// read the output of `git diff --name-status` into each collection's changes. files which
+/// aren't secrets (like the dbs) are left out
+fn parse_pulled_changes(diff: &str) -> HashMap<String, PulledChanges> {
+    let mut pulled: HashMap<String, PulledChanges> = HashMap::new();
+    for line in diff.lines() {
+        let Some((status, path)) = line.split_once('\t') else {
+            continue;
+        };
+        let Some((collection_id, secret_id)) = path
+            .strip_prefix(PASS_SUBDIR)
+            .and_then(|path| path.strip_prefix('/'))
+            .and_then(|path| path.split_once('/'))
+            .and_then(|(collection_id, file)| Some((collection_id, file.strip_suffix(".gpg")?)))
+            .filter(|(_, secret_id)| !secret_id.is_empty() && !secret_id.contains('/'))
+        else {
+            continue;
+        };
 
-    pub async fn get_alias(&self, alias: Arc<String>) -> Result<String> {
-        let db = self.db.clone();
-        spawn_blocking(move || {
-            // open the aliases table
-            let tx = db.begin_read().into_result()?;
-            let table = raise_nonexistent_table!(tx.open_table(ALIASES_TABLE));
-            let target = table
-                .get(alias.as_str())
-                .into_result()?
-                .into_not_found()?
-                .value()
-                .to_owned();
-            Ok(target)
-        })
-        .await
-        .unwrap()
+        let changes = pulled.entry(collection_id.to_owned()).or_default();
+        let ids = match status {
+            "A" => &mut changes.added,
+            "D" => &mut changes.removed,
+            _ => &mut changes.changed,
+        };
+        ids.push(secret_id.to_owned());
     }
+    pulled
+}
 
-    pub async fn set_alias(&self, alias: Arc<String>, target: Option<String>) -> Result {
-        let db = self.db.clone();
-        Ok(spawn_blocking(move || -> RedbResult<_> {
-            // open the aliases table
-            let tx = db.begin_write()?;
-            let mut aliases = tx.open_table(ALIASES_TABLE)?;
-            let mut aliases_reverse = tx.open_multimap_table(ALIASES_TABLE_REVERSE)?;
+/// a pass entry name for each secret, from its label, or its id if the label can't be used.
+/// names which are already used (including the ones in `taken`) get a number after them
+fn entry_names(ids: &[String], labels: &HashMap<String, String>, mut taken: HashSet<String>) -> HashMap<String, String> {
+    let mut ids = ids.to_vec();
+    ids.sort();
 
-            // remove this alias from its old target's alias list
-            if let Some(old_target) = aliases.get(alias.as_str())? {
-                aliases_reverse.remove(old_target.value(), alias.as_str())?;
-            }
+    let mut names = HashMap::new();
+    for id in ids {
+        let base = labels
+            .get(&id)
+            .map(|label| {
+                let label: String = label
+                    .chars()
+                    .map(|c| if c == '/' { '-' } else { c })
+                    .filter(|c| !c.is_control())
+                    .collect();
+                label.trim().trim_start_matches('.').trim_start().to_owned()
+            })
+            .filter(|name| !name.is_empty())
+            .unwrap_or_else(|| id.clone());
 
-            if let Some(target) = target {
-                aliases.insert(alias.as_str(), target.as_str())?;
-                aliases_reverse.insert(target.as_str(), alias.as_str())?;
-            } else {
-                // remove it
-                aliases.remove(alias.as_str())?;
-            }
-            drop(aliases);
-            drop(aliases_reverse);
-            tx.commit()?;
-            Ok(())
-        })
-        .await
-        .unwrap()?)
+        let mut name = base.clone();
+        let mut n = 2;
+        while !taken.insert(name.clone()) {
+            name = format!("{base} ({n})");
+            n += 1;
+        }
+        names.insert(id, name);
     }
+    names
+}
 
-    pub async fn collections(&self) -> Vec<String> {
-        self.collection_dbs
-            .read()
-            .await
-            .keys()
-            .map(|a| a.to_owned())
-            .collect()
+/// find the secrets in a collection which expire at or before `before`
+/// returns a vec of (secret ID, expiry)
+fn expiring_in_collection(db: &Database, before: u64) -> RedbResult<Vec<(String, u64)>> {
+    let tx = db.begin_read()?;
+    if !reads_index(&tx)? {
+        let attributes_reverse = match tx.open_table(ATTRIBUTES_TABLE_REVERSE) {
+            Ok(table) => table,
+            Err(redb::TableError::TableDoesNotExist(_)) => return Ok(vec![]),
+            Err(e) => return Err(e.into()),
+        };
+        let mut expiring = vec![];
+        for entry in attributes_reverse.iter()? {
+            let (secret, attrs) = entry?;
+            let expires = attrs.value().get(EXPIRES_ATTRIBUTE).and_then(|value| parse_expiry(value));
+            if let Some(expires) = expires.filter(|expires| *expires <= before) {
+                expiring.push((secret.value().to_owned(), expires));
+            }
+        }
+        return Ok(expiring);
     }
 
-    /// create a collection, with an optional label and alias
-    /// returns the created collection name
-    /// if `label` is `None`, the collection will be called "Unttiled Collection"
-    pub async fn create_collection(
-        &self,
-        label: Option<String>,
-        alias: Option<String>,
-    ) -> Result<String> {
-        // I assume aliases are case sensitive
+    let attributes = match tx.open_multimap_table(ATTRIBUTES_TABLE) {
+        Ok(table) => table,
+        Err(redb::TableError::TableDoesNotExist(_)) => return Ok(vec![]),
+        Err(e) => return Err(e.into()),
+    };
 
-        let db = self.db.clone();
+    let mut expiring = vec![];
+    for entry in attributes.range((EXPIRES_ATTRIBUTE, "")..)? {
+        let (key, secrets) = entry?;
+        let (name, value) = key.value();
+        if name != EXPIRES_ATTRIBUTE {
+            break;
+        }
 
-        let collection_id = spawn_blocking(move || -> RedbResult<_> {
-            let tx = db.begin_write()?;
-            let mut aliases = tx.open_table(ALIASES_TABLE)?;
-            let mut aliases_reverse = tx.open_multimap_table(ALIASES_TABLE_REVERSE)?;
-            let mut labels = tx.open_table(LABELS_TABLE)?;
+        let Some(expires) = parse_expiry(value) else {
+            continue;
+        };
+        if expires > before {
+            continue;
+        }
 
-            let had_provided_label = label.is_some();
-            let label = label
-                .map(Cow::Owned)
-                .unwrap_or("Untitled Collection".into());
+        for secret in secrets {
+            expiring.push((secret?.value().to_owned(), expires));
+        }
+    }
 
-            // an existing alias
-            let existing_id = if let Some(alias) = alias.as_ref() {
-                if let Some(collection_id) = aliases.get(alias.as_str())? {
-                    let id = collection_id.value();
+    Ok(expiring)
+}
 
-                    // update the label if we were given one or there isn't one already
-                    // in the 2nd case, it just becomes Untitled Collection
-                    if had_provided_label || labels.get(id)?.is_none() {
-                        labels.insert(id, label.as_ref())?;
-                    }
+/// whether `attrs` contains every one of the `wanted` attributes
+fn has_attributes<'w>(
+    attrs: &HashMap<&str, &str>,
+    mut wanted: impl Iterator<Item = (&'w String, &'w String)>,
+) -> bool {
+    wanted.all(|(k, v)| attrs.get(k.as_str()) == Some(&v.as_str()))
+}
 
-                    Some(id.to_string())
-                } else {
-                    None
+/// whether `value` matches `pattern`, where `*` matches any run of characters and `?` any one
+fn wildcard_matches(pattern: &str, value: &str) -> bool {
+    let pattern: Vec<_> = pattern.chars().collect();
+    let value: Vec<_> = value.chars().collect();
+    let (mut p, mut v) = (0, 0);
+    // the last `*`, and how much of the value it has matched so far
+    let mut star = None;
+    while v < value.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, v));
+                p += 1;
+            }
+            Some(c) if *c == '?' || *c == value[v] => {
+                p += 1;
+                v += 1;
+            }
+            // let the last `*` match one more character
+            _ => match star {
+                Some((star_p, star_v)) => {
+                    star = Some((star_p, star_v + 1));
+                    p = star_p + 1;
+                    v = star_v + 1;
                 }
-            } else {
-                None
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// the secrets in a collection whose label contains `label` (which is lowercase), ignoring
+/// case, and which have every attribute in `patterns` with a value matching its wildcard pattern
+fn find_in_collection(label: &str, patterns: &HashMap<String, String>, db: &Database) -> Result<Vec<String>> {
+    let tx = db.begin_read().into_result()?;
+    let attributes_reverse = raise_nonexistent_table!(tx.open_table(ATTRIBUTES_TABLE_REVERSE), Ok(vec![]));
+    let labels = if label.is_empty() {
+        None
+    } else {
+        Some(raise_nonexistent_table!(tx.open_table(LABELS_TABLE), Ok(vec![])))
+    };
+
+    let mut found = vec![];
+    for entry in attributes_reverse.iter().into_result()? {
+        deadline::check()?;
+        let (secret_id, attrs) = entry.into_result()?;
+        let attrs = attrs.value();
+        let matches = patterns.iter().all(|(key, pattern)| {
+            attrs
+                .get(key.as_str())
+                .is_some_and(|value| wildcard_matches(pattern, value))
+        });
+        if !matches {
+            continue;
+        }
+        if let Some(labels) = &labels {
+            let Some(secret_label) = labels.get(secret_id.value()).into_result()? else {
+                continue;
+            };
+            if !secret_label.value().to_lowercase().contains(label) {
+                continue;
+            }
+        }
+        found.push(secret_id.value().to_owned());
+    }
+    Ok(found)
+}
+
+/// call `visit` with each secret in a collection which has all the given attributes,
+/// either itself or in one of its views, until it returns `ControlFlow::Break`
+fn visit_matches(
+    attrs: &HashMap<String, String>,
+    tx: &ReadTransaction,
+    mut visit: impl FnMut(&str) -> ControlFlow<()>,
+) -> Result {
+    let mut attr_iter = attrs.iter();
+    let Some((key, value)) = attr_iter.next() else {
+        return Ok(());
+    };
+
+    let attributes_reverse = raise_nonexistent_table!(tx.open_table(ATTRIBUTES_TABLE_REVERSE), Ok(()));
+    let views = match tx.open_multimap_table(VIEW_ATTRIBUTES_TABLE) {
+        Ok(view_attributes) => Some((view_attributes, tx.open_table(VIEWS_TABLE).into_result()?)),
+        Err(redb::TableError::TableDoesNotExist(_)) => None,
+        Err(e) => return Err(e).into_result(),
+    };
+    // only needed to skip secrets found by their own attributes and a view
+    let mut visited = HashSet::new();
+    let mut found = |secret_id: &str| {
+        if views.is_some() {
+            visited.insert(secret_id.to_owned());
+        }
+        visit(secret_id)
+    };
+
+    if reads_index(tx).into_result()? {
+        let attributes = raise_nonexistent_table!(tx.open_multimap_table(ATTRIBUTES_TABLE), Ok(()));
+        // get the secrets which fit the first K/V attr pair, and filter the items from there
+        for secret_id_guard in attributes.get((key.as_str(), value.as_str())).into_result()? {
+            deadline::check()?;
+            let secret_id_guard = secret_id_guard.into_result()?;
+            let secret_id = secret_id_guard.value();
+            // get the attributes for this secret
+            let Some(secret_attrs) = attributes_reverse.get(secret_id).into_result()? else {
+                continue;
             };
+            // make sure it's a subset of the remaining `attrs`
+            if has_attributes(&secret_attrs.value(), attr_iter.clone()) && found(secret_id).is_break() {
+                return Ok(());
+            }
+        }
+    } else {
+        // no index, so check every secret
+        for entry in attributes_reverse.iter().into_result()? {
+            deadline::check()?;
+            let (secret_id, secret_attrs) = entry.into_result()?;
+            if has_attributes(&secret_attrs.value(), attrs.iter()) && found(secret_id.value()).is_break() {
+                return Ok(());
+            }
+        }
+    }
+
+    let Some((view_attributes, views)) = views else {
+        return Ok(());
+    };
+    for view_key in view_attributes.get((key.as_str(), value.as_str())).into_result()? {
+        let view_key = view_key.into_result()?;
+        let (secret_id, index) = view_key.value();
+        if visited.contains(secret_id) {
+            continue;
+        }
+        let Some(view) = views.get((secret_id, index)).into_result()? else {
+            continue;
+        };
+        if has_attributes(&view.value(), attr_iter.clone()) {
+            visited.insert(secret_id.to_owned());
+            if visit(secret_id).is_break() {
+                break;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// remove a secret's views, or only the one with the given attributes
+/// returns whether any were removed
+fn remove_views(
+    tx: &WriteTransaction,
+    secret_id: &str,
+    only: Option<&HashMap<&str, &str>>,
+) -> RedbResult<bool> {
+    let mut views = tx.open_table(VIEWS_TABLE)?;
+    let mut view_attributes = tx.open_multimap_table(VIEW_ATTRIBUTES_TABLE)?;
+
+    let mut removed = vec![];
+    for entry in views.range((secret_id, 0)..=(secret_id, u32::MAX))? {
+        let (key, view) = entry?;
+        let view = view.value();
+        if only.is_none_or(|only| *only == view) {
+            let view = view
+                .into_iter()
+                .map(|(k, v)| (k.to_owned(), v.to_owned()))
+                .collect::<Vec<_>>();
+            removed.push((key.value().1, view));
+        }
+    }
+
+    for (index, view) in &removed {
+        views.remove((secret_id, *index))?;
+        for (k, v) in view {
+            view_attributes.remove((k.as_str(), v.as_str()), (secret_id, *index))?;
+        }
+    }
+    Ok(!removed.is_empty())
+}
+
+/// remember that a secret was called `label` until `time`
+/// the oldest labels are forgotten once there are too many
+fn record_old_label(tx: &WriteTransaction, secret_id: &str, label: &str, time: u64) -> RedbResult<()> {
+    let mut history = tx.open_table(LABEL_HISTORY_TABLE)?;
+    let mut indexes = vec![];
+    for entry in history.range((secret_id, 0)..=(secret_id, u32::MAX))? {
+        indexes.push(entry?.0.value().1);
+    }
+
+    let next = indexes.last().map_or(0, |index| index + 1);
+    history.insert((secret_id, next), (time, label))?;
+    let excess = (indexes.len() + 1).saturating_sub(LABEL_HISTORY_LIMIT);
+    for index in &indexes[..excess] {
+        history.remove((secret_id, *index))?;
+    }
+    Ok(())
+}
+
+/// search a collection for the given attributes
+/// returns a vec of secret IDs
+pub fn search_collection(attrs: &HashMap<String, String>, db: &Database) -> Result<Vec<String>> {
+    let tx = db.begin_read().into_result()?;
+    let mut secrets = vec![];
+    visit_matches(attrs, &tx, |secret_id| {
+        secrets.push(secret_id.to_owned());
+        ControlFlow::Continue(())
+    })?;
+    Ok(secrets)
+}
+
+/// like `search_collection`, with each secret's label and attributes, all read in one transaction
+fn search_collection_with_info(attrs: &HashMap<String, String>, db: &Database) -> Result<Vec<SecretInfo>> {
+    let tx = db.begin_read().into_result()?;
+    let mut secrets = vec![];
+    visit_matches(attrs, &tx, |secret_id| {
+        secrets.push(secret_id.to_owned());
+        ControlFlow::Continue(())
+    })?;
+    if secrets.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let labels = raise_nonexistent_table!(tx.open_table(LABELS_TABLE), Ok(vec![]));
+    let attributes_reverse = raise_nonexistent_table!(tx.open_table(ATTRIBUTES_TABLE_REVERSE), Ok(vec![]));
+    secrets
+        .into_iter()
+        .map(|secret_id| {
+            let label = labels
+                .get(secret_id.as_str())
+                .into_result()?
+                .map(|label| label.value().to_owned())
+                .unwrap_or_default();
+            let attributes = attributes_reverse
+                .get(secret_id.as_str())
+                .into_result()?
+                .map(|attrs| {
+                    attrs
+                        .value()
+                        .into_iter()
+                        .map(|(k, v)| (k.to_owned(), v.to_owned()))
+                        .collect()
+                })
+                .unwrap_or_default();
+            Ok((secret_id, label, attributes))
+        })
+        .collect()
+}
+
+/// count the secrets in a collection which have all the given attributes, up to `limit`
+fn count_matches(attrs: &HashMap<String, String>, db: &Database, limit: u64) -> Result<u64> {
+    let mut count = 0;
+    if limit > 0 {
+        let tx = db.begin_read().into_result()?;
+        visit_matches(attrs, &tx, |_| {
+            count += 1;
+            if count < limit {
+                ControlFlow::Continue(())
+            } else {
+                ControlFlow::Break(())
+            }
+        })?;
+    }
+    Ok(count)
+}
+
+/// add `delta` to the item count of the collection this transaction is for
+/// the reverse attributes table must not be open in this transaction
+fn adjust_item_count(tx: &WriteTransaction, delta: i64) -> RedbResult<()> {
+    let mut metadata = tx.open_table(METADATA_TABLE)?;
+    let count = match metadata.get(ITEM_COUNT_KEY)? {
+        Some(count) => count.value(),
+        // collections created before we kept a count
+        // this runs before the reverse table is modified, so it's the old count
+        None => tx.open_table(ATTRIBUTES_TABLE_REVERSE)?.len()?,
+    };
+    metadata.insert(ITEM_COUNT_KEY, count.saturating_add_signed(delta))?;
+    Ok(())
+}
+
+/// append a table's entries to `out`, one per line. `hide_values` is for keys and hashes
+fn dump_table<K, V>(
+    tx: &ReadTransaction,
+    definition: TableDefinition<K, V>,
+    hide_values: bool,
+    out: &mut impl Write,
+) -> Result
+where
+    K: Key + 'static,
+    V: Value + 'static,
+    for<'a> K::SelfType<'a>: Debug,
+    for<'a> V::SelfType<'a>: Debug,
+{
+    let table = raise_nonexistent_table!(tx.open_table(definition), Ok(()));
+    writeln!(out, "{} ({} entries)", definition.name(), table.len().into_result()?)?;
+    for entry in table.iter().into_result()? {
+        let (key, value) = entry.into_result()?;
+        if hide_values {
+            writeln!(out, "  {:?}: <hidden>", key.value())?;
+        } else {
+            writeln!(out, "  {:?}: {:?}", key.value(), value.value())?;
+        }
+    }
+    writeln!(out)?;
+    Ok(())
+}
+
+fn dump_multimap_table<K, V>(
+    tx: &ReadTransaction,
+    definition: MultimapTableDefinition<K, V>,
+    out: &mut impl Write,
+) -> Result
+where
+    K: Key + 'static,
+    V: Key + 'static,
+    for<'a> K::SelfType<'a>: Debug,
+    for<'a> V::SelfType<'a>: Debug,
+{
+    let table = raise_nonexistent_table!(tx.open_multimap_table(definition), Ok(()));
+    writeln!(out, "{} ({} entries)", definition.name(), table.len().into_result()?)?;
+    for entry in table.iter().into_result()? {
+        let (key, values) = entry.into_result()?;
+        let values = values
+            .map(|value| Ok(format!("{:?}", value?.value())))
+            .collect::<RedbResult<Vec<_>>>()?;
+        writeln!(out, "  {:?}: [{}]", key.value(), values.join(", "))?;
+    }
+    writeln!(out)?;
+    Ok(())
+}
+
+/// run `write`, then check with `read` that `value` was stored, retrying once
+async fn verify_write<W, R>(
+    name: &str,
+    value: &[u8],
+    mut write: impl FnMut() -> W,
+    mut read: impl FnMut() -> R,
+) -> Result
+where
+    W: Future<Output = Result>,
+    R: Future<Output = Result<Vec<u8>>>,
+{
+    for attempt in 1..=2 {
+        write().await?;
+
+        match read().await {
+            Ok(written) if written == value => return Ok(()),
+            Ok(_) => warn!("Secret {name} did not match after writing it (attempt {attempt})"),
+            Err(e) => warn!("Could not read back secret {name} (attempt {attempt}): {e}"),
+        }
+    }
+
+    Err(Error::WriteVerificationFailed)
+}
+
+/// print every table of a collections.redb or attributes.redb, for debugging
+/// the file is copied into memory first, so this works while the daemon has it open, never
+/// changes it, and leaves no copy of the metadata behind
+pub fn dump_db(path: &Path, out: &mut impl Write) -> Result {
+    use redb::{backends::InMemoryBackend, StorageBackend};
+
+    let contents = std::fs::read(path)?;
+    let copy = InMemoryBackend::new();
+    copy.set_len(contents.len() as u64)?;
+    copy.write(0, &contents)?;
+    let db = Database::builder().create_with_backend(copy).into_result()?;
+    dump_tables(&db, out)
+}
+
+fn dump_tables(db: &Database, out: &mut impl Write) -> Result {
+    let tx = db.begin_read().into_result()?;
+
+    // collections.redb
+    dump_table(&tx, LABELS_TABLE, false, out)?;
+    dump_table(&tx, ALIASES_TABLE, false, out)?;
+    dump_multimap_table(&tx, ALIASES_TABLE_REVERSE, out)?;
+    dump_table(&tx, SORT_ORDER_TABLE, false, out)?;
+    dump_table(&tx, COLORS_TABLE, false, out)?;
+    dump_table(&tx, KNOWN_CLIENTS_TABLE, false, out)?;
+    dump_table(&tx, KEYS_TABLE, true, out)?;
+
+    // attributes.redb
+    dump_table(&tx, METADATA_TABLE, false, out)?;
+    dump_multimap_table(&tx, ATTRIBUTES_TABLE, out)?;
+    dump_table(&tx, ATTRIBUTES_TABLE_REVERSE, false, out)?;
+    dump_table(&tx, VIEWS_TABLE, false, out)?;
+    dump_multimap_table(&tx, VIEW_ATTRIBUTES_TABLE, out)?;
+    dump_table(&tx, CHECKSUMS_TABLE, true, out)?;
+    dump_table(&tx, CONTENT_TYPES_TABLE, false, out)?;
+    dump_table(&tx, ITEM_PINS_TABLE, true, out)?;
+    dump_table(&tx, LAST_ACCESS_TABLE, false, out)?;
+    dump_table(&tx, LABEL_HISTORY_TABLE, false, out)?;
+    dump_table(&tx, TRASH_TABLE, false, out)?;
+
+    // e.g. from a newer version
+    let known = [
+        LABELS_TABLE.name(),
+        ALIASES_TABLE.name(),
+        SORT_ORDER_TABLE.name(),
+        COLORS_TABLE.name(),
+        KNOWN_CLIENTS_TABLE.name(),
+        KEYS_TABLE.name(),
+        METADATA_TABLE.name(),
+        ATTRIBUTES_TABLE_REVERSE.name(),
+        VIEWS_TABLE.name(),
+        CHECKSUMS_TABLE.name(),
+        CONTENT_TYPES_TABLE.name(),
+        ITEM_PINS_TABLE.name(),
+        LAST_ACCESS_TABLE.name(),
+        LABEL_HISTORY_TABLE.name(),
+        TRASH_TABLE.name(),
+    ];
+    let known_multimap = [
+        ALIASES_TABLE_REVERSE.name(),
+        ATTRIBUTES_TABLE.name(),
+        VIEW_ATTRIBUTES_TABLE.name(),
+    ];
+    for table in tx.list_tables().into_result()? {
+        if !known.contains(&table.name()) {
+            writeln!(out, "{} (unknown table)", table.name())?;
+        }
+    }
+    for table in tx.list_multimap_tables().into_result()? {
+        if !known_multimap.contains(&table.name()) {
+            writeln!(out, "{} (unknown multimap table)", table.name())?;
+        }
+    }
+    Ok(())
+}
+
+/// a secret's file, relative to the password store
+fn secret_file_name(collection_id: &str, secret_id: &str) -> PathBuf {
+    Path::new(PASS_SUBDIR)
+        .join(collection_id)
+        .join(format!("{secret_id}.gpg"))
+}
+
+/// where a secret's file is kept while it's in the trash, relative to the password store
+fn trash_path(collection_id: &str, secret_id: &str) -> PathBuf {
+    Path::new(PASS_SUBDIR)
+        .join(collection_id)
+        .join(TRASH_DIR)
+        .join(secret_id)
+}
+
+/// remove a secret from a collection's db
+fn remove_secret_entries(tx: &WriteTransaction, secret_id: &str) -> Result {
+    // before the reverse table is modified, so collections without a count are counted right
+    adjust_item_count(tx, -1).into_result()?;
+    let mut attributes_table = open_index(tx).into_result()?;
+    let mut attributes_table_reverse = tx.open_table(ATTRIBUTES_TABLE_REVERSE).into_result()?;
+
+    // get the attrs for this secret
+    let attrs_guard = attributes_table_reverse
+        .remove(secret_id)
+        .into_result()?
+        .into_not_found()?;
+    if let Some(attributes_table) = &mut attributes_table {
+        for (k, v) in attrs_guard.value() {
+            attributes_table.remove((k, v), secret_id).into_result()?;
+        }
+    }
+
+    drop(attributes_table);
+    drop(attrs_guard);
+    drop(attributes_table_reverse);
+    remove_secret_details(tx, secret_id)?;
+    remove_views(tx, secret_id, None).into_result()?;
+    Ok(())
+}
+
+/// remove what's kept about a secret besides its attributes
+fn remove_secret_details(tx: &WriteTransaction, secret_id: &str) -> Result {
+    tx.open_table(CHECKSUMS_TABLE)
+        .into_result()?
+        .remove(secret_id)
+        .into_result()?;
+    tx.open_table(CONTENT_TYPES_TABLE)
+        .into_result()?
+        .remove(secret_id)
+        .into_result()?;
+    tx.open_table(ITEM_PINS_TABLE)
+        .into_result()?
+        .remove(secret_id)
+        .into_result()?;
+    tx.open_table(LAST_ACCESS_TABLE)
+        .into_result()?
+        .remove(secret_id)
+        .into_result()?;
+    tx.open_table(LABEL_HISTORY_TABLE)
+        .into_result()?
+        .retain_in((secret_id, 0)..=(secret_id, u32::MAX), |_, _| false)
+        .into_result()?;
+    Ok(())
+}
+
+/// move a value in a table keyed by secret id to another id
+fn move_entry<V: Value + 'static>(
+    tx: &WriteTransaction,
+    definition: TableDefinition<&str, V>,
+    from: &str,
+    to: &str,
+) -> Result {
+    let mut table = tx.open_table(definition).into_result()?;
+    let bytes = match table.remove(from).into_result()? {
+        Some(value) => V::as_bytes(&value.value()).as_ref().to_vec(),
+        None => return Ok(()),
+    };
+    table.insert(to, V::from_bytes(&bytes)).into_result()?;
+    Ok(())
+}
+
+/// give a secret's entries a new id. its views are dropped, since they could only be added
+/// through its old path
+fn rename_secret_entries(tx: &WriteTransaction, from: &str, to: &str) -> Result {
+    let attrs: HashMap<String, String> = tx
+        .open_table(ATTRIBUTES_TABLE_REVERSE)
+        .into_result()?
+        .get(from)
+        .into_result()?
+        .into_not_found()?
+        .value()
+        .into_iter()
+        .map(|(k, v)| (k.to_owned(), v.to_owned()))
+        .collect();
+    // removing it first keeps the item count
+    remove_views(tx, from, None).into_result()?;
+    {
+        let mut attributes_table = open_index(tx).into_result()?;
+        tx.open_table(ATTRIBUTES_TABLE_REVERSE).into_result()?.remove(from).into_result()?;
+        if let Some(attributes_table) = &mut attributes_table {
+            for (k, v) in &attrs {
+                attributes_table.remove((k.as_str(), v.as_str()), from).into_result()?;
+            }
+        }
+    }
+    replace_attrs(tx, to, &attrs).into_result()?;
+
+    move_entry(tx, LABELS_TABLE, from, to)?;
+    move_entry(tx, CHECKSUMS_TABLE, from, to)?;
+    move_entry(tx, CONTENT_TYPES_TABLE, from, to)?;
+    move_entry(tx, ITEM_PINS_TABLE, from, to)?;
+    move_entry(tx, LAST_ACCESS_TABLE, from, to)?;
+
+    let mut history = tx.open_table(LABEL_HISTORY_TABLE).into_result()?;
+    let mut old_labels = vec![];
+    for entry in history.range((from, 0)..=(from, u32::MAX)).into_result()? {
+        let (key, value) = entry.into_result()?;
+        let (time, label) = value.value();
+        old_labels.push((key.value().1, time, label.to_owned()));
+    }
+    history.retain_in((from, 0)..=(from, u32::MAX), |_, _| false).into_result()?;
+    for (index, time, label) in &old_labels {
+        history.insert((to, *index), (*time, label.as_str())).into_result()?;
+    }
+    Ok(())
+}
+
+/// move a secret's attributes to the trash table, so searches no longer find it
+/// its views are removed, but everything else is kept for when it's restored
+fn trash_secret_entries(tx: &WriteTransaction, secret_id: &str, deleted: u64) -> Result {
+    // this has to happen before the reverse table is opened
+    adjust_item_count(tx, -1).into_result()?;
+
+    let mut attributes_table = open_index(tx).into_result()?;
+    let mut attributes_table_reverse = tx.open_table(ATTRIBUTES_TABLE_REVERSE).into_result()?;
+    let attrs_guard = attributes_table_reverse
+        .remove(secret_id)
+        .into_result()?
+        .into_not_found()?;
+    let attrs = attrs_guard.value();
+    if let Some(attributes_table) = &mut attributes_table {
+        for (k, v) in &attrs {
+            attributes_table.remove((*k, *v), secret_id).into_result()?;
+        }
+    }
+    tx.open_table(TRASH_TABLE)
+        .into_result()?
+        .insert(secret_id, (deleted, attrs))
+        .into_result()?;
+
+    drop(attributes_table);
+    drop(attrs_guard);
+    drop(attributes_table_reverse);
+    remove_views(tx, secret_id, None).into_result()?;
+    Ok(())
+}
+
+/// move a secret's attributes from the trash table back to the attribute tables
+fn restore_secret_entries(tx: &WriteTransaction, secret_id: &str) -> Result {
+    let attrs: HashMap<String, String> = tx
+        .open_table(TRASH_TABLE)
+        .into_result()?
+        .remove(secret_id)
+        .into_result()?
+        .into_not_found()?
+        .value()
+        .1
+        .into_iter()
+        .map(|(k, v)| (k.to_owned(), v.to_owned()))
+        .collect();
+
+    adjust_item_count(tx, 1).into_result()?;
+    replace_attrs(tx, secret_id, &attrs).into_result()?;
+    Ok(())
+}
+
+/// replace a secret's attributes in the attribute tables
+fn replace_attrs(tx: &WriteTransaction, secret_id: &str, attrs: &HashMap<String, String>) -> RedbResult<()> {
+    let attributes_table = open_index(tx)?;
+    let mut attributes_table_reverse = tx.open_table(ATTRIBUTES_TABLE_REVERSE)?;
+
+    let attrs_ref = attrs
+        .iter()
+        .map(|(k, v)| (k.as_str(), v.as_str()))
+        .collect::<HashMap<_, _>>();
+
+    let old_attrs = attributes_table_reverse.insert(secret_id, attrs_ref)?;
+    let Some(mut attributes_table) = attributes_table else {
+        return Ok(());
+    };
+    if let Some(old_attrs) = old_attrs {
+        // remove the old attributes
+        for (k, v) in old_attrs.value() {
+            attributes_table.remove((k, v), secret_id)?;
+        }
+    }
+
+    // insert the new attributes
+    for (k, v) in attrs {
+        attributes_table.insert((k.as_str(), v.as_str()), secret_id)?;
+    }
+    Ok(())
+}
+
+/// recalculate the item count of the collection this transaction is for
+/// the reverse attributes table must not be open in this transaction
+fn recount_items(tx: &WriteTransaction) -> RedbResult<()> {
+    let count = tx.open_table(ATTRIBUTES_TABLE_REVERSE)?.len()?;
+    tx.open_table(METADATA_TABLE)?.insert(ITEM_COUNT_KEY, count)?;
+    Ok(())
+}
+
+/// the keys of a table keyed by secret id
+fn secret_ids<V: Value + 'static>(tx: &WriteTransaction, definition: TableDefinition<&str, V>) -> Result<Vec<String>> {
+    let table = tx.open_table(definition).into_result()?;
+    let mut ids = vec![];
+    for entry in table.iter().into_result()? {
+        ids.push(entry.into_result()?.0.value().to_owned());
+    }
+    Ok(ids)
+}
+
+/// remove the entries of the collection this transaction is for which aren't in `keep`,
+/// and rebuild its indexes from the reverse attributes table
+/// returns the ids which were removed
+fn repair_entries(tx: &WriteTransaction, keep: impl Fn(&str) -> bool) -> Result<Vec<String>> {
+    let mut orphans = secret_ids(tx, ATTRIBUTES_TABLE_REVERSE)?;
+    orphans.retain(|secret_id| !keep(secret_id));
+    for secret_id in &orphans {
+        remove_secret_entries(tx, secret_id)?;
+    }
+
+    let mut known: HashSet<_> = secret_ids(tx, ATTRIBUTES_TABLE_REVERSE)?.into_iter().collect();
+    known.extend(secret_ids(tx, TRASH_TABLE)?);
+
+    // details left behind by secrets which are gone
+    let mut stale: HashSet<_> = secret_ids(tx, CHECKSUMS_TABLE)?.into_iter().collect();
+    stale.extend(secret_ids(tx, CONTENT_TYPES_TABLE)?);
+    stale.extend(secret_ids(tx, ITEM_PINS_TABLE)?);
+    stale.extend(secret_ids(tx, LAST_ACCESS_TABLE)?);
+    for entry in tx.open_table(LABEL_HISTORY_TABLE).into_result()?.iter().into_result()? {
+        stale.insert(entry.into_result()?.0.value().0.to_owned());
+    }
+    for secret_id in stale.difference(&known) {
+        remove_secret_details(tx, secret_id)?;
+    }
+
+    // rebuild the (key, value) --> secrets tables from scratch
+    let indexed = is_indexed(&tx.open_table(METADATA_TABLE).into_result()?).into_result()?;
+    tx.delete_multimap_table(ATTRIBUTES_TABLE).into_result()?;
+    if indexed {
+        let attributes_reverse = tx.open_table(ATTRIBUTES_TABLE_REVERSE).into_result()?;
+        let mut attributes = tx.open_multimap_table(ATTRIBUTES_TABLE).into_result()?;
+        for entry in attributes_reverse.iter().into_result()? {
+            let (secret_id, attrs) = entry.into_result()?;
+            for (k, v) in attrs.value() {
+                attributes.insert((k, v), secret_id.value()).into_result()?;
+            }
+        }
+    }
+
+    let mut views = tx.open_table(VIEWS_TABLE).into_result()?;
+    views
+        .retain(|(secret_id, _), _| known.contains(secret_id))
+        .into_result()?;
+    tx.delete_multimap_table(VIEW_ATTRIBUTES_TABLE).into_result()?;
+    let mut view_attributes = tx.open_multimap_table(VIEW_ATTRIBUTES_TABLE).into_result()?;
+    for entry in views.iter().into_result()? {
+        let (key, view) = entry.into_result()?;
+        for (k, v) in view.value() {
+            view_attributes.insert((k, v), key.value()).into_result()?;
+        }
+    }
+    drop(views);
+    drop(view_attributes);
+
+    recount_items(tx).into_result()?;
+    Ok(orphans)
+}
+
+/// whether a collection keeps the (key, value) --> secrets table, given its metadata
+fn is_indexed(metadata: &impl ReadableTable<&'static str, u64>) -> RedbResult<bool> {
+    Ok(metadata.get(INDEXED_KEY)?.is_none_or(|indexed| indexed.value() != 0))
+}
+
+/// whether searches in the collection this transaction is for can use the index
+fn reads_index(tx: &ReadTransaction) -> RedbResult<bool> {
+    match tx.open_table(METADATA_TABLE) {
+        Ok(metadata) => is_indexed(&metadata),
+        Err(redb::TableError::TableDoesNotExist(_)) => Ok(true),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// open the (key, value) --> secrets table, if the collection this transaction is for keeps it
+/// the metadata table must not be open in this transaction
+fn open_index(tx: &WriteTransaction) -> RedbResult<Option<IndexTable<'_>>> {
+    if !is_indexed(&tx.open_table(METADATA_TABLE)?)? {
+        return Ok(None);
+    }
+    Ok(Some(tx.open_multimap_table(ATTRIBUTES_TABLE)?))
+}
+
+/// build or drop a collection's index so it matches `mode`
+/// returns whether it's kept now, if that changed
+fn apply_index_mode(db: &Database, mode: IndexMode) -> RedbResult<Option<bool>> {
+    let tx = db.begin_write()?;
+    let mut metadata = tx.open_table(METADATA_TABLE)?;
+    let attributes_reverse = tx.open_table(ATTRIBUTES_TABLE_REVERSE)?;
+    let indexed = is_indexed(&metadata)?;
+    let count = attributes_reverse.len()?;
+    let wanted = match mode {
+        IndexMode::Always => true,
+        IndexMode::Never => false,
+        IndexMode::Auto if indexed => count >= INDEX_MIN_ITEMS / 2,
+        IndexMode::Auto => count >= INDEX_MIN_ITEMS,
+    };
+    if wanted == indexed {
+        return Ok(None);
+    }
+
+    // start from scratch either way, in case an old index was left behind
+    tx.delete_multimap_table(ATTRIBUTES_TABLE)?;
+    if wanted {
+        let mut attributes = tx.open_multimap_table(ATTRIBUTES_TABLE)?;
+        for entry in attributes_reverse.iter()? {
+            let (secret_id, attrs) = entry?;
+            for (k, v) in attrs.value() {
+                attributes.insert((k, v), secret_id.value())?;
+            }
+        }
+    }
+    metadata.insert(INDEXED_KEY, wanted as u64)?;
+
+    drop(metadata);
+    drop(attributes_reverse);
+    tx.commit()?;
+    Ok(Some(wanted))
+}
+
+/// an id for a new secret derived from its attributes, or its label if it has none, so an
+/// item created again with the same ones gets the same id (and object path) on every machine.
+/// items alike get the next id in the sequence, skipping the ones in `taken`
+fn stable_secret_id(label: &str, attributes: &HashMap<String, String>, taken: &HashSet<String>) -> String {
+    let mut hasher = blake3::Hasher::new();
+    if attributes.is_empty() {
+        hasher.update(b"label\0").update(label.as_bytes());
+    } else {
+        let mut attributes = attributes.iter().collect::<Vec<_>>();
+        attributes.sort();
+        for (key, value) in attributes {
+            // D-Bus strings can't contain NUL
+            hasher.update(key.as_bytes()).update(b"\0").update(value.as_bytes()).update(b"\0");
+        }
+    }
+
+    let mut output = hasher.finalize_xof();
+    loop {
+        let mut bytes = [0; 8];
+        output.fill(&mut bytes);
+        let id = bytes
+            .iter()
+            .map(|byte| NANOID_ALPHABET[*byte as usize % NANOID_ALPHABET.len()])
+            .collect::<String>();
+        if !taken.contains(&id) {
+            return id;
+        }
+    }
+}
+
+/// the result of checking a collection's secrets against its `.gpg-id`
+#[derive(Debug)]
+pub struct RecipientCheck {
+    pub collection_id: String,
+    /// number of secrets that were checked
+    pub checked: u32,
+    /// number of secrets encrypted to keys other than the current recipients
+    pub stale: u32,
+    /// false if the collection has no `.gpg-id`, so nothing was checked
+    pub initialized: bool,
+}
+
+/// how many secrets are encrypted to a key
+#[derive(Debug)]
+pub struct KeyUsage {
+    pub key_id: String,
+    pub items: u32,
+    /// None if the key isn't in the keyring
+    pub key: Option<KeyInfo>,
+}
+
+/// where the values of a collection's secrets are kept
+enum Backend<'s> {
+    Pass(&'s PasswordStore),
+    /// the password store's own entries, which are read-only
+    Tree(&'s PasswordStore),
+    Passthrough(&'s PassthroughStore),
+    #[cfg(feature = "vault")]
+    Vault(&'s VaultStore, &'s VaultLocation),
+}
+
+/// how many changes are kept for subscribers which haven't seen them yet
+const CHANGES_CAPACITY: usize = 256;
+
+/// a change to a collection or secret
+#[derive(Clone, Debug)]
+pub struct StoreChange {
+    pub collection_id: String,
+    /// None if the collection itself changed
+    pub secret_id: Option<String>,
+    pub kind: ChangeKind,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum ChangeKind {
+    Created,
+    Changed,
+    Deleted,
+}
+
+impl ChangeKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ChangeKind::Created => "created",
+            ChangeKind::Changed => "changed",
+            ChangeKind::Deleted => "deleted",
+        }
+    }
+}
+
+/// held while a secret is written
+type WriteLock = Arc<Mutex<()>>;
+
+/// when a secret was created and last modified, if known
+pub type SecretTimes = (Option<SystemTime>, Option<SystemTime>);
+
+#[derive(Debug, Clone)]
+pub struct SecretStore<'a> {
+    pass: &'a PasswordStore,
+    /// where the dbs are kept, if that's not in the password store
+    metadata: Option<Arc<PasswordStore>>,
+    pub config: &'a Config,
+    /// only locked while a handle is looked up, so a long transaction in one collection doesn't
+    /// hold up the others, or adding and removing collections
+    collection_dbs: Arc<RwLock<HashMap<String, Arc<Database>>>>,
+    /// collections.redb. replaced if its file is, see `reload`
+    db: Arc<SyncRwLock<Arc<Database>>>,
+    /// only set if checksums are enabled
+    checksum_key: Option<[u8; blake3::KEY_LEN]>,
+    /// only set if the write queue is enabled
+    write_queue: Option<Arc<WriteQueue>>,
+    /// only set if the secret cache is enabled
+    secret_cache: Option<Arc<SecretCache>>,
+    /// ids of locked collections
+    locked: Arc<SyncRwLock<HashSet<String>>>,
+    /// the items clients can see in each collection: ones which are stored and on the object
+    /// server. Items and SearchItems both read it, so they agree while items are added and removed
+    item_view: Arc<SyncRwLock<HashMap<String, HashSet<String>>>>,
+    alias_lock: Arc<Mutex<()>>,
+    /// held while a secret with a stable id is created, so two alike can't get the same one
+    stable_id_lock: Arc<Mutex<()>>,
+    /// held while each secret is written, so a rotation can't overwrite a newer value
+    write_locks: Arc<SyncMutex<HashMap<(String, String), WriteLock>>>,
+    passthrough: Arc<Vec<PassthroughStore>>,
+    mirrors: Arc<Vec<Mirror>>,
+    /// id of the collection serving the rest of the password store, if it's enabled
+    pass_tree: Option<Arc<String>>,
+    changes: broadcast::Sender<StoreChange>,
+    /// ids of collections whose directory was found to be gone, so they're forgotten
+    lost_collections: broadcast::Sender<String>,
+    /// when reads from each high-security collection were last confirmed
+    /// held while asking, so only one confirmation shows at a time
+    confirmed_reads: Arc<Mutex<HashMap<String, Instant>>>,
+    /// when gpg-agent will next be told to forget each keygrip's passphrase
+    passphrase_clears: Arc<SyncMutex<HashMap<String, Instant>>>,
+    /// only set if some collections are kept in vault
+    #[cfg(feature = "vault")]
+    vault: Option<Arc<VaultStore>>,
+}
+
+impl<'a> SecretStore<'a> {
+    pub async fn new(pass: &'a PasswordStore, config: &'a Config) -> Result<Self> {
+        let metadata = match config.metadata_dir() {
+            Some(directory) => {
+                let mut metadata = pass.in_directory(directory.clone());
+                // it's not a password store, so pass can't manage it
+                metadata.use_cli = false;
+                let moved = move_metadata(pass, &metadata).await?;
+                if moved > 0 {
+                    info!("Moved {moved} database(s) to {}", directory.display());
+                }
+                Some(Arc::new(metadata))
+            }
+            None => None,
+        };
+        let metadata_store = metadata.as_deref().unwrap_or(pass);
+        if let Some(filesystem) = network_filesystem(&metadata_store.directory) {
+            error!(
+                "The databases in {} are on {filesystem}, where locking isn't reliable and they can be corrupted. {}",
+                metadata_store.directory.display(),
+                if metadata.is_some() {
+                    "Set metadata-dir to a local directory"
+                } else {
+                    "Pass --metadata-in-tmpfs (or set metadata-in-tmpfs = true) to keep them in a local directory"
+                }
+            );
+        }
+
+        let collections = Self::get_current_collections(metadata_store).await?;
+        for (id, db) in &collections {
+            match apply_index_mode(db, config.attribute_index).into_result()? {
+                Some(true) => info!("Built the attribute index for collection {id}"),
+                Some(false) => info!("Dropped the attribute index for collection {id}; searches will check each secret"),
+                None => {}
+            }
+        }
+
+        let db = open_db(metadata_store, Path::new(PASS_SUBDIR).join(COLLECTIONS_DB)).await?;
+
+        let checksum_key = if config.checksums {
+            Some(Self::get_checksum_key(&db)?)
+        } else {
+            None
+        };
+
+        if config.write_queue.enabled && pass.use_cli {
+            warn!("The write queue is disabled when using the pass CLI");
+        }
+        let write_queue = match config.write_queue.spool_dir() {
+            Some(spool_dir) if config.write_queue.enabled && !pass.use_cli => Some(Arc::new(
+                WriteQueue::open(spool_dir, config.write_queue.max_pending).await?,
+            )),
+            _ => None,
+        };
+        let secret_cache = (config.secret_cache.ttl_seconds > 0).then(|| {
+            Arc::new(SecretCache::new(
+                Duration::from_secs(config.secret_cache.ttl_seconds),
+                config.secret_cache.max_entries,
+            ))
+        });
+
+        let passthrough = if config.passthrough.is_empty() {
+            vec![]
+        } else {
+            let connection = Connection::session().await?;
+            config
+                .passthrough
+                .iter()
+                .map(|rule| PassthroughStore::new(connection.clone(), rule))
+                .collect::<Result<_>>()?
+        };
+
+        #[cfg(feature = "vault")]
+        let vault = match &config.vault {
+            Some(vault) => Some(Arc::new(VaultStore::from_config(vault)?)),
+            None => None,
+        };
+
+        let mut store = Self {
+            pass,
+            metadata,
+            config,
+            collection_dbs: Arc::new(RwLock::new(collections)),
+            db: Arc::new(SyncRwLock::new(Arc::new(db))),
+            checksum_key,
+            write_queue,
+            secret_cache,
+            locked: Default::default(),
+            item_view: Default::default(),
+            alias_lock: Default::default(),
+            stable_id_lock: Default::default(),
+            write_locks: Default::default(),
+            passthrough: Arc::new(passthrough),
+            mirrors: Arc::new(config.mirror.iter().map(|mirror| Mirror::new(pass, mirror)).collect()),
+            pass_tree: None,
+            changes: broadcast::channel(CHANGES_CAPACITY).0,
+            lost_collections: broadcast::channel(CHANGES_CAPACITY).0,
+            confirmed_reads: Default::default(),
+            passphrase_clears: Default::default(),
+            #[cfg(feature = "vault")]
+            vault,
+        };
+
+        store.migrate_legacy_db().await?;
+        store.pass_tree = store.open_pass_tree().await?.map(Arc::new);
+
+        Ok(store)
+    }
+
+    /// get the key used to hash secret values, generating it if necessary
+    /// a random key means checksums can't be compared with hashes of guessed values
+    fn get_checksum_key(db: &Database) -> Result<[u8; blake3::KEY_LEN]> {
+        let tx = db.begin_write().into_result()?;
+        let mut table = tx.open_table(KEYS_TABLE).into_result()?;
+        let existing = table
+            .get(CHECKSUM_KEY)
+            .into_result()?
+            .and_then(|key| key.value().try_into().ok());
+        let key = match existing {
+            Some(key) => key,
+            None => {
+                let key: [u8; blake3::KEY_LEN] = rand::random();
+                table.insert(CHECKSUM_KEY, key.as_slice()).into_result()?;
+                key
+            }
+        };
+        drop(table);
+        tx.commit().into_result()?;
+        Ok(key)
+    }
+
+    /// hash a secret value, if checksums are enabled
+    fn checksum(&self, value: &[u8]) -> Option<blake3::Hash> {
+        self.checksum_key
+            .as_ref()
+            .map(|key| blake3::keyed_hash(key, value))
+    }
+
+    /// the stored high-security PIN, if one is set
+    async fn stored_pin(&self) -> Result<Option<Vec<u8>>> {
+        let db = self.main_db();
+        run_blocking(move || {
+            let tx = db.begin_read().into_result()?;
+            let table = raise_nonexistent_table!(tx.open_table(KEYS_TABLE), Ok(None));
+            let pin = table.get(PIN_KEY).into_result()?;
+            Ok(pin.map(|pin| pin.value().to_owned()))
+        })
+        .await?
+    }
+
+    /// ask for the PIN with pinentry, allowing three attempts
+    /// returns whether the right PIN was entered
+    async fn check_pin(&self, stored: &[u8], description: &str) -> Result<bool> {
+        let pinentry = self.config.high_security.pinentry();
+        let mut error = None;
+        for _ in 0..3 {
+            let Some(pin) = get_pin(pinentry, description, "PIN:", error).await? else {
+                return Ok(false);
+            };
+            if self.collection_pin_matches(stored, pin).await? {
+                return Ok(true);
+            }
+            error = Some("Wrong PIN");
+        }
+        Ok(false)
+    }
+
+    /// check a PIN against the stored high-security PIN. one stored by older versions is
+    /// replaced with an argon2id hash once the right PIN is entered
+    async fn collection_pin_matches(&self, stored: &[u8], pin: String) -> Result<bool> {
+        match std::str::from_utf8(stored) {
+            Ok(stored) if stored.starts_with('$') => {
+                // argon2 is slow on purpose
+                let stored = stored.to_owned();
+                run_blocking(move || pin_matches(&stored, &pin)).await
+            }
+            _ => {
+                if !legacy_pin_matches(stored, &pin) {
+                    return Ok(false);
+                }
+                self.store_pin(pin).await?;
+                info!("Replaced the old hash of the high-security PIN with an argon2id one");
+                Ok(true)
+            }
+        }
+    }
+
+    /// hash and store the high-security PIN
+    async fn store_pin(&self, pin: String) -> Result {
+        let db = self.main_db();
+        run_blocking(move || -> RedbResult<_> {
+            let hash = hash_pin(&pin);
+            let tx = db.begin_write()?;
+            let mut table = tx.open_table(KEYS_TABLE)?;
+            table.insert(PIN_KEY, hash.as_bytes())?;
+            drop(table);
+            tx.commit()?;
+            Ok(())
+        })
+        .await??;
+        Ok(())
+    }
+
+    /// ask for a new high-security PIN with pinentry (after the current one, if it's set)
+    pub async fn set_pin(&self) -> Result {
+        if let Some(stored) = self.stored_pin().await? {
+            if !self.check_pin(&stored, "Enter the current PIN").await? {
+                return Err(Error::PermissionDenied);
+            }
+        }
+
+        let pinentry = self.config.high_security.pinentry();
+        let description = "Choose a PIN for reading high-security collections";
+        let mut error = None;
+        let pin = loop {
+            let Some(pin) = get_pin(pinentry, description, "New PIN:", error).await? else {
+                return Err(Error::PermissionDenied);
+            };
+            if pin.is_empty() {
+                error = Some("The PIN can't be empty");
+                continue;
+            }
+            let Some(repeated) = get_pin(pinentry, "Enter the PIN again", "New PIN:", None).await?
+            else {
+                return Err(Error::PermissionDenied);
+            };
+            if pin == repeated {
+                break pin;
+            }
+            error = Some("The PINs don't match");
+        };
+
+        self.store_pin(pin).await
+    }
+
+    /// ask the user to confirm that a client can read a secret from a high-security
+    /// collection. fails with `PermissionDenied` if they don't
+    pub async fn confirm_read(&self, collection_id: &str) -> Result {
+        let config = &self.config.high_security;
+        if config.collections.is_empty() {
+            return Ok(());
+        }
+        let collection_id = Arc::new(collection_id.to_owned());
+        let aliases = self.list_aliases_for_collection(collection_id.clone()).await?;
+        if !matches_collection(&config.collections, &collection_id, &aliases) {
+            return Ok(());
+        }
+
+        let mut confirmed_reads = self.confirmed_reads.lock().await;
+        let grace = Duration::from_secs(config.grace_seconds);
+        if confirmed_reads
+            .get(&*collection_id)
+            .is_some_and(|confirmed| confirmed.elapsed() < grace)
+        {
+            return Ok(());
+        }
+
+        let confirmed = timing::phase(Phase::Prompt, async {
+            match config.method {
+                GateMethod::Pin => match self.stored_pin().await? {
+                    Some(stored) => {
+                        let label = self
+                            .get_label(collection_id.clone())
+                            .await
+                            .unwrap_or_else(|_| collection_id.to_string());
+                        let description =
+                            format!("An application wants to read a secret from \"{label}\"");
+                        self.check_pin(&stored, &description).await
+                    }
+                    None => {
+                        warn!("No PIN is set. Run `pass-secret-service set-pin` to set one");
+                        Ok(false)
+                    }
+                },
+                GateMethod::Command => gate::run_command(&config.command, &collection_id).await,
+            }
+        })
+        .await?;
+
+        if !confirmed {
+            return Err(Error::PermissionDenied);
+        }
+        confirmed_reads.insert(collection_id.to_string(), Instant::now());
+        Ok(())
+    }
+
+    /// the hash of the PIN needed to read a secret, if it has one
+    async fn item_pin(&self, collection_id: Arc<String>, secret_id: Arc<String>) -> Result<Option<String>> {
+        let db = self.collection_db(collection_id.as_str()).await?;
+        run_blocking(move || {
+            let tx = db.begin_read().into_result()?;
+            let pins = raise_nonexistent_table!(tx.open_table(ITEM_PINS_TABLE), Ok(None));
+            let pin = pins.get(secret_id.as_str()).into_result()?;
+            Ok(pin.map(|pin| pin.value().to_owned()))
+        })
+        .await?
+    }
+
+    pub async fn has_item_pin(&self, collection_id: Arc<String>, secret_id: Arc<String>) -> Result<bool> {
+        Ok(self.item_pin(collection_id, secret_id).await?.is_some())
+    }
+
+    /// ask for the PIN of a secret which has one before a client can read it
+    /// fails with `PermissionDenied` if it isn't entered
+    pub async fn confirm_item_read(&self, collection_id: &str, secret_id: &str) -> Result {
+        let collection_id = Arc::new(collection_id.to_owned());
+        let secret_id = Arc::new(secret_id.to_owned());
+        let Some(stored) = self.item_pin(collection_id.clone(), secret_id.clone()).await? else {
+            return Ok(());
+        };
+
+        // the same grace period as high-security collections
+        let key = format!("{collection_id}/{secret_id}");
+        let mut confirmed_reads = self.confirmed_reads.lock().await;
+        let grace = Duration::from_secs(self.config.high_security.grace_seconds);
+        if confirmed_reads
+            .get(&key)
+            .is_some_and(|confirmed| confirmed.elapsed() < grace)
+        {
+            return Ok(());
+        }
+
+        let label = self
+            .get_secret_label(collection_id.clone(), secret_id.clone())
+            .await
+            .unwrap_or_else(|_| secret_id.to_string());
+        let description = format!("An application wants to read \"{label}\", which needs a PIN");
+        let confirmed =
+            timing::phase(Phase::Prompt, self.check_item_pin(&stored, &description)).await?;
+
+        if !confirmed {
+            return Err(Error::PermissionDenied);
+        }
+        confirmed_reads.insert(key, Instant::now());
+        Ok(())
+    }
+
+    /// ask for an item's PIN with pinentry, allowing three attempts
+    async fn check_item_pin(&self, stored: &str, description: &str) -> Result<bool> {
+        let pinentry = self.config.high_security.pinentry();
+        let mut error = None;
+        for _ in 0..3 {
+            let Some(pin) = get_pin(pinentry, description, "PIN:", error).await? else {
+                return Ok(false);
+            };
+            // argon2 is slow on purpose
+            let stored = stored.to_owned();
+            if run_blocking(move || pin_matches(&stored, &pin)).await? {
+                return Ok(true);
+            }
+            error = Some("Wrong PIN");
+        }
+        Ok(false)
+    }
+
+    /// ask for a new PIN for a secret with pinentry (after its current one, if it has one)
+    /// an empty PIN removes it. returns whether the secret needs a PIN now
+    pub async fn set_item_pin(&self, collection_id: Arc<String>, secret_id: Arc<String>) -> Result<bool> {
+        let label = self
+            .get_secret_label(collection_id.clone(), secret_id.clone())
+            .await
+            .unwrap_or_else(|_| secret_id.to_string());
+        if let Some(stored) = self.item_pin(collection_id.clone(), secret_id.clone()).await? {
+            let description = format!("Enter the current PIN for \"{label}\"");
+            if !self.check_item_pin(&stored, &description).await? {
+                return Err(Error::PermissionDenied);
+            }
+        }
+
+        let pinentry = self.config.high_security.pinentry();
+        let description = format!("Choose a PIN for reading \"{label}\". Leave it empty to remove it");
+        let mut error = None;
+        let pin = loop {
+            let Some(pin) = get_pin(pinentry, &description, "New PIN:", error).await? else {
+                return Err(Error::PermissionDenied);
+            };
+            if pin.is_empty() {
+                break pin;
+            }
+            let Some(repeated) = get_pin(pinentry, "Enter the PIN again", "New PIN:", None).await?
+            else {
+                return Err(Error::PermissionDenied);
+            };
+            if pin == repeated {
+                break pin;
+            }
+            error = Some("The PINs don't match");
+        };
+
+        let protected = !pin.is_empty();
+        let db = self.collection_db(&collection_id).await?;
+        let secret = secret_id.clone();
+        run_blocking(move || {
+            let hash = (!pin.is_empty()).then(|| hash_pin(&pin));
+
+            let tx = db.begin_write().into_result()?;
+            let mut pins = tx.open_table(ITEM_PINS_TABLE).into_result()?;
+            match hash {
+                Some(hash) => pins.insert(secret.as_str(), hash.as_str()).into_result()?,
+                None => pins.remove(secret.as_str()).into_result()?,
+            };
+            drop(pins);
+            tx.commit().into_result()?;
+            Ok::<_, Error>(())
+        })
+        .await??;
+
+        // a new PIN has to be entered again
+        self.confirmed_reads
+            .lock()
+            .await
+            .remove(&format!("{collection_id}/{secret_id}"));
+        self.publish_change(&collection_id, Some(&secret_id), ChangeKind::Changed);
+        Ok(protected)
+    }
+
+    /// hold this while changing aliases, so they don't change between updating
+    /// the db and the object server
+    pub async fn lock_aliases(&self) -> MutexGuard<'_, ()> {
+        self.alias_lock.lock().await
+    }
+
+    /// get the changes made to collections and secrets from now on
+    pub fn subscribe_changes(&self) -> broadcast::Receiver<StoreChange> {
+        self.changes.subscribe()
+    }
+
+    /// get the ids of collections whose directory was found to be gone from now on
+    pub fn subscribe_lost_collections(&self) -> broadcast::Receiver<String> {
+        self.lost_collections.subscribe()
+    }
+
+    /// fail if a collection's directory was removed behind our back, and report it so it's
+    /// forgotten (see `subscribe_lost_collections`)
+    pub async fn ensure_collection_exists(&self, collection_id: &str) -> Result {
+        if self.collection_exists(collection_id).await? {
+            return Ok(());
+        }
+        // nobody may be listening yet
+        let _ = self.lost_collections.send(collection_id.to_owned());
+        Err(io::Error::from(io::ErrorKind::NotFound).into())
+    }
+
+    fn publish_change(&self, collection_id: &str, secret_id: Option<&str>, kind: ChangeKind) {
+        // the rest of the request builds on this change, so it's finished
+        deadline::disarm();
+        // it's fine if nothing is listening
+        let _ = self.changes.send(StoreChange {
+            collection_id: collection_id.to_owned(),
+            secret_id: secret_id.map(str::to_owned),
+            kind,
+        });
+    }
+
+    /// whether this is the collection serving the rest of the password store
+    pub fn is_read_only(&self, collection_id: &str) -> bool {
+        self.pass_tree
+            .as_deref()
+            .is_some_and(|id| id.as_str() == collection_id)
+    }
+
+    pub fn is_locked(&self, collection_id: &str) -> bool {
+        self.locked.read().unwrap().contains(collection_id)
+    }
+
+    /// show items to clients, once they're stored and on the object server
+    pub fn show_items(&self, collection_id: &str, secret_ids: impl IntoIterator<Item = String>) {
+        self.item_view
+            .write()
+            .unwrap()
+            .entry(collection_id.to_owned())
+            .or_default()
+            .extend(secret_ids);
+    }
+
+    /// hide an item from clients, before it's removed from the store and the object server
+    pub fn hide_item(&self, collection_id: &str, secret_id: &str) {
+        if let Some(items) = self.item_view.write().unwrap().get_mut(collection_id) {
+            items.remove(secret_id);
+        }
+    }
+
+    /// hide all of a collection's items, before it's removed
+    pub fn hide_collection(&self, collection_id: &str) {
+        self.item_view.write().unwrap().remove(collection_id);
+    }
+
+    /// the items clients can see in a collection
+    pub fn visible_items(&self, collection_id: &str) -> Vec<String> {
+        self.item_view
+            .read()
+            .unwrap()
+            .get(collection_id)
+            .map(|items| items.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// lock or unlock a collection
+    /// returns true if its state changed
+    pub fn set_locked(&self, collection_id: &str, locked: bool) -> bool {
+        let mut locked_collections = self.locked.write().unwrap();
+        if locked {
+            self.uncache(collection_id, None);
+            locked_collections.insert(collection_id.to_owned())
+        } else {
+            locked_collections.remove(collection_id)
+        }
+    }
+
+    /// unlock collections, decrypting one secret for each set of gpg keys so the
+    /// user is only asked for each key's passphrase once
+    /// returns the ids of the collections which were unlocked
+    pub async fn unlock_collections(&self, collection_ids: Vec<Arc<String>>) -> Result<Vec<Arc<String>>> {
+        // collections encrypted to the same keys only need one decryption
+        let mut groups = HashMap::<BTreeSet<String>, Vec<Arc<String>>>::new();
+        let mut unlocked = vec![];
+        for id in collection_ids {
+            if !matches!(self.backend(&id).await, Ok(Backend::Pass(_))) {
+                // no gpg keys involved - just check the secrets can be read
+                if self.can_decrypt_any(std::slice::from_ref(&id)).await? && self.set_locked(&id, false) {
+                    unlocked.push(id);
+                }
+                continue;
+            }
+
+            match self.collection_recipients(&id).await {
+                Ok(recipients) => groups
+                    .entry(recipients.into_iter().flatten().collect())
+                    .or_default()
+                    .push(id),
+                Err(e) => warn!("Could not get the recipients of collection {id}: {e}"),
+            }
+        }
+
+        for ids in groups.into_values() {
+            if self.can_decrypt_any(&ids).await? {
+                unlocked.extend(ids.into_iter().filter(|id| self.set_locked(id, false)));
+            }
+        }
+        Ok(unlocked)
+    }
+
+    /// try to decrypt a secret from one of these collections, prompting for the passphrase
+    /// if none of them has a secret, one is encrypted to the first one's keys to decrypt instead.
+    /// collections which aren't encrypted with gpg have nothing to check then, so they stay locked
+    async fn can_decrypt_any(&self, collection_ids: &[Arc<String>]) -> Result<bool> {
+        for id in collection_ids {
+            if let Some(secret) = self.list_secrets(id).await?.into_iter().next() {
+                let decrypted = self.read_secret(id, &secret, true).await;
+                return Ok(self.check_decrypted(id, decrypted));
+            }
+        }
+
+        let Some(id) = collection_ids.first() else {
+            return Ok(false);
+        };
+        if !matches!(self.backend(id).await?, Backend::Pass(_)) {
+            warn!("Not unlocking collection {id}: it has no secrets to check it can be read with");
+            return Ok(false);
+        }
+        let decrypted = match self.encrypt_for(id, UNLOCK_CHECK.to_vec()).await {
+            Ok(encrypted) => self.decrypt(encrypted).await,
+            Err(e) => Err(e),
+        };
+        Ok(self.check_decrypted(id, decrypted))
+    }
+
+    /// whether decrypting a secret to unlock a collection worked, logging why if it didn't
+    fn check_decrypted(&self, collection_id: &str, decrypted: Result<Vec<u8>>) -> bool {
+        match decrypted {
+            Ok(_) => true,
+            Err(Error::GpgError(stderr)) if self.config.no_interactive && agent::needs_passphrase(&stderr) => {
+                warn!("Could not unlock collection {collection_id}: its passphrase isn't cached, and no-interactive is set");
+                false
+            }
+            Err(e) => {
+                warn!("Could not unlock collection {collection_id}: {e}");
+                false
+            }
+        }
+    }
+
+    /// find where a collection's secrets are kept
+    async fn backend(&self, collection_id: &str) -> Result<Backend<'_>> {
+        if self.is_read_only(collection_id) {
+            return Ok(Backend::Tree(self.pass));
+        }
+
+        let has_rules = !self.passthrough.is_empty();
+        #[cfg(feature = "vault")]
+        let has_rules = has_rules || self.vault.is_some();
+        if !has_rules {
+            return Ok(Backend::Pass(self.pass));
+        }
+
+        let aliases = self
+            .list_aliases_for_collection(Arc::new(collection_id.to_owned()))
+            .await?;
+
+        if let Some(store) = self
+            .passthrough
+            .iter()
+            .find(|store| store.serves(collection_id, &aliases))
+        {
+            return Ok(Backend::Passthrough(store));
+        }
+
+        #[cfg(feature = "vault")]
+        if let Some(vault) = self.vault.as_deref() {
+            if vault.serves(collection_id, &aliases) {
+                return Ok(Backend::Vault(vault, vault.location(collection_id, &aliases)));
+            }
+        }
+
+        Ok(Backend::Pass(self.pass))
+    }
+
+    /// the name of the backend a collection's secrets are kept in: "pass", "tree" (the password
+    /// store's own entries), "passthrough", or "vault"
+    pub async fn backend_name(&self, collection_id: &str) -> Result<&'static str> {
+        Ok(match self.backend(collection_id).await? {
+            Backend::Pass(_) => "pass",
+            Backend::Tree(_) => "tree",
+            Backend::Passthrough(_) => "passthrough",
+            #[cfg(feature = "vault")]
+            Backend::Vault(..) => "vault",
+        })
+    }
+
+    /// the mirrors a collection is copied to
+    async fn mirrors_for(&self, collection_id: &str) -> Result<Vec<&Mirror>> {
+        if self.mirrors.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let aliases = self
+            .list_aliases_for_collection(Arc::new(collection_id.to_owned()))
+            .await?;
+        Ok(self
+            .mirrors
+            .iter()
+            .filter(|mirror| mirror.serves(collection_id, &aliases))
+            .collect())
+    }
+
+    /// apply mirrored writes and reconcile the mirrors in the background. does nothing without mirrors
+    pub async fn run_mirrors(&self) {
+        join_all(self.mirrors.iter().map(|mirror| mirror.run(self))).await;
+    }
+
+    /// lock a secret for writing. locks nobody is waiting on are dropped as new ones are taken
+    async fn lock_secret(&self, collection_id: &str, secret_id: &str) -> OwnedMutexGuard<()> {
+        let lock = {
+            let mut locks = self.write_locks.lock().unwrap();
+            locks.retain(|_, lock| Arc::strong_count(lock) > 1);
+            locks
+                .entry((collection_id.to_owned(), secret_id.to_owned()))
+                .or_default()
+                .clone()
+        };
+        lock.lock_owned().await
+    }
+
+    /// write a password, with its metadata if it should be embedded (see `entry_metadata`),
+    /// then copy it to any mirrors
+    async fn write_password(
+        &self,
+        collection_id: &str,
+        secret_id: &str,
+        value: Vec<u8>,
+        metadata: Option<EntryMetadata>,
+    ) -> Result {
+        let _guard = self.lock_secret(collection_id, secret_id).await;
+        self.write_password_locked(collection_id, secret_id, value, metadata)
+            .await
+    }
+
+    /// `write_password`, for when the secret's write lock is already held
+    async fn write_password_locked(
+        &self,
+        collection_id: &str,
+        secret_id: &str,
+        value: Vec<u8>,
+        metadata: Option<EntryMetadata>,
+    ) -> Result {
+        // writing would recreate the directory without its db
+        if !self.collection_exists(collection_id).await? {
+            return Err(io::Error::from(io::ErrorKind::NotFound).into());
+        }
+        self.uncache(collection_id, Some(secret_id));
+
+        let mirrors = self.mirrors_for(collection_id).await?;
+        let mirrored = (!mirrors.is_empty()).then(|| value.clone());
+
+        let value = match metadata {
+            Some(metadata) if matches!(self.backend(collection_id).await?, Backend::Pass(_)) => {
+                entry_contents(value, &metadata, self.config.metadata_format)
+            }
+            _ => value,
+        };
+
+        // queued writes only reach the backend later, so reading one back would just get the
+        // queued value. they aren't verified
+        let queued = self.write_queue.is_some() && matches!(self.backend(collection_id).await?, Backend::Pass(_));
+        if self.config.verify_writes && !queued {
+            self.write_verified(collection_id, secret_id, value).await?;
+        } else {
+            self.write_to_backend(collection_id, secret_id, value).await?;
+        }
+
+        if let Some(value) = mirrored {
+            for mirror in mirrors {
+                mirror.write(collection_id, secret_id, value.clone());
+            }
+        }
+        Ok(())
+    }
+
+    /// write a password and read it back to check it was stored correctly, retrying once
+    async fn write_verified(&self, collection_id: &str, secret_id: &str, value: Vec<u8>) -> Result {
+        verify_write(
+            &format!("{collection_id}/{secret_id}"),
+            &value,
+            || self.write_to_backend(collection_id, secret_id, value.clone()),
+            || self.read_entry(collection_id, secret_id, true),
+        )
+        .await
+    }
+
+    /// write a password, going through the write queue if it's enabled
+    async fn write_to_backend(&self, collection_id: &str, secret_id: &str, value: Vec<u8>) -> Result {
+        let pass = match self.backend(collection_id).await? {
+            Backend::Pass(pass) => pass,
+            Backend::Tree(_) => return Err(Error::ReadOnly),
+            Backend::Passthrough(store) => {
+                return timing::phase(Phase::Backend, store.write(collection_id, secret_id, value)).await
+            }
+            #[cfg(feature = "vault")]
+            Backend::Vault(vault, location) => {
+                return timing::phase(Phase::Backend, vault.write(location, collection_id, secret_id, &value)).await
+            }
+        };
+
+        let path = Path::new(PASS_SUBDIR).join(collection_id).join(secret_id);
+        match &self.write_queue {
+            Some(queue) => queue.write(pass, &path, value).await,
+            None => pass.write_password(path, value).await,
+        }
+    }
+
+    /// the password store this is serving
+    pub fn store_path(&self) -> &Path {
+        &self.pass.directory
+    }
+
+    /// the state of the write queue, if it's enabled
+    pub fn write_queue_status(&self) -> Option<QueueStatus> {
+        self.write_queue.as_ref().map(|queue| queue.status())
+    }
+
+    /// remove expired secrets from the cache in the background. does nothing if it's disabled
+    pub async fn expire_cached_secrets(&self) {
+        if let Some(cache) = &self.secret_cache {
+            cache.run().await;
+        }
+    }
+
+    /// forget the cached value of a secret, or of every secret in a collection
+    fn uncache(&self, collection_id: &str, secret_id: Option<&str>) {
+        match (&self.secret_cache, secret_id) {
+            (Some(cache), Some(secret_id)) => cache.remove(collection_id, secret_id),
+            (Some(cache), None) => cache.clear_collection(collection_id),
+            (None, _) => {}
+        }
+    }
+
+    /// retry queued writes in the background. does nothing if the queue is disabled
+    pub async fn retry_queued_writes(&self) {
+        if let Some(queue) = &self.write_queue {
+            queue.run(self.pass).await;
+        }
+    }
+
+    /// find (or create) the collection serving the rest of the password store, and sync it
+    /// with the entries. if that's disabled, the collection is removed
+    async fn open_pass_tree(&self) -> Result<Option<String>> {
+        let existing = self.resolve_collection(pass_tree::ALIAS).await?;
+        if !self.config.expose_pass_tree {
+            if let Some(id) = existing {
+                info!("Removing collection {id}, since expose-pass-tree is disabled");
+                self.delete_collection(Arc::new(id)).await?;
+            }
+            return Ok(None);
+        }
+
+        let id = match existing {
+            Some(id) => id,
+            None => {
+                self.create_collection(Some("Password Store".into()), Some(pass_tree::ALIAS.into()))
+                    .await?
+            }
+        };
+        self.sync_pass_tree(&id).await?;
+        Ok(Some(id))
+    }
+
+    /// add the entries which aren't in the pass tree collection yet, and remove the ones
+    /// which are gone. the labels and attributes of the others aren't changed
+    async fn sync_pass_tree(&self, collection_id: &str) -> Result {
+        let entries: HashSet<_> = pass_tree::list_entries(self.pass)
+            .await?
+            .iter()
+            .map(|path| pass_tree::entry_id(path))
+            .collect();
+        let indexed: HashSet<_> = self
+            .list_secrets_with_attrs(collection_id)
+            .await?
+            .into_iter()
+            .collect();
+
+        let db = self.collection_db(collection_id).await?;
+        let (added, removed) = run_blocking(move || -> Result<_> {
+            let tx = db.begin_write().into_result()?;
+
+            let removed = indexed.difference(&entries).count();
+            for secret_id in indexed.difference(&entries) {
+                remove_secret_entries(&tx, secret_id)?;
+                tx.open_table(LABELS_TABLE)
+                    .into_result()?
+                    .remove(secret_id.as_str())
+                    .into_result()?;
+            }
+
+            let mut added = 0;
+            for secret_id in entries.difference(&indexed) {
+                let path = pass_tree::entry_path(secret_id).expect("ids are made from paths");
+                replace_attrs(&tx, secret_id, &pass_tree::entry_attributes(&path)).into_result()?;
+                tx.open_table(LABELS_TABLE)
+                    .into_result()?
+                    .insert(secret_id.as_str(), path.as_str())
+                    .into_result()?;
+                added += 1;
+            }
+            recount_items(&tx).into_result()?;
+            tx.commit().into_result()?;
+
+            Ok((added, removed))
+        })
+        .await??;
+
+        if added > 0 || removed > 0 {
+            info!("Added {added} and removed {removed} entries in the pass tree collection");
+        }
+        Ok(())
+    }
+
+    /// older versions kept all metadata in a single `attributes.redb` at the root of the
+    /// secret service dir. copy anything in there into the current layout and keep a backup
+    async fn migrate_legacy_db(&self) -> Result {
+        let legacy_path = Path::new(PASS_SUBDIR).join(ATTRIBUTES_DB);
+        if !self.pass.file_exists(&legacy_path).await? {
+            return Ok(());
+        }
+
+        warn!(
+            "Found a legacy metadata database at {}, migrating it",
+            self.pass.directory.join(&legacy_path).display()
+        );
+
+        // figure out which collection each secret file lives in. they aren't in the collections'
+        // dbs yet, so `list_secrets` would leave them out
+        let mut secret_collections = HashMap::new();
+        for collection_id in self.collections().await {
+            for secret_id in self.list_secret_files(&collection_id).await? {
+                secret_collections.insert(secret_id, collection_id.clone());
+            }
+        }
+
+        let legacy_db = open_db(self.pass, &legacy_path).await?;
+        let db = self.main_db();
+        let cols = self.all_collection_dbs().await;
+
+        let (migrated_collections, migrated_secrets) = run_blocking(move || -> Result<_> {
+            let legacy_tx = legacy_db.begin_read().into_result()?;
+
+            let mut migrated_collections = 0;
+            // collection id -> (secret id -> (label, attributes))
+            let mut secrets = HashMap::<&str, HashMap<String, LegacySecret>>::new();
+
+            let tx = db.begin_write().into_result()?;
+            let mut labels = tx.open_table(LABELS_TABLE).into_result()?;
+            let mut aliases = tx.open_table(ALIASES_TABLE).into_result()?;
+            let mut aliases_reverse = tx.open_multimap_table(ALIASES_TABLE_REVERSE).into_result()?;
+
+            // labels were shared between collections and secrets
+            if let Some(legacy_labels) = open_legacy_table(legacy_tx.open_table(LABELS_TABLE))? {
+                for entry in legacy_labels.iter().into_result()? {
+                    let (id, label) = entry.into_result()?;
+                    let (id, label) = (id.value(), label.value());
+
+                    if cols.contains_key(id) {
+                        // don't clobber anything set since the upgrade
+                        if labels.get(id).into_result()?.is_none() {
+                            labels.insert(id, label).into_result()?;
+                            migrated_collections += 1;
+                        }
+                    } else if let Some(collection_id) = secret_collections.get(id) {
+                        secrets
+                            .entry(collection_id.as_str())
+                            .or_default()
+                            .entry(id.to_owned())
+                            .or_default()
+                            .0 = Some(label.to_owned());
+                    }
+                }
+            }
+
+            if let Some(legacy_aliases) = open_legacy_table(legacy_tx.open_table(ALIASES_TABLE))? {
+                for entry in legacy_aliases.iter().into_result()? {
+                    let (alias, target) = entry.into_result()?;
+                    let (alias, target) = (alias.value(), target.value());
+
+                    // only keep aliases which point to collections that still exist
+                    if cols.contains_key(target) && aliases.get(alias).into_result()?.is_none() {
+                        aliases.insert(alias, target).into_result()?;
+                        aliases_reverse.insert(target, alias).into_result()?;
+                    }
+                }
+            }
+
+            if let Some(legacy_attrs) =
+                open_legacy_table(legacy_tx.open_table(ATTRIBUTES_TABLE_REVERSE))?
+            {
+                for entry in legacy_attrs.iter().into_result()? {
+                    let (id, attrs) = entry.into_result()?;
+                    if let Some(collection_id) = secret_collections.get(id.value()) {
+                        secrets
+                            .entry(collection_id.as_str())
+                            .or_default()
+                            .entry(id.value().to_owned())
+                            .or_default()
+                            .1 = Some(
+                            attrs
+                                .value()
+                                .into_iter()
+                                .map(|(k, v)| (k.to_owned(), v.to_owned()))
+                                .collect(),
+                        );
+                    }
+                }
+            }
+
+            drop(labels);
+            drop(aliases);
+            drop(aliases_reverse);
+            tx.commit().into_result()?;
+
+            let mut migrated_secrets = 0;
+
+            // write the secret metadata into each collection's db
+            for (collection_id, secrets) in secrets {
+                let db = cols.get(collection_id).into_not_found()?;
+                let tx = db.begin_write().into_result()?;
+                let mut attributes_table = open_index(&tx).into_result()?;
+                let mut attributes_table_reverse =
+                    tx.open_table(ATTRIBUTES_TABLE_REVERSE).into_result()?;
+                let mut labels_table = tx.open_table(LABELS_TABLE).into_result()?;
+
+                for (secret_id, (label, attrs)) in secrets {
+                    let secret_id = secret_id.as_str();
+                    let mut migrated = false;
+
+                    if let Some(label) = label {
+                        if labels_table.get(secret_id).into_result()?.is_none() {
+                            labels_table.insert(secret_id, label.as_str()).into_result()?;
+                            migrated = true;
+                        }
+                    }
+
+                    if let Some(attrs) = attrs {
+                        if attributes_table_reverse.get(secret_id).into_result()?.is_none() {
+                            if let Some(attributes_table) = &mut attributes_table {
+                                for (k, v) in &attrs {
+                                    attributes_table
+                                        .insert((k.as_str(), v.as_str()), secret_id)
+                                        .into_result()?;
+                                }
+                            }
+                            let attrs_ref = attrs
+                                .iter()
+                                .map(|(k, v)| (k.as_str(), v.as_str()))
+                                .collect::<HashMap<_, _>>();
+                            attributes_table_reverse
+                                .insert(secret_id, attrs_ref)
+                                .into_result()?;
+                            migrated = true;
+                        }
+                    }
+
+                    if migrated {
+                        migrated_secrets += 1;
+                    }
+                }
+
+                drop(attributes_table);
+                drop(attributes_table_reverse);
+                drop(labels_table);
+                recount_items(&tx).into_result()?;
+                tx.commit().into_result()?;
+            }
+
+            Ok((migrated_collections, migrated_secrets))
+        })
+        .await??;
+
+        // move the legacy db out of the way so we don't migrate it again
+        let mut backup_path = legacy_path.with_extension("redb.bak");
+        let mut n = 1;
+        while self.pass.file_exists(&backup_path).await? {
+            backup_path = legacy_path.with_extension(format!("redb.bak.{n}"));
+            n += 1;
+        }
+        self.pass.rename_file(&legacy_path, &backup_path).await?;
+
+        warn!(
+            "Migrated metadata for {migrated_collections} collection(s) and {migrated_secrets} secret(s). The legacy database was kept at {}",
+            self.pass.directory.join(&backup_path).display()
+        );
+
+        Ok(())
+    }
+
+    /// open the db of each collection in `metadata`, the store the dbs are kept in
+    async fn get_current_collections(metadata: &PasswordStore) -> Result<HashMap<String, Arc<Database>>> {
+        let mut collections = HashMap::new();
+
+        for id in metadata.list_dirs(PASS_SUBDIR).await? {
+            // make the DB for this collection
+            let db_path = Path::new(PASS_SUBDIR).join(&id).join(ATTRIBUTES_DB);
+            let db = open_db(metadata, db_path).await?;
+            collections.insert(id, Arc::new(db));
+        }
+
+        Ok(collections)
+    }
+
+    pub async fn get_label(&self, collection_id: Arc<String>) -> Result<String> {
+        let db = self.main_db();
+        run_blocking(move || {
+            let tx = db.begin_read().into_result()?;
+            let table = raise_nonexistent_table!(tx.open_table(LABELS_TABLE));
+            let label = table
+                .get(collection_id.as_str())
+                .into_result()?
+                .into_not_found()?;
+
+            Ok(label.value().to_owned())
+        })
+        .await?
+    }
+
+    pub async fn set_label(&self, collection_id: Arc<String>, label: String) -> Result {
+        let db = self.main_db();
+        let id = collection_id.clone();
+        run_blocking(move || -> RedbResult<_> {
+            let tx = db.begin_write()?;
+            let mut table = tx.open_table(LABELS_TABLE)?;
+            table.insert(id.as_str(), &*label)?;
+            drop(table);
+            tx.commit()?;
+            Ok(())
+        })
+        .await??;
+
+        self.publish_change(&collection_id, None, ChangeKind::Changed);
+        Ok(())
+    }
+
+    /// where the user wants a collection listed, if they've chosen
+    pub async fn get_sort_order(&self, collection_id: Arc<String>) -> Result<Option<u32>> {
+        let db = self.main_db();
+        run_blocking(move || {
+            let tx = db.begin_read().into_result()?;
+            let table = raise_nonexistent_table!(tx.open_table(SORT_ORDER_TABLE), Ok(None));
+            let order = table.get(collection_id.as_str()).into_result()?;
+            Ok(order.map(|order| order.value()))
+        })
+        .await?
+    }
+
+    pub async fn set_sort_order(&self, collection_id: Arc<String>, order: Option<u32>) -> Result {
+        let db = self.main_db();
+        let id = collection_id.clone();
+        run_blocking(move || -> RedbResult<_> {
+            let tx = db.begin_write()?;
+            let mut table = tx.open_table(SORT_ORDER_TABLE)?;
+            match order {
+                Some(order) => table.insert(id.as_str(), order)?,
+                None => table.remove(id.as_str())?,
+            };
+            drop(table);
+            tx.commit()?;
+            Ok(())
+        })
+        .await??;
+
+        self.publish_change(&collection_id, None, ChangeKind::Changed);
+        Ok(())
+    }
+
+    /// the color frontends should show a collection with, if the user set one
+    pub async fn get_color(&self, collection_id: Arc<String>) -> Result<Option<String>> {
+        let db = self.main_db();
+        run_blocking(move || {
+            let tx = db.begin_read().into_result()?;
+            let table = raise_nonexistent_table!(tx.open_table(COLORS_TABLE), Ok(None));
+            let color = table.get(collection_id.as_str()).into_result()?;
+            Ok(color.map(|color| color.value().to_owned()))
+        })
+        .await?
+    }
+
+    pub async fn set_color(&self, collection_id: Arc<String>, color: Option<String>) -> Result {
+        let db = self.main_db();
+        let id = collection_id.clone();
+        run_blocking(move || -> RedbResult<_> {
+            let tx = db.begin_write()?;
+            let mut table = tx.open_table(COLORS_TABLE)?;
+            match color {
+                Some(color) => table.insert(id.as_str(), color.as_str())?,
+                None => table.remove(id.as_str())?,
+            };
+            drop(table);
+            tx.commit()?;
+            Ok(())
+        })
+        .await??;
+
+        self.publish_change(&collection_id, None, ChangeKind::Changed);
+        Ok(())
+    }
+
+    /// remember a client executable
+    /// returns true if it hasn't been seen before
+    pub async fn record_client(&self, exe: String) -> Result<bool> {
+        let db = self.main_db();
+        Ok(run_blocking(move || -> RedbResult<_> {
+            let tx = db.begin_write()?;
+            let mut table = tx.open_table(KNOWN_CLIENTS_TABLE)?;
+            let is_new = table.get(exe.as_str())?.is_none();
+            if is_new {
+                let now = SystemTime::now()
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .map(|d| d.as_secs())
+                    .unwrap_or_default();
+                table.insert(exe.as_str(), now)?;
+            }
+            drop(table);
+            tx.commit()?;
+            Ok(is_new)
+        })
+        .await??)
+    }
+
+    /// whether a gnome-keyring keyring was imported before
+    pub async fn keyring_imported(&self, name: Arc<String>) -> Result<bool> {
+        let db = self.main_db();
+        run_blocking(move || {
+            let tx = db.begin_read().into_result()?;
+            let table = raise_nonexistent_table!(tx.open_table(IMPORTED_KEYRINGS_TABLE), Ok(false));
+            Ok(table.get(name.as_str()).into_result()?.is_some())
+        })
+        .await?
+    }
+
+    /// remember that a keyring was imported, so it isn't imported again
+    pub async fn record_imported_keyring(&self, name: Arc<String>, collection_id: Arc<String>) -> Result {
+        let db = self.main_db();
+        Ok(run_blocking(move || -> RedbResult<_> {
+            let tx = db.begin_write()?;
+            let mut table = tx.open_table(IMPORTED_KEYRINGS_TABLE)?;
+            table.insert(name.as_str(), collection_id.as_str())?;
+            drop(table);
+            tx.commit()?;
+            Ok(())
+        })
+        .await??)
+    }
+
+    /// returns a hashmap of collection id to vec of aliases
+    pub async fn list_all_aliases(&self) -> Result<HashMap<String, Vec<String>>> {
+        let db = self.main_db();
+        run_blocking(move || -> Result<_> {
+            // open the aliases table
+            let tx = db.begin_read().into_result()?;
+            let table = raise_nonexistent_table!(tx.open_multimap_table(ALIASES_TABLE_REVERSE), Ok(HashMap::new()));
+            Ok(table
+                .iter()
+                .into_result()?
+                .map(|i| {
+                    let (target, aliases) = i?;
+                    let aliases = aliases
+                        .map(|a| RedbResult::Ok(a?.value().to_owned()))
+                        .collect::<RedbResult<Vec<_>>>()?;
+                    Ok((target.value().to_owned(), aliases))
+                })
+                .collect::<RedbResult<_>>()?)
+        })
+        .await?
+    }
+
+    /// list the aliases that point to a collection
+    pub async fn list_aliases_for_collection(
+        &self,
+        collection_id: Arc<String>,
+    ) -> Result<Vec<String>> {
+        let db = self.main_db();
+        run_blocking(move || -> Result<_> {
+            let tx = db.begin_read().into_result()?;
+            
+            let aliases_reverse =
+                raise_nonexistent_table!(tx.open_multimap_table(ALIASES_TABLE_REVERSE));
+
+            // get the aliases for this collection and stringify each one
+            Ok(aliases_reverse
+                .get(collection_id.as_str())
+                .into_result()?
+                .map(|el| Ok(el?.value().to_owned()))
+                .collect::<RedbResult<Vec<_>>>()?)
+        })
+        .await?
+    }
+
+    pub async fn get_alias(&self, alias: Arc<String>) -> Result<String> {
+        let db = self.main_db();
+        run_blocking(move || {
+            // open the aliases table
+            let tx = db.begin_read().into_result()?;
+            let table = raise_nonexistent_table!(tx.open_table(ALIASES_TABLE));
+            let target = table
+                .get(alias.as_str())
+                .into_result()?
+                .into_not_found()?
+                .value()
+                .to_owned();
+            Ok(target)
+        })
+        .await?
+    }
+
+    /// find the id of a collection given its id or one of its aliases
+    pub async fn resolve_collection(&self, name: &str) -> Result<Option<String>> {
+        if self.collection_dbs.read().await.contains_key(name) {
+            return Ok(Some(name.to_owned()));
+        }
+
+        match self.get_alias(Arc::new(name.to_owned())).await {
+            Ok(id) => Ok(Some(id)),
+            Err(Error::IoError(e)) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub async fn set_alias(&self, alias: Arc<String>, target: Option<String>) -> Result {
+        let db = self.main_db();
+        Ok(run_blocking(move || -> RedbResult<_> {
+            // open the aliases table
+            let tx = db.begin_write()?;
+            let mut aliases = tx.open_table(ALIASES_TABLE)?;
+            let mut aliases_reverse = tx.open_multimap_table(ALIASES_TABLE_REVERSE)?;
+
+            // remove this alias from its old target's alias list
+            if let Some(old_target) = aliases.get(alias.as_str())? {
+                aliases_reverse.remove(old_target.value(), alias.as_str())?;
+            }
+
+            if let Some(target) = target {
+                aliases.insert(alias.as_str(), target.as_str())?;
+                aliases_reverse.insert(target.as_str(), alias.as_str())?;
+            } else {
+                // remove it
+                aliases.remove(alias.as_str())?;
+            }
+            drop(aliases);
+            drop(aliases_reverse);
+            tx.commit()?;
+            Ok(())
+        })
+        .await??)
+    }
+
+    /// remove an alias if it still points at `target`, e.g. because that collection is gone
+    /// returns whether it was removed
+    pub async fn remove_alias_if_target(&self, alias: Arc<String>, target: String) -> Result<bool> {
+        let db = self.main_db();
+        Ok(run_blocking(move || -> RedbResult<_> {
+            let tx = db.begin_write()?;
+            let mut aliases = tx.open_table(ALIASES_TABLE)?;
+            let mut aliases_reverse = tx.open_multimap_table(ALIASES_TABLE_REVERSE)?;
+
+            let current = aliases.get(alias.as_str())?.map(|t| t.value().to_owned());
+            let stale = current.as_ref() == Some(&target);
+            if stale {
+                aliases.remove(alias.as_str())?;
+                aliases_reverse.remove(target.as_str(), alias.as_str())?;
+            }
+
+            drop(aliases);
+            drop(aliases_reverse);
+            tx.commit()?;
+            Ok(stale)
+        })
+        .await??)
+    }
+
+    pub async fn collections(&self) -> Vec<String> {
+        self.collection_dbs
+            .read()
+            .await
+            .keys()
+            .map(|a| a.to_owned())
+            .collect()
+    }
+
+    /// ids of every collection, sorted by label unless that's disabled
+    /// collections with a sort order come first
+    pub async fn sorted_collections(&self) -> Result<Vec<String>> {
+        let mut ids = self.collections().await;
+        let db = self.main_db();
+        let by_label = self.config.sort_by_label;
+        run_blocking(move || -> Result<_> {
+            if by_label {
+                sort_by_label(&mut ids, &read_labels(&db)?);
+            }
+            // stable, so the rest stay sorted by label
+            let orders = read_sort_orders(&db)?;
+            ids.sort_by_key(|id| orders.get(id).map_or((1, 0), |&order| (0, order)));
+            Ok(ids)
+        })
+        .await?
+    }
+
+    /// create a collection, with an optional label and alias
+    /// returns the created collection name
+    /// if `label` is `None`, the collection will be called "Unttiled Collection"
+    pub async fn create_collection(
+        &self,
+        label: Option<String>,
+        alias: Option<String>,
+    ) -> Result<String> {
+        self.create_collection_in(label, alias, None).await
+    }
+
+    /// create a collection, with its directory linked to an existing one in the password store
+    /// if `directory` is given
+    async fn create_collection_in(
+        &self,
+        label: Option<String>,
+        alias: Option<String>,
+        directory: Option<&Path>,
+    ) -> Result<String> {
+        // I assume aliases are case sensitive
+
+        // a new directory gets its own .gpg-id. its keys are checked before anything is created,
+        // so a typo doesn't leave a collection nothing can be written to
+        let recipients = match directory {
+            Some(_) => None,
+            None => self.config.new_collection_recipients(alias.as_deref()),
+        };
+        if let Some(recipients) = recipients {
+            // boxed, since running gpg is a large future and this is deep in D-Bus methods' stacks
+            Box::pin(self.pass.recipient_key_ids(recipients)).await?;
+        }
+
+        let db = self.main_db();
+
+        let collection_id = run_blocking(move || -> RedbResult<_> {
+            let tx = db.begin_write()?;
+            let mut aliases = tx.open_table(ALIASES_TABLE)?;
+            let mut aliases_reverse = tx.open_multimap_table(ALIASES_TABLE_REVERSE)?;
+            let mut labels = tx.open_table(LABELS_TABLE)?;
+
+            let had_provided_label = label.is_some();
+            let label = label
+                .map(Cow::Owned)
+                .unwrap_or("Untitled Collection".into());
+
+            // an existing alias
+            let existing_id = if let Some(alias) = alias.as_ref() {
+                if let Some(collection_id) = aliases.get(alias.as_str())? {
+                    let id = collection_id.value();
+
+                    // update the label if we were given one or there isn't one already
+                    // in the 2nd case, it just becomes Untitled Collection
+                    if had_provided_label || labels.get(id)?.is_none() {
+                        labels.insert(id, label.as_ref())?;
+                    }
+
+                    Some(id.to_string())
+                } else {
+                    None
+                }
+            } else {
+                None
+            };
+
+            // if we couldn't find an existing ID, make a new collection
+            let id = if let Some(id) = existing_id {
+                id
+            } else {
+                let id = format!("{}_{}", slugify(&label), nanoid!(4, &NANOID_ALPHABET));
+
+                // set the label and alias
+                if let Some(alias) = alias.as_ref() {
+                    // remove this alias from its old target's alias list
+                    if let Some(old_target) = aliases.insert(alias.as_str(), id.as_str())? {
+                        aliases_reverse.remove(old_target.value(), alias.as_str())?;
+                    }
+                    aliases_reverse.insert(id.as_str(), alias.as_str())?;
+                }
+                labels.insert(id.as_str(), label.as_ref())?;
+
+                id
+            };
+
+            drop(aliases);
+            drop(aliases_reverse);
+            drop(labels);
+            tx.commit()?;
+
+            Ok(id)
+        })
+        .await??;
+
+        let mut collections = self.collection_dbs.write().await;
+
+        if !collections.contains_key(&collection_id) {
+            // we need to actually create this collection
+
+            let mut collection_path = Path::new(PASS_SUBDIR).join(&collection_id);
+            match directory {
+                Some(directory) => self.pass.link_dir(&collection_path, directory).await?,
+                None => self.pass.make_dir(&collection_path).await?,
+            }
+            if let Some(recipients) = recipients {
+                self.pass.write_gpg_id(&collection_path, recipients).await?;
+            }
+
+            collection_path.push(ATTRIBUTES_DB);
+            let db = open_db(self.metadata_store(), collection_path).await?;
+
+            let created = SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default();
+            let tx = db.begin_write().into_result()?;
+            let mut metadata = tx.open_table(METADATA_TABLE).into_result()?;
+            metadata.insert(CREATED_KEY, created).into_result()?;
+            // it's empty, so only index it if that's forced
+            let indexed = self.config.attribute_index == IndexMode::Always;
+            metadata.insert(INDEXED_KEY, indexed as u64).into_result()?;
+            drop(metadata);
+            tx.commit().into_result()?;
+
+            collections.insert(collection_id.clone(), Arc::new(db));
+            self.publish_change(&collection_id, None, ChangeKind::Created);
+        }
+
+        Ok(collection_id)
+    }
+
+    /// the full path of a dir in the password store (relative to it). fails if it's outside
+    pub async fn store_directory(&self, directory: &Path) -> Result<PathBuf> {
+        self.pass.check_dir_in_store(directory).await?;
+        Ok(self.pass.directory.join(directory))
+    }
+
+    /// decrypt an entry anywhere in the password store, by its path relative to it (without
+    /// `.gpg`), e.g. to import it. gpg may prompt, unless `no-interactive` is set
+    pub async fn read_store_entry(&self, path: &Path) -> Result<Vec<u8>> {
+        self.pass.read_password(path, !self.config.no_interactive).await
+    }
+
+    /// encrypt a value to the recipients of the collection's `.gpg-id`, e.g. to export it
+    pub async fn encrypt_for(&self, collection_id: &str, value: Vec<u8>) -> Result<Vec<u8>> {
+        let recipients = self.pass.get_recipients(Path::new(PASS_SUBDIR).join(collection_id)).await?;
+        self.pass.encrypt_to(&recipients, value).await
+    }
+
+    /// decrypt a value which isn't in the password store, e.g. to import it. gpg may prompt,
+    /// unless `no-interactive` is set
+    pub async fn decrypt(&self, contents: Vec<u8>) -> Result<Vec<u8>> {
+        self.pass.decrypt(contents, !self.config.no_interactive).await
+    }
+
+    /// serve a dir elsewhere in the password store as a new collection, without moving its
+    /// entries, by linking the collection's dir to it. each entry becomes an item labelled with
+    /// its name. returns the collection's id, and the entries which were skipped since their
+    /// names can't be used as item ids
+    pub async fn adopt_directory(&self, directory: &Path, label: String) -> Result<(String, Vec<String>)> {
+        let hidden = directory
+            .components()
+            .any(|c| c.as_os_str().to_string_lossy().starts_with('.'));
+        if directory.starts_with(PASS_SUBDIR) || hidden {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} can't be a collection", directory.display()),
+            )
+            .into());
+        }
+        // before the collection is created, so it isn't left without a dir
+        self.pass.check_dir_in_store(directory).await?;
+
+        let collection_id = self.create_collection_in(Some(label), None, Some(directory)).await?;
+
+        let (entries, skipped): (Vec<_>, Vec<_>) = self
+            .list_secret_files(&collection_id)
+            .await?
+            .into_iter()
+            .partition(|name| is_valid_id(name));
+        let Ok(db) = self.collection_db(&collection_id).await else {
+            return Ok((collection_id, skipped));
+        };
+        run_blocking(move || -> RedbResult<()> {
+            let tx = db.begin_write()?;
+            for secret_id in &entries {
+                replace_attrs(&tx, secret_id, &HashMap::new())?;
+                tx.open_table(LABELS_TABLE)?.insert(secret_id.as_str(), secret_id.as_str())?;
+            }
+            recount_items(&tx)?;
+            tx.commit()?;
+            Ok(())
+        })
+        .await??;
+
+        Ok((collection_id, skipped))
+    }
+
+    /// delete a collection and all its secrets
+    pub async fn delete_collection(&self, collection_id: Arc<String>) -> Result {
+        // look these up before the aliases are removed
+        let mirrors = self.mirrors_for(&collection_id).await?;
+
+        // remove the values kept elsewhere
+        match self.backend(&collection_id).await? {
+            Backend::Pass(_) => {}
+            Backend::Tree(_) => return Err(Error::ReadOnly),
+            Backend::Passthrough(store) => {
+                for secret_id in store.list(&collection_id).await? {
+                    store.delete(&collection_id, &secret_id).await?;
+                }
+            }
+            #[cfg(feature = "vault")]
+            Backend::Vault(vault, location) => {
+                for secret_id in vault.list(location, &collection_id).await? {
+                    vault.delete(location, &collection_id, &secret_id).await?;
+                }
+            }
+        }
+
+        // remove it from the collection db map
+        self.collection_dbs.write().await.remove(&*collection_id);
+        self.set_locked(&collection_id, false);
+        self.uncache(&collection_id, None);
+
+        // remove the dir
+        let collection_path = Path::new(PASS_SUBDIR).join(&*collection_id);
+        self.pass.remove_dir(&collection_path).await?;
+        if let Some(metadata) = &self.metadata {
+            metadata.remove_dir(&collection_path).await?;
+        }
+        for mirror in mirrors {
+            mirror.delete_collection(&collection_id);
+        }
+
+        self.publish_change(&collection_id, None, ChangeKind::Deleted);
+        self.remove_collection_entries(collection_id).await
+    }
+
+    /// move a collection's entries out of the secret service's directory to `target` (relative
+    /// to the password store), each named after its item's label, so they can be used with pass
+    /// alone. its db is removed, but the collection is left for the caller to forget
+    /// returns the new name of each item's entry
+    pub async fn eject_collection(&self, collection_id: Arc<String>, target: &Path) -> Result<HashMap<String, String>> {
+        match self.backend(&collection_id).await? {
+            Backend::Pass(_) => {}
+            Backend::Tree(_) => return Err(Error::ReadOnly),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("collection {collection_id} isn't kept in the password store"),
+                )
+                .into())
+            }
+        }
+
+        let invalid = |why: &str| -> Error {
+            io::Error::new(io::ErrorKind::InvalidInput, format!("{} {why}", target.display())).into()
+        };
+        let normal = target
+            .components()
+            .all(|c| matches!(c, Component::Normal(name) if !name.to_string_lossy().starts_with('.')));
+        if target.as_os_str().is_empty() || !normal || target.starts_with(PASS_SUBDIR) {
+            return Err(invalid("can't be used for the collection's entries"));
+        }
+        if self.pass.file_exists(target).await? {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} already exists", target.display()),
+            )
+            .into());
+        }
+        let collection_path = Path::new(PASS_SUBDIR).join(&*collection_id);
+        if self.pass.is_symlink(&collection_path).await? {
+            return Err(invalid("would be a copy of an adopted directory. Delete the collection instead"));
+        }
+        if let Some(queue) = &self.write_queue {
+            let secrets = self.list_secrets_with_attrs(&collection_id).await?;
+            if secrets.iter().any(|secret_id| queue.is_pending(&collection_path.join(secret_id))) {
+                return Err(io::Error::new(
+                    io::ErrorKind::WouldBlock,
+                    format!("collection {collection_id} has writes waiting to be retried"),
+                )
+                .into());
+            }
+        }
+
+        let secrets = self.list_secrets(&collection_id).await?;
+        let db = self.collection_db(&collection_id).await?;
+        let labels = run_blocking(move || read_labels(&db)).await??;
+        // other files in the dir move with it, and keep their names
+        let others = self
+            .list_secret_files(&collection_id)
+            .await?
+            .into_iter()
+            .filter(|name| !secrets.contains(name))
+            .collect();
+        let names = entry_names(&secrets, &labels, others);
+
+        self.uncache(&collection_id, None);
+        for (secret_id, name) in &names {
+            if secret_id != name {
+                self.pass
+                    .move_password(collection_path.join(secret_id), collection_path.join(name))
+                    .await?;
+            }
+        }
+        self.pass.move_dir(&collection_path, target).await?;
+        match &self.metadata {
+            Some(metadata) => metadata.remove_dir(&collection_path).await?,
+            None => self.pass.delete_file(target.join(ATTRIBUTES_DB)).await?,
+        }
+        Ok(names)
+    }
+
+    /// whether a collection's directory and db still exist
+    /// they can be removed behind our back, e.g. with `rm -rf`
+    pub async fn collection_exists(&self, collection_id: &str) -> Result<bool> {
+        let db_path = Path::new(PASS_SUBDIR).join(collection_id).join(ATTRIBUTES_DB);
+        self.metadata_store().file_exists(db_path).await
+    }
+
+    /// the store the dbs are kept in: the password store, unless `metadata-dir` is set
+    fn metadata_store(&self) -> &PasswordStore {
+        self.metadata.as_deref().unwrap_or(self.pass)
+    }
+
+    /// the db with collections' labels and aliases
+    fn main_db(&self) -> Arc<Database> {
+        self.db.read().unwrap().clone()
+    }
+
+    /// the db with a collection's secrets' labels and attributes
+    async fn collection_db(&self, collection_id: &str) -> Result<Arc<Database>> {
+        self.collection_dbs
+            .read()
+            .await
+            .get(collection_id)
+            .cloned()
+            .into_not_found()
+    }
+
+    /// the dbs of every collection, by id
+    async fn all_collection_dbs(&self) -> HashMap<String, Arc<Database>> {
+        self.collection_dbs.read().await.clone()
+    }
+
+    /// open the dbs whose files were replaced outside the daemon (e.g. by a git pull) again,
+    /// and the dbs of collections which were added. collections which were removed are left
+    /// for `Collection::resync_if_deleted`
+    /// returns the ids of the added collections
+    pub async fn reload(&self) -> Result<Vec<String>> {
+        let metadata_store = self.metadata_store();
+
+        if let Some(db) = reopen_db(metadata_store, Path::new(PASS_SUBDIR).join(COLLECTIONS_DB)).await? {
+            info!("Reloaded the collections database");
+            *self.db.write().unwrap() = Arc::new(db);
+        }
+
+        let mut added = vec![];
+        let mut collection_dbs = self.collection_dbs.write().await;
+        for id in metadata_store.list_dirs(PASS_SUBDIR).await? {
+            let db_path = Path::new(PASS_SUBDIR).join(&id).join(ATTRIBUTES_DB);
+            if !metadata_store.file_exists(&db_path).await? {
+                continue;
+            }
+            let Some(db) = reopen_db(metadata_store, db_path).await? else {
+                continue;
+            };
+            apply_index_mode(&db, self.config.attribute_index).into_result()?;
+            if collection_dbs.insert(id.clone(), Arc::new(db)).is_some() {
+                info!("Reloaded the database of collection {id}");
+            } else {
+                info!("Found new collection {id}");
+                added.push(id);
+            }
+        }
+        drop(collection_dbs);
+
+        if let Some(id) = &self.pass_tree {
+            self.sync_pass_tree(id).await?;
+        }
+        Ok(added)
+    }
+
+    /// run git in the password store, and in the metadata directory if it's a repo of its
+    /// own. returns what it printed
+    pub async fn git(&self, args: &[&str]) -> Result<String> {
+        let mut output = self.pass.run_git(args).await?;
+        if let Some(metadata) = &self.metadata {
+            if metadata.file_exists(".git").await? {
+                output.push_str(&metadata.run_git(args).await?);
+            }
+        }
+        Ok(output)
+    }
+
+    /// the commit the password store is at, if it's a git repo
+    pub async fn git_head(&self) -> Option<String> {
+        let head = self.pass.run_git(&["rev-parse", "HEAD"]).await.ok()?;
+        Some(head.trim().to_owned())
+    }
+
+    /// the secrets which were added, changed, and removed in each collection since the password
+    /// store was at commit `since`, e.g. by a git pull
+    pub async fn changes_since(&self, since: &str) -> Result<HashMap<String, PulledChanges>> {
+        let diff = self
+            .pass
+            .run_git(&["diff", "--name-status", "--no-renames", since, "HEAD", "--", PASS_SUBDIR])
+            .await?;
+        Ok(parse_pulled_changes(&diff))
+    }
+
+    /// forget a collection whose directory was removed externally
+    pub async fn forget_collection(&self, collection_id: Arc<String>) -> Result {
+        self.collection_dbs.write().await.remove(&*collection_id);
+        self.set_locked(&collection_id, false);
+        self.uncache(&collection_id, None);
+
+        self.publish_change(&collection_id, None, ChangeKind::Deleted);
+        self.remove_collection_entries(collection_id).await
+    }
+
+    /// remove a collection's aliases, label, and display hints from our db
+    async fn remove_collection_entries(&self, collection_id: Arc<String>) -> Result {
+        let db = self.main_db();
+
+        run_blocking(move || -> RedbResult<_> {
+            let tx = db.begin_write()?;
+
+            let mut aliases = tx.open_table(ALIASES_TABLE)?;
+            let mut aliases_reverse = tx.open_multimap_table(ALIASES_TABLE_REVERSE)?;
+            let mut labels = tx.open_table(LABELS_TABLE)?;
+
+            // remove each alias
+            for alias in aliases_reverse.remove_all(collection_id.as_str())? {
+                aliases.remove(alias?.value())?;
+            }
+
+            // remove the label
+            labels.remove(collection_id.as_str())?;
+            tx.open_table(SORT_ORDER_TABLE)?.remove(collection_id.as_str())?;
+            tx.open_table(COLORS_TABLE)?.remove(collection_id.as_str())?;
+
+            drop(aliases);
+            drop(aliases_reverse);
+            drop(labels);
+
+            tx.commit()?;
+
+            Ok(())
+        })
+        .await??;
+
+        Ok(())
+    }
+
+    /// search all collections for secrets matching the given attributes
+    /// returns a map of collection id to items
+    pub async fn search_all_collections(
+        &self,
+        attributes: HashMap<String, String>,
+    ) -> Result<HashMap<String, Vec<String>>> {
+        let cols = self.all_collection_dbs().await;
+        let mut found = run_blocking(move || -> Result<HashMap<_, _>> {
+            cols.into_iter()
+                .map(|(id, db)| {
+                    // search each collection
+                    Ok((id, search_collection(&attributes, &db)?))
+                })
+                .collect()
+        })
+        .await??;
+
+        self.retain_visible(&mut found, String::as_str);
+        Ok(found)
+    }
+
+    /// like `search_all_collections`, with each secret's label and attributes
+    pub async fn search_all_collections_with_info(
+        &self,
+        attributes: HashMap<String, String>,
+    ) -> Result<HashMap<String, Vec<SecretInfo>>> {
+        let cols = self.all_collection_dbs().await;
+        let mut found = run_blocking(move || -> Result<HashMap<_, _>> {
+            cols.into_iter()
+                .map(|(id, db)| Ok((id, search_collection_with_info(&attributes, &db)?)))
+                .collect()
+        })
+        .await??;
+
+        self.retain_visible(&mut found, |(secret_id, _, _)| secret_id);
+        Ok(found)
+    }
+
+    /// find the secrets in every collection whose label contains `label`, ignoring case, and
+    /// whose attributes match `patterns`, which can have `*` and `?` wildcards in their values
+    /// an empty label matches any. every secret is checked, so this is slower than a search
+    pub async fn find_secrets(
+        &self,
+        label: &str,
+        patterns: HashMap<String, String>,
+    ) -> Result<HashMap<String, Vec<String>>> {
+        let label = label.to_lowercase();
+        let cols = self.all_collection_dbs().await;
+        let mut found = run_blocking(move || -> Result<HashMap<_, _>> {
+            cols.into_iter()
+                .map(|(id, db)| Ok((id, find_in_collection(&label, &patterns, &db)?)))
+                .collect()
+        })
+        .await??;
+
+        self.retain_visible(&mut found, String::as_str);
+        Ok(found)
+    }
+
+    /// leave out items which are being added or removed
+    fn retain_visible<T>(&self, found: &mut HashMap<String, Vec<T>>, secret_id: impl Fn(&T) -> &str) {
+        let view = self.item_view.read().unwrap();
+        for (collection_id, secrets) in found {
+            let visible = view.get(collection_id);
+            secrets.retain(|secret| visible.is_some_and(|visible| visible.contains(secret_id(secret))));
+        }
+    }
+
+    /// count the secrets in every collection which match the given attributes, up to `limit`
+    /// only reads the attribute index, so nothing is decrypted
+    pub async fn count_matching(&self, attributes: HashMap<String, String>, limit: u64) -> Result<u64> {
+        let cols = self.all_collection_dbs().await;
+        run_blocking(move || -> Result<_> {
+            let mut count = 0;
+            for db in cols.values() {
+                if count >= limit {
+                    break;
+                }
+                count += count_matches(&attributes, db, limit - count)?;
+            }
+            Ok(count)
+        })
+        .await?
+    }
+
+    /// search the specific collection for secrets matching the given attributes
+    pub async fn search_collection(
+        &self,
+        collection_id: Arc<String>,
+        attributes: Arc<HashMap<String, String>>,
+    ) -> Result<Vec<String>> {
+        let db = self.collection_db(&collection_id).await?;
+        let mut found = run_blocking(move || {
+            search_collection(&attributes, &db)
+        })
+        .await??;
+
+        // leave out items which are being added or removed
+        let view = self.item_view.read().unwrap();
+        let visible = view.get(collection_id.as_str());
+        found.retain(|id| visible.is_some_and(|visible| visible.contains(id)));
+        Ok(found)
+    }
+
+    /// the secrets in a collection with exactly the given attributes, not counting reserved ones
+    /// nothing matches empty attributes
+    pub async fn exact_matches(
+        &self,
+        collection_id: Arc<String>,
+        attributes: &HashMap<String, String>,
+    ) -> Result<Vec<SecretInfo>> {
+        let query: HashMap<_, _> = attributes
+            .iter()
+            .filter(|(key, _)| !is_reserved(key))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+        let db = self.collection_db(&collection_id).await?;
+        let mut found = run_blocking(move || {
+            let mut found = search_collection_with_info(&query, &db)?;
+            let wanted = identifying_attributes(&query);
+            found.retain(|(_, _, attrs)| identifying_attributes(attrs) == wanted);
+            Ok::<_, Error>(found)
+        })
+        .await??;
+
+        let view = self.item_view.read().unwrap();
+        let visible = view.get(collection_id.as_str());
+        found.retain(|(id, _, _)| visible.is_some_and(|visible| visible.contains(id)));
+        Ok(found)
+    }
+
+    /// the groups of secrets in a collection with the same attributes (besides reserved ones),
+    /// each oldest first: by version, then by when their files were written
+    /// secrets without attributes are never duplicates
+    pub async fn duplicate_groups(&self, collection_id: Arc<String>) -> Result<Vec<Vec<String>>> {
+        let db = self.collection_db(&collection_id).await?;
+        let groups = run_blocking(move || -> Result<Vec<Vec<(String, u64)>>> {
+            let tx = db.begin_read().into_result()?;
+            let attributes_reverse =
+                raise_nonexistent_table!(tx.open_table(ATTRIBUTES_TABLE_REVERSE), Ok(vec![]));
+            let mut groups = BTreeMap::<Vec<(String, String)>, Vec<(String, u64)>>::new();
+            for entry in attributes_reverse.iter().into_result()? {
+                let (secret_id, attrs) = entry.into_result()?;
+                let attrs: HashMap<String, String> = attrs
+                    .value()
+                    .into_iter()
+                    .map(|(k, v)| (k.to_owned(), v.to_owned()))
+                    .collect();
+                let key: Vec<_> = identifying_attributes(&attrs)
+                    .into_iter()
+                    .map(|(k, v)| (k.to_owned(), v.to_owned()))
+                    .collect();
+                if !key.is_empty() {
+                    groups
+                        .entry(key)
+                        .or_default()
+                        .push((secret_id.value().to_owned(), secret_version(&attrs)));
+                }
+            }
+            Ok(groups.into_values().filter(|group| group.len() > 1).collect())
+        })
+        .await??;
+
+        let visible: HashSet<_> = self.visible_items(&collection_id).into_iter().collect();
+        let mut sorted = vec![];
+        for group in groups {
+            let group: Vec<_> = group.into_iter().filter(|(id, _)| visible.contains(id)).collect();
+            if group.len() < 2 {
+                continue;
+            }
+            let mut keyed = Vec::with_capacity(group.len());
+            for (id, version) in group {
+                let modified = self
+                    .secret_times(&collection_id, &id)
+                    .await
+                    .ok()
+                    .and_then(|(_, modified)| modified);
+                keyed.push(((version, modified), id));
+            }
+            keyed.sort();
+            sorted.push(keyed.into_iter().map(|(_, id)| id).collect());
+        }
+        Ok(sorted)
+    }
+
+    /// how many secrets in a collection have each attribute
+    pub async fn count_attribute_keys(&self, collection_id: Arc<String>) -> Result<BTreeMap<String, u64>> {
+        let db = self.collection_db(collection_id.as_ref()).await?;
+        run_blocking(move || {
+            let tx = db.begin_read().into_result()?;
+            let attributes_reverse =
+                raise_nonexistent_table!(tx.open_table(ATTRIBUTES_TABLE_REVERSE), Ok(BTreeMap::new()));
+
+            let mut counts = BTreeMap::new();
+            for entry in attributes_reverse.iter().into_result()? {
+                let (_, attrs) = entry.into_result()?;
+                for key in attrs.value().into_keys() {
+                    *counts.entry(key.to_owned()).or_default() += 1;
+                }
+            }
+            Ok(counts)
+        })
+        .await?
+    }
+
+    /// get the number of secrets in a collection without listing its directory
+    pub async fn count_secrets(&self, collection_id: Arc<String>) -> Result<u64> {
+        let db = self.collection_db(collection_id.as_ref()).await?;
+        run_blocking(move || {
+            let tx = db.begin_read().into_result()?;
+
+            match tx.open_table(METADATA_TABLE) {
+                Ok(metadata) => {
+                    if let Some(count) = metadata.get(ITEM_COUNT_KEY).into_result()? {
+                        return Ok(count.value());
+                    }
+                }
+                Err(redb::TableError::TableDoesNotExist(_)) => {}
+                Err(e) => return Err(e).into_result(),
+            }
+
+            // we haven't started counting yet
+            let attributes_reverse =
+                raise_nonexistent_table!(tx.open_table(ATTRIBUTES_TABLE_REVERSE), Ok(0));
+            attributes_reverse.len().into_result()
+        })
+        .await?
+    }
+
+    /// when a collection was created, in unix seconds
+    /// older collections use the birth time of their directory, or 0 if it's unknown
+    pub async fn collection_created(&self, collection_id: Arc<String>) -> Result<u64> {
+        let db = self.collection_db(&collection_id).await?;
+        let recorded = run_blocking(move || -> Result<_> {
+            let tx = db.begin_read().into_result()?;
+            let metadata = raise_nonexistent_table!(tx.open_table(METADATA_TABLE), Ok(None));
+            let created = metadata.get(CREATED_KEY).into_result()?;
+            Ok(created.map(|created| created.value()))
+        })
+        .await??;
+        if let Some(created) = recorded {
+            return Ok(created);
+        }
+
+        let collection_path = Path::new(PASS_SUBDIR).join(&*collection_id);
+        let metadata = self.pass.stat_file(collection_path).await?;
+        Ok(metadata
+            .created()
+            .ok()
+            .and_then(|created| created.duration_since(SystemTime::UNIX_EPOCH).ok())
+            .map(|d| d.as_secs())
+            .unwrap_or_default())
+    }
+
+    /// get the filesystem metadata for this collection
+    pub async fn stat_collection(&self, collection_id: &str) -> Result<Metadata> {
+        // just use the attributes db file rather than actually calculating the last modified date
+        let collection_path = Path::new(PASS_SUBDIR)
+            .join(collection_id)
+            .join(ATTRIBUTES_DB);
+        self.metadata_store().stat_file(collection_path).await
+    }
+
+    /// ids of the secrets in a collection: the entries in its dir which are also in its db, so
+    /// other files there (e.g. backups) are never served as items
+    pub async fn list_secrets(&self, collection_id: &str) -> Result<Vec<String>> {
+        let mut ids = self.list_secret_files(collection_id).await?;
+        if let Backend::Pass(_) = self.backend(collection_id).await? {
+            let known: HashSet<_> = self
+                .list_secrets_with_attrs(collection_id)
+                .await?
+                .into_iter()
+                .collect();
+            ids.retain(|id| known.contains(id));
+        }
+        Ok(ids)
+    }
+
+    /// find the collections and secrets whose ids can't be part of an object path as they are,
+    /// and rename the secrets stored in pass. collections can't be renamed, since their
+    /// databases are open, so they (and secrets which can't be renamed) are served at escaped
+    /// paths instead
+    pub async fn fix_unreachable_ids(&self) -> Result<Vec<UnreachableId>> {
+        let mut found = vec![];
+        for collection_id in self.collections().await {
+            if !is_valid_id(&collection_id) {
+                warn!("Collection {collection_id:?} is served at an escaped path, since its id has characters besides letters, digits, and underscores. Rename its directory to fix it");
+                found.push(UnreachableId {
+                    collection_id: collection_id.clone(),
+                    secret_id: None,
+                    renamed_to: None,
+                });
+            }
+
+            let invalid: Vec<_> = self
+                .list_secrets(&collection_id)
+                .await?
+                .into_iter()
+                .filter(|secret_id| !is_valid_id(secret_id))
+                .collect();
+            if invalid.is_empty() {
+                continue;
+            }
+            let mut taken: HashSet<_> = self.list_secret_files(&collection_id).await?.into_iter().collect();
+            let is_pass = matches!(self.backend(&collection_id).await?, Backend::Pass(_));
+            for secret_id in invalid {
+                let renamed_to = if is_pass {
+                    let new_id = escaped_id(&secret_id, &taken);
+                    match self.rename_secret(&collection_id, &secret_id, &new_id).await {
+                        Ok(()) => {
+                            warn!("Renamed item {secret_id:?} in collection {collection_id} to {new_id}, since ids can only have letters, digits, and underscores");
+                            taken.insert(new_id.clone());
+                            Some(new_id)
+                        }
+                        Err(e) => {
+                            warn!("Item {secret_id:?} in collection {collection_id} could not be renamed, so it's served at an escaped path: {e}");
+                            None
+                        }
+                    }
+                } else {
+                    warn!("Item {secret_id:?} in collection {collection_id} is served at an escaped path, since its id has characters besides letters, digits, and underscores");
+                    None
+                };
+                found.push(UnreachableId {
+                    collection_id: collection_id.clone(),
+                    secret_id: Some(secret_id),
+                    renamed_to,
+                });
+            }
+        }
+        Ok(found)
+    }
+
+    /// move a secret in pass to a new id, with everything kept about it
+    async fn rename_secret(&self, collection_id: &str, from: &str, to: &str) -> Result {
+        let Backend::Pass(pass) = self.backend(collection_id).await? else {
+            return Err(Error::ReadOnly);
+        };
+        let from_path = Path::new(PASS_SUBDIR).join(collection_id).join(from);
+        let to_path = Path::new(PASS_SUBDIR).join(collection_id).join(to);
+        pass.move_password(&from_path, &to_path).await?;
+
+        let db = self.collection_db(collection_id).await?;
+        let (from_id, to_id) = (from.to_owned(), to.to_owned());
+        let renamed = run_blocking(move || -> Result {
+            let tx = db.begin_write().into_result()?;
+            rename_secret_entries(&tx, &from_id, &to_id)?;
+            tx.commit().into_result()?;
+            Ok(())
+        })
+        .await?;
+        if let Err(e) = renamed {
+            // so the entry isn't left without its metadata
+            pass.move_password(&to_path, &from_path).await?;
+            return Err(e);
+        }
+
+        self.uncache(collection_id, Some(from));
+        Ok(())
+    }
+
+    /// the items clients can see in a collection whose secrets are gone, e.g. since adding them
+    /// failed partway. items being added or deleted meanwhile aren't included
+    pub async fn stale_items(&self, collection_id: &str) -> Result<Vec<String>> {
+        // before listing, so items added since then aren't stale
+        let visible = self.visible_items(collection_id);
+        let stored: HashSet<_> = self.list_secrets(collection_id).await?.into_iter().collect();
+        // and after, since deleted items stop being visible before they're gone
+        let still_visible: HashSet<_> = self.visible_items(collection_id).into_iter().collect();
+        Ok(visible
+            .into_iter()
+            .filter(|secret_id| still_visible.contains(secret_id) && !stored.contains(secret_id))
+            // queued writes' files don't exist yet
+            .filter(|secret_id| {
+                !self.write_queue.as_ref().is_some_and(|queue| {
+                    queue.is_pending(&Path::new(PASS_SUBDIR).join(collection_id).join(secret_id))
+                })
+            })
+            .collect())
+    }
+
+    /// ids of every entry in a collection's dir, including ones which aren't in its db, e.g.
+    /// because they were pulled without it
+    pub async fn list_secret_files(&self, collection_id: &str) -> Result<Vec<String>> {
+        let Backend::Pass(pass) = self.backend(collection_id).await? else {
+            // don't depend on the remote store being reachable: the local attributes
+            // have an entry for every secret we wrote there
+            return self.list_secrets_with_attrs(collection_id).await;
+        };
+
+        let collection_path = Path::new(PASS_SUBDIR).join(collection_id);
+
+        Ok(pass
+            .list_items(collection_path)
+            .await?
+            .into_iter()
+            .filter_map(|(file_type, mut name)| {
+                if file_type.is_file() && name.ends_with(".gpg") {
+                    // remove the ".gpg"
+                    name.truncate(name.len() - 4);
+                    Some(name)
+                } else {
+                    None
+                }
+            })
+            .collect())
+    }
+
+    /// ids of the secrets clients can see in a collection, sorted by label unless that's disabled
+    pub async fn sorted_secrets(&self, collection_id: &str) -> Result<Vec<String>> {
+        let mut ids = self.visible_items(collection_id);
+        if self.config.sort_by_label {
+            let db = self.collection_db(collection_id).await?;
+            let labels = run_blocking(move || read_labels(&db)).await??;
+            sort_by_label(&mut ids, &labels);
+        }
+        Ok(ids)
+    }
+
+    /// the secrets our db has attributes for, even if the collection's directory is gone
+    pub async fn list_secrets_with_attrs(&self, collection_id: &str) -> Result<Vec<String>> {
+        let db = self.collection_db(collection_id).await?;
+        run_blocking(move || {
+            let tx = db.begin_read().into_result()?;
+            let attributes_reverse =
+                raise_nonexistent_table!(tx.open_table(ATTRIBUTES_TABLE_REVERSE), Ok(vec![]));
+            Ok(attributes_reverse
+                .iter()
+                .into_result()?
+                .map(|entry| Ok(entry?.0.value().to_owned()))
+                .collect::<RedbResult<_>>()?)
+        })
+        .await?
+    }
+
+    /// the filters configured for a collection's secrets
+    async fn filters(&self, collection_id: &str) -> Result<Option<&FilterConfig>> {
+        let aliases = self
+            .list_aliases_for_collection(Arc::new(collection_id.to_owned()))
+            .await?;
+        Ok(self.config.filters(collection_id, &aliases))
+    }
+
+    /// apply a collection's read filters to a secret before it's returned to a client
+    pub async fn filter_read(&self, collection_id: &str, secret_id: &str, value: Vec<u8>) -> Result<Vec<u8>> {
+        let content_type = self
+            .stored_content_type(Arc::new(collection_id.to_owned()), Arc::new(secret_id.to_owned()))
+            .await?;
+        let filters = self.filters(collection_id).await?;
+        Ok(apply_filters(read_filters(filters, content_type.as_deref()), value))
+    }
+
+    /// apply a collection's write filters to a secret from a client
+    pub async fn filter_write(&self, collection_id: &str, value: Vec<u8>, content_type: &str) -> Result<Vec<u8>> {
+        let filters = self.filters(collection_id).await?;
+        Ok(apply_filters(write_filters(filters, content_type), value))
+    }
+
+    /// get the key ids of the current recipients for a collection
+    async fn collection_recipients(&self, collection_id: &str) -> Result<Vec<HashSet<String>>> {
+        let collection_path = Path::new(PASS_SUBDIR).join(collection_id);
+        let recipients = self.pass.get_recipients(collection_path).await?;
+        self.pass.recipient_key_ids(&recipients).await
+    }
+
+    /// check whether a secret is encrypted to keys other than the current recipients
+    async fn is_secret_stale(
+        &self,
+        collection_id: &str,
+        secret_id: &str,
+        recipients: &[HashSet<String>],
+    ) -> Result<bool> {
+        let secret_path = Path::new(PASS_SUBDIR).join(collection_id).join(secret_id);
+        Ok(self
+            .pass
+            .read_password_key_ids(secret_path)
+            .await?
+            // assume secrets we can't parse are fine
+            .is_some_and(|key_ids| openpgp::is_stale(&key_ids, recipients)))
+    }
+
+    /// compare the keys a sample of each collection's secrets are encrypted to
+    /// with the collection's current `.gpg-id` recipients
+    pub async fn check_recipients(&self, sample_size: usize) -> Result<Vec<RecipientCheck>> {
+        let mut checks = vec![];
+
+        for collection_id in self.collections().await {
+            // only secrets in pass are encrypted
+            if !matches!(self.backend(&collection_id).await?, Backend::Pass(_)) {
+                continue;
+            }
+
+            let mut check = RecipientCheck {
+                collection_id,
+                checked: 0,
+                stale: 0,
+                initialized: true,
+            };
+            // one collection without keys shouldn't stop the others from being checked
+            let recipients = match self.collection_recipients(&check.collection_id).await {
+                Ok(recipients) => recipients,
+                Err(Error::NotInitialized(_)) => {
+                    check.initialized = false;
+                    checks.push(check);
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+
+            for secret_id in self
+                .list_secrets(&check.collection_id)
+                .await?
+                .iter()
+                .take(sample_size)
+            {
+                check.checked += 1;
+                if self
+                    .is_secret_stale(&check.collection_id, secret_id, &recipients)
+                    .await?
+                {
+                    check.stale += 1;
+                }
+            }
+
+            checks.push(check);
+        }
+
+        Ok(checks)
+    }
+
+    /// count the secrets encrypted to each key, most used first
+    /// only the packet headers are read, so nothing is decrypted
+    pub async fn key_usage(&self) -> Result<Vec<KeyUsage>> {
+        let mut counts = HashMap::<String, u32>::new();
+        for collection_id in self.collections().await {
+            // only secrets in pass are encrypted
+            if !matches!(self.backend(&collection_id).await?, Backend::Pass(_)) {
+                continue;
+            }
+
+            for secret_id in self.list_secrets(&collection_id).await? {
+                let secret_path = Path::new(PASS_SUBDIR).join(&collection_id).join(secret_id);
+                // secrets we can't parse aren't counted
+                let Some(mut key_ids) = self.pass.read_password_key_ids(secret_path).await? else {
+                    continue;
+                };
+                key_ids.sort();
+                key_ids.dedup();
+                for key_id in key_ids {
+                    *counts.entry(key_id).or_default() += 1;
+                }
+            }
+        }
+
+        let mut keys = self.pass.key_info().await?;
+        let mut usage: Vec<_> = counts
+            .into_iter()
+            .map(|(key_id, items)| KeyUsage {
+                key: keys.remove(&key_id),
+                key_id,
+                items,
+            })
+            .collect();
+        usage.sort_by(|a, b| b.items.cmp(&a.items).then_with(|| a.key_id.cmp(&b.key_id)));
+        Ok(usage)
+    }
+
+    /// re-encrypt every secret in a collection which isn't encrypted to the current recipients
+    /// returns the number of secrets which were re-encrypted
+    pub async fn reencrypt_collection(&self, collection_id: &str) -> Result<u32> {
+        if !matches!(self.backend(collection_id).await?, Backend::Pass(_)) {
+            return Ok(0);
+        }
+
+        let recipients = self.collection_recipients(collection_id).await?;
+
+        let mut count = 0;
+        for secret_id in self.list_secrets(collection_id).await? {
+            if self
+                .is_secret_stale(collection_id, &secret_id, &recipients)
+                .await?
+            {
+                let value = self.read_secret(collection_id, &secret_id, true).await?;
+                self.set_secret(collection_id, &secret_id, value).await?;
+                count += 1;
+            }
+        }
+
+        Ok(count)
+    }
+
+    /// how old a collection's secrets can get before they're rotated, if it has a policy
+    async fn rotation_max_age(&self, collection_id: &str) -> Result<Option<Duration>> {
+        let rotation = &self.config.rotation;
+        if !rotation.is_enabled() || !matches!(self.backend(collection_id).await?, Backend::Pass(_))
+        {
+            return Ok(None);
+        }
+
+        let aliases = self
+            .list_aliases_for_collection(Arc::new(collection_id.to_owned()))
+            .await?;
+        let months = u64::from(rotation.max_age_months(collection_id, &aliases));
+        Ok((months > 0).then(|| Duration::from_secs(months * 30 * 24 * 60 * 60)))
+    }
+
+    /// when a secret was last encrypted, if it's older than the max age
+    async fn rotation_due(
+        &self,
+        collection_id: &str,
+        secret_id: &str,
+        max_age: Duration,
+    ) -> Result<Option<SystemTime>> {
+        // secrets are only written when they're encrypted
+        let (_, modified) = self.secret_times(collection_id, secret_id).await?;
+        Ok(modified.filter(|modified| modified.elapsed().is_ok_and(|age| age > max_age)))
+    }
+
+    /// re-encrypt a secret which was just read if its collection's rotation policy says it's due
+    /// returns whether it was re-encrypted
+    pub async fn rotate_if_due(
+        &self,
+        collection_id: &str,
+        secret_id: &str,
+        value: Vec<u8>,
+    ) -> Result<bool> {
+        let Some(max_age) = self.rotation_max_age(collection_id).await? else {
+            return Ok(false);
+        };
+
+        // if it was written since it was read, this is no longer due, so we won't overwrite the
+        // new value. checked under the write lock, so nothing can be written in between
+        let _guard = self.lock_secret(collection_id, secret_id).await;
+        if self
+            .rotation_due(collection_id, secret_id, max_age)
+            .await?
+            .is_none()
+        {
+            return Ok(false);
+        }
+
+        let metadata = self.entry_metadata(collection_id, secret_id).await?;
+        self.write_password_locked(collection_id, secret_id, value, metadata)
+            .await?;
+        Ok(true)
+    }
+
+    /// find secrets which will be rotated when they're next read
+    /// returns a vec of (collection ID, secret ID, unix time it was last encrypted)
+    pub async fn pending_rotation(&self) -> Result<Vec<(String, String, u64)>> {
+        let mut pending = vec![];
+
+        for collection_id in self.collections().await {
+            let Some(max_age) = self.rotation_max_age(&collection_id).await? else {
+                continue;
+            };
+
+            for secret_id in self.list_secrets(&collection_id).await? {
+                if let Some(encrypted) = self
+                    .rotation_due(&collection_id, &secret_id, max_age)
+                    .await?
+                {
+                    let encrypted = encrypted
+                        .duration_since(SystemTime::UNIX_EPOCH)
+                        .map(|d| d.as_secs())
+                        .unwrap_or_default();
+                    pending.push((collection_id.clone(), secret_id, encrypted));
+                }
+            }
+        }
+
+        Ok(pending)
+    }
+
+    /// decrypt a secret stored in the given collection with the given id
+    /// if can_prompt is true, a gpg prompt may show, unless `no-interactive` is set
+    pub async fn read_secret(
+        &self,
+        collection_id: &str,
+        secret_id: &str,
+        can_prompt: bool,
+    ) -> Result<Vec<u8>> {
+        let entry = self.read_entry(collection_id, secret_id, can_prompt).await?;
+        Ok(match split_metadata(&entry, self.config.metadata_format) {
+            Some((value, _)) => value,
+            None => entry,
+        })
+    }
+
+    /// decrypt several secrets of a collection with one gpg process, e.g. for GetSecrets. only
+    /// secrets in the password store which aren't queued or cached are decrypted, and only if
+    /// `$XDG_RUNTIME_DIR` is a private tmpfs, since gpg writes them to files there briefly. the
+    /// others (and any which fail) are left out, to be read one at a time with `read_secret`
+    pub async fn read_secrets(
+        &self,
+        collection_id: &str,
+        secret_ids: &[String],
+        can_prompt: bool,
+    ) -> HashMap<String, Vec<u8>> {
+        let can_prompt = can_prompt && !self.config.no_interactive;
+        let Some(scratch) = std::env::var_os("XDG_RUNTIME_DIR")
+            .map(PathBuf::from)
+            .filter(|scratch| private_tmpfs(scratch))
+        else {
+            return HashMap::new();
+        };
+        let (pass, tree) = match self.backend(collection_id).await {
+            Ok(Backend::Pass(pass)) => (pass, false),
+            Ok(Backend::Tree(pass)) => (pass, true),
+            _ => return HashMap::new(),
+        };
+
+        // (secret id, path, version of its file)
+        let mut pending = vec![];
+        for secret_id in secret_ids {
+            let path = if tree {
+                let Some(path) = pass_tree::entry_path(secret_id) else {
+                    continue;
+                };
+                PathBuf::from(path)
+            } else {
+                let path = Path::new(PASS_SUBDIR).join(collection_id).join(secret_id);
+                if self.write_queue.as_ref().is_some_and(|queue| queue.is_pending(&path)) {
+                    continue;
+                }
+                path
+            };
+
+            let mut file_name = path.as_os_str().to_owned();
+            file_name.push(".gpg");
+            let version: Option<FileVersion> = match pass.stat_file(file_name).await {
+                Ok(metadata) => metadata.modified().ok().map(|modified| (modified, metadata.len())),
+                Err(_) => continue,
+            };
+            if let (Some(cache), Some(version)) = (&self.secret_cache, version) {
+                if cache.get(collection_id, secret_id, version).is_some() {
+                    continue;
+                }
+            }
+            pending.push((secret_id, path, version));
+        }
+        // one is read as quickly on its own
+        if pending.len() < 2 {
+            return HashMap::new();
+        }
+
+        let paths: Vec<_> = pending.iter().map(|(_, path, _)| path.clone()).collect();
+        let entries = match pass.read_passwords(&paths, &scratch, can_prompt).await {
+            Ok(entries) => entries,
+            Err(e) => {
+                warn!("Could not decrypt the secrets of collection {collection_id} together: {e}");
+                return HashMap::new();
+            }
+        };
+
+        let mut secrets = HashMap::new();
+        for ((secret_id, _, version), entry) in pending.into_iter().zip(entries) {
+            let Some(entry) = entry else {
+                continue;
+            };
+            if let (Some(cache), Some(version)) = (&self.secret_cache, version) {
+                cache.insert(collection_id, secret_id, version, &entry);
+            }
+            let value = match split_metadata(&entry, self.config.metadata_format) {
+                Some((value, _)) => value,
+                None => entry,
+            };
+            secrets.insert(secret_id.clone(), value);
+        }
+        if !tree {
+            if let Some(path) = paths.first() {
+                if let Err(e) = self.schedule_passphrase_clear(collection_id, path).await {
+                    warn!("Could not schedule clearing the passphrase for {collection_id}: {e}");
+                }
+            }
+        }
+        secrets
+    }
+
+    /// read a secret's whole entry, including any metadata embedded in it
+    async fn read_entry(
+        &self,
+        collection_id: &str,
+        secret_id: &str,
+        can_prompt: bool,
+    ) -> Result<Vec<u8>> {
+        let can_prompt = can_prompt && !self.config.no_interactive;
+        let pass = match self.backend(collection_id).await? {
+            Backend::Pass(pass) => pass,
+            Backend::Tree(pass) => {
+                let path = pass_tree::entry_path(secret_id).into_not_found()?;
+                return self.decrypt_entry(pass, collection_id, secret_id, Path::new(&path), can_prompt).await
+            }
+            Backend::Passthrough(store) => {
+                return timing::phase(Phase::Backend, store.read(collection_id, secret_id)).await
+            }
+            #[cfg(feature = "vault")]
+            Backend::Vault(vault, location) => {
+                return timing::phase(Phase::Backend, vault.read(location, collection_id, secret_id)).await
+            }
+        };
+
+        let secret_path = Path::new(PASS_SUBDIR).join(collection_id).join(secret_id);
+
+        // the newest value might not have been written yet
+        if let Some(queue) = &self.write_queue {
+            if let Some(value) = queue.read(pass, &secret_path, can_prompt).await {
+                return value;
+            }
+        }
+
+        let value = self.decrypt_entry(pass, collection_id, secret_id, &secret_path, can_prompt).await?;
+        if let Err(e) = self.schedule_passphrase_clear(collection_id, &secret_path).await {
+            warn!("Could not schedule clearing the passphrase for {collection_id}: {e}");
+        }
+        Ok(value)
+    }
+
+    /// decrypt an entry in the password store, or take it from the cache if that's enabled
+    /// and the file hasn't changed since
+    async fn decrypt_entry(
+        &self,
+        pass: &PasswordStore,
+        collection_id: &str,
+        secret_id: &str,
+        path: &Path,
+        can_prompt: bool,
+    ) -> Result<Vec<u8>> {
+        let Some(cache) = &self.secret_cache else {
+            return pass.read_password(path, can_prompt).await;
+        };
+
+        let mut file_name = path.as_os_str().to_owned();
+        file_name.push(".gpg");
+        let version: Option<FileVersion> = match pass.stat_file(file_name).await {
+            Ok(metadata) => metadata.modified().ok().map(|modified| (modified, metadata.len())),
+            Err(_) => None,
+        };
+        if let Some(value) = version.and_then(|version| cache.get(collection_id, secret_id, version)) {
+            return Ok(value);
+        }
+
+        let value = pass.read_password(path, can_prompt).await?;
+        if let Some(version) = version {
+            cache.insert(collection_id, secret_id, version, &value);
+        }
+        Ok(value)
+    }
+
+    /// if the collection has a passphrase timeout, make gpg-agent forget the passphrase
+    /// of the key used to decrypt a secret once it passes
+    async fn schedule_passphrase_clear(&self, collection_id: &str, secret_path: &Path) -> Result {
+        if self.config.passphrase_timeouts.is_empty() {
+            return Ok(());
+        }
+        let aliases = self
+            .list_aliases_for_collection(Arc::new(collection_id.to_owned()))
+            .await?;
+        let Some(timeout) = self.config.passphrase_timeout(collection_id, &aliases) else {
+            return Ok(());
+        };
+
+        let key_ids = self
+            .pass
+            .read_password_key_ids(secret_path)
+            .await?
+            .unwrap_or_default();
+        let deadline = Instant::now() + timeout;
+
+        let mut keygrips = self.pass.keygrips(&key_ids).await?;
+        {
+            let mut clears = self.passphrase_clears.lock().unwrap();
+            // an earlier clear is already scheduled. the timeout isn't extended by reading again
+            keygrips.retain(|keygrip| clears.get(keygrip).is_none_or(|at| *at > deadline));
+            for keygrip in &keygrips {
+                clears.insert(keygrip.clone(), deadline);
+            }
+        }
+
+        let clears = self.passphrase_clears.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep_until(deadline.into()).await;
+            for keygrip in keygrips {
+                {
+                    let mut clears = clears.lock().unwrap();
+                    if clears.get(&keygrip) == Some(&deadline) {
+                        clears.remove(&keygrip);
+                    }
+                }
+                if let Err(e) = agent::clear_passphrase(&keygrip).await {
+                    warn!("Could not clear the passphrase for {keygrip}: {e}");
+                }
+            }
+        });
+        Ok(())
+    }
+
+    /// find all secrets which expire at or before the given unix time
+    /// returns a vec of (collection ID, secret ID, expiry)
+    pub async fn list_expiring_secrets(&self, before: u64) -> Result<Vec<(String, String, u64)>> {
+        let cols = self.all_collection_dbs().await;
+        run_blocking(move || -> Result<_> {
+            let mut expiring = vec![];
+            for (id, db) in cols {
+                for (secret_id, expires) in expiring_in_collection(&db, before)? {
+                    expiring.push((id.clone(), secret_id, expires));
+                }
+            }
+            Ok(expiring)
+        })
+        .await?
+    }
+
+    pub async fn read_secret_attrs(
+        &self,
+        collection_id: Arc<String>,
+        secret_id: Arc<String>,
+    ) -> Result<HashMap<String, String>> {
+        // delete the attributes
+        let db = self.collection_db(&collection_id).await?;
+        run_blocking(move || {
+            let tx = db.begin_read().into_result()?;
+            let attributes_table_reverse =
+                raise_nonexistent_table!(tx.open_table(ATTRIBUTES_TABLE_REVERSE));
+
+            let secret_id = secret_id.as_str();
+
+            let attrs_guard = attributes_table_reverse
+                .get(secret_id)
+                .into_result()?
+                .into_not_found()?;
+            let attrs = attrs_guard.value();
+            Ok(attrs
+                .into_iter()
+                .map(|(k, v)| (k.to_owned(), v.to_owned()))
+                .collect())
+        })
+        .await?
+    }
+
+    /// remove a secret and its attributes
+    pub async fn delete_secret(
+        &self,
+        collection_id: Arc<String>,
+        secret_id: Arc<String>,
+    ) -> Result {
+        let secret_path = Path::new(PASS_SUBDIR)
+            .join(&*collection_id)
+            .join(&*secret_id);
+
+        self.uncache(&collection_id, Some(&secret_id));
+
+        // delete the password, or move it to the trash
+        let mut trashed = false;
+        match self.backend(&collection_id).await? {
+            Backend::Pass(pass) => {
+                if let Some(queue) = &self.write_queue {
+                    queue.cancel(&secret_path).await;
+                }
+                // a secret whose write was still queued has no file to keep
+                trashed = self.config.trash.retention_days > 0
+                    && pass.file_exists(secret_file_name(&collection_id, &secret_id)).await?;
+                if trashed {
+                    pass.move_password(secret_path, trash_path(&collection_id, &secret_id))
+                        .await?;
+                } else {
+                    pass.delete_password(secret_path).await?;
+                }
+            }
+            Backend::Tree(_) => return Err(Error::ReadOnly),
+            Backend::Passthrough(store) => {
+                timing::phase(Phase::Backend, store.delete(&collection_id, &secret_id)).await?
+            }
+            #[cfg(feature = "vault")]
+            Backend::Vault(vault, location) => {
+                timing::phase(Phase::Backend, vault.delete(location, &collection_id, &secret_id)).await?
+            }
+        }
+        for mirror in self.mirrors_for(&collection_id).await? {
+            mirror.delete(&collection_id, &secret_id);
+        }
+
+        if trashed {
+            self.trash_attributes(collection_id.clone(), secret_id.clone()).await?;
+        } else {
+            self.remove_attributes(collection_id.clone(), secret_id.clone()).await?;
+        }
+
+        self.publish_change(&collection_id, Some(&secret_id), ChangeKind::Deleted);
+        Ok(())
+    }
+
+    async fn trash_attributes(&self, collection_id: Arc<String>, secret_id: Arc<String>) -> Result {
+        let deleted = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        let db = self.collection_db(&collection_id).await?;
+        run_blocking(move || -> Result<()> {
+            let tx = db.begin_write().into_result()?;
+            trash_secret_entries(&tx, &secret_id, deleted)?;
+            tx.commit().into_result()?;
+
+            Ok(())
+        })
+        .await?
+    }
+
+    /// the secrets in a collection's trash: (id, label, unix seconds it was deleted), most
+    /// recently deleted first
+    pub async fn list_trash(&self, collection_id: Arc<String>) -> Result<Vec<(String, String, u64)>> {
+        let db = self.collection_db(&collection_id).await?;
+        run_blocking(move || {
+            let tx = db.begin_read().into_result()?;
+            let trash = raise_nonexistent_table!(tx.open_table(TRASH_TABLE), Ok(vec![]));
+            let labels = read_labels(&db)?;
+
+            let mut trashed = trash
+                .iter()
+                .into_result()?
+                .map(|entry| {
+                    let (id, value) = entry.into_result()?;
+                    let id = id.value().to_owned();
+                    let label = labels.get(&id).cloned().unwrap_or_default();
+                    Ok((id, label, value.value().0))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            trashed.sort_by_key(|(_, _, deleted)| Reverse(*deleted));
+            Ok(trashed)
+        })
+        .await?
+    }
+
+    /// move a secret out of its collection's trash
+    pub async fn restore_secret(&self, collection_id: Arc<String>, secret_id: Arc<String>) -> Result {
+        let Backend::Pass(pass) = self.backend(&collection_id).await? else {
+            return Err(io::Error::from(io::ErrorKind::NotFound).into());
+        };
+        if !self
+            .list_trash(collection_id.clone())
+            .await?
+            .iter()
+            .any(|(id, _, _)| *id == *secret_id)
+        {
+            return Err(io::Error::from(io::ErrorKind::NotFound).into());
+        }
+        // e.g. a secret with the same id was pulled from another machine since
+        if pass.file_exists(secret_file_name(&collection_id, &secret_id)).await? {
+            return Err(io::Error::from(io::ErrorKind::AlreadyExists).into());
+        }
+
+        let secret_path = Path::new(PASS_SUBDIR).join(&*collection_id).join(&*secret_id);
+        pass.move_password(trash_path(&collection_id, &secret_id), secret_path)
+            .await?;
+
+        let db = self.collection_db(&collection_id).await?;
+        let secret = secret_id.clone();
+        run_blocking(move || -> Result<()> {
+            let tx = db.begin_write().into_result()?;
+            restore_secret_entries(&tx, &secret)?;
+            tx.commit().into_result()?;
+
+            Ok(())
+        })
+        .await??;
+
+        self.publish_change(&collection_id, Some(&secret_id), ChangeKind::Created);
+        Ok(())
+    }
+
+    /// delete the secrets in a collection's trash which `purge(id, unix seconds it was
+    /// deleted)` picks for good. returns how many were purged
+    pub async fn purge_trash(
+        &self,
+        collection_id: Arc<String>,
+        purge: impl Fn(&str, u64) -> bool,
+    ) -> Result<u32> {
+        let Backend::Pass(pass) = self.backend(&collection_id).await? else {
+            return Ok(0);
+        };
+        let purged: Vec<_> = self
+            .list_trash(collection_id.clone())
+            .await?
+            .into_iter()
+            .filter(|(id, _, deleted)| purge(id, *deleted))
+            .map(|(id, _, _)| id)
+            .collect();
+        if purged.is_empty() {
+            return Ok(0);
+        }
+
+        for id in &purged {
+            pass.delete_password(trash_path(&collection_id, id)).await?;
+        }
+
+        let db = self.collection_db(&collection_id).await?;
+        let ids = purged.clone();
+        run_blocking(move || -> Result<()> {
+            let tx = db.begin_write().into_result()?;
+            let mut trash = tx.open_table(TRASH_TABLE).into_result()?;
+            for id in &ids {
+                trash.remove(id.as_str()).into_result()?;
+                remove_secret_details(&tx, id)?;
+            }
+            drop(trash);
+            tx.commit().into_result()?;
+
+            Ok(())
+        })
+        .await??;
+
+        Ok(purged.len() as u32)
+    }
+
+    /// purge secrets which have been in the trash for longer than the retention period, every
+    /// hour. if the trash is disabled, anything left in it is purged once
+    pub async fn purge_old_trash(&self) {
+        let retention = u64::from(self.config.trash.retention_days) * 24 * 60 * 60;
+        let mut interval = tokio::time::interval(TRASH_PURGE_INTERVAL);
+        loop {
+            interval.tick().await;
+            let cutoff = SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default()
+                .saturating_sub(retention);
+            for id in self.collections().await {
+                match self.purge_trash(Arc::new(id.clone()), |_, deleted| deleted <= cutoff).await {
+                    Ok(0) => {}
+                    Ok(count) => info!("Purged {count} secret(s) from the trash of collection {id}"),
+                    Err(e) => warn!("Could not purge the trash of collection {id}: {e}"),
+                }
+            }
+            if retention == 0 {
+                return;
+            }
+        }
+    }
+
+    /// drop what's kept about a secret whose password was deleted outside the daemon
+    pub async fn forget_secret(&self, collection_id: Arc<String>, secret_id: Arc<String>) -> Result {
+        self.uncache(&collection_id, Some(&secret_id));
+        match self.remove_attributes(collection_id, secret_id).await {
+            // it never had any, e.g. it was added and removed elsewhere
+            Err(Error::IoError(e)) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            result => result,
+        }
+    }
+
+    async fn remove_attributes(&self, collection_id: Arc<String>, secret_id: Arc<String>) -> Result {
+        let db = self.collection_db(&collection_id).await?;
+        run_blocking(move || -> Result<()> {
+            let tx = db.begin_write().into_result()?;
+            remove_secret_entries(&tx, &secret_id)?;
+            tx.commit().into_result()?;
+
+            Ok(())
+        })
+        .await?
+    }
+
+    pub async fn secret_times(&self, collection_id: &str, secret_id: &str) -> Result<SecretTimes> {
+        let (pass, secret_path) = match self.backend(collection_id).await? {
+            Backend::Pass(pass) => (pass, Path::new(PASS_SUBDIR).join(collection_id).join(secret_id)),
+            Backend::Tree(pass) => (pass, PathBuf::from(pass_tree::entry_path(secret_id).into_not_found()?)),
+            Backend::Passthrough(store) => return store.times(collection_id, secret_id).await,
+            #[cfg(feature = "vault")]
+            Backend::Vault(vault, location) => return vault.times(location, collection_id, secret_id).await,
+        };
+
+        let mut file_name = secret_path.into_os_string();
+        file_name.push(".gpg");
+        let metadata = pass.stat_file(file_name).await?;
+        Ok((metadata.created().ok(), metadata.modified().ok()))
+    }
+
+    /// a secret's path in the password store (relative to it and without `.gpg`, as `pass show`
+    /// takes it) and the size of its encrypted file
+    /// returns None if the collection's secrets aren't stored in pass
+    pub async fn secret_file(&self, collection_id: &str, secret_id: &str) -> Result<Option<(String, u64)>> {
+        let (pass, pass_path) = match self.backend(collection_id).await? {
+            Backend::Pass(pass) => {
+                let path = Path::new(PASS_SUBDIR).join(collection_id).join(secret_id);
+                (pass, path.to_string_lossy().into_owned())
+            }
+            Backend::Tree(pass) => (pass, pass_tree::entry_path(secret_id).into_not_found()?),
+            _ => return Ok(None),
+        };
+
+        let metadata = pass.stat_file(format!("{pass_path}.gpg")).await?;
+        Ok(Some((pass_path, metadata.len())))
+    }
+
+    /// whether reading a secret would show pinentry: it has its own PIN, or gpg-agent doesn't
+    /// have the passphrase of any key it was encrypted to cached. secrets stored elsewhere
+    /// never need one
+    pub async fn requires_prompt(&self, collection_id: &str, secret_id: &str) -> Result<bool> {
+        if self
+            .has_item_pin(Arc::new(collection_id.to_owned()), Arc::new(secret_id.to_owned()))
+            .await?
+        {
+            return Ok(true);
+        }
+
+        let (pass, path) = match self.backend(collection_id).await? {
+            Backend::Pass(pass) => (pass, Path::new(PASS_SUBDIR).join(collection_id).join(secret_id)),
+            Backend::Tree(pass) => (pass, pass_tree::entry_path(secret_id).into_not_found()?.into()),
+            _ => return Ok(false),
+        };
+        let key_ids = pass.read_password_key_ids(&path).await?.unwrap_or_default();
+        let keygrips = pass.keygrips(&key_ids).await?;
+        // without a secret key it can't be decrypted at all
+        if keygrips.is_empty() {
+            return Ok(false);
+        }
+        // gpg only needs one of them
+        for keygrip in keygrips {
+            if agent::is_unlocked(&keygrip).await? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// creates a new secret in a collection with the given label, attributes, and value
+    /// returns the secret ID
+    pub async fn create_secret(
+        &self,
+        collection_id: Arc<String>,
+        label: Option<String>,
+        secret: Vec<u8>,
+        attributes: Arc<HashMap<String, String>>,
+    ) -> Result<String> {
+        let label = label.unwrap_or_else(|| "Untitled Secret".to_owned());
+        let (secret_id, _stable_ids) = if self.config.stable_item_ids {
+            let guard = self.stable_id_lock.lock().await;
+            let mut taken: HashSet<_> = self.list_secret_files(&collection_id).await?.into_iter().collect();
+            // so restoring a secret from the trash can't replace a new one
+            taken.extend(self.list_trash(collection_id.clone()).await?.into_iter().map(|(id, _, _)| id));
+            (stable_secret_id(&label, &attributes, &taken), Some(guard))
+        } else {
+            (nanoid!(8, &NANOID_ALPHABET), None)
+        };
+
+        let checksum = self.checksum(&secret);
+
+        // write the password
+        let metadata = self.config.embed_metadata.then(|| EntryMetadata {
+            label: Some(label.clone()),
+            attributes: (*attributes).clone(),
+        });
+        self.write_password(&collection_id, &secret_id, secret, metadata)
+            .await?;
+        self.add_secret_entries(collection_id.clone(), secret_id.clone(), label, checksum, attributes)
+            .await?;
+
+        self.publish_change(&collection_id, Some(&secret_id), ChangeKind::Created);
+        Ok(secret_id)
+    }
+
+    /// create a secret whose file isn't encrypted, for tests which don't have gpg
+    #[cfg(test)]
+    pub async fn create_test_secret(
+        &self,
+        collection_id: Arc<String>,
+        attributes: Arc<HashMap<String, String>>,
+    ) -> Result<String> {
+        let secret_id = nanoid!(8, &NANOID_ALPHABET);
+        let path = Path::new(PASS_SUBDIR).join(&*collection_id).join(&secret_id);
+        self.pass.write_encrypted(path, b"not encrypted").await?;
+        self.add_secret_entries(collection_id.clone(), secret_id.clone(), "Test".into(), None, attributes)
+            .await?;
+
+        self.publish_change(&collection_id, Some(&secret_id), ChangeKind::Created);
+        Ok(secret_id)
+    }
+
+    /// add a new secret's label, attributes, and checksum to its collection's db
+    async fn add_secret_entries(
+        &self,
+        collection_id: Arc<String>,
+        secret_id: String,
+        label: String,
+        checksum: Option<blake3::Hash>,
+        attributes: Arc<HashMap<String, String>>,
+    ) -> Result {
+        let db = self.collection_db(&collection_id).await?;
+        run_blocking(move || {
+            let tx = db.begin_write().into_result()?;
+            // this has to happen before the reverse table is opened
+            adjust_item_count(&tx, 1).into_result()?;
+
+            let mut attributes_table = open_index(&tx).into_result()?;
+            let mut attributes_table_reverse =
+                tx.open_table(ATTRIBUTES_TABLE_REVERSE).into_result()?;
+            let mut labels_table = tx.open_table(LABELS_TABLE).into_result()?;
+            let mut checksums_table = tx.open_table(CHECKSUMS_TABLE).into_result()?;
+
+            let value = secret_id.as_str();
+
+            if let Some(checksum) = checksum {
+                checksums_table
+                    .insert(value, checksum.as_bytes().as_slice())
+                    .into_result()?;
+            }
+
+            labels_table.insert(value, label.as_str()).into_result()?;
+
+            let attributes_ref = attributes
+                .iter()
+                .map(|(k, v)| (k.as_str(), v.as_str()))
+                .collect::<HashMap<_, _>>();
+
+            // insert the new attributes
+            if let Some(attributes_table) = &mut attributes_table {
+                for (k, v) in &*attributes {
+                    attributes_table
+                        .insert((k.as_str(), v.as_str()), value)
+                        .into_result()?;
+                }
+            }
+            attributes_table_reverse
+                .insert(value, attributes_ref)
+                .into_result()?;
+
+            drop(attributes_table);
+            drop(attributes_table_reverse);
+            drop(labels_table);
+            drop(checksums_table);
+            tx.commit().into_result()?;
+
+            Ok(())
+        })
+        .await?
+    }
+
+    pub async fn set_secret(&self, collection_id: &str, secret_id: &str, value: Vec<u8>) -> Result {
+        let checksum = self.checksum(&value);
+
+        // write the password
+        let metadata = self.entry_metadata(collection_id, secret_id).await?;
+        self.write_password(collection_id, secret_id, value, metadata)
+            .await?;
+        self.publish_change(collection_id, Some(secret_id), ChangeKind::Changed);
+
+        // update the checksum
+        // if checksums are disabled, remove the old one so it can't go stale
+        let db = self.collection_db(collection_id).await?;
+        let secret_id = secret_id.to_owned();
+        run_blocking(move || {
+            let tx = db.begin_write().into_result()?;
+            let mut checksums_table = tx.open_table(CHECKSUMS_TABLE).into_result()?;
+            match checksum {
+                Some(checksum) => checksums_table
+                    .insert(secret_id.as_str(), checksum.as_bytes().as_slice())
+                    .map(|_| ()),
+                None => checksums_table.remove(secret_id.as_str()).map(|_| ()),
+            }
+            .into_result()?;
+            drop(checksums_table);
+            tx.commit().into_result()?;
+
+            Ok(())
+        })
+        .await?
+    }
+
+    /// get the hex checksum of a secret's value
+    /// returns None if checksums are disabled or the secret was written without one
+    pub async fn get_secret_checksum(
+        &self,
+        collection_id: Arc<String>,
+        secret_id: Arc<String>,
+    ) -> Result<Option<String>> {
+        if self.checksum_key.is_none() {
+            return Ok(None);
+        }
 
-            // if we couldn't find an existing ID, make a new collection
-            let id = if let Some(id) = existing_id {
-                id
-            } else {
-                let id = format!("{}_{}", slugify(&label), nanoid!(4, &NANOID_ALPHABET));
+        let db = self.collection_db(collection_id.as_str()).await?;
+        run_blocking(move || {
+            let tx = db.begin_read().into_result()?;
+            let checksums_table =
+                raise_nonexistent_table!(tx.open_table(CHECKSUMS_TABLE), Ok(None));
+            let checksum = checksums_table.get(secret_id.as_str()).into_result()?;
 
-                // set the label and alias
-                if let Some(alias) = alias.as_ref() {
-                    // remove this alias from its old target's alias list
-                    if let Some(old_target) = aliases.insert(alias.as_str(), id.as_str())? {
-                        aliases_reverse.remove(old_target.value(), alias.as_str())?;
-                    }
-                    aliases_reverse.insert(id.as_str(), alias.as_str())?;
-                }
-                labels.insert(id.as_str(), label.as_ref())?;
+            Ok(checksum
+                .and_then(|checksum| <[u8; blake3::OUT_LEN]>::try_from(checksum.value()).ok())
+                .map(|bytes| blake3::Hash::from_bytes(bytes).to_hex().to_string()))
+        })
+        .await?
+    }
 
-                id
+    /// the content type a secret was stored with
+    pub async fn get_content_type(&self, collection_id: Arc<String>, secret_id: Arc<String>) -> Result<String> {
+        Ok(self
+            .stored_content_type(collection_id, secret_id)
+            .await?
+            .unwrap_or_else(|| DEFAULT_CONTENT_TYPE.to_owned()))
+    }
+
+    /// the content type a client stored a secret with, or None if it wasn't stored through the
+    /// API (e.g. an entry made with pass)
+    async fn stored_content_type(&self, collection_id: Arc<String>, secret_id: Arc<String>) -> Result<Option<String>> {
+        let db = self.collection_db(collection_id.as_str()).await?;
+        run_blocking(move || {
+            let tx = db.begin_read().into_result()?;
+            let content_types = raise_nonexistent_table!(tx.open_table(CONTENT_TYPES_TABLE), Ok(None));
+            let content_type = content_types.get(secret_id.as_str()).into_result()?;
+            Ok(content_type.map(|content_type| content_type.value().to_owned()))
+        })
+        .await?
+    }
+
+    pub async fn set_content_type(
+        &self,
+        collection_id: Arc<String>,
+        secret_id: Arc<String>,
+        content_type: String,
+    ) -> Result {
+        let db = self.collection_db(collection_id.as_str()).await?;
+        run_blocking(move || {
+            // kept for text too, so entries made with pass (which have none) can be told apart
+            let content_type = if content_type.is_empty() { DEFAULT_CONTENT_TYPE.to_owned() } else { content_type };
+            // most secrets keep theirs, so don't write when nothing changed
+            let read_tx = db.begin_read().into_result()?;
+            let stored = match read_tx.open_table(CONTENT_TYPES_TABLE) {
+                Ok(table) => table
+                    .get(secret_id.as_str())
+                    .into_result()?
+                    .map(|stored| stored.value().to_owned()),
+                Err(redb::TableError::TableDoesNotExist(_)) => None,
+                Err(e) => return Err(e).into_result(),
             };
+            drop(read_tx);
+            if stored.as_deref() == Some(content_type.as_str()) {
+                return Ok(());
+            }
 
-            drop(aliases);
-            drop(aliases_reverse);
-            drop(labels);
-            tx.commit()?;
+            let tx = db.begin_write().into_result()?;
+            let mut content_types = tx.open_table(CONTENT_TYPES_TABLE).into_result()?;
+            content_types
+                .insert(secret_id.as_str(), content_type.as_str())
+                .into_result()?;
+            drop(content_types);
+            tx.commit().into_result()?;
+            Ok(())
+        })
+        .await?
+    }
 
-            Ok(id)
+    /// when the secret was last read and by which client. None if it hasn't been read
+    /// since this was recorded
+    pub async fn last_access(
+        &self,
+        collection_id: Arc<String>,
+        secret_id: Arc<String>,
+    ) -> Result<Option<(u64, String)>> {
+        let db = self.collection_db(collection_id.as_str()).await?;
+        run_blocking(move || {
+            let tx = db.begin_read().into_result()?;
+            let accesses = raise_nonexistent_table!(tx.open_table(LAST_ACCESS_TABLE), Ok(None));
+            let access = accesses.get(secret_id.as_str()).into_result()?;
+            Ok(access.map(|access| {
+                let (time, exe) = access.value();
+                (time, exe.to_owned())
+            }))
         })
-        .await
-        .unwrap()?;
+        .await?
+    }
 
-        let mut collections = self.collection_dbs.write().await;
+    /// record that `exe` just read the secret
+    pub async fn record_access(&self, collection_id: Arc<String>, secret_id: Arc<String>, exe: String) -> Result {
+        let db = self.collection_db(collection_id.as_str()).await?;
+        run_blocking(move || {
+            let now = SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default();
+            let tx = db.begin_write().into_result()?;
+            tx.open_table(LAST_ACCESS_TABLE)
+                .into_result()?
+                .insert(secret_id.as_str(), (now, exe.as_str()))
+                .into_result()?;
+            tx.commit().into_result()?;
+            Ok(())
+        })
+        .await?
+    }
 
-        if !collections.contains_key(&collection_id) {
-            // we need to actually create this collection
+    pub async fn set_secret_label(
+        &self,
+        collection_id: Arc<String>,
+        secret_id: Arc<String>,
+        label: String,
+    ) -> Result {
+        // write the attributes
+        let db = self.collection_db(&collection_id).await?;
+        let secret = secret_id.clone();
+        run_blocking(move || {
+            let tx = db.begin_write().into_result()?;
+            let mut labels_table = tx.open_table(LABELS_TABLE).into_result()?;
 
-            let mut collection_path = Path::new(PASS_SUBDIR).join(&collection_id);
-            self.pass.make_dir(&collection_path).await?;
+            let old_label = labels_table
+                .insert(secret.as_str(), label.as_str())
+                .into_result()?
+                .map(|old| old.value().to_owned());
+            if let Some(old_label) = old_label.filter(|old_label| *old_label != label) {
+                let now = SystemTime::now()
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .map(|d| d.as_secs())
+                    .unwrap_or_default();
+                record_old_label(&tx, &secret, &old_label, now).into_result()?;
+            }
 
-            collection_path.push(ATTRIBUTES_DB);
-            let db = open_db(&self.pass, collection_path).await?;
+            drop(labels_table);
+            tx.commit().into_result()?;
 
-            collections.insert(collection_id.clone(), db);
-        }
+            Ok::<_, Error>(())
+        })
+        .await??;
 
-        Ok(collection_id)
+        self.rewrite_entry(&collection_id, &secret_id).await?;
+        self.publish_change(&collection_id, Some(&secret_id), ChangeKind::Changed);
+        Ok(())
     }
 
-    /// delete a collection and all its secrets
-    pub async fn delete_collection(&self, collection_id: Arc<String>) -> Result {
-        // remove it from the collection db map
-        self.collection_dbs.write().await.remove(&*collection_id);
-        // remove the dir
-        let collection_path = Path::new(PASS_SUBDIR).join(&*collection_id);
-        self.pass.remove_dir(collection_path).await?;
+    /// the labels a secret had before, with when each was changed (unix seconds), oldest first
+    pub async fn label_history(
+        &self,
+        collection_id: Arc<String>,
+        secret_id: Arc<String>,
+    ) -> Result<Vec<(String, u64)>> {
+        let db = self.collection_db(&collection_id).await?;
+        run_blocking(move || {
+            let tx = db.begin_read().into_result()?;
+            let history = raise_nonexistent_table!(tx.open_table(LABEL_HISTORY_TABLE), Ok(vec![]));
 
-        let db = self.db.clone();
+            let secret_id = secret_id.as_str();
+            history
+                .range((secret_id, 0)..=(secret_id, u32::MAX))
+                .into_result()?
+                .map(|entry| {
+                    let (_, value) = entry.into_result()?;
+                    let (time, label) = value.value();
+                    Ok((label.to_owned(), time))
+                })
+                .collect()
+        })
+        .await?
+    }
 
-        // remove entries from our db
-        spawn_blocking(move || -> RedbResult<_> {
-            let tx = db.begin_write()?;
+    pub async fn get_secret_label(
+        &self,
+        collection_id: Arc<String>,
+        secret_id: Arc<String>,
+    ) -> Result<String> {
+        let db = self.collection_db(&collection_id).await?;
+        run_blocking(move || {
+            let tx = db.begin_read().into_result()?;
+            let labels_table = raise_nonexistent_table!(tx.open_table(LABELS_TABLE));
 
-            let mut aliases = tx.open_table(ALIASES_TABLE)?;
-            let mut aliases_reverse = tx.open_multimap_table(ALIASES_TABLE_REVERSE)?;
-            let mut labels = tx.open_table(LABELS_TABLE)?;
+            let label = labels_table
+                .get(secret_id.as_str())
+                .into_result()?
+                .into_not_found()?
+                .value()
+                .to_owned();
 
-            // remove each alias
-            for alias in aliases_reverse.remove_all(collection_id.as_str())? {
-                aliases.remove(alias?.value())?;
+            Ok(label)
+        })
+        .await?
+    }
+
+    /// the secondary attribute sets ("views") which also find a secret in searches
+    pub async fn read_secret_views(
+        &self,
+        collection_id: Arc<String>,
+        secret_id: Arc<String>,
+    ) -> Result<Vec<HashMap<String, String>>> {
+        let db = self.collection_db(&collection_id).await?;
+        run_blocking(move || {
+            let tx = db.begin_read().into_result()?;
+            let views = raise_nonexistent_table!(tx.open_table(VIEWS_TABLE), Ok(vec![]));
+
+            let secret_id = secret_id.as_str();
+            views
+                .range((secret_id, 0)..=(secret_id, u32::MAX))
+                .into_result()?
+                .map(|entry| {
+                    let (_, view) = entry.into_result()?;
+                    Ok(view
+                        .value()
+                        .into_iter()
+                        .map(|(k, v)| (k.to_owned(), v.to_owned()))
+                        .collect())
+                })
+                .collect()
+        })
+        .await?
+    }
+
+    /// add a view to a secret
+    /// returns false if it already has one with the same attributes
+    pub async fn add_secret_view(
+        &self,
+        collection_id: Arc<String>,
+        secret_id: Arc<String>,
+        attrs: HashMap<String, String>,
+    ) -> Result<bool> {
+        let db = self.collection_db(&collection_id).await?;
+        let secret = secret_id.clone();
+        let added = run_blocking(move || {
+            let tx = db.begin_write().into_result()?;
+
+            let secret_id = secret.as_str();
+            // make sure the secret exists
+            tx.open_table(ATTRIBUTES_TABLE_REVERSE)
+                .into_result()?
+                .get(secret_id)
+                .into_result()?
+                .into_not_found()?;
+
+            let mut views = tx.open_table(VIEWS_TABLE).into_result()?;
+            let mut view_attributes = tx.open_multimap_table(VIEW_ATTRIBUTES_TABLE).into_result()?;
+
+            let attrs_ref = attrs
+                .iter()
+                .map(|(k, v)| (k.as_str(), v.as_str()))
+                .collect::<HashMap<_, _>>();
+
+            let mut index = 0;
+            for entry in views
+                .range((secret_id, 0)..=(secret_id, u32::MAX))
+                .into_result()?
+            {
+                let (key, view) = entry.into_result()?;
+                if view.value() == attrs_ref {
+                    return Ok::<_, Error>(false);
+                }
+                index = key.value().1 + 1;
             }
 
-            // remove the label
-            labels.remove(collection_id.as_str())?;
+            for (k, v) in &attrs_ref {
+                view_attributes
+                    .insert((*k, *v), (secret_id, index))
+                    .into_result()?;
+            }
+            views.insert((secret_id, index), attrs_ref).into_result()?;
 
-            drop(aliases);
-            drop(aliases_reverse);
-            drop(labels);
+            drop(views);
+            drop(view_attributes);
+            tx.commit().into_result()?;
+            Ok(true)
+        })
+        .await??;
 
-            tx.commit()?;
+        if added {
+            self.publish_change(&collection_id, Some(&secret_id), ChangeKind::Changed);
+        }
+        Ok(added)
+    }
 
-            Ok(())
+    /// remove the view with the given attributes from a secret
+    /// returns false if it doesn't have one
+    pub async fn remove_secret_view(
+        &self,
+        collection_id: Arc<String>,
+        secret_id: Arc<String>,
+        attrs: HashMap<String, String>,
+    ) -> Result<bool> {
+        let db = self.collection_db(&collection_id).await?;
+        let secret = secret_id.clone();
+        let removed = run_blocking(move || {
+            let tx = db.begin_write().into_result()?;
+
+            let attrs_ref = attrs
+                .iter()
+                .map(|(k, v)| (k.as_str(), v.as_str()))
+                .collect::<HashMap<_, _>>();
+            let removed = remove_views(&tx, &secret, Some(&attrs_ref)).into_result()?;
+
+            tx.commit().into_result()?;
+            Ok::<_, Error>(removed)
         })
-        .await
-        .unwrap()?;
+        .await??;
 
-        Ok(())
+        if removed {
+            self.publish_change(&collection_id, Some(&secret_id), ChangeKind::Changed);
+        }
+        Ok(removed)
     }
 
-    /// search all collections for secrets matching the given attributes
-    /// returns a map of collection id to items
-    pub async fn search_all_collections(
+    /// read the attributes for the given secret
+    pub async fn set_secret_attrs(
         &self,
-        attributes: HashMap<String, String>,
-    ) -> Result<HashMap<String, Vec<String>>> {
-        let collections = self.collection_dbs.clone();
-        spawn_blocking(move || -> Result<_> {
-            let cols = collections.blocking_read();
-            cols.iter()
-                .map(|(id, db)| {
-                    // search each collection
-                    Ok((id.to_owned(), search_collection(&attributes, db)?))
+        collection_id: Arc<String>,
+        secret_id: Arc<String>,
+        attrs: HashMap<String, String>,
+    ) -> Result {
+        let db = self.collection_db(&collection_id).await?;
+        let secret = secret_id.clone();
+        run_blocking(move || {
+            let tx = db.begin_write().into_result()?;
+            replace_attrs(&tx, &secret, &attrs).into_result()?;
+            // it may not have been indexed before, e.g. if its file was pulled without its metadata
+            recount_items(&tx).into_result()?;
+            tx.commit().into_result()?;
+
+            Ok::<_, Error>(())
+        })
+        .await??;
+
+        self.rewrite_entry(&collection_id, &secret_id).await?;
+        self.publish_change(&collection_id, Some(&secret_id), ChangeKind::Changed);
+        Ok(())
+    }
+
+    /// the metadata to write into a secret's entry, if that's enabled
+    async fn entry_metadata(&self, collection_id: &str, secret_id: &str) -> Result<Option<EntryMetadata>> {
+        if !self.config.embed_metadata || self.is_read_only(collection_id) {
+            return Ok(None);
+        }
+        let collection_id = Arc::new(collection_id.to_owned());
+        let secret_id = Arc::new(secret_id.to_owned());
+        Ok(Some(EntryMetadata {
+            label: Some(self.get_secret_label(collection_id.clone(), secret_id.clone()).await?),
+            attributes: self.read_secret_attrs(collection_id, secret_id).await?,
+        }))
+    }
+
+    /// write a secret's current label and attributes into its entry, if that's enabled
+    async fn rewrite_entry(&self, collection_id: &str, secret_id: &str) -> Result {
+        let Some(metadata) = self.entry_metadata(collection_id, secret_id).await? else {
+            return Ok(());
+        };
+        let value = self.read_secret(collection_id, secret_id, true).await?;
+        self.write_password(collection_id, secret_id, value, Some(metadata))
+            .await
+    }
+
+    /// remove a collection's entries for secrets whose files are gone, and rebuild its indexes,
+    /// e.g. after its db was merged by git
+    /// returns the ids of the secrets which were removed, and the number of files with no entry
+    pub async fn repair_collection(&self, collection_id: &str) -> Result<(Vec<String>, u32)> {
+        let files: HashSet<_> = self.list_secret_files(collection_id).await?.into_iter().collect();
+        let pending: HashSet<_> = match &self.write_queue {
+            Some(queue) => self
+                .list_secrets_with_attrs(collection_id)
+                .await?
+                .into_iter()
+                .filter(|secret_id| {
+                    queue.is_pending(&Path::new(PASS_SUBDIR).join(collection_id).join(secret_id))
                 })
-                .collect()
+                .collect(),
+            None => HashSet::new(),
+        };
+
+        let db = self.collection_db(collection_id).await?;
+        let (removed, unindexed) = run_blocking(move || {
+            let tx = db.begin_write().into_result()?;
+            // queued writes' files don't exist yet
+            let removed = repair_entries(&tx, |secret_id| {
+                files.contains(secret_id) || pending.contains(secret_id)
+            })?;
+            let indexed: HashSet<_> = secret_ids(&tx, ATTRIBUTES_TABLE_REVERSE)?.into_iter().collect();
+            let unindexed = files.difference(&indexed).count() as u32;
+            tx.commit().into_result()?;
+
+            Ok::<_, Error>((removed, unindexed))
         })
-        .await
-        .unwrap()
+        .await??;
+
+        for secret_id in &removed {
+            self.uncache(collection_id, Some(secret_id));
+            self.publish_change(collection_id, Some(secret_id), ChangeKind::Deleted);
+        }
+        Ok((removed, unindexed))
+    }
+
+    /// replace a secret's label and attributes with the ones embedded in its entry,
+    /// e.g. after it was changed by pass or pulled with git
+    /// returns false if the entry doesn't have any
+    pub async fn reindex_secret(&self, collection_id: Arc<String>, secret_id: Arc<String>) -> Result<bool> {
+        let entry = self.read_entry(&collection_id, &secret_id, true).await?;
+        let Some((_, metadata)) = split_metadata(&entry, self.config.metadata_format) else {
+            return Ok(false);
+        };
+        // e.g. entries made with gopass
+        let label = metadata.label.unwrap_or_else(|| secret_id.to_string());
+
+        let db = self.collection_db(&collection_id).await?;
+        let secret = secret_id.clone();
+        run_blocking(move || {
+            let tx = db.begin_write().into_result()?;
+            replace_attrs(&tx, &secret, &metadata.attributes).into_result()?;
+            let mut labels_table = tx.open_table(LABELS_TABLE).into_result()?;
+            labels_table
+                .insert(secret.as_str(), label.as_str())
+                .into_result()?;
+            drop(labels_table);
+            // it may not have been indexed before
+            recount_items(&tx).into_result()?;
+            tx.commit().into_result()?;
+
+            Ok::<_, Error>(())
+        })
+        .await??;
+
+        self.publish_change(&collection_id, Some(&secret_id), ChangeKind::Changed);
+        Ok(true)
+    }
+}
+
+#[test]
+fn test_parse_expiry() {
+    assert_eq!(parse_expiry("1700000000"), Some(1700000000));
+    assert_eq!(parse_expiry("1970-01-01"), Some(0));
+    assert_eq!(parse_expiry("2024-03-01"), Some(1709251200));
+    assert_eq!(parse_expiry(" 2000-02-29 "), Some(951782400));
+
+    assert_eq!(parse_expiry("1969-12-31"), None);
+    assert_eq!(parse_expiry("2024-13-01"), None);
+    assert_eq!(parse_expiry("next tuesday"), None);
+}
+
+#[test]
+fn test_apply_filters() {
+    let strip = [Filter::StripTrailingNewline];
+    assert_eq!(apply_filters(&strip, b"hunter2\n".to_vec()), b"hunter2");
+    assert_eq!(apply_filters(&strip, b"hunter2\r\n".to_vec()), b"hunter2");
+    // only one
+    assert_eq!(apply_filters(&strip, b"hunter2\n\n".to_vec()), b"hunter2\n");
+
+    let add = [Filter::AddTrailingNewline];
+    assert_eq!(apply_filters(&add, b"hunter2".to_vec()), b"hunter2\n");
+    assert_eq!(apply_filters(&add, b"hunter2\n".to_vec()), b"hunter2\n");
+
+    let trim = [Filter::Trim];
+    assert_eq!(apply_filters(&trim, b" \thunter 2 \n".to_vec()), b"hunter 2");
+    assert_eq!(apply_filters(&trim, b" \n".to_vec()), b"");
+
+    // in order
+    assert_eq!(apply_filters(&[Filter::Trim, Filter::AddTrailingNewline], b" x ".to_vec()), b"x\n");
+}
+
+#[test]
+fn test_read_filters() {
+    let trim = FilterConfig {
+        read: vec![Filter::Trim],
+        write: vec![Filter::AddTrailingNewline],
+    };
+
+    // only entries made with pass lose their newline by default
+    assert_eq!(read_filters(None, None), [Filter::StripTrailingNewline]);
+    let stored = read_filters(None, Some(DEFAULT_CONTENT_TYPE));
+    assert_eq!(apply_filters(stored, b"hunter2\n".to_vec()), b"hunter2\n");
+    assert!(read_filters(None, Some("application/octet-stream")).is_empty());
+
+    // configured filters apply to text
+    assert_eq!(read_filters(Some(&trim), None), [Filter::Trim]);
+    assert_eq!(read_filters(Some(&trim), Some(DEFAULT_CONTENT_TYPE)), [Filter::Trim]);
+    assert!(read_filters(Some(&trim), Some("application/octet-stream")).is_empty());
+
+    assert!(write_filters(None, DEFAULT_CONTENT_TYPE).is_empty());
+    assert_eq!(write_filters(Some(&trim), ""), [Filter::AddTrailingNewline]);
+    assert!(write_filters(Some(&trim), "application/octet-stream").is_empty());
+}
+
+#[test]
+fn test_search_views() {
+    let db = Database::builder()
+        .create_with_backend(redb::backends::InMemoryBackend::new())
+        .unwrap();
+    let attrs = |pairs: &[(&str, &str)]| -> HashMap<String, String> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    };
+    let search = |pairs: &[(&str, &str)]| {
+        let mut found = search_collection(&attrs(pairs), &db).unwrap();
+        found.sort();
+        found
+    };
+
+    // s1 uses the new schema, with a view for the old one. s2 only has the old one
+    let tx = db.begin_write().unwrap();
+    {
+        let mut attributes = tx.open_multimap_table(ATTRIBUTES_TABLE).unwrap();
+        let mut attributes_reverse = tx.open_table(ATTRIBUTES_TABLE_REVERSE).unwrap();
+        for (id, version) in [("s1", "new"), ("s2", "old")] {
+            attributes.insert(("user", "me"), id).unwrap();
+            attributes.insert(("schema", version), id).unwrap();
+            attributes_reverse
+                .insert(id, HashMap::from([("user", "me"), ("schema", version)]))
+                .unwrap();
+        }
+        let mut views = tx.open_table(VIEWS_TABLE).unwrap();
+        let mut view_attributes = tx.open_multimap_table(VIEW_ATTRIBUTES_TABLE).unwrap();
+        views
+            .insert(
+                ("s1", 0),
+                HashMap::from([("user", "me"), ("app", "legacy"), ("schema", "old")]),
+            )
+            .unwrap();
+        view_attributes.insert(("user", "me"), ("s1", 0)).unwrap();
+        view_attributes.insert(("app", "legacy"), ("s1", 0)).unwrap();
+        view_attributes.insert(("schema", "old"), ("s1", 0)).unwrap();
     }
+    tx.commit().unwrap();
+
+    assert_eq!(search(&[("schema", "old")]), ["s1", "s2"]);
+    assert_eq!(search(&[("schema", "new")]), ["s1"]);
+    assert_eq!(search(&[("app", "legacy")]), ["s1"]);
+    // found by its attributes and the view, but only listed once
+    assert_eq!(search(&[("user", "me")]), ["s1", "s2"]);
+    // attributes from the view and the item aren't mixed
+    assert!(search(&[("app", "legacy"), ("schema", "new")]).is_empty());
+    assert_eq!(count_matches(&attrs(&[("user", "me")]), &db, 1).unwrap(), 1);
+
+    let tx = db.begin_write().unwrap();
+    assert!(!remove_views(&tx, "s1", Some(&HashMap::from([("schema", "new")]))).unwrap());
+    assert!(remove_views(&tx, "s1", None).unwrap());
+    tx.commit().unwrap();
+    assert_eq!(search(&[("schema", "old")]), ["s2"]);
+}
+
+#[test]
+fn test_repair_entries() {
+    let db = Database::builder()
+        .create_with_backend(redb::backends::InMemoryBackend::new())
+        .unwrap();
+    let search = |pairs: &[(&str, &str)]| {
+        let attrs = pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        let mut found = search_collection(&attrs, &db).unwrap();
+        found.sort();
+        found
+    };
 
-    /// search the specific collection for secrets matching the given attributes
-    pub async fn search_collection(
-        &self,
-        collection_id: Arc<String>,
-        attributes: Arc<HashMap<String, String>>,
-    ) -> Result<Vec<String>> {
-        let collections = self.collection_dbs.clone();
-        spawn_blocking(move || {
-            let cols = collections.blocking_read();
-            let db = cols.get(collection_id.as_ref()).into_not_found()?;
-            Ok(search_collection(&attributes, db)?)
-        })
-        .await
-        .unwrap()
+    // the index is missing s1 and still has s3, and s2's file is gone
+    let tx = db.begin_write().unwrap();
+    {
+        let mut attributes = tx.open_multimap_table(ATTRIBUTES_TABLE).unwrap();
+        let mut attributes_reverse = tx.open_table(ATTRIBUTES_TABLE_REVERSE).unwrap();
+        attributes.insert(("user", "me"), "s2").unwrap();
+        attributes.insert(("user", "me"), "s3").unwrap();
+        for id in ["s1", "s2"] {
+            attributes_reverse.insert(id, HashMap::from([("user", "me")])).unwrap();
+        }
+        tx.open_table(VIEWS_TABLE)
+            .unwrap()
+            .insert(("s2", 0), HashMap::from([("app", "legacy")]))
+            .unwrap();
+        tx.open_multimap_table(VIEW_ATTRIBUTES_TABLE)
+            .unwrap()
+            .insert(("app", "legacy"), ("s2", 0))
+            .unwrap();
+        tx.open_table(CONTENT_TYPES_TABLE).unwrap().insert("s3", "text/plain").unwrap();
     }
+    tx.commit().unwrap();
+    assert_eq!(search(&[("user", "me")]), ["s2"]);
 
-    /// get the filesystem metadata for this collection
-    pub async fn stat_collection(&self, collection_id: &str) -> Result<Metadata> {
-        // just use the attributes db file rather than actually calculating the last modified date
-        let collection_path = Path::new(PASS_SUBDIR)
-            .join(&collection_id)
-            .join(ATTRIBUTES_DB);
-        Ok(self.pass.stat_file(collection_path).await?)
+    let tx = db.begin_write().unwrap();
+    assert_eq!(repair_entries(&tx, |secret_id| secret_id == "s1").unwrap(), ["s2"]);
+    tx.commit().unwrap();
+
+    assert_eq!(search(&[("user", "me")]), ["s1"]);
+    assert!(search(&[("app", "legacy")]).is_empty());
+    let tx = db.begin_read().unwrap();
+    assert!(tx.open_table(CONTENT_TYPES_TABLE).unwrap().is_empty().unwrap());
+    let metadata = tx.open_table(METADATA_TABLE).unwrap();
+    assert_eq!(metadata.get(ITEM_COUNT_KEY).unwrap().unwrap().value(), 1);
+}
+
+#[test]
+fn test_remove_from_uncounted_collection() {
+    let db = Database::builder()
+        .create_with_backend(redb::backends::InMemoryBackend::new())
+        .unwrap();
+    // a collection from before the count was kept
+    let tx = db.begin_write().unwrap();
+    {
+        let mut attributes_reverse = tx.open_table(ATTRIBUTES_TABLE_REVERSE).unwrap();
+        for id in ["s1", "s2", "s3"] {
+            attributes_reverse.insert(id, HashMap::from([("user", "me")])).unwrap();
+        }
     }
+    tx.commit().unwrap();
 
-    pub async fn list_secrets(&self, collection_id: &str) -> Result<Vec<String>> {
-        let collection_path = Path::new(PASS_SUBDIR).join(&collection_id);
+    let tx = db.begin_write().unwrap();
+    remove_secret_entries(&tx, "s2").unwrap();
+    tx.commit().unwrap();
 
-        Ok(self
-            .pass
-            .list_items(collection_path)
-            .await?
-            .into_iter()
-            .filter_map(|(file_type, mut name)| {
-                if file_type.is_file() && name.ends_with(".gpg") {
-                    // remove the ".gpg"
-                    name.truncate(name.len() - 4);
-                    Some(name)
-                } else {
-                    None
-                }
+    let tx = db.begin_read().unwrap();
+    let metadata = tx.open_table(METADATA_TABLE).unwrap();
+    assert_eq!(metadata.get(ITEM_COUNT_KEY).unwrap().unwrap().value(), 2);
+}
+
+#[test]
+fn test_label_history() {
+    let db = Database::builder()
+        .create_with_backend(redb::backends::InMemoryBackend::new())
+        .unwrap();
+    let history = |secret_id: &str| {
+        let tx = db.begin_read().unwrap();
+        let table = tx.open_table(LABEL_HISTORY_TABLE).unwrap();
+        table
+            .range((secret_id, 0)..=(secret_id, u32::MAX))
+            .unwrap()
+            .map(|entry| {
+                let (_, value) = entry.unwrap();
+                let (time, label) = value.value();
+                (label.to_owned(), time)
             })
-            .collect())
-    }
+            .collect::<Vec<_>>()
+    };
 
-    /// decrypt a secret stored in the given collection with the given id
-    /// if can_prompt is true, a gpg prompt may show
-    pub async fn read_secret(
-        &self,
-        collection_id: &str,
-        secret_id: &str,
-        can_prompt: bool,
-    ) -> Result<Vec<u8>> {
-        let secret_path = Path::new(PASS_SUBDIR).join(collection_id).join(secret_id);
+    let tx = db.begin_write().unwrap();
+    record_old_label(&tx, "s1", "Password", 10).unwrap();
+    record_old_label(&tx, "s1", "Mail password", 20).unwrap();
+    record_old_label(&tx, "s2", "Other", 30).unwrap();
+    tx.commit().unwrap();
+    assert_eq!(history("s1"), [("Password".to_owned(), 10), ("Mail password".to_owned(), 20)]);
 
-        Ok(self.pass.read_password(secret_path, can_prompt).await?)
+    // only the newest are kept
+    let tx = db.begin_write().unwrap();
+    for time in 0..LABEL_HISTORY_LIMIT as u64 {
+        record_old_label(&tx, "s1", &format!("Label {time}"), 100 + time).unwrap();
     }
+    tx.commit().unwrap();
+    let s1 = history("s1");
+    assert_eq!(s1.len(), LABEL_HISTORY_LIMIT);
+    assert_eq!(s1[0], ("Label 0".to_owned(), 100));
+    assert_eq!(history("s2"), [("Other".to_owned(), 30)]);
 
-    /// read the attributes for the given secret
-    pub async fn read_secret_attrs(
-        &self,
-        collection_id: Arc<String>,
-        secret_id: Arc<String>,
-    ) -> Result<HashMap<String, String>> {
-        // delete the attributes
-        let collections = self.collection_dbs.clone();
-        spawn_blocking(move || {
-            let cols = collections.blocking_read();
-            let db = cols.get(&*collection_id).into_not_found()?;
-            let tx = db.begin_read().into_result()?;
-            let attributes_table_reverse =
-                raise_nonexistent_table!(tx.open_table(ATTRIBUTES_TABLE_REVERSE));
+    // and they're removed with the secret
+    let tx = db.begin_write().unwrap();
+    tx.open_table(ATTRIBUTES_TABLE_REVERSE).unwrap().insert("s1", HashMap::new()).unwrap();
+    remove_secret_entries(&tx, "s1").unwrap();
+    tx.commit().unwrap();
+    assert!(history("s1").is_empty());
+    assert_eq!(history("s2").len(), 1);
+}
 
-            let secret_id = secret_id.as_str();
+#[test]
+fn test_stable_secret_id() {
+    let attrs = |pairs: &[(&str, &str)]| -> HashMap<String, String> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    };
+    let none = HashSet::new();
 
-            let attrs_guard = attributes_table_reverse
-                .get(secret_id)
-                .into_result()?
-                .into_not_found()?;
-            let attrs = attrs_guard.value();
-            Ok(attrs
-                .into_iter()
-                .map(|(k, v)| (k.to_owned(), v.to_owned()))
-                .collect())
-        })
-        .await
-        .unwrap()
-    }
+    let id = stable_secret_id("GitHub", &attrs(&[("user", "me"), ("host", "github.com")]), &none);
+    assert_eq!(id.len(), 8);
+    assert!(id.chars().all(|c| NANOID_ALPHABET.contains(&c)));
+    // the label doesn't matter when there are attributes
+    assert_eq!(stable_secret_id("Other", &attrs(&[("host", "github.com"), ("user", "me")]), &none), id);
+    assert_ne!(stable_secret_id("GitHub", &attrs(&[("user", "me")]), &none), id);
+    assert_ne!(stable_secret_id("GitHub", &attrs(&[("user", "me\0host"), ("github.com", "")]), &none), id);
 
-    /// remove a secret and its attributes
-    pub async fn delete_secret(
-        &self,
-        collection_id: Arc<String>,
-        secret_id: Arc<String>,
-    ) -> Result {
-        let secret_path = Path::new(PASS_SUBDIR)
-            .join(&*collection_id)
-            .join(&*secret_id);
+    // it is when there aren't
+    let unlabeled = stable_secret_id("GitHub", &HashMap::new(), &none);
+    assert_eq!(stable_secret_id("GitHub", &HashMap::new(), &none), unlabeled);
+    assert_ne!(stable_secret_id("GitLab", &HashMap::new(), &none), unlabeled);
 
-        // delete the password
-        self.pass.delete_password(secret_path).await?;
+    // an item alike gets the next one, the same each time
+    let taken = HashSet::from([id.clone()]);
+    let next = stable_secret_id("GitHub", &attrs(&[("user", "me"), ("host", "github.com")]), &taken);
+    assert_ne!(next, id);
+    let taken = HashSet::from([id, next.clone()]);
+    let third = stable_secret_id("GitHub", &attrs(&[("user", "me"), ("host", "github.com")]), &taken);
+    assert!(!taken.contains(&third));
+}
 
-        // delete the attributes
-        let collections = self.collection_dbs.clone();
-        spawn_blocking(move || -> Result<()> {
-            let cols = collections.blocking_read();
-            let db = cols.get(&*collection_id).into_not_found()?;
+#[test]
+fn test_index_modes() {
+    let db = Database::builder()
+        .create_with_backend(redb::backends::InMemoryBackend::new())
+        .unwrap();
+    let attrs = |pairs: &[(&str, &str)]| -> HashMap<String, String> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    };
+    let search = |pairs: &[(&str, &str)]| {
+        let mut found = search_collection(&attrs(pairs), &db).unwrap();
+        found.sort();
+        found
+    };
+    let write = |secret_id: &str, pairs: &[(&str, &str)]| {
+        let tx = db.begin_write().unwrap();
+        replace_attrs(&tx, secret_id, &attrs(pairs)).unwrap();
+        tx.commit().unwrap();
+    };
+    let has_index = || {
+        let tx = db.begin_read().unwrap();
+        match tx.open_multimap_table(ATTRIBUTES_TABLE) {
+            Ok(_) => true,
+            Err(redb::TableError::TableDoesNotExist(_)) => false,
+            Err(e) => panic!("{e}"),
+        }
+    };
 
-            let tx = db.begin_write().into_result()?;
-            let mut attributes_table = tx.open_multimap_table(ATTRIBUTES_TABLE).into_result()?;
-            let mut attributes_table_reverse =
-                tx.open_table(ATTRIBUTES_TABLE_REVERSE).into_result()?;
+    // collections from before the mode existed are indexed
+    write("s1", &[("user", "me"), (EXPIRES_ATTRIBUTE, "100")]);
+    write("s2", &[("user", "me"), ("app", "mail")]);
+    assert_eq!(search(&[("user", "me")]), ["s1", "s2"]);
 
-            let secret_id = secret_id.as_str();
+    // too small to keep it
+    assert_eq!(apply_index_mode(&db, IndexMode::Auto).unwrap(), Some(false));
+    assert!(!has_index());
+    assert_eq!(search(&[("user", "me")]), ["s1", "s2"]);
+    assert_eq!(search(&[("user", "me"), ("app", "mail")]), ["s2"]);
+    assert_eq!(expiring_in_collection(&db, 200).unwrap(), [("s1".to_owned(), 100)]);
 
-            // get the attrs for this secret
-            let attrs_guard = attributes_table_reverse
-                .remove(secret_id)
-                .into_result()?
-                .into_not_found()?;
-            let attrs = attrs_guard.value();
-            for (k, v) in attrs {
-                attributes_table.remove((k, v), secret_id).into_result()?;
-            }
+    // changes are found without it
+    write("s1", &[("user", "you")]);
+    write("s3", &[("user", "me")]);
+    assert!(!has_index());
+    assert_eq!(search(&[("user", "me")]), ["s2", "s3"]);
+    assert!(expiring_in_collection(&db, 200).unwrap().is_empty());
+    let tx = db.begin_write().unwrap();
+    remove_secret_entries(&tx, "s3").unwrap();
+    tx.commit().unwrap();
 
-            drop(attributes_table);
-            drop(attrs_guard);
-            drop(attributes_table_reverse);
-            tx.commit().into_result()?;
+    // rebuilt from the secrets' attributes
+    assert_eq!(apply_index_mode(&db, IndexMode::Auto).unwrap(), None);
+    assert_eq!(apply_index_mode(&db, IndexMode::Always).unwrap(), Some(true));
+    assert!(has_index());
+    assert_eq!(search(&[("user", "me")]), ["s2"]);
+    assert_eq!(search(&[("user", "you")]), ["s1"]);
+    assert_eq!(apply_index_mode(&db, IndexMode::Always).unwrap(), None);
+}
 
-            Ok(())
-        })
-        .await
-        .unwrap()?;
+#[test]
+fn test_embed_metadata() {
+    use MetadataFormat::Browserpass;
 
-        Ok(())
-    }
+    let metadata = EntryMetadata {
+        label: Some("GitHub".to_owned()),
+        attributes: HashMap::from([
+            ("xdg:schema".to_owned(), "org.example.Token".to_owned()),
+            ("user".to_owned(), "me".to_owned()),
+        ]),
+    };
+    let entry = embed_metadata(b"hunter2\n".to_vec(), &metadata, Browserpass).unwrap();
+    assert_eq!(
+        entry,
+        b"hunter2\npass:label: GitHub\nuser: me\nxdg:schema: org.example.Token\n"
+    );
+    assert_eq!(split_metadata(&entry, Browserpass), Some((b"hunter2".to_vec(), metadata)));
 
-    pub async fn stat_secret(&self, collection_id: &str, secret_id: &str) -> Result<Metadata> {
-        let secret_path = Path::new(PASS_SUBDIR)
-            .join(&*collection_id)
-            .join(&format!("{secret_id}.gpg"));
+    // plain entries, including multi-line ones, are left alone
+    assert_eq!(split_metadata(b"hunter2\n", Browserpass), None);
+    assert_eq!(split_metadata(b"hunter2\nuser: me\n", Browserpass), None);
+    assert_eq!(split_metadata(b"line 1\nline 2\npass:label: x\n", Browserpass), None);
 
-        Ok(self.pass.stat_file(secret_path).await?)
-    }
+    let empty = EntryMetadata {
+        label: Some(String::new()),
+        attributes: HashMap::new(),
+    };
+    assert!(embed_metadata(b"line 1\nline 2".to_vec(), &empty, Browserpass).is_err());
+    let bad_key = EntryMetadata {
+        label: Some(String::new()),
+        attributes: HashMap::from([("a: b".to_owned(), String::new())]),
+    };
+    assert!(embed_metadata(b"x".to_vec(), &bad_key, Browserpass).is_err());
+    assert!(embed_metadata(vec![0xff, 0xfe], &empty, Browserpass).is_err());
 
-    /// creates a new secret in a collection with the given label, attributes, and value
-    /// returns the secret ID
-    pub async fn create_secret(
-        &self,
-        collection_id: Arc<String>,
-        label: Option<String>,
-        secret: Vec<u8>,
-        attributes: Arc<HashMap<String, String>>,
-    ) -> Result<String> {
-        let collection_dir = Path::new(PASS_SUBDIR).join(&*collection_id);
+    // those are written without it instead of failing
+    assert_eq!(entry_contents(b"line 1\nline 2".to_vec(), &empty, Browserpass), b"line 1\nline 2");
+    assert_eq!(entry_contents(vec![0xff, 0xfe], &empty, Browserpass), [0xff, 0xfe]);
+    assert_eq!(entry_contents(b"hunter2".to_vec(), &empty, Browserpass), b"hunter2\npass:label: \n");
+}
 
-        let secret_id = nanoid!(8, &NANOID_ALPHABET);
+#[test]
+fn test_gopass_metadata() {
+    use MetadataFormat::Gopass;
 
-        let secret_path = collection_dir.join(&*secret_id);
+    let metadata = EntryMetadata {
+        label: Some("GitHub".to_owned()),
+        attributes: HashMap::from([("user".to_owned(), "me".to_owned())]),
+    };
+    // the rest of the secret goes after the metadata
+    let entry = embed_metadata(b"hunter2\nrecovery: abc\n".to_vec(), &metadata, Gopass).unwrap();
+    assert_eq!(entry, b"hunter2\npass:label: GitHub\nuser: me\n\nrecovery: abc\n");
+    assert_eq!(
+        split_metadata(&entry, Gopass),
+        Some((b"hunter2\nrecovery: abc".to_vec(), metadata))
+    );
 
-        // write the password
-        self.pass.write_password(secret_path, secret).await?;
+    // entries made with gopass, which have no label
+    let (secret, metadata) = split_metadata(b"hunter2\nurl: example.com\nnotes\nmore notes\n", Gopass).unwrap();
+    assert_eq!(secret, b"hunter2\nnotes\nmore notes");
+    assert_eq!(metadata.label, None);
+    assert_eq!(metadata.attributes, HashMap::from([("url".to_owned(), "example.com".to_owned())]));
+    let (secret, metadata) = split_metadata(b"hunter2\n---\nuser: me\n", Gopass).unwrap();
+    assert_eq!(secret, b"hunter2");
+    assert_eq!(metadata.attributes, HashMap::from([("user".to_owned(), "me".to_owned())]));
 
-        // write the attributes
-        let collections = self.collection_dbs.clone();
-        spawn_blocking(move || {
-            let cols = collections.blocking_read();
+    assert_eq!(split_metadata(b"hunter2\n", Gopass), None);
+    assert_eq!(split_metadata(b"hunter2\njust notes\n", Gopass), None);
+}
+
+#[test]
+fn test_parse_pulled_changes() {
+    let diff = format!(
+        "A\t{PASS_SUBDIR}/work/new.gpg\nM\t{PASS_SUBDIR}/work/edited.gpg\nT\t{PASS_SUBDIR}/work/retyped.gpg\n\
+         D\t{PASS_SUBDIR}/personal/old.gpg\nM\t{PASS_SUBDIR}/work/{ATTRIBUTES_DB}\nM\t{PASS_SUBDIR}/{COLLECTIONS_DB}\n\
+         A\t{PASS_SUBDIR}/work/nested/entry.gpg\nM\telsewhere/work/entry.gpg\n"
+    );
+    let pulled = parse_pulled_changes(&diff);
+    assert_eq!(
+        pulled,
+        HashMap::from([
+            (
+                "work".to_owned(),
+                PulledChanges {
+                    added: vec!["new".to_owned()],
+                    changed: vec!["edited".to_owned(), "retyped".to_owned()],
+                    removed: vec![],
+                }
+            ),
+            (
+                "personal".to_owned(),
+                PulledChanges {
+                    removed: vec!["old".to_owned()],
+                    ..Default::default()
+                }
+            ),
+        ])
+    );
+    assert!(parse_pulled_changes("").is_empty());
+}
 
-            // get the db or return an error
-            let db = cols.get(&*collection_id).into_not_found()?;
+#[test]
+fn test_entry_names() {
+    let ids = ["a", "b", "c", "d", "e"].map(String::from);
+    let labels = HashMap::from([
+        ("a".to_owned(), "GitHub".to_owned()),
+        ("b".to_owned(), "GitHub".to_owned()),
+        ("c".to_owned(), "work/email".to_owned()),
+        ("d".to_owned(), " .hidden\n".to_owned()),
+    ]);
+    let names = entry_names(&ids, &labels, HashSet::from(["e".to_owned()]));
+    assert_eq!(names["a"], "GitHub");
+    assert_eq!(names["b"], "GitHub (2)");
+    assert_eq!(names["c"], "work-email");
+    assert_eq!(names["d"], "hidden");
+    // no label, and its id is taken by another file
+    assert_eq!(names["e"], "e (2)");
+}
 
-            let tx = db.begin_write().into_result()?;
-            let mut attributes_table = tx.open_multimap_table(ATTRIBUTES_TABLE).into_result()?;
-            let mut attributes_table_reverse =
-                tx.open_table(ATTRIBUTES_TABLE_REVERSE).into_result()?;
-            let mut labels_table = tx.open_table(LABELS_TABLE).into_result()?;
+#[test]
+fn test_keep_reserved() {
+    let existing = HashMap::from([
+        ("user".to_owned(), "me".to_owned()),
+        (EXPIRES_ATTRIBUTE.to_owned(), "2030-01-01".to_owned()),
+    ]);
 
-            let value = secret_id.as_str();
+    // written back unchanged
+    let mut attributes = existing.clone();
+    attributes.insert("user".to_owned(), "you".to_owned());
+    assert!(keep_reserved(&mut attributes, &existing).is_empty());
+    assert_eq!(attributes[EXPIRES_ATTRIBUTE], "2030-01-01");
 
-            let label = label
-                .map(Cow::Owned)
-                .unwrap_or_else(|| "Untitled Secret".into());
-            labels_table.insert(value, label.as_ref()).into_result()?;
+    let mut attributes = HashMap::from([
+        (EXPIRES_ATTRIBUTE.to_owned(), "2099-01-01".to_owned()),
+        ("me.grimsteel.client".to_owned(), "firefox".to_owned()),
+    ]);
+    assert_eq!(
+        keep_reserved(&mut attributes, &existing),
+        ["me.grimsteel.client", EXPIRES_ATTRIBUTE]
+    );
+    assert_eq!(
+        attributes,
+        HashMap::from([(EXPIRES_ATTRIBUTE.to_owned(), "2030-01-01".to_owned())])
+    );
+}
 
-            let attributes_ref = attributes
-                .iter()
-                .map(|(k, v)| (k.as_str(), v.as_str()))
-                .collect::<HashMap<_, _>>();
+#[tokio::test]
+async fn test_metadata_dir() {
+    let dir = std::env::temp_dir().join(format!(
+        "pass-secret-service-test-{}",
+        nanoid!(8, &NANOID_ALPHABET)
+    ));
+    let pass = Box::leak(Box::new(PasswordStore::with_directory(dir.join("pass"))));
+    let collection_id = {
+        let config = Box::leak(Box::new(Config::default()));
+        let store = SecretStore::new(pass, config).await.unwrap();
+        store.create_collection(Some("Work".to_owned()), None).await.unwrap()
+    };
+    let db_path = Path::new(PASS_SUBDIR).join(&collection_id).join(ATTRIBUTES_DB);
+    assert!(pass.file_exists(&db_path).await.unwrap());
 
-            // insert the new attributes
-            for (k, v) in &*attributes {
-                attributes_table
-                    .insert((k.as_str(), v.as_str()), value)
-                    .into_result()?;
-            }
-            attributes_table_reverse
-                .insert(value, attributes_ref)
-                .into_result()?;
+    // the existing dbs are moved over
+    let config = Box::leak(Box::new(Config {
+        metadata_dir: Some(dir.join("metadata")),
+        ..Default::default()
+    }));
+    let store = SecretStore::new(pass, config).await.unwrap();
+    assert!(!pass.file_exists(&db_path).await.unwrap());
+    assert!(dir.join("metadata").join(&db_path).exists());
+    assert!(dir
+        .join("metadata")
+        .join(PASS_SUBDIR)
+        .join(COLLECTIONS_DB)
+        .exists());
+    assert!(store.collection_exists(&collection_id).await.unwrap());
 
-            drop(attributes_table);
-            drop(attributes_table_reverse);
-            drop(labels_table);
-            tx.commit().into_result()?;
+    store.delete_collection(Arc::new(collection_id.clone())).await.unwrap();
+    assert!(!dir.join("pass").join(PASS_SUBDIR).join(&collection_id).exists());
+    assert!(!dir.join("metadata").join(PASS_SUBDIR).join(&collection_id).exists());
 
-            Ok(secret_id)
-        })
-        .await
-        .unwrap()
-    }
+    std::fs::remove_dir_all(dir).unwrap();
+}
 
-    pub async fn set_secret(&self, collection_id: &str, secret_id: &str, value: Vec<u8>) -> Result {
-        let collection_dir = Path::new(PASS_SUBDIR).join(&*collection_id);
+#[tokio::test(flavor = "multi_thread")]
+async fn test_busy_collection_db() {
+    let dir = std::env::temp_dir().join(format!(
+        "pass-secret-service-test-{}",
+        nanoid!(8, &NANOID_ALPHABET)
+    ));
+    let pass = Box::leak(Box::new(PasswordStore::with_directory(dir.clone())));
+    let config = Box::leak(Box::new(Config::default()));
+    let store = SecretStore::new(pass, config).await.unwrap();
+    let busy = store.create_collection(Some("Busy".to_owned()), None).await.unwrap();
+    let other = Arc::new(store.create_collection(Some("Other".to_owned()), None).await.unwrap());
 
-        let secret_path = collection_dir.join(&*secret_id);
+    // a long write transaction in one collection, like a reindex
+    let db = store.collection_db(&busy).await.unwrap();
+    let (started_tx, started_rx) = tokio::sync::oneshot::channel();
+    let (release_tx, release_rx) = std::sync::mpsc::channel::<()>();
+    let long_write = tokio::task::spawn_blocking(move || {
+        let tx = db.begin_write().unwrap();
+        started_tx.send(()).unwrap();
+        release_rx.recv().unwrap();
+        tx.commit().unwrap();
+    });
+    started_rx.await.unwrap();
 
-        // write the password
-        self.pass.write_password(secret_path, value).await?;
+    // doesn't hold up the other collections, or adding and removing them
+    tokio::time::timeout(Duration::from_secs(5), async {
+        store.create_test_secret(other.clone(), Default::default()).await.unwrap();
+        assert_eq!(store.count_secrets(other.clone()).await.unwrap(), 1);
+        let added = store.create_collection(Some("Added".to_owned()), None).await.unwrap();
+        store.delete_collection(Arc::new(added)).await.unwrap();
+    })
+    .await
+    .expect("the busy collection held up the others");
 
-        Ok(())
-    }
+    release_tx.send(()).unwrap();
+    long_write.await.unwrap();
+    std::fs::remove_dir_all(dir).unwrap();
+}
 
-    pub async fn set_secret_label(
-        &self,
-        collection_id: Arc<String>,
-        secret_id: Arc<String>,
-        label: String,
-    ) -> Result {
-        // write the attributes
-        let collections = self.collection_dbs.clone();
-        spawn_blocking(move || {
-            let cols = collections.blocking_read();
+#[tokio::test]
+async fn test_unlock_empty_collection() {
+    let dir = std::env::temp_dir().join(format!(
+        "pass-secret-service-test-{}",
+        nanoid!(8, &NANOID_ALPHABET)
+    ));
+    let pass = Box::leak(Box::new(PasswordStore::with_directory(dir.clone())));
+    let config = Box::leak(Box::new(Config::default()));
+    let store = SecretStore::new(pass, config).await.unwrap();
+    let collection_id = Arc::new(store.create_collection(Some("Empty".to_owned()), None).await.unwrap());
+    store.set_locked(&collection_id, true);
 
-            // get the db or return an error
-            let db = cols.get(&*collection_id).into_not_found()?;
+    // there's no secret to decrypt, and nothing can be encrypted to check the keys with (the
+    // store has no .gpg-id), so it isn't unlocked without checking the passphrase
+    assert!(store.unlock_collections(vec![collection_id.clone()]).await.unwrap().is_empty());
+    assert!(store.is_locked(&collection_id));
 
-            let tx = db.begin_write().into_result()?;
-            let mut labels_table = tx.open_table(LABELS_TABLE).into_result()?;
+    std::fs::remove_dir_all(dir).unwrap();
+}
 
-            labels_table
-                .insert(secret_id.as_str(), label.as_str())
-                .into_result()?;
+#[tokio::test]
+async fn test_legacy_pin() {
+    let dir = std::env::temp_dir().join(format!(
+        "pass-secret-service-test-{}",
+        nanoid!(8, &NANOID_ALPHABET)
+    ));
+    let pass = Box::leak(Box::new(PasswordStore::with_directory(dir.clone())));
+    let config = Box::leak(Box::new(Config::default()));
+    let store = SecretStore::new(pass, config).await.unwrap();
 
-            drop(labels_table);
-            tx.commit().into_result()?;
+    // as older versions stored it
+    let key: [u8; blake3::KEY_LEN] = rand::random();
+    let legacy = [key.as_slice(), blake3::keyed_hash(&key, b"1234").as_bytes()].concat();
+    let tx = store.main_db().begin_write().unwrap();
+    tx.open_table(KEYS_TABLE).unwrap().insert(PIN_KEY, legacy.as_slice()).unwrap();
+    tx.commit().unwrap();
 
-            Ok(())
-        })
+    // a wrong PIN leaves it as it is
+    assert!(!store.collection_pin_matches(&legacy, "4321".into()).await.unwrap());
+    assert_eq!(store.stored_pin().await.unwrap().unwrap(), legacy);
+
+    assert!(store.collection_pin_matches(&legacy, "1234".into()).await.unwrap());
+    let stored = store.stored_pin().await.unwrap().unwrap();
+    assert!(stored.starts_with(b"$argon2id$"));
+    assert!(store.collection_pin_matches(&stored, "1234".into()).await.unwrap());
+    assert!(!store.collection_pin_matches(&stored, "4321".into()).await.unwrap());
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_migrate_legacy_db() {
+    let dir = std::env::temp_dir().join(format!(
+        "pass-secret-service-test-{}",
+        nanoid!(8, &NANOID_ALPHABET)
+    ));
+    let pass = Box::leak(Box::new(PasswordStore::with_directory(dir.clone())));
+    let config = Box::leak(Box::new(Config::default()));
+    let store = SecretStore::new(pass, config).await.unwrap();
+    let collection_id = Arc::new(store.create_collection(Some("Work".to_owned()), None).await.unwrap());
+    let secret_id = Arc::new("legacy".to_owned());
+    pass.write_encrypted(Path::new(PASS_SUBDIR).join(&*collection_id).join(&*secret_id), b"not encrypted")
         .await
-        .unwrap()
-    }
+        .unwrap();
 
-    pub async fn get_secret_label(
-        &self,
-        collection_id: Arc<String>,
-        secret_id: Arc<String>,
-    ) -> Result<String> {
-        let collections = self.collection_dbs.clone();
-        spawn_blocking(move || {
-            let cols = collections.blocking_read();
+    // as older versions laid it out: everything in one db, labels shared by collections and secrets
+    let legacy_path = dir.join(PASS_SUBDIR).join(ATTRIBUTES_DB);
+    let legacy = Database::create(&legacy_path).unwrap();
+    let tx = legacy.begin_write().unwrap();
+    let mut labels = tx.open_table(LABELS_TABLE).unwrap();
+    labels.insert(collection_id.as_str(), "Old label").unwrap();
+    labels.insert(secret_id.as_str(), "Legacy secret").unwrap();
+    drop(labels);
+    let mut aliases = tx.open_table(ALIASES_TABLE).unwrap();
+    aliases.insert("legacy", collection_id.as_str()).unwrap();
+    aliases.insert("gone", "missing").unwrap();
+    drop(aliases);
+    let mut attributes = tx.open_table(ATTRIBUTES_TABLE_REVERSE).unwrap();
+    attributes.insert(secret_id.as_str(), HashMap::from([("user", "me")])).unwrap();
+    drop(attributes);
+    tx.commit().unwrap();
+    drop(legacy);
 
-            // get the db or return an error
-            let db = cols.get(&*collection_id).into_not_found()?;
+    let migrated = || async {
+        (
+            store.get_label(collection_id.clone()).await.unwrap(),
+            store.get_secret_label(collection_id.clone(), secret_id.clone()).await.unwrap(),
+            store.read_secret_attrs(collection_id.clone(), secret_id.clone()).await.unwrap(),
+            // the items aren't registered, so look in the db directly
+            search_collection(
+                &HashMap::from([("user".to_owned(), "me".to_owned())]),
+                &store.collection_db(&collection_id).await.unwrap(),
+            )
+            .unwrap(),
+            store.get_alias(Arc::new("legacy".to_owned())).await.ok(),
+            store.get_alias(Arc::new("gone".to_owned())).await.ok(),
+            store.count_secrets(collection_id.clone()).await.unwrap(),
+        )
+    };
 
-            let tx = db.begin_read().into_result()?;
-            let labels_table = raise_nonexistent_table!(tx.open_table(LABELS_TABLE));
+    store.migrate_legacy_db().await.unwrap();
+    let expected = (
+        // the collection's label was already set
+        "Work".to_owned(),
+        "Legacy secret".to_owned(),
+        HashMap::from([("user".to_owned(), "me".to_owned())]),
+        vec![secret_id.to_string()],
+        Some(collection_id.to_string()),
+        None,
+        1,
+    );
+    assert_eq!(migrated().await, expected);
+    let backup = legacy_path.with_extension("redb.bak");
+    assert!(!legacy_path.exists());
+    assert!(backup.exists());
 
-            let label = labels_table
-                .get(secret_id.as_str())
-                .into_result()?
-                .into_not_found()?
-                .value()
-                .to_owned();
+    // a second run finds nothing to migrate
+    store.migrate_legacy_db().await.unwrap();
+    assert_eq!(migrated().await, expected);
 
-            Ok(label)
-        })
-        .await
-        .unwrap()
-    }
+    // and if the legacy db comes back, it doesn't overwrite anything
+    std::fs::rename(&backup, &legacy_path).unwrap();
+    store.migrate_legacy_db().await.unwrap();
+    assert_eq!(migrated().await, expected);
 
-    /// read the attributes for the given secret
-    pub async fn set_secret_attrs(
-        &self,
-        collection_id: Arc<String>,
-        secret_id: Arc<String>,
-        attrs: HashMap<String, String>,
-    ) -> Result {
-        let collections = self.collection_dbs.clone();
-        spawn_blocking(move || {
-            let cols = collections.blocking_read();
-            let db = cols.get(&*collection_id).into_not_found()?;
-            let tx = db.begin_write().into_result()?;
-            let mut attributes_table = tx.open_multimap_table(ATTRIBUTES_TABLE).into_result()?;
-            let mut attributes_table_reverse =
-                tx.open_table(ATTRIBUTES_TABLE_REVERSE).into_result()?;
+    std::fs::remove_dir_all(dir).unwrap();
+}
 
-            let attrs_ref = attrs
-                .iter()
-                .map(|(k, v)| (k.as_str(), v.as_str()))
-                .collect::<HashMap<_, _>>();
+#[tokio::test]
+async fn test_check_uninitialized_recipients() {
+    let dir = std::env::temp_dir().join(format!(
+        "pass-secret-service-test-{}",
+        nanoid!(8, &NANOID_ALPHABET)
+    ));
+    let pass = Box::leak(Box::new(PasswordStore::with_directory(dir.clone())));
+    let config = Box::leak(Box::new(Config::default()));
+    let store = SecretStore::new(pass, config).await.unwrap();
+    let first = store.create_collection(Some("First".to_owned()), None).await.unwrap();
+    let second = store.create_collection(Some("Second".to_owned()), None).await.unwrap();
 
-            if let Some(old_attrs) = attributes_table_reverse
-                .insert(secret_id.as_str(), attrs_ref)
-                .into_result()?
-            {
-                // remove the old attributes
-                for (k, v) in old_attrs.value() {
-                    attributes_table
-                        .remove((k, v), secret_id.as_str())
-                        .into_result()?;
-                }
-            }
+    // neither has a .gpg-id, which is reported instead of failing the whole check
+    let mut checks = store.check_recipients(10).await.unwrap();
+    checks.sort_by(|a, b| a.collection_id.cmp(&b.collection_id));
+    let mut expected = [first, second];
+    expected.sort();
+    assert_eq!(checks.iter().map(|c| c.collection_id.as_str()).collect::<Vec<_>>(), expected);
+    assert!(checks.iter().all(|c| !c.initialized && c.checked == 0));
 
-            // insert the new attributes
-            for (k, v) in &attrs {
-                attributes_table
-                    .insert((k.as_str(), v.as_str()), secret_id.as_str())
-                    .into_result()?;
-            }
+    std::fs::remove_dir_all(dir).unwrap();
+}
 
-            drop(attributes_table);
-            drop(attributes_table_reverse);
+#[tokio::test(flavor = "multi_thread")]
+async fn test_rotation_after_write() {
+    let dir = std::env::temp_dir().join(format!(
+        "pass-secret-service-test-{}",
+        nanoid!(8, &NANOID_ALPHABET)
+    ));
+    let pass = Box::leak(Box::new(PasswordStore::with_directory(dir.clone())));
+    let config = Box::leak(Box::new(Config {
+        rotation: crate::config::RotationConfig {
+            max_age_months: 1,
+            ..Default::default()
+        },
+        ..Default::default()
+    }));
+    let store = SecretStore::new(pass, config).await.unwrap();
+    let collection_id = Arc::new(store.create_collection(Some("Work".to_owned()), None).await.unwrap());
+    let secret_id = store.create_test_secret(collection_id.clone(), Default::default()).await.unwrap();
+    let file = dir
+        .join(PASS_SUBDIR)
+        .join(&*collection_id)
+        .join(format!("{secret_id}.gpg"));
+    let set_modified = |time| std::fs::File::options().write(true).open(&file).unwrap().set_modified(time).unwrap();
+    set_modified(SystemTime::now() - Duration::from_secs(365 * 24 * 60 * 60));
 
-            tx.commit().into_result()?;
+    // a SetSecret is writing it when the rotation starts
+    let guard = store.lock_secret(&collection_id, &secret_id).await;
+    let rotation = tokio::spawn({
+        let store = store.clone();
+        let (collection_id, secret_id) = (collection_id.clone(), secret_id.clone());
+        async move { store.rotate_if_due(&collection_id, &secret_id, b"stale".to_vec()).await }
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(!rotation.is_finished());
+    set_modified(SystemTime::now());
+    drop(guard);
 
-            Ok(())
-        })
-        .await
-        .unwrap()
-    }
+    // so the new value isn't overwritten with the one read before it
+    assert!(!rotation.await.unwrap().unwrap());
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_dump_db() {
+    let path = std::env::temp_dir().join(format!("pass-secret-service-test-{}.redb", nanoid!(8)));
+    let db = Database::create(&path).unwrap();
+    let tx = db.begin_write().unwrap();
+    tx.open_table(LABELS_TABLE).unwrap().insert("default_AbCd", "Default").unwrap();
+    tx.open_multimap_table(ALIASES_TABLE_REVERSE).unwrap().insert("default_AbCd", "default").unwrap();
+    tx.open_table(KEYS_TABLE).unwrap().insert(CHECKSUM_KEY, [1u8; 32].as_slice()).unwrap();
+    tx.open_table(TableDefinition::<&str, &str>::new("from-the-future")).unwrap();
+    tx.commit().unwrap();
+
+    // while it's open
+    let contents = std::fs::read(&path).unwrap();
+    let mut out = vec![];
+    dump_db(&path, &mut out).unwrap();
+    assert_eq!(std::fs::read(&path).unwrap(), contents);
+    let out = String::from_utf8(out).unwrap();
+    assert!(out.contains("labels (1 entries)\n  \"default_AbCd\": \"Default\"\n"));
+    assert!(out.contains("aliases_reverse (1 entries)\n  \"default_AbCd\": [\"default\"]\n"));
+    assert!(out.contains("keys (1 entries)\n  \"checksum\": <hidden>\n"));
+    assert!(out.contains("from-the-future (unknown table)"));
+    // tables it doesn't have aren't listed
+    assert!(!out.contains("trash"));
+
+    drop(db);
+    std::fs::remove_file(path).unwrap();
+}
+
+#[tokio::test]
+async fn test_verify_write() {
+    let path = std::env::temp_dir().join(format!("pass-secret-service-test-{}", nanoid!(8)));
+    let writes = std::cell::Cell::new(0);
+    let read = || async { Ok(std::fs::read(&path)?) };
+
+    // the file is corrupted after every write
+    let write = || async {
+        writes.set(writes.get() + 1);
+        std::fs::write(&path, b"value")?;
+        std::fs::write(&path, b"valve")?;
+        Ok(())
+    };
+    let result = verify_write("test/secret", b"value", write, read).await;
+    assert!(matches!(result, Err(Error::WriteVerificationFailed)), "{result:?}");
+    assert_eq!(writes.get(), 2);
+
+    // only the first time
+    writes.set(0);
+    let write = || async {
+        writes.set(writes.get() + 1);
+        std::fs::write(&path, if writes.get() == 1 { b"valve" } else { b"value" })?;
+        Ok(())
+    };
+    verify_write("test/secret", b"value", write, read).await.unwrap();
+    assert_eq!(writes.get(), 2);
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_find_in_collection() {
+    assert!(wildcard_matches("*", ""));
+    assert!(wildcard_matches("https://*.example.com/*", "https://mail.example.com/login"));
+    assert!(!wildcard_matches("https://*.example.com/*", "https://example.org/"));
+    assert!(wildcard_matches("v?", "v2"));
+    assert!(!wildcard_matches("v?", "v10"));
+    assert!(wildcard_matches("a*b*c", "aXbYbZc"));
+    assert!(!wildcard_matches("exact", "exactly"));
+
+    let db = Database::builder()
+        .create_with_backend(redb::backends::InMemoryBackend::new())
+        .unwrap();
+    let tx = db.begin_write().unwrap();
+    let mut labels = tx.open_table(LABELS_TABLE).unwrap();
+    labels.insert("s1", "GitHub token").unwrap();
+    labels.insert("s2", "Mail password").unwrap();
+    drop(labels);
+    let mut attributes = tx.open_table(ATTRIBUTES_TABLE_REVERSE).unwrap();
+    attributes.insert("s1", HashMap::from([("url", "https://github.com/login")])).unwrap();
+    attributes.insert("s2", HashMap::from([("url", "https://mail.example.com/")])).unwrap();
+    drop(attributes);
+    tx.commit().unwrap();
+
+    let patterns = |pairs: &[(&str, &str)]| {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect::<HashMap<_, _>>()
+    };
+    assert_eq!(find_in_collection("github", &patterns(&[]), &db).unwrap(), ["s1"]);
+    assert_eq!(find_in_collection("", &patterns(&[("url", "https://*")]), &db).unwrap().len(), 2);
+    assert_eq!(
+        find_in_collection("password", &patterns(&[("url", "*example*")]), &db).unwrap(),
+        ["s2"]
+    );
+    // the key has to be there
+    assert!(find_in_collection("", &patterns(&[("user", "*")]), &db).unwrap().is_empty());
+}
+
+#[test]
+fn test_escaped_id() {
+    assert!(is_valid_id("AbCd_1234"));
+    assert!(!is_valid_id("work.email"));
+    assert!(!is_valid_id(""));
+
+    let taken = HashSet::from(["work_email".to_owned()]);
+    assert_eq!(escaped_id("work-email", &HashSet::new()), "work_email");
+    let escaped = escaped_id("work-email", &taken);
+    assert!(escaped.starts_with("work_email_") && is_valid_id(&escaped));
+    assert!(is_valid_id(&escaped_id("ü", &HashSet::new())));
 }
@@ -0,0 +1,77 @@
+use std::{
+    env,
+    ffi::OsStr,
+    io,
+    os::{
+        linux::net::SocketAddrExt,
+        unix::{
+            ffi::OsStrExt,
+            net::{SocketAddr, UnixDatagram},
+        },
+    },
+    path::Path,
+};
+
+use log::warn;
+use tokio::{
+    select,
+    signal::unix::{signal, Signal, SignalKind},
+};
+
+/// tell systemd about the daemon's state (e.g. `READY=1`) when it's running as a
+/// `Type=notify` service. does nothing otherwise
+pub fn notify(state: &str) {
+    let Some(path) = env::var_os("NOTIFY_SOCKET") else {
+        return;
+    };
+    if let Err(e) = send(&path, state) {
+        warn!("Could not notify systemd: {e}");
+    }
+}
+
+fn send(path: &OsStr, state: &str) -> io::Result<()> {
+    let address = match path.as_bytes().strip_prefix(b"@") {
+        // in the abstract namespace
+        Some(name) => SocketAddr::from_abstract_name(name)?,
+        None => SocketAddr::from_pathname(Path::new(path))?,
+    };
+    UnixDatagram::unbound()?.send_to_addr(state.as_bytes(), &address)?;
+    Ok(())
+}
+
+/// SIGTERM (which systemd sends to stop the service) and SIGINT
+/// once this exists, they no longer kill the daemon immediately
+pub struct ShutdownSignals {
+    terminate: Signal,
+    interrupt: Signal,
+}
+
+impl ShutdownSignals {
+    pub fn listen() -> io::Result<Self> {
+        Ok(Self {
+            terminate: signal(SignalKind::terminate())?,
+            interrupt: signal(SignalKind::interrupt())?,
+        })
+    }
+
+    /// wait for one of them. returns its name
+    pub async fn recv(&mut self) -> &'static str {
+        select! {
+            _ = self.terminate.recv() => "SIGTERM",
+            _ = self.interrupt.recv() => "SIGINT",
+        }
+    }
+}
+
+#[test]
+fn test_send() {
+    let path = env::temp_dir().join(format!("pass-secret-service-notify-{}", std::process::id()));
+    let socket = UnixDatagram::bind(&path).unwrap();
+
+    send(path.as_os_str(), "READY=1").unwrap();
+    let mut message = [0; 16];
+    let len = socket.recv(&mut message).unwrap();
+    assert_eq!(&message[..len], b"READY=1");
+
+    std::fs::remove_file(path).unwrap();
+}
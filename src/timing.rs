@@ -0,0 +1,117 @@
+use std::{
+    cell::RefCell,
+    fmt::Write,
+    future::Future,
+    sync::OnceLock,
+    time::{Duration, Instant},
+};
+
+use log::warn;
+
+use crate::deadline;
+
+/// where a request spent its time
+#[derive(Clone, Copy, Debug)]
+pub enum Phase {
+    /// redb transactions
+    Database,
+    /// gpg, including any pinentry gpg-agent shows
+    Gpg,
+    /// reading and writing files in the password store
+    Files,
+    /// high-security PIN or confirmation prompts
+    Prompt,
+    /// remote stores like Vault
+    Backend,
+}
+
+const PHASES: [(Phase, &str); 5] = [
+    (Phase::Database, "database"),
+    (Phase::Gpg, "gpg"),
+    (Phase::Files, "files"),
+    (Phase::Prompt, "prompt"),
+    (Phase::Backend, "backend"),
+];
+
+#[derive(Default, Debug)]
+struct Timings {
+    phases: [Duration; PHASES.len()],
+    /// whether a phase is being timed. phases inside it (or running alongside it)
+    /// aren't counted, so the breakdown doesn't add up to more than the total
+    in_phase: bool,
+}
+
+tokio::task_local! {
+    static TIMINGS: RefCell<Timings>;
+}
+
+/// requests which take at least this long are logged
+static THRESHOLD: OnceLock<Duration> = OnceLock::new();
+
+/// log requests which take at least `threshold`. zero disables logging
+pub fn init(threshold: Duration) {
+    if !threshold.is_zero() {
+        let _ = THRESHOLD.set(threshold);
+    }
+}
+
+/// run a D-Bus method, logging it with a breakdown of where the time went if it's slow
+/// it's also given the request deadline
+/// the method is boxed, since every D-Bus method's future is inlined into its interface's dispatch
+pub async fn timed<F: Future>(method: &str, f: F) -> F::Output {
+    let f = Box::pin(deadline::scoped(f));
+    let Some(threshold) = THRESHOLD.get() else {
+        return f.await;
+    };
+
+    let start = Instant::now();
+    let (output, timings) = TIMINGS
+        .scope(Default::default(), async {
+            let output = f.await;
+            (output, TIMINGS.with(RefCell::take))
+        })
+        .await;
+    let total = start.elapsed();
+
+    if total >= *threshold {
+        let mut breakdown = String::new();
+        for ((_, name), duration) in PHASES.iter().zip(timings.phases) {
+            if !duration.is_zero() {
+                let _ = write!(breakdown, "{name} {duration:.2?}, ");
+            }
+        }
+        let other = total.saturating_sub(timings.phases.iter().sum());
+        warn!("{method} took {total:.2?} ({breakdown}other {other:.2?})");
+    }
+    output
+}
+
+/// count the time a future takes towards a phase of the current request
+/// time spent waiting for the user isn't counted towards its deadline
+pub async fn phase<F: Future>(phase: Phase, f: F) -> F::Output {
+    let start = Instant::now();
+    let output = record_phase(phase, f).await;
+    if let Phase::Prompt = phase {
+        deadline::extend(start.elapsed());
+    }
+    output
+}
+
+async fn record_phase<F: Future>(phase: Phase, f: F) -> F::Output {
+    // not in a timed request, or already in a phase
+    let entered = TIMINGS
+        .try_with(|timings| !std::mem::replace(&mut timings.borrow_mut().in_phase, true))
+        .unwrap_or(false);
+    if !entered {
+        return f.await;
+    }
+
+    let start = Instant::now();
+    let output = f.await;
+    let _ = TIMINGS.try_with(|timings| {
+        let mut timings = timings.borrow_mut();
+        timings.phases[phase as usize] += start.elapsed();
+        timings.in_phase = false;
+    });
+    output
+}
@@ -0,0 +1,256 @@
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    fs::read_to_string,
+    io,
+    time::{Duration, SystemTime},
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde_json::{json, Value};
+
+use crate::{
+    config::{matches_collection, VaultConfig},
+    crash::run_blocking,
+    error::{Error, Result},
+    secret_store::days_from_civil,
+};
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// a token which isn't printed in debug output
+struct Token(String);
+
+impl Debug for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Token(..)")
+    }
+}
+
+/// where a collection's secrets are kept: `<mount>/<prefix>/<collection id>/`
+#[derive(Debug, PartialEq)]
+pub struct VaultLocation {
+    mount: String,
+    prefix: String,
+}
+
+impl VaultLocation {
+    fn new(mount: &str, prefix: &str) -> Self {
+        Self {
+            mount: mount.trim_matches('/').to_owned(),
+            prefix: prefix.trim_matches('/').to_owned(),
+        }
+    }
+}
+
+/// stores secret values in a HashiCorp Vault or OpenBao KV v2 engine
+/// each secret is stored at `<prefix>/<collection id>/<secret id>` with its value base64 encoded
+#[derive(Debug)]
+pub struct VaultStore {
+    agent: ureq::Agent,
+    address: String,
+    token: Token,
+    namespace: Option<String>,
+    /// where collections without their own location are kept
+    location: VaultLocation,
+    /// the locations of some collections, by id or alias
+    locations: HashMap<String, VaultLocation>,
+    /// ids or aliases of the collections in vault
+    collections: Vec<String>,
+}
+
+/// parse an RFC 3339 timestamp in UTC, ignoring fractional seconds
+fn parse_timestamp(timestamp: &str) -> Option<SystemTime> {
+    let (date, time) = timestamp.strip_suffix('Z')?.split_once('T')?;
+
+    let mut date = date.splitn(3, '-').map(str::parse::<i64>);
+    let days = days_from_civil(date.next()?.ok()?, date.next()?.ok()?, date.next()?.ok()?);
+
+    let time = time.split('.').next()?;
+    let mut time = time.splitn(3, ':').map(str::parse::<i64>);
+    let seconds = time.next()?.ok()? * 3600 + time.next()?.ok()? * 60 + time.next()?.ok()?;
+
+    let since_epoch = u64::try_from(days * 86400 + seconds).ok()?;
+    Some(SystemTime::UNIX_EPOCH + Duration::from_secs(since_epoch))
+}
+
+/// send a request, returning None if the path doesn't exist
+async fn send(request: ureq::Request, body: Option<Value>) -> Result<Option<Value>> {
+    run_blocking(move || {
+        let result = match body {
+            Some(body) => request.send_json(body),
+            None => request.call(),
+        };
+
+        match result {
+            // no content
+            Ok(response) if response.status() == 204 => Ok(Some(Value::Null)),
+            Ok(response) => Ok(Some(response.into_json()?)),
+            Err(ureq::Error::Status(404, _)) => Ok(None),
+            Err(ureq::Error::Status(status, response)) => Err(Error::Backend(format!(
+                "Vault returned {status}: {}",
+                response.into_string().unwrap_or_default()
+            ))),
+            Err(e) => Err(Error::Backend(e.to_string())),
+        }
+    })
+    .await?
+}
+
+impl VaultStore {
+    pub fn from_config(config: &VaultConfig) -> Result<Self> {
+        let token = read_to_string(&config.token_file)?.trim().to_owned();
+
+        Ok(Self {
+            agent: ureq::AgentBuilder::new().timeout(TIMEOUT).build(),
+            address: config.address.trim_end_matches('/').to_owned(),
+            token: Token(token),
+            namespace: config.namespace.clone(),
+            location: VaultLocation::new(&config.mount, &config.prefix),
+            locations: config
+                .locations
+                .iter()
+                .map(|(collection, location)| {
+                    let mount = location.mount.as_deref().unwrap_or(&config.mount);
+                    let prefix = location.prefix.as_deref().unwrap_or(&config.prefix);
+                    (collection.clone(), VaultLocation::new(mount, prefix))
+                })
+                .collect(),
+            collections: config.collections.clone(),
+        })
+    }
+
+    /// whether a collection (with the given aliases) is kept in vault
+    pub fn serves(&self, collection_id: &str, aliases: &[String]) -> bool {
+        matches_collection(&self.collections, collection_id, aliases)
+    }
+
+    /// where a collection (with the given aliases) is kept, by its id before its aliases
+    pub fn location(&self, collection_id: &str, aliases: &[String]) -> &VaultLocation {
+        self.locations
+            .get(collection_id)
+            .or_else(|| aliases.iter().find_map(|alias| self.locations.get(alias)))
+            .unwrap_or(&self.location)
+    }
+
+    fn request(&self, location: &VaultLocation, method: &str, path: &str) -> ureq::Request {
+        let request = self
+            .agent
+            .request(method, &format!("{}/v1/{}/{path}", self.address, location.mount))
+            .set("X-Vault-Token", &self.token.0);
+
+        match &self.namespace {
+            Some(namespace) => request.set("X-Vault-Namespace", namespace),
+            None => request,
+        }
+    }
+
+    /// path of a secret under the data or metadata endpoint
+    fn secret_path(location: &VaultLocation, endpoint: &str, collection_id: &str, secret_id: &str) -> String {
+        format!("{endpoint}/{}/{collection_id}/{secret_id}", location.prefix)
+    }
+
+    pub async fn read(&self, location: &VaultLocation, collection_id: &str, secret_id: &str) -> Result<Vec<u8>> {
+        let path = Self::secret_path(location, "data", collection_id, secret_id);
+        let request = self.request(location, "GET", &path);
+        let response = send(request, None)
+            .await?
+            .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))?;
+
+        response["data"]["data"]["value"]
+            .as_str()
+            .and_then(|value| STANDARD.decode(value).ok())
+            .ok_or_else(|| Error::Backend("Vault secret has no valid value".into()))
+    }
+
+    pub async fn write(&self, location: &VaultLocation, collection_id: &str, secret_id: &str, value: &[u8]) -> Result {
+        let path = Self::secret_path(location, "data", collection_id, secret_id);
+        let request = self.request(location, "POST", &path);
+        let body = json!({ "data": { "value": STANDARD.encode(value) } });
+        send(request, Some(body)).await?;
+        Ok(())
+    }
+
+    /// delete every version of a secret
+    pub async fn delete(&self, location: &VaultLocation, collection_id: &str, secret_id: &str) -> Result {
+        let path = Self::secret_path(location, "metadata", collection_id, secret_id);
+        let request = self.request(location, "DELETE", &path);
+        send(request, None).await?;
+        Ok(())
+    }
+
+    pub async fn list(&self, location: &VaultLocation, collection_id: &str) -> Result<Vec<String>> {
+        let request = self
+            .request(location, "GET", &format!("metadata/{}/{collection_id}", location.prefix))
+            .query("list", "true");
+
+        let Some(response) = send(request, None).await? else {
+            // nothing has been written to this collection yet
+            return Ok(vec![]);
+        };
+
+        Ok(response["data"]["keys"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(Value::as_str)
+            // skip subfolders
+            .filter(|key| !key.ends_with('/'))
+            .map(str::to_owned)
+            .collect())
+    }
+
+    /// when a secret was created and last updated
+    pub async fn times(
+        &self,
+        location: &VaultLocation,
+        collection_id: &str,
+        secret_id: &str,
+    ) -> Result<(Option<SystemTime>, Option<SystemTime>)> {
+        let path = Self::secret_path(location, "metadata", collection_id, secret_id);
+        let request = self.request(location, "GET", &path);
+        let response = send(request, None)
+            .await?
+            .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))?;
+
+        let time = |key: &str| response["data"][key].as_str().and_then(parse_timestamp);
+        Ok((time("created_time"), time("updated_time")))
+    }
+}
+
+#[test]
+fn test_parse_timestamp() {
+    let time = |secs| Some(SystemTime::UNIX_EPOCH + Duration::from_secs(secs));
+
+    assert_eq!(parse_timestamp("2018-03-22T02:24:06.945319214Z"), time(1521685446));
+    assert_eq!(parse_timestamp("1970-01-01T00:00:00Z"), time(0));
+    // not UTC
+    assert_eq!(parse_timestamp("2018-03-22T02:24:06+01:00"), None);
+}
+
+#[test]
+fn test_location() {
+    let config: VaultConfig = toml::from_str(
+        r#"
+        address = "https://vault.example.com"
+        token-file = "/dev/null"
+        mount = "/secret/"
+        collections = ["*"]
+
+        [locations.work]
+        mount = "team"
+        prefix = "shared/"
+
+        [locations.abc]
+        prefix = "other"
+        "#,
+    )
+    .unwrap();
+    let vault = VaultStore::from_config(&config).unwrap();
+
+    assert_eq!(vault.location("xyz", &[]), &VaultLocation::new("secret", "pass-secret-service"));
+    // by alias
+    assert_eq!(vault.location("xyz", &["work".to_owned()]), &VaultLocation::new("team", "shared"));
+    // the id comes first, and the rest is taken from [vault]
+    assert_eq!(vault.location("abc", &["work".to_owned()]), &VaultLocation::new("secret", "other"));
+}
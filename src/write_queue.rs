@@ -0,0 +1,368 @@
+use std::{
+    collections::VecDeque,
+    ffi::OsStr,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, SystemTime},
+};
+
+use log::{info, warn};
+use nanoid::nanoid;
+use tokio::{
+    fs::{read, read_dir, remove_file, DirBuilder, OpenOptions},
+    io::AsyncWriteExt,
+    sync::{Mutex as AsyncMutex, Notify},
+};
+
+use crate::{
+    error::{Error, Result},
+    pass::PasswordStore,
+    secret_store::NANOID_ALPHABET,
+};
+
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(5 * 60);
+
+#[derive(Debug, Clone)]
+enum PendingValue {
+    /// encrypted, and saved in the spool dir (if possible)
+    Encrypted(Vec<u8>),
+    /// couldn't be encrypted yet, so this is only kept in memory
+    Plaintext(Vec<u8>),
+}
+
+#[derive(Debug, Clone)]
+struct PendingWrite {
+    id: u64,
+    /// path of the secret, relative to the password store
+    path: PathBuf,
+    value: PendingValue,
+    spool_file: Option<PathBuf>,
+}
+
+#[derive(Debug, Default)]
+struct QueueState {
+    pending: VecDeque<PendingWrite>,
+    next_id: u64,
+    last_error: Option<String>,
+}
+
+/// summary of the writes waiting to be retried
+#[derive(Debug, Default)]
+pub struct QueueStatus {
+    pub pending: u32,
+    /// number of pending writes saved in the spool dir
+    pub spooled: u32,
+    pub last_error: Option<String>,
+}
+
+/// writes which failed because the store or gpg was unavailable, retried with backoff
+#[derive(Debug)]
+pub struct WriteQueue {
+    spool_dir: PathBuf,
+    max_pending: usize,
+    state: Mutex<QueueState>,
+    /// held while writing to the store, so a retry can't overwrite a newer write
+    io_lock: AsyncMutex<()>,
+    notify: Notify,
+}
+
+/// parse a spool file: the secret path, a newline, then the encrypted value
+fn parse_spool_file(contents: &[u8]) -> Option<(PathBuf, Vec<u8>)> {
+    let newline = contents.iter().position(|b| *b == b'\n')?;
+    let path = PathBuf::from(OsStr::from_bytes(&contents[..newline]));
+    Some((path, contents[newline + 1..].to_vec()))
+}
+
+impl WriteQueue {
+    /// create the queue, loading any writes left in the spool dir
+    pub async fn open(spool_dir: PathBuf, max_pending: usize) -> Result<Self> {
+        DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(&spool_dir)
+            .await?;
+
+        let mut files = vec![];
+        let mut entries = read_dir(&spool_dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            files.push(entry.path());
+        }
+        // names start with the time they were queued
+        files.sort();
+
+        let mut state = QueueState::default();
+        for file in files {
+            match parse_spool_file(&read(&file).await?) {
+                Some((path, encrypted)) => {
+                    state.pending.push_back(PendingWrite {
+                        id: state.next_id,
+                        path,
+                        value: PendingValue::Encrypted(encrypted),
+                        spool_file: Some(file),
+                    });
+                    state.next_id += 1;
+                }
+                None => warn!("Ignoring invalid spool file {}", file.display()),
+            }
+        }
+
+        if !state.pending.is_empty() {
+            info!("Loaded {} pending writes from {}", state.pending.len(), spool_dir.display());
+        }
+
+        Ok(Self {
+            spool_dir,
+            max_pending,
+            state: Mutex::new(state),
+            io_lock: AsyncMutex::new(()),
+            notify: Notify::new(),
+        })
+    }
+
+    pub fn status(&self) -> QueueStatus {
+        let state = self.state.lock().unwrap();
+        QueueStatus {
+            pending: state.pending.len() as u32,
+            spooled: state
+                .pending
+                .iter()
+                .filter(|write| write.spool_file.is_some())
+                .count() as u32,
+            last_error: state.last_error.clone(),
+        }
+    }
+
+    /// write a password, queueing it if the store or gpg is unavailable
+    pub async fn write(&self, pass: &PasswordStore, path: &Path, value: Vec<u8>) -> Result {
+        let _io = self.io_lock.lock().await;
+
+        // this write supersedes anything queued for the same secret
+        self.cancel_locked(path).await;
+
+        match pass.encrypt_password(path, value.clone()).await {
+            Ok(encrypted) => match pass.write_encrypted(path, &encrypted).await {
+                Ok(()) => Ok(()),
+                Err(Error::IoError(e)) => {
+                    self.enqueue(path, PendingValue::Encrypted(encrypted), e)
+                        .await
+                }
+                Err(e) => Err(e),
+            },
+            Err(Error::IoError(e)) => self.enqueue(path, PendingValue::Plaintext(value), e).await,
+            Err(e) => Err(e),
+        }
+    }
+
+    /// read the queued value of a password, if there is one
+    pub async fn read(
+        &self,
+        pass: &PasswordStore,
+        path: &Path,
+        can_prompt: bool,
+    ) -> Option<Result<Vec<u8>>> {
+        let value = {
+            let state = self.state.lock().unwrap();
+            state
+                .pending
+                .iter()
+                .rev()
+                .find(|write| write.path == path)?
+                .value
+                .clone()
+        };
+
+        Some(match value {
+            PendingValue::Plaintext(value) => Ok(value),
+            PendingValue::Encrypted(encrypted) => pass.decrypt(encrypted, can_prompt).await,
+        })
+    }
+
+    /// whether a write to a password is queued
+    pub fn is_pending(&self, path: &Path) -> bool {
+        let state = self.state.lock().unwrap();
+        state.pending.iter().any(|write| write.path == path)
+    }
+
+    /// drop any queued writes to a password
+    pub async fn cancel(&self, path: &Path) {
+        let _io = self.io_lock.lock().await;
+        self.cancel_locked(path).await;
+    }
+
+    async fn cancel_locked(&self, path: &Path) {
+        let removed: VecDeque<_> = {
+            let mut state = self.state.lock().unwrap();
+            let (removed, kept) = state
+                .pending
+                .drain(..)
+                .partition(|write| write.path == path);
+            state.pending = kept;
+            removed
+        };
+
+        for write in removed {
+            self.remove_spool_file(&write).await;
+        }
+    }
+
+    async fn enqueue(&self, path: &Path, value: PendingValue, error: std::io::Error) -> Result {
+        if self.state.lock().unwrap().pending.len() >= self.max_pending {
+            warn!("Write queue is full");
+            return Err(error.into());
+        }
+
+        warn!("Queueing write to {}: {error}", path.display());
+
+        let spool_file = match &value {
+            PendingValue::Encrypted(encrypted) => self.spool(path, encrypted).await,
+            PendingValue::Plaintext(_) => {
+                warn!("{} could not be encrypted, so it will be lost if the daemon exits", path.display());
+                None
+            }
+        };
+
+        {
+            let mut state = self.state.lock().unwrap();
+            let id = state.next_id;
+            state.next_id += 1;
+            state.pending.push_back(PendingWrite {
+                id,
+                path: path.to_owned(),
+                value,
+                spool_file,
+            });
+            state.last_error = Some(error.to_string());
+        }
+
+        self.notify.notify_one();
+        Ok(())
+    }
+
+    /// save an encrypted write in the spool dir
+    async fn spool(&self, path: &Path, encrypted: &[u8]) -> Option<PathBuf> {
+        let queued_at = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or_default();
+        let file_path = self
+            .spool_dir
+            .join(format!("{queued_at:020}-{}", nanoid!(8, &NANOID_ALPHABET)));
+
+        let result = async {
+            let mut file = OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .mode(0o600)
+                .open(&file_path)
+                .await?;
+            file.write_all(path.as_os_str().as_bytes()).await?;
+            file.write_all(b"\n").await?;
+            file.write_all(encrypted).await?;
+            file.sync_all().await
+        }
+        .await;
+
+        match result {
+            Ok(()) => Some(file_path),
+            Err(e) => {
+                warn!("Could not save pending write to the spool dir: {e}");
+                None
+            }
+        }
+    }
+
+    async fn remove_spool_file(&self, write: &PendingWrite) {
+        if let Some(file) = &write.spool_file {
+            if let Err(e) = remove_file(file).await {
+                warn!("Could not remove spool file {}: {e}", file.display());
+            }
+        }
+    }
+
+    /// retry pending writes in order
+    /// returns false if one failed
+    async fn flush(&self, pass: &PasswordStore) -> bool {
+        loop {
+            let _io = self.io_lock.lock().await;
+            let Some(write) = self.state.lock().unwrap().pending.front().cloned() else {
+                return true;
+            };
+
+            let result = match &write.value {
+                PendingValue::Encrypted(encrypted) => {
+                    pass.write_encrypted(&write.path, encrypted).await
+                }
+                PendingValue::Plaintext(value) => {
+                    match pass.encrypt_password(&write.path, value.clone()).await {
+                        Ok(encrypted) => {
+                            let result = pass.write_encrypted(&write.path, &encrypted).await;
+                            if result.is_err() {
+                                // at least we can keep it on disk now
+                                let spool_file = self.spool(&write.path, &encrypted).await;
+                                let mut state = self.state.lock().unwrap();
+                                if let Some(pending) =
+                                    state.pending.iter_mut().find(|w| w.id == write.id)
+                                {
+                                    pending.value = PendingValue::Encrypted(encrypted);
+                                    pending.spool_file = spool_file;
+                                }
+                            }
+                            result
+                        }
+                        Err(e) => Err(e),
+                    }
+                }
+            };
+
+            match result {
+                Ok(()) => {
+                    info!("Wrote queued secret {}", write.path.display());
+                    let removed = {
+                        let mut state = self.state.lock().unwrap();
+                        let index = state.pending.iter().position(|w| w.id == write.id);
+                        index.and_then(|i| state.pending.remove(i))
+                    };
+                    if let Some(removed) = removed {
+                        self.remove_spool_file(&removed).await;
+                    }
+                }
+                Err(e) => {
+                    self.state.lock().unwrap().last_error = Some(e.to_string());
+                    return false;
+                }
+            }
+        }
+    }
+
+    /// retry pending writes forever, backing off while they keep failing
+    pub async fn run(&self, pass: &PasswordStore) {
+        let mut backoff = INITIAL_BACKOFF;
+        loop {
+            if self.state.lock().unwrap().pending.is_empty() {
+                self.notify.notified().await;
+                backoff = INITIAL_BACKOFF;
+            }
+
+            tokio::time::sleep(backoff).await;
+
+            if self.flush(pass).await {
+                self.state.lock().unwrap().last_error = None;
+                backoff = INITIAL_BACKOFF;
+            } else {
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_parse_spool_file() {
+    let (path, encrypted) = parse_spool_file(b"secret-service/default/abc\n\x85\n\x01").unwrap();
+    assert_eq!(path, Path::new("secret-service/default/abc"));
+    // newlines in the encrypted value are kept
+    assert_eq!(encrypted, b"\x85\n\x01");
+
+    assert!(parse_spool_file(b"no newline").is_none());
+}